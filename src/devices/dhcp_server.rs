@@ -0,0 +1,228 @@
+use super::{Device, Module, WireMsg};
+use crate::{
+    dhcp::server::{DhcpServer, DhcpServerConfig},
+    protocols::{
+        arp::{ArpOperation, ArpPacket},
+        ethernet::{
+            EthernetFrame, EthernetFrameRef, FrameProtocol, MacAddress, ETHERNET_BROADCAST_MAC_ADDR,
+        },
+        ipv4::Ipv4Packet,
+        Packet,
+    },
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+// A host with a single interface that only hands out addresses. It answers ARP for its own
+// address so renewing clients can reach it.
+pub struct DhcpServerDevice {
+    address: MacAddress,
+    module: Module,
+    server: Arc<Mutex<DhcpServer>>,
+}
+
+impl DhcpServerDevice {
+    pub fn new(address: MacAddress, config: DhcpServerConfig) -> Self {
+        Self {
+            address,
+            module: Module::new(1),
+            server: Arc::new(Mutex::new(DhcpServer::new(config))),
+        }
+    }
+
+    // shared with the running device, to look at the leases
+    pub fn server(&self) -> Arc<Mutex<DhcpServer>> {
+        Arc::clone(&self.server)
+    }
+
+    fn handle_frame(&mut self, msg: WireMsg, now: Instant) {
+        let frame = match EthernetFrameRef::new(&msg.data) {
+            Ok(frame) => frame,
+            Err(err) => {
+                log::error!(
+                    "DHCP server {}: parsing ethernet frame: {err:?}",
+                    self.address
+                );
+                return;
+            }
+        };
+
+        if frame.destin() != self.address && frame.destin() != ETHERNET_BROADCAST_MAC_ADDR {
+            return;
+        }
+
+        match frame.protocol() {
+            Ok(FrameProtocol::Apr) => {
+                let Ok(arp) = ArpPacket::decode(frame.payload()) else {
+                    return;
+                };
+                let server_address = self.server.lock().unwrap().config().address;
+                if arp.operation == ArpOperation::Request && arp.target_ip == server_address {
+                    let reply = arp.reply_to(self.address);
+                    self.send(arp.sender_mac, FrameProtocol::Apr, reply.to_bytes());
+                }
+            }
+            Ok(FrameProtocol::Ipv4) => {
+                let Ok(packet) = Ipv4Packet::decode(frame.payload()) else {
+                    return;
+                };
+                let reply = self.server.lock().unwrap().handle_packet(&packet, now);
+                if let Some((destin, reply)) = reply {
+                    self.send(destin, FrameProtocol::Ipv4, reply.to_bytes());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn send(&self, destin: MacAddress, protocol: FrameProtocol, data: Box<[u8]>) {
+        let frame = EthernetFrame {
+            source: self.address,
+            destin,
+            protocol,
+            data,
+        };
+        let interface = self.module.get_interface(0).unwrap();
+        if let Err(err) = interface.send(frame.to_bytes()) {
+            log::debug!(
+                "DHCP server {}: failed to send frame: {err:?}",
+                self.address
+            );
+        }
+    }
+}
+
+impl Device for DhcpServerDevice {
+    fn get_mac_address(&self) -> MacAddress {
+        self.address
+    }
+
+    fn get_module(&mut self) -> &mut Module {
+        &mut self.module
+    }
+
+    fn kind(&self) -> &'static str {
+        "dhcp server"
+    }
+
+    fn addresses(&self) -> Vec<String> {
+        let server = self.server.lock().unwrap();
+        let config = server.config();
+        vec![format!("{}/{}", config.address, config.prefix_len)]
+    }
+
+    fn run(&mut self) {
+        log::debug!("DHCP server {} running...", self.address);
+        while let Some(msg) = self.module.wait_for_msg() {
+            self.handle_frame(msg, Instant::now());
+        }
+        log::debug!("DHCP server {} shutting down...", self.address);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DhcpServerDevice;
+    use crate::{
+        devices::Device,
+        dhcp::{
+            client::{DhcpClient, DhcpState},
+            server::DhcpServerConfig,
+        },
+        links,
+        protocols::{
+            arp::{ArpOperation, ArpPacket},
+            ethernet::{EthernetFrame, FrameProtocol, MacAddress, ETHERNET_BROADCAST_MAC_ADDR},
+            ipv4::Ipv4Packet,
+            Packet,
+        },
+    };
+    use std::{
+        net::Ipv4Addr,
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    const SERVER_MAC: MacAddress = MacAddress::new([1; 6]);
+    const CLIENT_MAC: MacAddress = MacAddress::new([2; 6]);
+
+    #[test]
+    fn leases_addresses_over_a_link() {
+        let server_address = Ipv4Addr::new(10, 0, 0, 2);
+        let config = DhcpServerConfig::new(
+            server_address,
+            24,
+            Ipv4Addr::new(10, 0, 0, 10),
+            Ipv4Addr::new(10, 0, 0, 20),
+        );
+        let mut device = DhcpServerDevice::new(SERVER_MAC, config);
+        let server = device.server();
+        let handle = device.get_module().handle();
+
+        let (end, peer) = links::create_link();
+        let (sender, receiver) = mpsc::channel();
+        peer.attach_receiver(move |data| {
+            sender
+                .send(EthernetFrame::from_raw_bytes(&data).unwrap())
+                .unwrap()
+        })
+        .unwrap();
+        device.get_module().attach_link(0, end);
+        let thread = thread::spawn(move || device.run());
+
+        let send = |destin, protocol, data| {
+            let frame = EthernetFrame {
+                source: CLIENT_MAC,
+                destin,
+                protocol,
+                data,
+            };
+            peer.send(frame.to_bytes()).unwrap();
+        };
+
+        let mut client = DhcpClient::new(CLIENT_MAC);
+        client.start(Instant::now());
+        while client.state() != DhcpState::Bound {
+            for packet in client.poll(Instant::now()) {
+                send(
+                    ETHERNET_BROADCAST_MAC_ADDR,
+                    FrameProtocol::Ipv4,
+                    packet.to_bytes(),
+                );
+            }
+
+            let frame = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(frame.source, SERVER_MAC);
+            let packet = Ipv4Packet::decode(&frame.data).unwrap();
+            assert!(client.handle_packet(&packet, Instant::now()));
+        }
+
+        let address = client.lease().unwrap().address;
+        assert_eq!(address, Ipv4Addr::new(10, 0, 0, 10));
+        let leases: Vec<_> = server
+            .lock()
+            .unwrap()
+            .leases(Instant::now())
+            .map(|(address, lease)| (address, lease.mac))
+            .collect();
+        assert_eq!(leases, vec![(address, CLIENT_MAC)]);
+
+        // renewals are sent straight to the server
+        let request = ArpPacket::request(CLIENT_MAC, address, server_address);
+        send(
+            ETHERNET_BROADCAST_MAC_ADDR,
+            FrameProtocol::Apr,
+            request.to_bytes(),
+        );
+        let frame = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        let reply = ArpPacket::decode(&frame.data).unwrap();
+        assert_eq!(reply.operation, ArpOperation::Reply);
+        assert_eq!(reply.sender_mac, SERVER_MAC);
+
+        handle.shutdown();
+        thread.join().unwrap();
+    }
+}
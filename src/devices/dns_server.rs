@@ -0,0 +1,363 @@
+use super::{
+    endpoint::{IpEndpoint, IpPacket},
+    Device, Module, ModuleEvent, WireMsg,
+};
+use crate::{
+    dns::{
+        resolver::{LookupId, Resolver},
+        zone::Zone,
+    },
+    protocols::{
+        dns::{in_zone, DnsMessage, ResponseCode, DNS_PORT},
+        ethernet::MacAddress,
+        ipv4::{IpProtocol, Ipv4Packet},
+        udp::UdpDatagram,
+        Packet,
+    },
+};
+use std::{collections::HashMap, net::Ipv4Addr, time::Instant};
+
+// where to send the answer of a query the resolver works on
+struct Client {
+    address: Ipv4Addr,
+    port: u16,
+    query: DnsMessage,
+}
+
+// A name server with a single interface. It answers authoritatively for its zones and, once
+// recursion is enabled, resolves the other names for its clients starting from the root servers.
+pub struct DnsServer {
+    address: MacAddress,
+    module: Module,
+    endpoint: IpEndpoint,
+    zones: Vec<Zone>,
+    resolver: Option<Resolver>,
+    waiting: HashMap<LookupId, Client>,
+}
+
+impl DnsServer {
+    pub fn new(
+        address: MacAddress,
+        ip_address: Ipv4Addr,
+        prefix_len: u8,
+        gateway: Option<Ipv4Addr>,
+    ) -> Self {
+        let mut endpoint = IpEndpoint::new(address);
+        endpoint.configure(ip_address, prefix_len, gateway);
+        Self {
+            address,
+            module: Module::new(1),
+            endpoint,
+            zones: Vec::new(),
+            resolver: None,
+            waiting: HashMap::new(),
+        }
+    }
+
+    pub fn add_zone(&mut self, zone: Zone) {
+        self.zones.push(zone);
+    }
+
+    pub fn enable_recursion(&mut self, root_hints: Vec<Ipv4Addr>) {
+        self.resolver = Some(Resolver::recursive(root_hints));
+    }
+
+    fn handle_frame(&mut self, msg: WireMsg, now: Instant) {
+        let interface = self.module.get_interface(0).unwrap();
+        let Some(IpPacket::V4(packet)) = self.endpoint.receive(interface, &msg.data, now) else {
+            return;
+        };
+        if packet.protocol != IpProtocol::Udp {
+            return;
+        }
+        let Ok(datagram) = UdpDatagram::decode(&packet.data) else {
+            return;
+        };
+        if datagram.destin_port != DNS_PORT {
+            return;
+        }
+
+        let message = match DnsMessage::decode(&datagram.data) {
+            Ok(message) => message,
+            Err(err) => {
+                log::warn!(
+                    "DNS server {}: invalid message from {}: {err:?}",
+                    self.address,
+                    packet.source
+                );
+                return;
+            }
+        };
+
+        if message.response {
+            if let Some(resolver) = &mut self.resolver {
+                resolver.handle_response(packet.source, &message, now);
+            }
+        } else if message.questions.len() == 1 {
+            let client = Client {
+                address: packet.source,
+                port: datagram.source_port,
+                query: message,
+            };
+            self.answer(client, now);
+        }
+    }
+
+    fn answer(&mut self, client: Client, now: Instant) {
+        let question = &client.query.questions[0];
+        let zone = self
+            .zones
+            .iter()
+            .filter(|zone| in_zone(&question.name, zone.origin()))
+            .max_by_key(|zone| zone.origin().len());
+
+        let response = zone.map(|zone| zone.answer(&client.query));
+        let recurse = client.query.recursion_desired
+            && self.resolver.is_some()
+            && response.as_ref().is_none_or(|response| {
+                !response.authoritative && response.response_code == ResponseCode::NoError
+            });
+
+        if recurse {
+            let resolver = self.resolver.as_mut().unwrap();
+            let id = resolver.resolve(question.clone(), now);
+            self.waiting.insert(id, client);
+            return;
+        }
+
+        let response = response
+            .unwrap_or_else(|| DnsMessage::response_to(&client.query, ResponseCode::Refused));
+        self.reply(&client, response, now);
+    }
+
+    fn reply(&mut self, client: &Client, mut response: DnsMessage, now: Instant) {
+        response.recursion_available = self.resolver.is_some();
+        let datagram = UdpDatagram {
+            source_port: DNS_PORT,
+            destin_port: client.port,
+            data: response.to_bytes(),
+        };
+        self.send(client.address, datagram, now);
+    }
+
+    fn send(&mut self, destin: Ipv4Addr, datagram: UdpDatagram, now: Instant) {
+        let source = self.endpoint.address().unwrap();
+        let packet = Ipv4Packet::new(source, destin, IpProtocol::Udp, datagram.to_bytes());
+        let interface = self.module.get_interface(0).unwrap();
+        if !self.endpoint.send(interface, packet, now) {
+            log::warn!("DNS server {}: no route to {destin}", self.address);
+        }
+    }
+
+    fn poll_resolver(&mut self, now: Instant) {
+        let Some(resolver) = &mut self.resolver else {
+            return;
+        };
+        let queries = resolver.poll(now);
+        let results = resolver.take_results();
+
+        for (server, query) in queries {
+            let datagram = UdpDatagram {
+                source_port: DNS_PORT,
+                destin_port: DNS_PORT,
+                data: query.to_bytes(),
+            };
+            self.send(server, datagram, now);
+        }
+
+        for resolution in results {
+            let Some(client) = self.waiting.remove(&resolution.id) else {
+                continue;
+            };
+            let response = match resolution.result {
+                Ok(records) => {
+                    let mut response =
+                        DnsMessage::response_to(&client.query, ResponseCode::NoError);
+                    response.answers = records;
+                    response
+                }
+                Err(code) => DnsMessage::response_to(&client.query, code),
+            };
+            self.reply(&client, response, now);
+        }
+    }
+}
+
+impl Device for DnsServer {
+    fn get_mac_address(&self) -> MacAddress {
+        self.address
+    }
+
+    fn get_module(&mut self) -> &mut Module {
+        &mut self.module
+    }
+
+    fn kind(&self) -> &'static str {
+        "dns server"
+    }
+
+    fn addresses(&self) -> Vec<String> {
+        self.endpoint.cidr().into_iter().collect()
+    }
+
+    fn run(&mut self) {
+        log::debug!("DNS server {} running...", self.address);
+
+        loop {
+            let deadline = self
+                .resolver
+                .as_ref()
+                .and_then(|resolver| resolver.next_deadline());
+            let event = match deadline {
+                Some(deadline) => self
+                    .module
+                    .wait_for_event_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => Some(self.module.wait_for_event()),
+            };
+
+            let now = Instant::now();
+            match event {
+                Some(ModuleEvent::Msg(msg)) => self.handle_frame(msg, now),
+                Some(ModuleEvent::Shutdown) => {
+                    log::debug!("DNS server {} shutting down...", self.address);
+                    return;
+                }
+                _ => {}
+            }
+
+            self.poll_resolver(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DnsServer;
+    use crate::{
+        devices::{
+            endpoint::{IpEndpoint, IpPacket},
+            switch::Layer2Switch,
+            Device, Module, ModuleEvent,
+        },
+        dns::{resolver::Resolver, zone::Zone},
+        links,
+        protocols::{
+            dns::{DnsMessage, DnsQuestion, RecordType, DNS_PORT},
+            ethernet::MacAddress,
+            ipv4::{IpProtocol, Ipv4Packet},
+            udp::UdpDatagram,
+            Packet,
+        },
+    };
+    use std::{
+        net::Ipv4Addr,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    const ROOT_ZONE: &str = "$ORIGIN .
+com.                 NS  a.gtld-servers.net.
+a.gtld-servers.net.  A   10.0.1.1
+";
+
+    const COM_ZONE: &str = "$ORIGIN com.
+example              NS  ns1.example
+ns1.example          A   10.0.2.1
+";
+
+    const EXAMPLE_ZONE: &str = "$ORIGIN example.com.
+@                    NS  ns1
+ns1                  A   10.0.2.1
+www                  CNAME web
+web                  A   10.0.2.80
+";
+
+    const RESOLVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 53);
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 100);
+    const CLIENT_PORT: u16 = 40000;
+
+    #[test]
+    fn resolves_through_the_hierarchy() {
+        let mut servers = Vec::new();
+        let zones = [
+            (Ipv4Addr::new(10, 0, 0, 1), ROOT_ZONE),
+            (Ipv4Addr::new(10, 0, 1, 1), COM_ZONE),
+            (Ipv4Addr::new(10, 0, 2, 1), EXAMPLE_ZONE),
+        ];
+        for (i, (address, zone)) in zones.into_iter().enumerate() {
+            let mut server = DnsServer::new(MacAddress::new([i as u8 + 1; 6]), address, 16, None);
+            server.add_zone(Zone::parse(zone).unwrap());
+            servers.push(server);
+        }
+        let mut resolver = DnsServer::new(MacAddress::new([4; 6]), RESOLVER, 16, None);
+        resolver.enable_recursion(vec![Ipv4Addr::new(10, 0, 0, 1)]);
+        servers.push(resolver);
+
+        let mut switch = Layer2Switch::new(MacAddress::new([0xAA; 6]), 5);
+        for (i, server) in servers.iter_mut().enumerate() {
+            let (left, right) = links::create_link();
+            server.get_module().attach_link(0, left);
+            switch.get_module().attach_link(i as u32, right);
+        }
+        let mut client = Module::new(1);
+        let (left, right) = links::create_link();
+        client.attach_link(0, left);
+        switch.get_module().attach_link(4, right);
+
+        let mut handles = vec![switch.get_module().handle()];
+        handles.extend(servers.iter().map(|server| server.module.handle()));
+        let mut threads = vec![thread::spawn(move || switch.run())];
+        threads.extend(
+            servers
+                .into_iter()
+                .map(|mut server| thread::spawn(move || server.run())),
+        );
+
+        let mut endpoint = IpEndpoint::new(MacAddress::new([5; 6]));
+        endpoint.configure(CLIENT, 16, None);
+        let mut stub = Resolver::stub(vec![RESOLVER]);
+        let question = DnsQuestion::new("www.example.com", RecordType::A);
+        stub.resolve(question, Instant::now());
+
+        let started = Instant::now();
+        let resolution = loop {
+            assert!(started.elapsed() < Duration::from_secs(5), "no answer");
+            let interface = client.get_interface(0).unwrap();
+            for (server, query) in stub.poll(Instant::now()) {
+                let datagram = UdpDatagram {
+                    source_port: CLIENT_PORT,
+                    destin_port: DNS_PORT,
+                    data: query.to_bytes(),
+                };
+                let packet = Ipv4Packet::new(CLIENT, server, IpProtocol::Udp, datagram.to_bytes());
+                endpoint.send(interface, packet, Instant::now());
+            }
+            if let Some(resolution) = stub.take_results().pop() {
+                break resolution;
+            }
+
+            let Some(ModuleEvent::Msg(msg)) =
+                client.wait_for_event_timeout(Duration::from_millis(100))
+            else {
+                continue;
+            };
+            let interface = client.get_interface(0).unwrap();
+            let Some(IpPacket::V4(packet)) = endpoint.receive(interface, &msg.data, Instant::now())
+            else {
+                continue;
+            };
+            let datagram = UdpDatagram::decode(&packet.data).unwrap();
+            assert_eq!(datagram.destin_port, CLIENT_PORT);
+            let response = DnsMessage::decode(&datagram.data).unwrap();
+            assert!(response.recursion_available);
+            stub.handle_response(packet.source, &response, Instant::now());
+        };
+
+        assert_eq!(resolution.ipv4_addresses(), [Ipv4Addr::new(10, 0, 2, 80)]);
+
+        handles.iter().for_each(|handle| handle.shutdown());
+        threads
+            .into_iter()
+            .for_each(|thread| thread.join().unwrap());
+    }
+}
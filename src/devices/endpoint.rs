@@ -0,0 +1,452 @@
+use super::Interface;
+use crate::{
+    ndp::{slaac::Slaac, NeighborCache},
+    protocols::{
+        arp::{ArpOperation, ArpPacket},
+        ethernet::{
+            EthernetFrame, EthernetFrameRef, FrameProtocol, MacAddress, ETHERNET_BROADCAST_MAC_ADDR,
+        },
+        icmpv6::Icmpv6Packet,
+        ipv4::{Ipv4Packet, Ipv4Prefix},
+        ipv6::{Ipv6Packet, NextHeader},
+        Packet,
+    },
+    routing::InterfaceConfig,
+};
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, Ipv6Addr},
+    time::{Duration, Instant},
+};
+
+// same as the router's
+const ARP_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+const ARP_QUEUE_SIZE: usize = 16;
+
+struct PendingResolution {
+    requested_at: Instant,
+    packets: Vec<Ipv4Packet>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpPacket {
+    V4(Ipv4Packet),
+    V6(Ipv6Packet),
+}
+
+struct Ipv6State {
+    slaac: Slaac,
+    neighbors: NeighborCache,
+}
+
+// The IP side of a device with a single interface, like a host or a server: it answers ARP for
+// its address and sends packets to their destination, or to the gateway when it isn't on the same
+// network, resolving MAC addresses on the way. IPv6 is optional and configures itself.
+pub struct IpEndpoint {
+    mac: MacAddress,
+    config: Option<InterfaceConfig>,
+    gateway: Option<Ipv4Addr>,
+    arp_cache: HashMap<Ipv4Addr, MacAddress>,
+    pending: HashMap<Ipv4Addr, PendingResolution>,
+    // whether the IPv4 packets for other addresses are accepted too, for devices forwarding them
+    forwarding: bool,
+    ipv6: Option<Ipv6State>,
+}
+
+impl IpEndpoint {
+    pub fn new(mac: MacAddress) -> Self {
+        Self {
+            mac,
+            config: None,
+            gateway: None,
+            arp_cache: HashMap::new(),
+            pending: HashMap::new(),
+            forwarding: false,
+            ipv6: None,
+        }
+    }
+
+    pub fn configure(&mut self, address: Ipv4Addr, prefix_len: u8, gateway: Option<Ipv4Addr>) {
+        self.config = Some(InterfaceConfig::new(0, address, prefix_len));
+        self.gateway = gateway;
+    }
+
+    // forgets the address, like when a lease expires
+    pub fn unconfigure(&mut self) {
+        self.config = None;
+        self.gateway = None;
+        self.pending.clear();
+    }
+
+    pub fn mac(&self) -> MacAddress {
+        self.mac
+    }
+
+    pub fn address(&self) -> Option<Ipv4Addr> {
+        self.config.map(|config| config.address)
+    }
+
+    // e.g. 10.0.0.2/24
+    pub fn cidr(&self) -> Option<String> {
+        self.config
+            .map(|config| format!("{}/{}", config.address, config.prefix.prefix_len()))
+    }
+
+    pub fn prefix(&self) -> Option<Ipv4Prefix> {
+        self.config.map(|config| config.prefix)
+    }
+
+    pub fn gateway(&self) -> Option<Ipv4Addr> {
+        self.gateway
+    }
+
+    pub fn set_forwarding(&mut self, forwarding: bool) {
+        self.forwarding = forwarding;
+    }
+
+    // starts the autoconfiguration of the IPv6 addresses
+    pub fn enable_ipv6(&mut self, interface: &Interface, now: Instant) {
+        let mut slaac = Slaac::new(self.mac);
+        slaac.start(now);
+        self.ipv6 = Some(Ipv6State {
+            slaac,
+            neighbors: NeighborCache::new(self.mac),
+        });
+        self.poll(interface, now);
+    }
+
+    pub fn ipv6_addresses(&self) -> Vec<Ipv6Addr> {
+        self.ipv6
+            .as_ref()
+            .map(|ipv6| ipv6.slaac.addresses())
+            .unwrap_or_default()
+    }
+
+    pub fn ipv6_source_address(&self, destin: Ipv6Addr) -> Option<Ipv6Addr> {
+        self.ipv6.as_ref()?.slaac.source_address(destin)
+    }
+
+    // runs the IPv6 timers, due by `next_deadline`
+    pub fn poll(&mut self, interface: &Interface, now: Instant) {
+        let Some(ipv6) = self.ipv6.as_mut() else {
+            return;
+        };
+        for packet in ipv6.slaac.poll(now) {
+            self.send_ipv6_to(interface, packet.destin, packet, now);
+        }
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.ipv6.as_ref()?.slaac.next_deadline()
+    }
+
+    // Handles the frame, returning the IP packet it carries when it is for the endpoint. IPv4
+    // packets are returned even without an address so DHCP can work.
+    pub fn receive(
+        &mut self,
+        interface: &Interface,
+        data: &[u8],
+        now: Instant,
+    ) -> Option<IpPacket> {
+        let frame = EthernetFrameRef::new(data).ok()?;
+        if frame.destin() != self.mac && !frame.destin().is_multicast() {
+            return None;
+        }
+
+        match frame.protocol() {
+            Ok(FrameProtocol::Apr) => {
+                let arp = ArpPacket::decode(frame.payload()).ok()?;
+                self.handle_arp(interface, arp);
+                None
+            }
+            Ok(FrameProtocol::Ipv4) => {
+                let packet = Ipv4Packet::decode(frame.payload()).ok()?;
+                let for_us = match self.config {
+                    Some(config) => {
+                        self.forwarding
+                            || packet.destin == config.address
+                            || packet.destin == config.prefix.broadcast()
+                            || packet.destin.is_broadcast()
+                            || packet.destin.is_multicast()
+                    }
+                    None => true,
+                };
+                for_us.then_some(IpPacket::V4(packet))
+            }
+            Ok(FrameProtocol::Ipv6) => {
+                let packet = Ipv6Packet::decode(frame.payload()).ok()?;
+                self.handle_ipv6(interface, packet, now).map(IpPacket::V6)
+            }
+            _ => None,
+        }
+    }
+
+    fn handle_ipv6(
+        &mut self,
+        interface: &Interface,
+        packet: Ipv6Packet,
+        now: Instant,
+    ) -> Option<Ipv6Packet> {
+        let ipv6 = self.ipv6.as_mut()?;
+        if !ipv6.slaac.listens_to(packet.destin) {
+            return None;
+        }
+        if packet.protocol != NextHeader::Icmpv6 {
+            return Some(packet);
+        }
+
+        let message = Icmpv6Packet::decode(&packet.data).ok()?;
+        match message {
+            Icmpv6Packet::RouterSolicitation { .. } => None,
+            Icmpv6Packet::RouterAdvertisement(_) => {
+                ipv6.slaac.handle(&packet, &message, now);
+                self.poll(interface, now);
+                None
+            }
+            Icmpv6Packet::NeighborSolicitation { .. } | Icmpv6Packet::NeighborAdvertisement(_) => {
+                ipv6.slaac.handle(&packet, &message, now);
+                let own = ipv6.slaac.addresses();
+                let out = ipv6.neighbors.handle(&packet, &message, &own, false);
+                for (mac, packet) in out {
+                    self.send_ethernet(interface, mac, FrameProtocol::Ipv6, packet.to_bytes());
+                }
+                None
+            }
+            _ => Some(packet),
+        }
+    }
+
+    fn handle_arp(&mut self, interface: &Interface, arp: ArpPacket) {
+        let Some(address) = self.address() else {
+            return;
+        };
+
+        self.arp_cache.insert(arp.sender_ip, arp.sender_mac);
+        if let Some(pending) = self.pending.remove(&arp.sender_ip) {
+            for packet in pending.packets {
+                self.send_to(interface, arp.sender_mac, packet);
+            }
+        }
+
+        if arp.operation == ArpOperation::Request && arp.target_ip == address {
+            let reply = arp.reply_to(self.mac);
+            self.send_ethernet(
+                interface,
+                arp.sender_mac,
+                FrameProtocol::Apr,
+                reply.to_bytes(),
+            );
+        }
+    }
+
+    // Returns false when there is no way to the destination.
+    pub fn send(&mut self, interface: &Interface, packet: Ipv4Packet, now: Instant) -> bool {
+        let Some(InterfaceConfig {
+            address, prefix, ..
+        }) = self.config
+        else {
+            return false;
+        };
+
+        let destin = packet.destin;
+        if destin.is_broadcast() || destin.is_multicast() || destin == prefix.broadcast() {
+            self.send_to(interface, ETHERNET_BROADCAST_MAC_ADDR, packet);
+            return true;
+        }
+
+        let next_hop = match (prefix.contains(destin), self.gateway) {
+            (true, _) => destin,
+            (false, Some(gateway)) => gateway,
+            (false, None) => return false,
+        };
+        if let Some(mac) = self.arp_cache.get(&next_hop).copied() {
+            self.send_to(interface, mac, packet);
+            return true;
+        }
+
+        let pending = self.pending.entry(next_hop).or_insert(PendingResolution {
+            requested_at: now,
+            packets: Vec::new(),
+        });
+        if pending.packets.len() == ARP_QUEUE_SIZE {
+            pending.packets.remove(0);
+        }
+        pending.packets.push(packet);
+
+        let first = pending.packets.len() == 1;
+        if first || now.duration_since(pending.requested_at) >= ARP_RETRY_INTERVAL {
+            pending.requested_at = now;
+            let request = ArpPacket::request(self.mac, address, next_hop);
+            self.send_ethernet(
+                interface,
+                ETHERNET_BROADCAST_MAC_ADDR,
+                FrameProtocol::Apr,
+                request.to_bytes(),
+            );
+        }
+        true
+    }
+
+    // Returns false when there is no way to the destination, or no address to send from yet.
+    pub fn send_ipv6(&mut self, interface: &Interface, packet: Ipv6Packet, now: Instant) -> bool {
+        let Some(ipv6) = self.ipv6.as_ref() else {
+            return false;
+        };
+        match ipv6.slaac.next_hop(packet.destin) {
+            Some(next_hop) => self.send_ipv6_to(interface, next_hop, packet, now),
+            None => false,
+        }
+    }
+
+    fn send_ipv6_to(
+        &mut self,
+        interface: &Interface,
+        next_hop: Ipv6Addr,
+        packet: Ipv6Packet,
+        now: Instant,
+    ) -> bool {
+        let Some(ipv6) = self.ipv6.as_mut() else {
+            return false;
+        };
+        // solicitations come from the address of the packet, unless it is still being checked
+        let source = match ipv6.slaac.addresses().contains(&packet.source) {
+            true => packet.source,
+            false => match ipv6.slaac.source_address(next_hop) {
+                Some(source) => source,
+                None if next_hop.is_multicast() => Ipv6Addr::UNSPECIFIED,
+                None => return false,
+            },
+        };
+        if let Some((mac, packet)) = ipv6.neighbors.send(source, next_hop, packet, now) {
+            self.send_ethernet(interface, mac, FrameProtocol::Ipv6, packet.to_bytes());
+        }
+        true
+    }
+
+    // sends the packet without resolving anything, for replies to hosts without an address
+    pub fn send_to(&self, interface: &Interface, destin: MacAddress, packet: Ipv4Packet) {
+        self.send_ethernet(interface, destin, FrameProtocol::Ipv4, packet.to_bytes())
+    }
+
+    fn send_ethernet(
+        &self,
+        interface: &Interface,
+        destin: MacAddress,
+        protocol: FrameProtocol,
+        data: Box<[u8]>,
+    ) {
+        let frame = EthernetFrame {
+            source: self.mac,
+            destin,
+            protocol,
+            data,
+        };
+        if let Err(err) = interface.send(frame.to_bytes()) {
+            log::debug!("{}: failed to send frame: {err:?}", self.mac);
+        }
+    }
+}
+
+// Hosts for the tests of the devices that forward IP packets, each with an endpoint on the one
+// interface of its module.
+#[cfg(test)]
+pub mod test {
+    use super::{IpEndpoint, IpPacket};
+    use crate::{
+        devices::{Module, ModuleEvent},
+        links,
+        protocols::{
+            ethernet::MacAddress,
+            ipv4::{IpProtocol, Ipv4Packet},
+            udp::UdpDatagram,
+            Packet,
+        },
+    };
+    use std::{
+        net::{Ipv4Addr, SocketAddrV4},
+        time::{Duration, Instant},
+    };
+
+    pub type TestHost = (Module, IpEndpoint);
+
+    pub fn host(mac: MacAddress) -> TestHost {
+        (Module::new(1), IpEndpoint::new(mac))
+    }
+
+    // on a /24, with the last byte of its address as its mac
+    pub fn ipv4_host(address: Ipv4Addr, gateway: Option<Ipv4Addr>) -> TestHost {
+        let mut host = host(MacAddress::new([address.octets()[3]; 6]));
+        host.1.configure(address, 24, gateway);
+        host
+    }
+
+    // links each host to the interface of the device with its index
+    pub fn attach(hosts: &mut [TestHost], device: &mut Module) {
+        for (interface_id, (module, _)) in hosts.iter_mut().enumerate() {
+            let (left, right) = links::create_link();
+            module.attach_link(0, left);
+            device.attach_link(interface_id as u32, right);
+        }
+    }
+
+    pub fn send_udp(host: &mut TestHost, source_port: u16, destin: SocketAddrV4) {
+        let (module, endpoint) = host;
+        let datagram = UdpDatagram {
+            source_port,
+            destin_port: destin.port(),
+            data: Box::new([1, 2, 3]),
+        };
+        let packet = Ipv4Packet::new(
+            endpoint.address().unwrap(),
+            *destin.ip(),
+            IpProtocol::Udp,
+            datagram.to_bytes(),
+        );
+        let interface = module.get_interface(0).unwrap();
+        assert!(endpoint.send(interface, packet, Instant::now()));
+    }
+
+    // runs the hosts for a moment, returning the packets each received
+    pub fn step(hosts: &mut [TestHost]) -> Vec<(usize, IpPacket)> {
+        let mut received = Vec::new();
+        for (i, (module, endpoint)) in hosts.iter_mut().enumerate() {
+            while let Some(ModuleEvent::Msg(msg)) =
+                module.wait_for_event_timeout(Duration::from_millis(5))
+            {
+                let interface = module.get_interface(0).unwrap();
+                if let Some(packet) = endpoint.receive(interface, &msg.data, Instant::now()) {
+                    received.push((i, packet));
+                }
+            }
+            endpoint.poll(module.get_interface(0).unwrap(), Instant::now());
+        }
+        received
+    }
+
+    // Runs the hosts until `receiver` gets an IPv4 packet, or for a while if nothing comes. The
+    // others may have to answer ARP meanwhile.
+    pub fn receive(hosts: &mut [TestHost], receiver: usize) -> Option<Ipv4Packet> {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(1) {
+            let packet = step(hosts)
+                .into_iter()
+                .find_map(|(i, packet)| match packet {
+                    IpPacket::V4(packet) if i == receiver => Some(packet),
+                    _ => None,
+                });
+            if packet.is_some() {
+                return packet;
+            }
+        }
+        None
+    }
+
+    // the source and destination of a UDP packet
+    pub fn udp_addresses(packet: &Ipv4Packet) -> (SocketAddrV4, SocketAddrV4) {
+        let datagram = UdpDatagram::decode(&packet.data).unwrap();
+        (
+            SocketAddrV4::new(packet.source, datagram.source_port),
+            SocketAddrV4::new(packet.destin, datagram.destin_port),
+        )
+    }
+}
@@ -0,0 +1,340 @@
+use super::{Device, DropReason, Module, ModuleEvent, SharedTable, WireMsg};
+use crate::{
+    firewall::{
+        rules::{Action, RuleSet},
+        PacketFilter,
+    },
+    protocols::{
+        ethernet::{self, EthernetFrame, EthernetFrameRef, FrameProtocol, MacAddress},
+        icmp::{self, IcmpPacket, IcmpType},
+        ipv4::{IpProtocol, Ipv4Packet},
+        Packet,
+    },
+    trace::TraceEvent,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// how often the connections that timed out are forgotten
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(1);
+
+// A transparent firewall: it bridges its interfaces like a switch does, but every frame has to
+// get through the rules first. Flooded frames are checked once, without an out interface.
+pub struct Firewall {
+    address: MacAddress,
+    module: Module,
+    learn_table: HashMap<MacAddress, u32>,
+    filter: Arc<Mutex<PacketFilter>>,
+    last_expiry: Instant,
+}
+
+impl Firewall {
+    pub fn new(address: MacAddress, interface_nr: u32, rules: RuleSet) -> Self {
+        Self {
+            address,
+            module: Module::new(interface_nr),
+            learn_table: HashMap::new(),
+            filter: Arc::new(Mutex::new(PacketFilter::new(rules))),
+            last_expiry: Instant::now(),
+        }
+    }
+
+    // shared with the device's thread, so the hit counters can be read while it runs
+    pub fn filter(&self) -> Arc<Mutex<PacketFilter>> {
+        Arc::clone(&self.filter)
+    }
+
+    fn forward(&mut self, msg: WireMsg, now: Instant) {
+        let frame = match EthernetFrameRef::new(&msg.data) {
+            Ok(frame) => frame,
+            Err(err) => {
+                log::error!(
+                    "Firewall {}: parsing ethernet frame from interface {}: {err:?}",
+                    self.address,
+                    msg.interface_id
+                );
+                let interface = self.module.get_interface(msg.interface_id).unwrap();
+                interface.count_parse_error();
+                return;
+            }
+        };
+
+        if frame.source() != ethernet::ETHERNET_BROADCAST_MAC_ADDR {
+            self.learn_table.insert(frame.source(), msg.interface_id);
+        }
+
+        let out_interface = self.learn_table.get(&frame.destin()).copied();
+        if out_interface == Some(msg.interface_id) {
+            let interface = self.module.get_interface(msg.interface_id).unwrap();
+            interface.count_drop(DropReason::SameInterface);
+            return;
+        }
+
+        let action =
+            self.filter
+                .lock()
+                .unwrap()
+                .check(&msg.data, msg.interface_id, out_interface, now);
+
+        match (action, out_interface) {
+            (Action::Accept, Some(out_interface)) => {
+                self.module
+                    .trace_decision(TraceEvent::Forward, Some(out_interface), "accepted");
+                // the link might still go down in the meantime
+                let _ = self
+                    .module
+                    .get_interface(out_interface)
+                    .unwrap()
+                    .send(msg.data);
+            }
+            (Action::Accept, None) => {
+                self.module
+                    .trace_decision(TraceEvent::Flood, None, "accepted");
+                for interface in self.module.interfaces() {
+                    if interface.interface_id != msg.interface_id && interface.is_up() {
+                        let _ = interface.send(msg.data.clone());
+                    }
+                }
+            }
+            (Action::Drop | Action::Reject, _) => {
+                let interface = self.module.get_interface(msg.interface_id).unwrap();
+                interface.count_drop(DropReason::Filtered);
+                if action == Action::Reject {
+                    log::debug!(
+                        "Firewall {}: rejecting frame from interface {}",
+                        self.address,
+                        msg.interface_id
+                    );
+                    self.reject(frame, msg.interface_id);
+                }
+            }
+        }
+    }
+
+    // tells the sender that the packet was not allowed through, unless it was itself an error
+    fn reject(&self, frame: EthernetFrameRef, interface_id: u32) {
+        if frame.protocol() != Ok(FrameProtocol::Ipv4) {
+            return;
+        }
+        let Ok(packet) = Ipv4Packet::decode(frame.payload()) else {
+            return;
+        };
+        let is_error = packet.protocol == IpProtocol::Icmp
+            && matches!(
+                packet.data.first().map(|&kind| IcmpType::try_from(kind)),
+                Some(Ok(IcmpType::DestinationUnreachable | IcmpType::TimeExceeded))
+            );
+        if is_error || packet.destin.is_broadcast() || packet.destin.is_multicast() {
+            return;
+        }
+
+        let error = IcmpPacket::error(
+            IcmpType::DestinationUnreachable,
+            icmp::ICMP_ADMIN_PROHIBITED,
+            &packet.to_bytes(),
+        );
+        // the firewall has no address of its own, so it answers for the destination
+        let error = Ipv4Packet::new(
+            packet.destin,
+            packet.source,
+            IpProtocol::Icmp,
+            error.to_bytes(),
+        );
+        let frame = EthernetFrame {
+            source: self.address,
+            destin: frame.source(),
+            protocol: FrameProtocol::Ipv4,
+            data: error.to_bytes(),
+        };
+        let _ = self
+            .module
+            .get_interface(interface_id)
+            .unwrap()
+            .send(frame.to_bytes());
+    }
+}
+
+impl Device for Firewall {
+    fn get_mac_address(&self) -> MacAddress {
+        self.address
+    }
+
+    fn get_module(&mut self) -> &mut Module {
+        &mut self.module
+    }
+
+    fn kind(&self) -> &'static str {
+        "firewall"
+    }
+
+    fn tables(&self) -> Vec<(&'static str, SharedTable)> {
+        vec![("rules", self.filter.clone())]
+    }
+
+    fn run(&mut self) {
+        log::debug!("Firewall {} running...", self.address);
+        loop {
+            let event = self.module.wait_for_event_timeout(EXPIRE_INTERVAL);
+            let now = Instant::now();
+            match event {
+                Some(ModuleEvent::Msg(msg)) => self.forward(msg, now),
+                Some(ModuleEvent::LinkStateChanged {
+                    interface_id,
+                    up: false,
+                }) => {
+                    self.learn_table
+                        .retain(|_, learned| *learned != interface_id);
+                }
+                Some(ModuleEvent::Shutdown) => {
+                    log::debug!("Firewall {} shutting down...", self.address);
+                    return;
+                }
+                _ => {}
+            }
+
+            if now.duration_since(self.last_expiry) >= EXPIRE_INTERVAL {
+                self.filter.lock().unwrap().expire(now);
+                self.last_expiry = now;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Firewall;
+    use crate::{
+        devices::{
+            endpoint::test::{attach, ipv4_host, receive, send_udp},
+            Device, DropReason,
+        },
+        firewall::rules::RuleSet,
+        links,
+        protocols::{
+            ethernet::{EthernetFrame, FrameProtocol, MacAddress},
+            icmp::{IcmpType, ICMP_ADMIN_PROHIBITED},
+            ipv4::{IpProtocol, Ipv4Packet},
+            Packet,
+        },
+    };
+    use std::{
+        net::{Ipv4Addr, SocketAddrV4},
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    const INSIDE: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+    const OUTSIDE: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 3);
+
+    #[test]
+    fn filters_between_interfaces() {
+        let rules = RuleSet::parse(
+            "accept ether arp\naccept state established\naccept in 0 proto udp\nreject in 1\ndefault drop",
+        )
+        .unwrap();
+        let mut firewall = Firewall::new(MacAddress::new([1; 6]), 2, rules);
+        let filter = firewall.filter();
+
+        let mut hosts = [INSIDE, OUTSIDE].map(|address| ipv4_host(address, None));
+        attach(&mut hosts, firewall.get_module());
+        let handle = firewall.get_module().handle();
+        let thread = thread::spawn(move || firewall.run());
+
+        // the inside host may open flows, and the replies come back through them
+        send_udp(&mut hosts[0], 5000, SocketAddrV4::new(OUTSIDE, 53));
+        assert_eq!(receive(&mut hosts, 1).unwrap().source, INSIDE);
+        send_udp(&mut hosts[1], 53, SocketAddrV4::new(INSIDE, 5000));
+        assert_eq!(receive(&mut hosts, 0).unwrap().source, OUTSIDE);
+
+        // but the outside one is turned away
+        send_udp(&mut hosts[1], 53, SocketAddrV4::new(INSIDE, 6000));
+        let error = receive(&mut hosts, 1).unwrap();
+        assert_eq!(error.source, INSIDE);
+        assert_eq!(error.protocol, IpProtocol::Icmp);
+        assert_eq!(
+            error.data[..2],
+            [
+                IcmpType::DestinationUnreachable as u8,
+                ICMP_ADMIN_PROHIBITED
+            ]
+        );
+        assert!(receive(&mut hosts, 0).is_none());
+
+        let hits: Vec<_> = filter
+            .lock()
+            .unwrap()
+            .rules()
+            .rules()
+            .iter()
+            .map(|rule| rule.hits)
+            .collect();
+        assert_eq!(hits, [2, 1, 1, 1]);
+
+        handle.shutdown();
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn checks_flooded_frames_once() {
+        let rules = RuleSet::parse("accept proto udp\ndefault drop").unwrap();
+        let mut firewall = Firewall::new(MacAddress::new([1; 6]), 3, rules);
+        let filter = firewall.filter();
+        let peers: Vec<_> = (0..3)
+            .map(|interface_id| {
+                let (end, peer) = links::create_link();
+                firewall.get_module().attach_link(interface_id, end);
+                let received = Arc::new(AtomicU32::new(0));
+                let copy = Arc::clone(&received);
+                peer.attach_receiver(move |_| {
+                    copy.fetch_add(1, Ordering::Relaxed);
+                })
+                .unwrap();
+                (peer, received)
+            })
+            .collect();
+
+        let frame = |source: u8, destin: u8, protocol| {
+            let packet = Ipv4Packet::new(INSIDE, OUTSIDE, protocol, Box::new([0; 8]));
+            EthernetFrame {
+                source: MacAddress::new([source; 6]),
+                destin: MacAddress::new([destin; 6]),
+                protocol: FrameProtocol::Ipv4,
+                data: packet.to_bytes(),
+            }
+            .to_bytes()
+        };
+        // both flooded, then sent back to where 2 is
+        peers[0].0.send(frame(2, 3, IpProtocol::Udp)).unwrap();
+        peers[0].0.send(frame(2, 3, IpProtocol::Tcp)).unwrap();
+        peers[0].0.send(frame(4, 2, IpProtocol::Udp)).unwrap();
+        let handle = firewall.get_module().handle();
+        handle.shutdown();
+        firewall.run();
+
+        let received: Vec<_> = peers
+            .iter()
+            .map(|(_, received)| received.load(Ordering::Relaxed))
+            .collect();
+        assert_eq!(received, [0, 1, 1]);
+        let rules = filter.lock().unwrap().rules().clone();
+        assert_eq!(rules.rules()[0].hits, 1);
+        assert_eq!(rules.default_hits(), 1);
+        let drops = &handle.stats().interfaces[0].drops;
+        assert_eq!(drops.get(&DropReason::Filtered), Some(&1));
+        assert_eq!(drops.get(&DropReason::SameInterface), Some(&1));
+
+        let (name, table) = &firewall.tables()[0];
+        assert_eq!(*name, "rules");
+        assert!(table
+            .lock()
+            .unwrap()
+            .to_string()
+            .starts_with("       1 accept proto udp\n"));
+    }
+}
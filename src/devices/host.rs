@@ -0,0 +1,1085 @@
+use super::{
+    endpoint::{IpEndpoint, IpPacket},
+    Device, Module, ModuleEvent,
+};
+use crate::{
+    dhcp::client::DhcpClient,
+    dns::resolver::Resolver,
+    protocols::{
+        dns::{DnsMessage, DnsQuestion, RecordType, DNS_PORT},
+        ethernet::{MacAddress, ETHERNET_BROADCAST_MAC_ADDR},
+        icmp::{self, IcmpPacket, IcmpType},
+        ipv4::{IpProtocol, Ipv4Packet},
+        tcp::{TcpFlags, TcpSegment},
+        udp::UdpDatagram,
+        Packet,
+    },
+    transport::tcp::TcpConnection,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::{Ipv4Addr, SocketAddrV4},
+    ops::RangeInclusive,
+    time::{Duration, Instant},
+};
+
+const EPHEMERAL_PORTS: RangeInclusive<u16> = 49152..=65535;
+// datagrams waiting on a socket before the new ones are dropped
+const UDP_QUEUE_SIZE: usize = 64;
+// where UDP probes go, counting up from there
+const TRACEROUTE_PORT: u16 = 33434;
+
+// what a UDP socket received, and from where
+type Datagram = (SocketAddrV4, Box<[u8]>);
+
+// the gauges of each connection in the DeviceStats
+pub const TCP_IN_FLIGHT: &str = "tcp_in_flight_bytes";
+pub const TCP_SEND_WINDOW: &str = "tcp_send_window_bytes";
+
+// the instance of its gauges
+fn connection_name(connection: &TcpConnection) -> String {
+    format!("{} {}", connection.local(), connection.remote())
+}
+
+pub enum AddressConfig {
+    Static {
+        address: Ipv4Addr,
+        prefix_len: u8,
+        gateway: Option<Ipv4Addr>,
+    },
+    Dhcp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketError {
+    AddressInUse,
+    // the host has no address (yet)
+    NoAddress,
+    NoRoute,
+    ConnectionRefused,
+    ConnectionReset,
+    NameNotFound,
+    TimedOut,
+    // the device is being removed from the simulation
+    Shutdown,
+}
+
+// Sockets are handles into the NetStack that created them.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UdpSocket(u16);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TcpListener(u16);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TcpStream(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeProtocol {
+    Udp,
+    Icmp,
+}
+
+// The ICMP message a probe caused: TimeExceeded from a router on the way, or what the destination
+// (or whatever stopped the probe) answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeReply {
+    pub from: Ipv4Addr,
+    pub message_type: IcmpType,
+    pub code: u8,
+    pub rtt: Duration,
+}
+
+// what answered a probe, when
+type ProbeAnswer = (Ipv4Addr, IcmpType, u8, Instant);
+
+impl UdpSocket {
+    pub fn port(&self) -> u16 {
+        self.0
+    }
+}
+
+// The network stack of a host with a single interface: ARP, the default gateway and the ICMP
+// echo responder come from its IpEndpoint, on top of which it demultiplexes UDP and TCP to
+// sockets. Its socket calls block, handling whatever arrives in the meantime, until they can
+// return or time out.
+pub struct NetStack {
+    module: Module,
+    endpoint: IpEndpoint,
+    dhcp: Option<DhcpClient>,
+    dns_servers: Vec<Ipv4Addr>,
+    resolver: Option<Resolver>,
+    // the port the resolver sends its queries from
+    resolver_port: Option<u16>,
+    udp_sockets: HashMap<u16, VecDeque<Datagram>>,
+    // the connections accepted on each port that weren't handed out yet
+    listeners: HashMap<u16, VecDeque<usize>>,
+    connections: HashMap<usize, TcpConnection>,
+    // the connections the program is done with, forgotten once they are closed
+    released: HashSet<usize>,
+    next_connection: usize,
+    next_port: u16,
+    next_iss: u32,
+    next_echo_sequence: u16,
+    // when the echo replies were received, by sequence number
+    echo_replies: HashMap<u16, Instant>,
+    next_probe_port: u16,
+    // the probes being waited for, by the UDP port they went to or their echo sequence number
+    probes: HashMap<(IpProtocol, u16), Option<ProbeAnswer>>,
+    shutdown: bool,
+}
+
+impl NetStack {
+    fn new(address: MacAddress, config: AddressConfig) -> Self {
+        let mut endpoint = IpEndpoint::new(address);
+        let dhcp = match config {
+            AddressConfig::Static {
+                address,
+                prefix_len,
+                gateway,
+            } => {
+                endpoint.configure(address, prefix_len, gateway);
+                None
+            }
+            AddressConfig::Dhcp => Some(DhcpClient::new(address)),
+        };
+        let bytes = address.as_bytes();
+
+        Self {
+            module: Module::new(1),
+            endpoint,
+            dhcp,
+            dns_servers: Vec::new(),
+            resolver: None,
+            resolver_port: None,
+            udp_sockets: HashMap::new(),
+            listeners: HashMap::new(),
+            connections: HashMap::new(),
+            released: HashSet::new(),
+            next_connection: 0,
+            next_port: *EPHEMERAL_PORTS.start(),
+            next_iss: u32::from_be_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]),
+            next_echo_sequence: 0,
+            echo_replies: HashMap::new(),
+            next_probe_port: TRACEROUTE_PORT,
+            probes: HashMap::new(),
+            shutdown: false,
+        }
+    }
+
+    pub fn mac(&self) -> MacAddress {
+        self.endpoint.mac()
+    }
+
+    pub fn address(&self) -> Option<Ipv4Addr> {
+        self.endpoint.address()
+    }
+
+    // the servers names are resolved with, DHCP replaces them with the ones of its lease
+    pub fn set_dns_servers(&mut self, servers: Vec<Ipv4Addr>) {
+        self.dns_servers = servers;
+        self.resolver = None;
+    }
+
+    pub fn wait_for_address(&mut self, timeout: Option<Duration>) -> Result<Ipv4Addr, SocketError> {
+        self.wait_until(timeout, |stack| stack.address().map(Ok))
+    }
+
+    // keeps the stack running for a while
+    pub fn sleep(&mut self, duration: Duration) -> Result<(), SocketError> {
+        match self.wait_until(Some(duration), |_| None::<Result<(), _>>) {
+            Err(SocketError::TimedOut) => Ok(()),
+            result => result,
+        }
+    }
+
+    // Binds a socket to the port, or to any free one when it is 0.
+    pub fn udp_bind(&mut self, port: u16) -> Result<UdpSocket, SocketError> {
+        let port = self.bind_port(IpProtocol::Udp, port)?;
+        self.udp_sockets.insert(port, VecDeque::new());
+        Ok(UdpSocket(port))
+    }
+
+    pub fn udp_close(&mut self, socket: UdpSocket) {
+        self.udp_sockets.remove(&socket.0);
+    }
+
+    pub fn send_to(
+        &mut self,
+        socket: &UdpSocket,
+        data: &[u8],
+        destin: SocketAddrV4,
+    ) -> Result<(), SocketError> {
+        self.send_udp(socket.0, data, destin)
+    }
+
+    pub fn recv_from(
+        &mut self,
+        socket: &UdpSocket,
+        timeout: Option<Duration>,
+    ) -> Result<(Box<[u8]>, SocketAddrV4), SocketError> {
+        self.wait_until(timeout, |stack| {
+            let (source, data) = stack.udp_sockets.get_mut(&socket.0)?.pop_front()?;
+            Some(Ok((data, source)))
+        })
+    }
+
+    pub fn tcp_listen(&mut self, port: u16) -> Result<TcpListener, SocketError> {
+        let port = self.bind_port(IpProtocol::Tcp, port)?;
+        self.listeners.insert(port, VecDeque::new());
+        Ok(TcpListener(port))
+    }
+
+    // waits for a connection to be established on the listener's port
+    pub fn tcp_accept(
+        &mut self,
+        listener: &TcpListener,
+        timeout: Option<Duration>,
+    ) -> Result<TcpStream, SocketError> {
+        self.wait_until(timeout, |stack| {
+            let backlog = stack.listeners.get_mut(&listener.0)?;
+            // the handshakes that failed are of no use
+            backlog.retain(|id| match stack.connections[id].is_closed() {
+                true => !stack.released.insert(*id),
+                false => true,
+            });
+            let position = backlog
+                .iter()
+                .position(|id| stack.connections[id].is_established())?;
+            backlog.remove(position).map(|id| Ok(TcpStream(id)))
+        })
+    }
+
+    pub fn tcp_connect(
+        &mut self,
+        remote: SocketAddrV4,
+        timeout: Option<Duration>,
+    ) -> Result<TcpStream, SocketError> {
+        let address = self.address().ok_or(SocketError::NoAddress)?;
+        let local = SocketAddrV4::new(address, self.ephemeral_port(IpProtocol::Tcp));
+        let id = self.add_connection(TcpConnection::connect(local, remote, self.next_iss));
+        self.next_iss = self.next_iss.wrapping_add(0x0100_0000);
+
+        let result = self.wait_until(timeout, |stack| {
+            let connection = &stack.connections[&id];
+            if connection.is_closed() {
+                Some(Err(SocketError::ConnectionRefused))
+            } else {
+                connection.is_established().then_some(Ok(()))
+            }
+        });
+        match result {
+            Ok(()) => Ok(TcpStream(id)),
+            Err(err) => {
+                self.tcp_abort(TcpStream(id));
+                Err(err)
+            }
+        }
+    }
+
+    pub fn peer_address(&self, stream: &TcpStream) -> SocketAddrV4 {
+        self.connections[&stream.0].remote()
+    }
+
+    pub fn tcp_send(&mut self, stream: &TcpStream, data: &[u8]) -> Result<(), SocketError> {
+        let connection = self.connections.get_mut(&stream.0).unwrap();
+        if connection.is_closed() {
+            return Err(SocketError::ConnectionReset);
+        }
+        connection.send(data);
+        self.flush(Instant::now());
+        Ok(())
+    }
+
+    // Returns what was received, or nothing once the peer closed the connection.
+    pub fn tcp_recv(
+        &mut self,
+        stream: &TcpStream,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<u8>, SocketError> {
+        self.wait_until(timeout, |stack| {
+            let connection = stack.connections.get_mut(&stream.0).unwrap();
+            let data = connection.read();
+            if !data.is_empty() || connection.peer_closed() {
+                Some(Ok(data))
+            } else if connection.is_closed() {
+                Some(Err(SocketError::ConnectionReset))
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn tcp_retransmissions(&self, stream: &TcpStream) -> u64 {
+        self.connections[&stream.0].retransmissions()
+    }
+
+    // waits until everything sent was acknowledged
+    pub fn tcp_flush(
+        &mut self,
+        stream: &TcpStream,
+        timeout: Option<Duration>,
+    ) -> Result<(), SocketError> {
+        self.wait_until(timeout, |stack| {
+            let connection = &stack.connections[&stream.0];
+            if connection.unacked() == 0 {
+                Some(Ok(()))
+            } else if connection.is_closed() {
+                Some(Err(SocketError::ConnectionReset))
+            } else {
+                None
+            }
+        })
+    }
+
+    // the connection closes gracefully in the background
+    pub fn tcp_close(&mut self, stream: TcpStream) {
+        self.connections.get_mut(&stream.0).unwrap().close();
+        self.released.insert(stream.0);
+        self.flush(Instant::now());
+    }
+
+    pub fn tcp_abort(&mut self, stream: TcpStream) {
+        self.connections.get_mut(&stream.0).unwrap().abort();
+        self.released.insert(stream.0);
+        self.flush(Instant::now());
+    }
+
+    // Sends an echo request, returning the round trip time of its reply.
+    pub fn ping(&mut self, destin: Ipv4Addr, timeout: Duration) -> Result<Duration, SocketError> {
+        let source = self.address().ok_or(SocketError::NoAddress)?;
+        let sequence = self.next_echo_sequence;
+        self.next_echo_sequence = sequence.wrapping_add(1);
+
+        let request = IcmpPacket::echo_request(self.echo_identifier(), sequence, Box::new([0; 32]));
+        let packet = Ipv4Packet::new(source, destin, IpProtocol::Icmp, request.to_bytes());
+        let sent_at = Instant::now();
+        if !self.send_ip(packet, sent_at) {
+            return Err(SocketError::NoRoute);
+        }
+        self.wait_until(Some(timeout), |stack| {
+            let received_at = stack.echo_replies.remove(&sequence)?;
+            Some(Ok(received_at.duration_since(sent_at)))
+        })
+    }
+
+    // Sends a probe that lives for `ttl` hops, returning the ICMP message it caused, if any came
+    // back in time.
+    pub fn probe(
+        &mut self,
+        destin: Ipv4Addr,
+        protocol: ProbeProtocol,
+        ttl: u8,
+        timeout: Duration,
+    ) -> Result<Option<ProbeReply>, SocketError> {
+        let source = self.address().ok_or(SocketError::NoAddress)?;
+        let (key, mut packet) = match protocol {
+            ProbeProtocol::Udp => {
+                let port = self.next_probe_port;
+                self.next_probe_port = port.checked_add(1).unwrap_or(TRACEROUTE_PORT);
+                let datagram = UdpDatagram {
+                    source_port: self.ephemeral_port(IpProtocol::Udp),
+                    destin_port: port,
+                    data: Box::new([0; 32]),
+                };
+                let packet = Ipv4Packet::new(source, destin, IpProtocol::Udp, datagram.to_bytes());
+                ((IpProtocol::Udp, port), packet)
+            }
+            ProbeProtocol::Icmp => {
+                let sequence = self.next_echo_sequence;
+                self.next_echo_sequence = sequence.wrapping_add(1);
+                let request =
+                    IcmpPacket::echo_request(self.echo_identifier(), sequence, Box::new([0; 32]));
+                let packet = Ipv4Packet::new(source, destin, IpProtocol::Icmp, request.to_bytes());
+                ((IpProtocol::Icmp, sequence), packet)
+            }
+        };
+        packet.ttl = ttl;
+
+        let sent_at = Instant::now();
+        if !self.send_ip(packet, sent_at) {
+            return Err(SocketError::NoRoute);
+        }
+        self.probes.insert(key, None);
+        let result = self.wait_until(Some(timeout), |stack| {
+            let (from, message_type, code, received_at) = (*stack.probes.get(&key)?)?;
+            Some(Ok(ProbeReply {
+                from,
+                message_type,
+                code,
+                rtt: received_at.duration_since(sent_at),
+            }))
+        });
+        self.probes.remove(&key);
+        match result {
+            Ok(reply) => Ok(Some(reply)),
+            Err(SocketError::TimedOut) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    // Looks up the IPv4 addresses of the name through the DNS servers.
+    pub fn resolve(
+        &mut self,
+        name: &str,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Ipv4Addr>, SocketError> {
+        if self.dns_servers.is_empty() {
+            return Err(SocketError::NameNotFound);
+        }
+        if self.resolver_port.is_none() {
+            self.resolver_port = Some(self.udp_bind(0)?.0);
+        }
+        let resolver = self
+            .resolver
+            .get_or_insert_with(|| Resolver::stub(self.dns_servers.clone()));
+        let id = resolver.resolve(DnsQuestion::new(name, RecordType::A), Instant::now());
+
+        self.wait_until(timeout, |stack| {
+            let results = stack.resolver.as_mut()?.take_results();
+            let resolution = results.into_iter().find(|resolution| resolution.id == id)?;
+            match resolution.ipv4_addresses() {
+                addresses if addresses.is_empty() => Some(Err(SocketError::NameNotFound)),
+                addresses => Some(Ok(addresses)),
+            }
+        })
+    }
+
+    fn echo_identifier(&self) -> u16 {
+        let mac = self.mac();
+        u16::from_be_bytes([mac.as_bytes()[4], mac.as_bytes()[5]])
+    }
+
+    fn port_in_use(&self, protocol: IpProtocol, port: u16) -> bool {
+        match protocol {
+            IpProtocol::Udp => self.udp_sockets.contains_key(&port),
+            _ => {
+                self.listeners.contains_key(&port)
+                    || self
+                        .connections
+                        .values()
+                        .any(|connection| connection.local().port() == port)
+            }
+        }
+    }
+
+    fn ephemeral_port(&mut self, protocol: IpProtocol) -> u16 {
+        loop {
+            let port = self.next_port;
+            self.next_port = match port {
+                u16::MAX => *EPHEMERAL_PORTS.start(),
+                _ => port + 1,
+            };
+            if !self.port_in_use(protocol, port) {
+                return port;
+            }
+        }
+    }
+
+    fn bind_port(&mut self, protocol: IpProtocol, port: u16) -> Result<u16, SocketError> {
+        match port {
+            0 => Ok(self.ephemeral_port(protocol)),
+            _ if self.port_in_use(protocol, port) => Err(SocketError::AddressInUse),
+            _ => Ok(port),
+        }
+    }
+
+    fn add_connection(&mut self, connection: TcpConnection) -> usize {
+        let id = self.next_connection;
+        self.next_connection += 1;
+        self.connections.insert(id, connection);
+        id
+    }
+
+    fn wait_until<T>(
+        &mut self,
+        timeout: Option<Duration>,
+        mut ready: impl FnMut(&mut Self) -> Option<Result<T, SocketError>>,
+    ) -> Result<T, SocketError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(result) = ready(self) {
+                return result;
+            }
+            if self.shutdown {
+                return Err(SocketError::Shutdown);
+            }
+            if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+                return Err(SocketError::TimedOut);
+            }
+            self.step(deadline);
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        let connections = self
+            .connections
+            .values()
+            .filter_map(TcpConnection::next_deadline);
+        [
+            self.endpoint.next_deadline(),
+            self.dhcp.as_ref().and_then(DhcpClient::next_deadline),
+            self.resolver.as_ref().and_then(Resolver::next_deadline),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(connections)
+        .min()
+    }
+
+    // sends whatever is waiting, then handles a single event if one comes before the deadline
+    fn step(&mut self, deadline: Option<Instant>) {
+        self.flush(Instant::now());
+        let wake_at = [deadline, self.next_deadline()].into_iter().flatten().min();
+        let event = match wake_at {
+            Some(wake_at) => self
+                .module
+                .wait_for_event_timeout(wake_at.saturating_duration_since(Instant::now())),
+            None => Some(self.module.wait_for_event()),
+        };
+
+        let now = Instant::now();
+        match event {
+            Some(ModuleEvent::Msg(msg)) => self.handle_frame(&msg.data, now),
+            Some(ModuleEvent::LinkStateChanged { up: true, .. }) => {
+                // it may have been moved to another network
+                if let Some(dhcp) = self.dhcp.as_mut() {
+                    dhcp.start(now);
+                }
+            }
+            Some(ModuleEvent::Shutdown) => self.shutdown = true,
+            _ => {}
+        }
+    }
+
+    // runs the timers and sends whatever is waiting to be sent
+    fn flush(&mut self, now: Instant) {
+        let interface = self.module.get_interface(0).unwrap();
+        self.endpoint.poll(interface, now);
+
+        if let Some(dhcp) = self.dhcp.as_mut() {
+            for packet in dhcp.poll(now) {
+                if packet.destin.is_broadcast() {
+                    self.endpoint
+                        .send_to(interface, ETHERNET_BROADCAST_MAC_ADDR, packet);
+                } else {
+                    self.endpoint.send(interface, packet, now);
+                }
+            }
+            self.apply_lease();
+        }
+
+        if let (Some(resolver), Some(port)) = (self.resolver.as_mut(), self.resolver_port) {
+            for (server, query) in resolver.poll(now) {
+                let _ = self.send_udp(port, &query.to_bytes(), SocketAddrV4::new(server, DNS_PORT));
+            }
+        }
+
+        let mut segments = Vec::new();
+        for connection in self.connections.values_mut() {
+            let (local, remote) = (connection.local(), connection.remote());
+            segments.extend(
+                connection
+                    .poll(now)
+                    .into_iter()
+                    .map(|segment| (*local.ip(), *remote.ip(), segment)),
+            );
+        }
+        for (source, destin, segment) in segments {
+            let packet = Ipv4Packet::new(source, destin, IpProtocol::Tcp, segment.to_bytes());
+            self.send_ip(packet, now);
+        }
+
+        for connection in self.connections.values() {
+            let instance = connection_name(connection);
+            let in_flight = connection.in_flight() as u64;
+            self.module.set_gauge(TCP_IN_FLIGHT, &instance, in_flight);
+            let window = connection.send_window() as u64;
+            self.module.set_gauge(TCP_SEND_WINDOW, &instance, window);
+        }
+        let (module, released) = (&self.module, &mut self.released);
+        self.connections.retain(|id, connection| {
+            let done = connection.is_closed() && released.remove(id);
+            if done {
+                module.remove_gauges(&connection_name(connection));
+            }
+            !done
+        });
+    }
+
+    fn apply_lease(&mut self) {
+        let Some(dhcp) = self.dhcp.as_ref() else {
+            return;
+        };
+        match dhcp.lease() {
+            Some(lease) if self.endpoint.address() != Some(lease.address) => {
+                log::info!(
+                    "Host {}: got address {}/{}",
+                    self.mac(),
+                    lease.address,
+                    lease.prefix_len
+                );
+                self.endpoint
+                    .configure(lease.address, lease.prefix_len, lease.gateway);
+                let servers = lease.dns_servers.clone();
+                self.set_dns_servers(servers);
+            }
+            None if self.endpoint.address().is_some() => self.endpoint.unconfigure(),
+            _ => {}
+        }
+    }
+
+    fn send_ip(&mut self, packet: Ipv4Packet, now: Instant) -> bool {
+        let interface = self.module.get_interface(0).unwrap();
+        self.endpoint.send(interface, packet, now)
+    }
+
+    fn send_udp(
+        &mut self,
+        port: u16,
+        data: &[u8],
+        destin: SocketAddrV4,
+    ) -> Result<(), SocketError> {
+        let source = self.address().ok_or(SocketError::NoAddress)?;
+        let datagram = UdpDatagram {
+            source_port: port,
+            destin_port: destin.port(),
+            data: data.into(),
+        };
+        let packet = Ipv4Packet::new(source, *destin.ip(), IpProtocol::Udp, datagram.to_bytes());
+        match self.send_ip(packet, Instant::now()) {
+            true => Ok(()),
+            false => Err(SocketError::NoRoute),
+        }
+    }
+
+    fn handle_frame(&mut self, data: &[u8], now: Instant) {
+        let interface = self.module.get_interface(0).unwrap();
+        let Some(IpPacket::V4(packet)) = self.endpoint.receive(interface, data, now) else {
+            return;
+        };
+
+        if let Some(dhcp) = self.dhcp.as_mut() {
+            if dhcp.handle_packet(&packet, now) {
+                self.apply_lease();
+                return;
+            }
+        }
+        // until DHCP gives it one, the endpoint accepts packets for any address
+        if self.address().is_none() {
+            return;
+        }
+
+        match packet.protocol {
+            IpProtocol::Icmp => self.handle_icmp(packet, now),
+            IpProtocol::Udp => self.handle_udp(packet, now),
+            IpProtocol::Tcp => self.handle_tcp(packet, now),
+            _ => {}
+        }
+    }
+
+    fn handle_icmp(&mut self, packet: Ipv4Packet, now: Instant) {
+        let Ok(message) = IcmpPacket::decode(&packet.data) else {
+            return;
+        };
+        match message.message_type {
+            IcmpType::EchoRequest if Some(packet.destin) == self.address() => {
+                let reply = Ipv4Packet::new(
+                    packet.destin,
+                    packet.source,
+                    IpProtocol::Icmp,
+                    message.echo_reply().to_bytes(),
+                );
+                self.send_ip(reply, now);
+            }
+            IcmpType::EchoReply if message.identifier() == self.echo_identifier() => {
+                let answer = (packet.source, message.message_type, message.code, now);
+                match self.probes.get_mut(&(IpProtocol::Icmp, message.sequence())) {
+                    Some(probe) => *probe = Some(answer),
+                    None => {
+                        self.echo_replies.insert(message.sequence(), now);
+                    }
+                }
+            }
+            IcmpType::DestinationUnreachable | IcmpType::TimeExceeded => {
+                let Some(key) = self.probed(&message.data) else {
+                    return;
+                };
+                if let Some(probe @ None) = self.probes.get_mut(&key) {
+                    *probe = Some((packet.source, message.message_type, message.code, now));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // the probe an ICMP error quotes, if it is one of ours
+    fn probed(&self, quote: &[u8]) -> Option<(IpProtocol, u16)> {
+        let header_len = (*quote.first()? & 0x0F) as usize * 4;
+        let protocol = IpProtocol::from(*quote.get(9)?);
+        let source: [u8; 4] = quote.get(12..16)?.try_into().unwrap();
+        if Some(Ipv4Addr::from(source)) != self.address() {
+            return None;
+        }
+        let transport = quote.get(header_len..header_len + 8)?;
+        let read_u16 = |i: usize| u16::from_be_bytes([transport[i], transport[i + 1]]);
+        match protocol {
+            // the destination port
+            IpProtocol::Udp => Some((protocol, read_u16(2))),
+            IpProtocol::Icmp
+                if transport[0] == IcmpType::EchoRequest as u8
+                    && read_u16(4) == self.echo_identifier() =>
+            {
+                Some((protocol, read_u16(6)))
+            }
+            _ => None,
+        }
+    }
+
+    fn handle_udp(&mut self, packet: Ipv4Packet, now: Instant) {
+        let Ok(datagram) = UdpDatagram::decode(&packet.data) else {
+            return;
+        };
+        let source = SocketAddrV4::new(packet.source, datagram.source_port);
+
+        if Some(datagram.destin_port) == self.resolver_port {
+            if let (Some(resolver), Ok(message)) =
+                (self.resolver.as_mut(), DnsMessage::decode(&datagram.data))
+            {
+                resolver.handle_response(packet.source, &message, now);
+            }
+        } else if let Some(queue) = self.udp_sockets.get_mut(&datagram.destin_port) {
+            if queue.len() < UDP_QUEUE_SIZE {
+                queue.push_back((source, datagram.data));
+            }
+        } else if Some(packet.destin) == self.address() {
+            let error = IcmpPacket::error(
+                IcmpType::DestinationUnreachable,
+                icmp::ICMP_PORT_UNREACHABLE,
+                &packet.to_bytes(),
+            );
+            let error = Ipv4Packet::new(
+                packet.destin,
+                packet.source,
+                IpProtocol::Icmp,
+                error.to_bytes(),
+            );
+            self.send_ip(error, now);
+        }
+    }
+
+    fn handle_tcp(&mut self, packet: Ipv4Packet, now: Instant) {
+        let Ok(segment) = TcpSegment::decode(&packet.data) else {
+            return;
+        };
+        let local = SocketAddrV4::new(packet.destin, segment.destin_port);
+        let remote = SocketAddrV4::new(packet.source, segment.source_port);
+
+        let connection = self.connections.values_mut().find(|connection| {
+            connection.local() == local && connection.remote() == remote && !connection.is_closed()
+        });
+        if let Some(connection) = connection {
+            connection.handle_segment(&segment, now);
+            return;
+        }
+
+        let accepted = self
+            .listeners
+            .contains_key(&local.port())
+            .then(|| TcpConnection::accept(local, remote, &segment, self.next_iss))
+            .flatten();
+        match accepted {
+            Some(mut connection) => {
+                self.next_iss = self.next_iss.wrapping_add(0x0100_0000);
+                connection.handle_segment(&segment, now);
+                let id = self.add_connection(connection);
+                self.listeners.get_mut(&local.port()).unwrap().push_back(id);
+            }
+            None if !segment.flags.contains(TcpFlags::RST) => self.reset(&packet, &segment, now),
+            None => {}
+        }
+    }
+
+    // answers a segment no connection wants (https://www.rfc-editor.org/rfc/rfc9293#section-3.10.7.1)
+    fn reset(&mut self, packet: &Ipv4Packet, segment: &TcpSegment, now: Instant) {
+        let (seq, ack, flags) = if segment.flags.contains(TcpFlags::ACK) {
+            (segment.ack, 0, TcpFlags::RST)
+        } else {
+            let len = segment.data.len() as u32
+                + segment.flags.contains(TcpFlags::SYN) as u32
+                + segment.flags.contains(TcpFlags::FIN) as u32;
+            (
+                0,
+                segment.seq.wrapping_add(len),
+                TcpFlags::RST | TcpFlags::ACK,
+            )
+        };
+        let reset = TcpSegment {
+            source_port: segment.destin_port,
+            destin_port: segment.source_port,
+            seq,
+            ack,
+            flags,
+            window: 0,
+            data: Box::new([]),
+        };
+        let reset = Ipv4Packet::new(
+            packet.destin,
+            packet.source,
+            IpProtocol::Tcp,
+            reset.to_bytes(),
+        );
+        self.send_ip(reset, now);
+    }
+}
+
+type Program = Box<dyn FnMut(&mut NetStack) + Send>;
+
+// A host running a program on top of its network stack. The stack keeps answering (pings,
+// connections that are closing...) after the program returns, until the host is shut down.
+pub struct Host {
+    address: MacAddress,
+    stack: NetStack,
+    program: Option<Program>,
+}
+
+impl Host {
+    pub fn new(address: MacAddress, config: AddressConfig) -> Self {
+        Self {
+            address,
+            stack: NetStack::new(address, config),
+            program: None,
+        }
+    }
+
+    pub fn with_program(
+        address: MacAddress,
+        config: AddressConfig,
+        program: impl FnMut(&mut NetStack) + Send + 'static,
+    ) -> Self {
+        Self {
+            program: Some(Box::new(program)),
+            ..Self::new(address, config)
+        }
+    }
+}
+
+impl Device for Host {
+    fn get_mac_address(&self) -> MacAddress {
+        self.address
+    }
+
+    fn get_module(&mut self) -> &mut Module {
+        &mut self.stack.module
+    }
+
+    fn kind(&self) -> &'static str {
+        "host"
+    }
+
+    fn addresses(&self) -> Vec<String> {
+        match self.stack.endpoint.cidr() {
+            Some(address) => vec![address],
+            None if self.stack.dhcp.is_some() => vec!["dhcp".into()],
+            None => Vec::new(),
+        }
+    }
+
+    fn run(&mut self) {
+        log::debug!("Host {} running...", self.address);
+
+        if let Some(dhcp) = self.stack.dhcp.as_mut() {
+            dhcp.start(Instant::now());
+        }
+
+        if let Some(mut program) = self.program.take() {
+            program(&mut self.stack);
+        }
+        while !self.stack.shutdown {
+            self.stack.step(None);
+        }
+        log::debug!("Host {} shutting down...", self.address);
+    }
+}
+
+// also for the tests of the programs that run on hosts
+#[cfg(test)]
+pub mod test {
+    use super::{AddressConfig, Host, SocketError};
+    use crate::{
+        devices::{
+            dhcp_server::DhcpServerDevice, dns_server::DnsServer, switch::Layer2Switch, Device,
+        },
+        dhcp::server::DhcpServerConfig,
+        dns::zone::Zone,
+        links,
+        protocols::ethernet::MacAddress,
+    };
+    use std::{
+        net::{Ipv4Addr, SocketAddrV4},
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+    const TIMEOUT: Option<Duration> = Some(Duration::from_secs(2));
+
+    pub fn static_config(address: Ipv4Addr) -> AddressConfig {
+        AddressConfig::Static {
+            address,
+            prefix_len: 24,
+            gateway: None,
+        }
+    }
+
+    // runs both hosts, connected to each other, until `done` or a timeout
+    pub fn run_pair(mut hosts: [Host; 2], mut done: impl FnMut() -> bool) {
+        let (left, right) = links::create_link();
+        hosts[0].get_module().attach_link(0, left);
+        hosts[1].get_module().attach_link(0, right);
+        let handles: Vec<_> = hosts
+            .iter_mut()
+            .map(|host| host.get_module().handle())
+            .collect();
+        let threads: Vec<_> = hosts
+            .into_iter()
+            .map(|mut host| thread::spawn(move || host.run()))
+            .collect();
+
+        let started = Instant::now();
+        while !done() && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        for handle in handles {
+            handle.shutdown();
+        }
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn serves_udp_and_tcp() {
+        // echoes a datagram, then a connection
+        let server = Host::with_program(MacAddress::new([2; 6]), static_config(SERVER), |stack| {
+            let socket = stack.udp_bind(7).unwrap();
+            let (data, source) = stack.recv_from(&socket, TIMEOUT).unwrap();
+            stack.send_to(&socket, &data, source).unwrap();
+
+            let listener = stack.tcp_listen(7).unwrap();
+            let stream = stack.tcp_accept(&listener, TIMEOUT).unwrap();
+            loop {
+                let data = stack.tcp_recv(&stream, TIMEOUT).unwrap();
+                if data.is_empty() {
+                    break;
+                }
+                stack.tcp_send(&stream, &data).unwrap();
+            }
+            stack.tcp_close(stream);
+        });
+
+        let (sender, receiver) = mpsc::channel();
+        let client = Host::with_program(
+            MacAddress::new([1; 6]),
+            static_config(CLIENT),
+            move |stack| {
+                let echo = SocketAddrV4::new(SERVER, 7);
+                assert!(stack.ping(SERVER, Duration::from_secs(2)).is_ok());
+
+                let socket = stack.udp_bind(0).unwrap();
+                assert_eq!(
+                    stack.udp_bind(socket.port()),
+                    Err(SocketError::AddressInUse)
+                );
+                stack.send_to(&socket, b"hello", echo).unwrap();
+                let (data, source) = stack.recv_from(&socket, TIMEOUT).unwrap();
+                sender.send((data.to_vec(), source)).unwrap();
+
+                let stream = stack.tcp_connect(echo, TIMEOUT).unwrap();
+                stack.tcp_send(&stream, b"hello over tcp").unwrap();
+                let mut data = Vec::new();
+                while data.len() < 14 {
+                    data.extend(stack.tcp_recv(&stream, TIMEOUT).unwrap());
+                }
+                stack.tcp_close(stream);
+                sender.send((data, echo)).unwrap();
+
+                let refused = stack.tcp_connect(SocketAddrV4::new(SERVER, 9), TIMEOUT);
+                sender.send((vec![], echo)).unwrap();
+                assert_eq!(refused.err(), Some(SocketError::ConnectionRefused));
+            },
+        );
+
+        let mut received = Vec::new();
+        run_pair([client, server], || {
+            received.extend(receiver.try_iter());
+            received.len() == 3
+        });
+
+        assert_eq!(
+            received[0],
+            (b"hello".to_vec(), SocketAddrV4::new(SERVER, 7))
+        );
+        assert_eq!(received[1].0, b"hello over tcp".to_vec());
+        assert_eq!(received.len(), 3);
+    }
+
+    #[test]
+    fn configures_itself_and_resolves_names() {
+        let server_address = Ipv4Addr::new(10, 0, 0, 1);
+        let dns_address = Ipv4Addr::new(10, 0, 0, 53);
+        let mut dhcp_config = DhcpServerConfig::new(
+            server_address,
+            24,
+            Ipv4Addr::new(10, 0, 0, 100),
+            Ipv4Addr::new(10, 0, 0, 199),
+        );
+        dhcp_config.dns_servers = vec![dns_address];
+        let mut dns = DnsServer::new(MacAddress::new([3; 6]), dns_address, 24, None);
+        dns.add_zone(Zone::parse("$ORIGIN example.com.\nwww A 10.0.0.80\n").unwrap());
+
+        let (sender, receiver) = mpsc::channel();
+        let host = Host::with_program(MacAddress::new([4; 6]), AddressConfig::Dhcp, move |stack| {
+            let address = stack.wait_for_address(Some(Duration::from_secs(5)));
+            let resolved = stack.resolve("www.example.com", TIMEOUT);
+            let missing = stack.resolve("mail.example.com", TIMEOUT);
+            sender.send((address, resolved, missing)).unwrap();
+        });
+
+        let mut devices: Vec<Box<dyn Device>> = vec![
+            Box::new(DhcpServerDevice::new(MacAddress::new([2; 6]), dhcp_config)),
+            Box::new(dns),
+            Box::new(host),
+        ];
+        let mut switch = Layer2Switch::new(MacAddress::new([1; 6]), 3);
+        for (i, device) in devices.iter_mut().enumerate() {
+            let (left, right) = links::create_link();
+            device.get_module().attach_link(0, left);
+            switch.get_module().attach_link(i as u32, right);
+        }
+        devices.push(Box::new(switch));
+        let handles: Vec<_> = devices
+            .iter_mut()
+            .map(|device| device.get_module().handle())
+            .collect();
+        let threads: Vec<_> = devices
+            .into_iter()
+            .map(|mut device| thread::spawn(move || device.run()))
+            .collect();
+
+        let (address, resolved, missing) = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(address, Ok(Ipv4Addr::new(10, 0, 0, 100)));
+        assert_eq!(resolved, Ok(vec![Ipv4Addr::new(10, 0, 0, 80)]));
+        assert_eq!(missing, Err(SocketError::NameNotFound));
+
+        for handle in handles {
+            handle.shutdown();
+        }
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
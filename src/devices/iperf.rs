@@ -0,0 +1,506 @@
+use super::host::{AddressConfig, Host, NetStack, SocketError};
+use crate::{
+    protocols::{ethernet::MacAddress, ipv4::IpProtocol},
+    traffic::{self, Generator, Pattern, SinkStats, PROBE_HEADER_SIZE},
+};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    net::SocketAddrV4,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// what the TCP client writes at once, small enough for the intervals to be accurate
+const TCP_CHUNK_SIZE: usize = 16 * 1024;
+// the sequence number of the datagram ending a UDP test
+const END_SEQUENCE: u64 = u64::MAX;
+const END_ATTEMPTS: usize = 10;
+const END_TIMEOUT: Duration = Duration::from_millis(250);
+// packets, bytes, lost and jitter of the server, sent back at the end of a UDP test
+const SERVER_REPORT_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IperfMode {
+    Tcp,
+    // sends at a constant bit rate, like iperf -u -b
+    Udp { rate: u64, packet_size: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IperfConfig {
+    pub mode: IperfMode,
+    pub duration: Duration,
+    pub interval: Duration,
+}
+
+impl Default for IperfConfig {
+    fn default() -> Self {
+        Self {
+            mode: IperfMode::Tcp,
+            duration: Duration::from_secs(10),
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntervalReport {
+    // since the start of the test
+    pub start: Duration,
+    pub end: Duration,
+    pub bytes: u64,
+}
+
+fn bits_per_second(bytes: u64, duration: Duration) -> f64 {
+    match duration.is_zero() {
+        true => 0.0,
+        false => bytes as f64 * 8.0 / duration.as_secs_f64(),
+    }
+}
+
+impl IntervalReport {
+    pub fn bits_per_second(&self) -> f64 {
+        bits_per_second(self.bytes, self.end - self.start)
+    }
+}
+
+impl Display for IntervalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>5.1}-{:<5.1} sec  {:>9.2} KBytes  {:>8.2} Mbits/sec",
+            self.start.as_secs_f64(),
+            self.end.as_secs_f64(),
+            self.bytes as f64 / 1024.0,
+            self.bits_per_second() / 1e6
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IperfReport {
+    pub intervals: Vec<IntervalReport>,
+    pub bytes: u64,
+    pub duration: Duration,
+    // TCP only
+    pub retransmissions: u64,
+    // UDP only, as seen by the server
+    pub packets: u64,
+    pub lost: u64,
+    pub jitter: Duration,
+}
+
+impl IperfReport {
+    pub fn bits_per_second(&self) -> f64 {
+        bits_per_second(self.bytes, self.duration)
+    }
+
+    pub fn loss_ratio(&self) -> f64 {
+        match self.packets + self.lost {
+            0 => 0.0,
+            total => self.lost as f64 / total as f64,
+        }
+    }
+}
+
+impl Display for IperfReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Interval           Transfer          Bitrate")?;
+        for interval in &self.intervals {
+            writeln!(f, "{interval}")?;
+        }
+        let total = IntervalReport {
+            start: Duration::ZERO,
+            end: self.duration,
+            bytes: self.bytes,
+        };
+        write!(f, "{total}")?;
+        if self.retransmissions > 0 {
+            write!(f, "  {} retransmissions", self.retransmissions)?;
+        }
+        if self.packets + self.lost > 0 {
+            write!(
+                f,
+                "  {:.3} ms jitter  {}/{} lost ({:.2}%)",
+                self.jitter.as_secs_f64() * 1000.0,
+                self.lost,
+                self.packets + self.lost,
+                self.loss_ratio() * 100.0
+            )?;
+        }
+        writeln!(f)
+    }
+}
+
+// splits the bytes transferred into intervals
+struct Intervals {
+    start: Instant,
+    length: Duration,
+    bytes: u64,
+    current: u64,
+    reports: Vec<IntervalReport>,
+}
+
+impl Intervals {
+    fn new(start: Instant, length: Duration) -> Self {
+        Self {
+            start,
+            length,
+            bytes: 0,
+            current: 0,
+            reports: Vec::new(),
+        }
+    }
+
+    fn close_until(&mut self, now: Instant) {
+        loop {
+            let start = self.length * self.reports.len() as u32;
+            let end = start + self.length;
+            if now < self.start + end {
+                return;
+            }
+            let report = IntervalReport {
+                start,
+                end,
+                bytes: std::mem::take(&mut self.current),
+            };
+            log::info!("iperf: {report}");
+            self.reports.push(report);
+        }
+    }
+
+    fn add(&mut self, bytes: u64, now: Instant) {
+        self.close_until(now);
+        self.current += bytes;
+        self.bytes += bytes;
+    }
+
+    // the report so far, the last interval may be shorter
+    fn finish(mut self, now: Instant) -> IperfReport {
+        self.close_until(now);
+        let duration = now - self.start;
+        let start = self.length * self.reports.len() as u32;
+        if duration > start {
+            self.reports.push(IntervalReport {
+                start,
+                end: duration,
+                bytes: self.current,
+            });
+        }
+        IperfReport {
+            intervals: self.reports,
+            bytes: self.bytes,
+            duration,
+            ..Default::default()
+        }
+    }
+}
+
+fn encode_server_report(stats: &SinkStats) -> Box<[u8]> {
+    [
+        stats.packets,
+        stats.bytes,
+        stats.lost,
+        stats.jitter().as_nanos() as u64,
+    ]
+    .iter()
+    .flat_map(|value| value.to_be_bytes())
+    .collect()
+}
+
+// the packets, bytes, lost packets and jitter the server saw
+fn decode_server_report(data: &[u8]) -> Option<(u64, u64, u64, Duration)> {
+    if data.len() != SERVER_REPORT_SIZE {
+        return None;
+    }
+    let value = |i: usize| u64::from_be_bytes(data[i * 8..i * 8 + 8].try_into().unwrap());
+    Some((value(0), value(1), value(2), Duration::from_nanos(value(3))))
+}
+
+// Measures the throughput to an IperfServer, like `iperf -c`. The report is shared so it can be
+// read once the host is done.
+pub struct IperfClient {
+    server: SocketAddrV4,
+    config: IperfConfig,
+    report: Arc<Mutex<Option<IperfReport>>>,
+}
+
+impl IperfClient {
+    pub fn new(server: SocketAddrV4, config: IperfConfig) -> Self {
+        Self {
+            server,
+            config,
+            report: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn report(&self) -> Arc<Mutex<Option<IperfReport>>> {
+        Arc::clone(&self.report)
+    }
+
+    pub fn run(&mut self, stack: &mut NetStack) -> Result<IperfReport, SocketError> {
+        stack.wait_for_address(None)?;
+        let report = match self.config.mode {
+            IperfMode::Tcp => self.run_tcp(stack)?,
+            IperfMode::Udp { rate, packet_size } => self.run_udp(stack, rate, packet_size)?,
+        };
+        *self.report.lock().unwrap() = Some(report.clone());
+        Ok(report)
+    }
+
+    fn run_tcp(&self, stack: &mut NetStack) -> Result<IperfReport, SocketError> {
+        let stream = stack.tcp_connect(self.server, Some(Duration::from_secs(10)))?;
+        let start = Instant::now();
+        let end = start + self.config.duration;
+        let mut intervals = Intervals::new(start, self.config.interval);
+
+        let chunk = [0; TCP_CHUNK_SIZE];
+        while Instant::now() < end {
+            stack.tcp_send(&stream, &chunk)?;
+            // only what the server acknowledged counts
+            match stack.tcp_flush(&stream, Some(end.saturating_duration_since(Instant::now()))) {
+                Ok(()) => intervals.add(chunk.len() as u64, Instant::now()),
+                Err(SocketError::TimedOut) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut report = intervals.finish(Instant::now());
+        report.retransmissions = stack.tcp_retransmissions(&stream);
+        stack.tcp_abort(stream);
+        Ok(report)
+    }
+
+    fn run_udp(
+        &self,
+        stack: &mut NetStack,
+        rate: u64,
+        packet_size: usize,
+    ) -> Result<IperfReport, SocketError> {
+        let socket = stack.udp_bind(0)?;
+        let mut generator = Generator::new(Pattern::Cbr { rate, packet_size }, 1);
+        let packet_size = generator.pattern().packet_size();
+
+        let start = Instant::now();
+        let end = start + self.config.duration;
+        let mut intervals = Intervals::new(start, self.config.interval);
+        let mut next = start;
+        let mut sequence = 0;
+        while next < end {
+            stack.sleep(next.saturating_duration_since(Instant::now()))?;
+            let now = Instant::now();
+            let data = traffic::encode_probe(sequence, now, packet_size);
+            stack.send_to(&socket, &data, self.server)?;
+            intervals.add(data.len() as u64, now);
+            sequence += 1;
+            next += generator.next_gap();
+        }
+        let mut report = intervals.finish(Instant::now());
+
+        // asks for what the server received
+        for _ in 0..END_ATTEMPTS {
+            let end = traffic::encode_probe(END_SEQUENCE, Instant::now(), PROBE_HEADER_SIZE);
+            stack.send_to(&socket, &end, self.server)?;
+            match stack.recv_from(&socket, Some(END_TIMEOUT)) {
+                Ok((data, _)) => {
+                    if let Some((packets, bytes, lost, jitter)) = decode_server_report(&data) {
+                        report.packets = packets;
+                        report.lost = lost;
+                        report.jitter = jitter;
+                        // what got through is what counts
+                        report.bytes = bytes;
+                        break;
+                    }
+                }
+                Err(SocketError::TimedOut) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        stack.udp_close(socket);
+        Ok(report)
+    }
+
+    pub fn into_host(mut self, address: MacAddress, config: AddressConfig) -> Host {
+        Host::with_program(address, config, move |stack| match self.run(stack) {
+            Ok(report) => log::info!("Host {address}: iperf to {}\n{report}", self.server),
+            Err(err) => log::warn!("Host {address}: iperf to {} failed: {err:?}", self.server),
+        })
+    }
+}
+
+// Receives the tests of IperfClients, over TCP or UDP, like `iperf -s`. It keeps the report of
+// every test it received.
+pub struct IperfServer {
+    port: u16,
+    protocol: IpProtocol,
+    interval: Duration,
+    reports: Arc<Mutex<Vec<IperfReport>>>,
+}
+
+impl IperfServer {
+    pub fn new(port: u16, protocol: IpProtocol) -> Self {
+        assert!(
+            matches!(protocol, IpProtocol::Tcp | IpProtocol::Udp),
+            "iperf runs over TCP or UDP"
+        );
+        Self {
+            port,
+            protocol,
+            interval: IperfConfig::default().interval,
+            reports: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn reports(&self) -> Arc<Mutex<Vec<IperfReport>>> {
+        Arc::clone(&self.reports)
+    }
+
+    // runs until the host shuts down
+    pub fn run(&mut self, stack: &mut NetStack) -> Result<(), SocketError> {
+        match self.protocol {
+            IpProtocol::Tcp => self.run_tcp(stack),
+            _ => self.run_udp(stack),
+        }
+    }
+
+    fn run_tcp(&mut self, stack: &mut NetStack) -> Result<(), SocketError> {
+        let listener = stack.tcp_listen(self.port)?;
+        loop {
+            let stream = stack.tcp_accept(&listener, None)?;
+            let mut intervals = Intervals::new(Instant::now(), self.interval);
+            loop {
+                match stack.tcp_recv(&stream, None) {
+                    Ok(data) if data.is_empty() => break,
+                    Ok(data) => intervals.add(data.len() as u64, Instant::now()),
+                    Err(SocketError::ConnectionReset) => break,
+                    Err(err) => return Err(err),
+                }
+            }
+            stack.tcp_close(stream);
+            let report = intervals.finish(Instant::now());
+            self.reports.lock().unwrap().push(report);
+        }
+    }
+
+    fn run_udp(&mut self, stack: &mut NetStack) -> Result<(), SocketError> {
+        let socket = stack.udp_bind(self.port)?;
+        let mut tests: HashMap<SocketAddrV4, (SinkStats, Intervals)> = HashMap::new();
+        // answered again when the client missed it
+        let mut finished: HashMap<SocketAddrV4, Box<[u8]>> = HashMap::new();
+        loop {
+            let (data, source) = stack.recv_from(&socket, None)?;
+            let now = Instant::now();
+            let Some((sequence, _)) = traffic::decode_probe(&data) else {
+                continue;
+            };
+
+            if sequence != END_SEQUENCE {
+                finished.remove(&source);
+                let (stats, intervals) = tests
+                    .entry(source)
+                    .or_insert_with(|| (SinkStats::default(), Intervals::new(now, self.interval)));
+                stats.record(&data, now);
+                intervals.add(data.len() as u64, now);
+                continue;
+            }
+
+            if let Some((stats, intervals)) = tests.remove(&source) {
+                let mut report = intervals.finish(now);
+                report.packets = stats.packets;
+                report.lost = stats.lost;
+                report.jitter = stats.jitter();
+                self.reports.lock().unwrap().push(report);
+                finished.insert(source, encode_server_report(&stats));
+            }
+            if let Some(reply) = finished.get(&source) {
+                stack.send_to(&socket, reply, source)?;
+            }
+        }
+    }
+
+    pub fn into_host(mut self, address: MacAddress, config: AddressConfig) -> Host {
+        Host::with_program(address, config, move |stack| {
+            let _ = self.run(stack);
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IperfClient, IperfConfig, IperfMode, IperfReport, IperfServer};
+    use crate::{
+        devices::host::test::{run_pair, static_config},
+        protocols::{ethernet::MacAddress, ipv4::IpProtocol},
+    };
+    use std::{
+        net::{Ipv4Addr, SocketAddrV4},
+        time::Duration,
+    };
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    // runs a test between two hosts connected to each other
+    fn measure(mode: IperfMode, protocol: IpProtocol) -> (IperfReport, IperfReport) {
+        let iperf = IperfConfig {
+            mode,
+            duration: Duration::from_millis(500),
+            interval: Duration::from_millis(100),
+        };
+        let client = IperfClient::new(SocketAddrV4::new(SERVER, 5201), iperf);
+        let server = IperfServer::new(5201, protocol);
+        let (client_report, server_reports) = (client.report(), server.reports());
+
+        let hosts = [
+            client.into_host(MacAddress::new([1; 6]), static_config(CLIENT)),
+            server.into_host(MacAddress::new([2; 6]), static_config(SERVER)),
+        ];
+        run_pair(hosts, || {
+            client_report.lock().unwrap().is_some() && !server_reports.lock().unwrap().is_empty()
+        });
+
+        let client_report = client_report.lock().unwrap().clone().unwrap();
+        let server_report = server_reports.lock().unwrap()[0].clone();
+        (client_report, server_report)
+    }
+
+    #[test]
+    fn tcp_throughput() {
+        let (client, server) = measure(IperfMode::Tcp, IpProtocol::Tcp);
+
+        assert!(client.bytes > 0);
+        // the last write may be acknowledged a little after the end
+        assert!((5..=6).contains(&client.intervals.len()));
+        assert_eq!(client.intervals[4].end, Duration::from_millis(500));
+        assert_eq!(
+            client
+                .intervals
+                .iter()
+                .map(|interval| interval.bytes)
+                .sum::<u64>(),
+            client.bytes
+        );
+        assert_eq!(client.retransmissions, 0);
+        // everything acknowledged was received
+        assert!(server.bytes >= client.bytes);
+    }
+
+    #[test]
+    fn udp_loss_and_jitter() {
+        let mode = IperfMode::Udp {
+            rate: 2_000_000,
+            packet_size: 1000,
+        };
+        let (client, server) = measure(mode, IpProtocol::Udp);
+
+        // 2 Mbit/s of 1000 bytes packets is one every 4 ms
+        assert_eq!(client.packets, 125);
+        assert_eq!(client.lost, 0);
+        assert_eq!(client.bytes, 125_000);
+        assert_eq!(server.packets, 125);
+        assert!(client.jitter < Duration::from_millis(10));
+        let rate = client.bits_per_second();
+        assert!((1_500_000.0..2_500_000.0).contains(&rate), "{rate}");
+    }
+}
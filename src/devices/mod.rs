@@ -1,23 +1,255 @@
+pub mod dhcp_server;
+pub mod dns_server;
+pub mod endpoint;
+pub mod firewall;
+pub mod host;
+pub mod iperf;
+pub mod nat;
+pub mod router;
 pub mod switch;
+pub mod traceroute;
+pub mod traffic;
 use crate::{
     links::{LinkData, LinkEnd, LinkError},
     protocols::ethernet::MacAddress,
+    trace::{HopId, TraceEvent, TraceRecord, Tracer},
 };
 
 use std::{
-    collections::VecDeque,
-    sync::{Arc, Condvar, Mutex},
+    collections::{BTreeMap, VecDeque},
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, OnceLock,
+    },
+    time::Duration,
 };
 
+type Connection = Arc<Mutex<Option<LinkEnd>>>;
+type MsgQueue = Arc<(Mutex<VecDeque<ModuleEvent>>, Condvar)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DropReason {
+    // the link was down or had nothing on its other end
+    LinkDown,
+    // the frame would have left through the interface it came from
+    SameInterface,
+    NoRoute,
+    TtlExpired,
+    // turned away by a firewall or a NAT
+    Filtered,
+}
+
+impl DropReason {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::LinkDown => "LinkDown",
+            Self::SameInterface => "SameInterface",
+            Self::NoRoute => "NoRoute",
+            Self::TtlExpired => "TtlExpired",
+            Self::Filtered => "Filtered",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterfaceStats {
+    pub tx_frames: u64,
+    pub tx_bytes: u64,
+    pub rx_frames: u64,
+    pub rx_bytes: u64,
+    // by the interface the frames arrived on, or were to leave through
+    pub drops: BTreeMap<DropReason, u64>,
+    pub parse_errors: u64,
+}
+
+impl InterfaceStats {
+    pub fn dropped(&self) -> u64 {
+        self.drops.values().sum()
+    }
+}
+
+// What went through a device. Besides the interface counters, devices keep counters of their
+// own (e.g. the floods of a switch) by name, and gauges of what they currently track by name and
+// instance (e.g. the bytes in flight of each TCP connection).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceStats {
+    pub interfaces: Vec<InterfaceStats>,
+    pub counters: BTreeMap<&'static str, u64>,
+    pub gauges: BTreeMap<(&'static str, String), u64>,
+    // the events waiting to be handled by the device, and the most there ever were
+    pub queued: usize,
+    pub max_queued: usize,
+}
+
+type Stats = Arc<Mutex<DeviceStats>>;
+
+pub type SharedTable = Arc<Mutex<dyn Display + Send>>;
+
+// The frame a traced device is handling, so its drops and forwarding decisions can be told which
+// frame they're about.
+#[derive(Debug, Default, Clone, Copy)]
+struct Handling {
+    frame: Option<u64>,
+    hop: Option<HopId>,
+    // once the device decided to forward it, what it sends is part of the frame's path
+    forwarding: bool,
+}
+
+#[derive(Default)]
+struct TraceContext {
+    tracer: OnceLock<(Tracer, MacAddress)>,
+    handling: Mutex<Handling>,
+}
+
+impl TraceContext {
+    fn record(
+        &self,
+        interface: Option<u32>,
+        event: TraceEvent,
+        frame: Option<u64>,
+        reason: &str,
+        hop: Option<HopId>,
+    ) {
+        if let Some((tracer, device)) = self.tracer.get() {
+            tracer.record(TraceRecord {
+                device: *device,
+                interface,
+                event,
+                frame,
+                length: None,
+                reason: Some(reason),
+                hop,
+            });
+        }
+    }
+
+    fn receive(&self, interface_id: u32, data: &LinkData) {
+        if let Some((tracer, device)) = self.tracer.get() {
+            tracer.receive(*device, interface_id, data);
+        }
+    }
+
+    // returns the frame and the hop it left from
+    fn transmit(&self, interface_id: u32, data: &LinkData) -> Option<(u64, Option<HopId>)> {
+        let (tracer, device) = self.tracer.get()?;
+        let handling = *self.handling.lock().unwrap();
+        let forwarded = match handling.frame {
+            Some(frame) if handling.forwarding => Some((frame, handling.hop)),
+            _ => None,
+        };
+        Some(tracer.transmit(*device, interface_id, data, forwarded))
+    }
+
+    // for drops and decisions about the frame being handled
+    fn handled(&self, interface: Option<u32>, event: TraceEvent, reason: &str) {
+        let mut handling = self.handling.lock().unwrap();
+        if matches!(event, TraceEvent::Forward | TraceEvent::Flood) {
+            handling.forwarding = true;
+        }
+        let handling = *handling;
+        self.record(interface, event, handling.frame, reason, handling.hop);
+    }
+
+    // every event taken off the queue ends the handling of the previous frame
+    fn start_handling(&self, event: Option<&ModuleEvent>) {
+        let Some((tracer, device)) = self.tracer.get() else {
+            return;
+        };
+        let handling = match event {
+            Some(ModuleEvent::Msg(msg)) => match tracer.handling(*device, &msg.data) {
+                Some((frame, hop)) => Handling {
+                    frame: Some(frame),
+                    hop,
+                    forwarding: false,
+                },
+                None => Handling::default(),
+            },
+            _ => Handling::default(),
+        };
+        *self.handling.lock().unwrap() = handling;
+    }
+}
+
+// Lets the simulation be paused: the devices stop taking events off their queues until it's
+// resumed, or let one event through (to any device) for each step.
+#[derive(Default)]
+pub struct Gate {
+    paused: AtomicBool,
+    steps: Mutex<u64>,
+    condvar: Condvar,
+}
+
+impl Gate {
+    pub fn pause(&self) {
+        let mut steps = self.steps.lock().unwrap();
+        *steps = 0;
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        let _steps = self.steps.lock().unwrap();
+        self.paused.store(false, Ordering::SeqCst);
+        self.condvar.notify_all();
+    }
+
+    pub fn step(&self) {
+        *self.steps.lock().unwrap() += 1;
+        self.condvar.notify_all();
+    }
+
+    // makes the waiting devices check whether they've been released
+    fn wake(&self) {
+        let _steps = self.steps.lock().unwrap();
+        self.condvar.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    // `released` lets a device through regardless, e.g. once it's being shut down
+    fn pass(&self, released: impl Fn() -> bool) {
+        if !self.is_paused() {
+            return;
+        }
+        let mut steps = self.steps.lock().unwrap();
+        while self.is_paused() && !released() {
+            if *steps > 0 {
+                *steps -= 1;
+                return;
+            }
+            steps = self.condvar.wait(steps).unwrap();
+        }
+    }
+}
+
 pub struct Interface {
     interface_id: u32,
-    connection: Option<LinkEnd>,
+    // shared with the ModuleHandle so links can be plugged while the device runs
+    connection: Connection,
+    stats: Stats,
+    trace: Arc<TraceContext>,
 }
 
 pub struct Module {
     interfaces: Vec<Interface>,
     interface_nr: u32,
-    msg_queue: Arc<(Mutex<VecDeque<WireMsg>>, Condvar)>,
+    msg_queue: MsgQueue,
+    stats: Stats,
+    trace: Arc<TraceContext>,
+    gate: Arc<OnceLock<Arc<Gate>>>,
+}
+
+// Lets the simulator plug and unplug links (or stop the device) while the
+// device's thread owns its module.
+#[derive(Clone)]
+pub struct ModuleHandle {
+    connections: Vec<Connection>,
+    msg_queue: MsgQueue,
+    stats: Stats,
+    trace: Arc<TraceContext>,
+    gate: Arc<OnceLock<Arc<Gate>>>,
 }
 
 pub struct WireMsg {
@@ -25,11 +257,32 @@ pub struct WireMsg {
     pub interface_id: u32,
 }
 
+pub enum ModuleEvent {
+    Msg(WireMsg),
+    LinkStateChanged { interface_id: u32, up: bool },
+    // the device was removed from the simulation and should return from run()
+    Shutdown,
+}
+
 pub trait Device: Send {
     fn get_mac_address(&self) -> MacAddress;
     fn get_module(&mut self) -> &mut Module;
     fn run(&mut self);
 
+    // what the device is and its addresses, for diagrams of the topology
+    fn kind(&self) -> &'static str {
+        "device"
+    }
+
+    fn addresses(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    // the tables it keeps by name (e.g. its routing table), to look at while it runs
+    fn tables(&self) -> Vec<(&'static str, SharedTable)> {
+        Vec::new()
+    }
+
     // default methods
     //fn attach_link(&mut self, interface_id: u32, link_end: LinkEnd) {
     //    self.get_module().attach_link(interface_id, link_end)
@@ -38,21 +291,58 @@ pub trait Device: Send {
 
 impl Interface {
     pub fn is_up(&self) -> bool {
-        // FIXME: start to consider the fact the other end might not have a handler
-        self.connection.is_some()
+        self.connection
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|connection| connection.is_up())
     }
 
-    pub fn send(&self, data: &[u8]) {
-        // TODO:
-        //  - start returing a Result<..>
-        //  - start sending Arc<..> to avoid copying c:
-        assert!(
-            self.is_up(),
-            "Sending data through interface {} that is down!",
-            self.interface_id
-        );
-        let connection = self.connection.as_ref().unwrap();
-        connection.send(data).unwrap();
+    pub fn send(&self, data: impl Into<LinkData>) -> Result<(), LinkError> {
+        let data = data.into();
+        let len = data.len() as u64;
+        let sent = self.trace.transmit(self.interface_id, &data);
+        let result = match self.connection.lock().unwrap().as_ref() {
+            Some(connection) => connection.send(data),
+            None => Err(LinkError::LinkIsDown),
+        };
+
+        let mut stats = self.stats.lock().unwrap();
+        let stats = &mut stats.interfaces[self.interface_id as usize];
+        match result {
+            Ok(()) => {
+                stats.tx_frames += 1;
+                stats.tx_bytes += len;
+            }
+            Err(_) => *stats.drops.entry(DropReason::LinkDown).or_default() += 1,
+        }
+        if let (Err(_), Some((frame, hop))) = (&result, sent) {
+            self.trace.record(
+                Some(self.interface_id),
+                TraceEvent::Drop,
+                Some(frame),
+                DropReason::LinkDown.name(),
+                hop,
+            );
+        }
+        result
+    }
+
+    // for the frames the device discards itself
+    pub fn count_drop(&self, reason: DropReason) {
+        self.trace
+            .handled(Some(self.interface_id), TraceEvent::Drop, reason.name());
+        let mut stats = self.stats.lock().unwrap();
+        *stats.interfaces[self.interface_id as usize]
+            .drops
+            .entry(reason)
+            .or_default() += 1;
+    }
+
+    pub fn count_parse_error(&self) {
+        self.trace
+            .handled(Some(self.interface_id), TraceEvent::Drop, "ParseError");
+        self.stats.lock().unwrap().interfaces[self.interface_id as usize].parse_errors += 1;
     }
 }
 
@@ -60,15 +350,25 @@ impl Module {
     pub fn new(interface_nr: u32) -> Self {
         assert!(interface_nr > 0, "Provided 0 as inteface_nr for Module");
 
+        let stats = Arc::new(Mutex::new(DeviceStats {
+            interfaces: vec![InterfaceStats::default(); interface_nr as usize],
+            ..Default::default()
+        }));
+        let trace = Arc::new(TraceContext::default());
         Self {
             interface_nr,
             interfaces: (0..interface_nr)
                 .map(|interface_id| Interface {
                     interface_id,
-                    connection: None,
+                    connection: Arc::new(Mutex::new(None)),
+                    stats: Arc::clone(&stats),
+                    trace: Arc::clone(&trace),
                 })
                 .collect(),
             msg_queue: Arc::new((Mutex::new(VecDeque::new()), Condvar::new())),
+            stats,
+            trace,
+            gate: Arc::new(OnceLock::new()),
         }
     }
 
@@ -81,25 +381,172 @@ impl Module {
     }
 
     pub fn attach_link(&mut self, interface_id: u32, link_end: LinkEnd) {
+        self.handle().attach(interface_id, link_end)
+    }
+
+    pub fn handle(&self) -> ModuleHandle {
+        ModuleHandle {
+            connections: self
+                .interfaces
+                .iter()
+                .map(|interface| Arc::clone(&interface.connection))
+                .collect(),
+            msg_queue: Arc::clone(&self.msg_queue),
+            stats: Arc::clone(&self.stats),
+            trace: Arc::clone(&self.trace),
+            gate: Arc::clone(&self.gate),
+        }
+    }
+
+    // for what forwarding devices decide about the frame they're handling
+    pub fn trace_decision(&self, event: TraceEvent, interface: Option<u32>, reason: &str) {
+        self.trace.handled(interface, event, reason);
+    }
+
+    // bumps one of the device's own counters
+    pub fn count(&self, counter: &'static str) {
+        *self
+            .stats
+            .lock()
+            .unwrap()
+            .counters
+            .entry(counter)
+            .or_default() += 1;
+    }
+
+    pub fn set_gauge(&self, gauge: &'static str, instance: &str, value: u64) {
+        let mut stats = self.stats.lock().unwrap();
+        match stats.gauges.get_mut(&(gauge, instance.to_string())) {
+            Some(current) => *current = value,
+            None => {
+                stats.gauges.insert((gauge, instance.into()), value);
+            }
+        }
+    }
+
+    // once the instance is gone
+    pub fn remove_gauges(&self, instance: &str) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.gauges.retain(|(_, of), _| of != instance);
+    }
+
+    pub fn interfaces(&self) -> impl Iterator<Item = &Interface> {
+        self.interfaces.iter()
+    }
+
+    pub fn wait_for_event(&mut self) -> ModuleEvent {
+        let (lock, condvar) = &*self.msg_queue;
+
+        let queue = condvar
+            .wait_while(lock.lock().unwrap(), |queue| queue.is_empty())
+            .unwrap();
+        // only this thread takes events off the queue, it's still there after waiting
+        let shutdown = Self::shutting_down(&queue);
+        drop(queue);
+        if !shutdown {
+            self.pass_gate();
+        }
+
+        let event = lock.lock().unwrap().pop_front().unwrap();
+        self.trace.start_handling(Some(&event));
+        event
+    }
+
+    // Returns None if nothing happened within `timeout`.
+    pub fn wait_for_event_timeout(&mut self, timeout: Duration) -> Option<ModuleEvent> {
+        let (lock, condvar) = &*self.msg_queue;
+
+        let (queue, _) = condvar
+            .wait_timeout_while(lock.lock().unwrap(), timeout, |queue| queue.is_empty())
+            .unwrap();
+        // a timeout is a step too, the device handles its timers then
+        let shutdown = Self::shutting_down(&queue);
+        drop(queue);
+        if !shutdown {
+            self.pass_gate();
+        }
+
+        let event = lock.lock().unwrap().pop_front();
+        self.trace.start_handling(event.as_ref());
+        event
+    }
+
+    // devices being shut down go through what's left of their queue without waiting
+    fn shutting_down(queue: &VecDeque<ModuleEvent>) -> bool {
+        queue
+            .iter()
+            .any(|event| matches!(event, ModuleEvent::Shutdown))
+    }
+
+    // while the simulation is paused, waits for it to be resumed or stepped
+    fn pass_gate(&self) {
+        if let Some(gate) = self.gate.get() {
+            let (lock, _) = &*self.msg_queue;
+            gate.pass(|| Self::shutting_down(&lock.lock().unwrap()));
+        }
+    }
+
+    // Link state changes are skipped, devices interested in them should use wait_for_event().
+    // Returns None once the device has been shut down.
+    pub fn wait_for_msg(&mut self) -> Option<WireMsg> {
+        loop {
+            match self.wait_for_event() {
+                ModuleEvent::Msg(msg) => return Some(msg),
+                ModuleEvent::Shutdown => return None,
+                ModuleEvent::LinkStateChanged { .. } => {}
+            }
+        }
+    }
+}
+
+impl ModuleHandle {
+    // returns how many events are now waiting
+    fn push_event(msg_queue: &MsgQueue, event: ModuleEvent) -> usize {
+        let (lock, condvar) = &**msg_queue;
+        let mut queue = lock.lock().unwrap();
+        queue.push_back(event);
+        if queue.len() == 1 {
+            condvar.notify_one() // there should only be one thread waiting for this
+        }
+        queue.len()
+    }
+
+    fn attach(&self, interface_id: u32, link_end: LinkEnd) {
         assert!(
-            interface_id < self.interface_nr,
+            (interface_id as usize) < self.connections.len(),
             "Invalid interface_id: {interface_id} for module with {} interfaces",
-            self.interface_nr
+            self.connections.len()
         );
 
-        let interface = &mut self.interfaces[interface_id as usize];
-        match interface.connection {
+        let mut connection = self.connections[interface_id as usize].lock().unwrap();
+        match *connection {
             None => {
                 let msg_queue = Arc::clone(&self.msg_queue);
+                let stats = Arc::clone(&self.stats);
+                let trace = Arc::clone(&self.trace);
                 link_end.attach_receiver(move |data| {
-                    let (lock, condvar) = &*msg_queue;
-                    let mut queue = lock.lock().unwrap();
-                    queue.push_back(WireMsg { interface_id, data });
-                    if queue.len() == 1 {
-                        condvar.notify_one() // there should only be one thread waiting for this
-                    }
+                    let len = data.len() as u64;
+                    // before it's queued, so the device finds where it came from
+                    trace.receive(interface_id, &data);
+                    let queued = Self::push_event(
+                        &msg_queue,
+                        ModuleEvent::Msg(WireMsg { interface_id, data }),
+                    );
+
+                    let mut stats = stats.lock().unwrap();
+                    stats.max_queued = stats.max_queued.max(queued);
+                    let stats = &mut stats.interfaces[interface_id as usize];
+                    stats.rx_frames += 1;
+                    stats.rx_bytes += len;
                 });
-                interface.connection = Some(link_end);
+                let msg_queue = Arc::clone(&self.msg_queue);
+                link_end.attach_state_listener(move |up| {
+                    Self::push_event(
+                        &msg_queue,
+                        ModuleEvent::LinkStateChanged { interface_id, up },
+                    );
+                });
+                *connection = Some(link_end);
             }
             Some(_) => {
                 // TODO: think if you want to panic or send a result error
@@ -108,18 +555,64 @@ impl Module {
         }
     }
 
-    pub fn interfaces(&self) -> impl Iterator<Item = &Interface> {
-        self.interfaces.iter()
+    // Unlike Module::attach_link this lets the (running) device know about the new link.
+    pub fn attach_link(&self, interface_id: u32, link_end: LinkEnd) {
+        self.attach(interface_id, link_end);
+        let up = self.connections[interface_id as usize]
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|connection| connection.is_up());
+        Self::push_event(
+            &self.msg_queue,
+            ModuleEvent::LinkStateChanged { interface_id, up },
+        );
     }
 
-    pub fn wait_for_msg(&mut self) -> WireMsg {
-        let (lock, condvar) = &*self.msg_queue;
+    // Dropping the returned end detaches it from its link. The device is told the link went down,
+    // unless it had no carrier already (e.g. the other end was detached first).
+    pub fn detach_link(&self, interface_id: u32) -> Option<LinkEnd> {
+        let link_end = self
+            .connections
+            .get(interface_id as usize)?
+            .lock()
+            .unwrap()
+            .take();
 
-        let mut queue = condvar
-            .wait_while(lock.lock().unwrap(), |queue| queue.is_empty())
-            .unwrap();
+        if link_end.as_ref().is_some_and(LinkEnd::is_up) {
+            Self::push_event(
+                &self.msg_queue,
+                ModuleEvent::LinkStateChanged {
+                    interface_id,
+                    up: false,
+                },
+            );
+        }
+        link_end
+    }
+
+    pub fn shutdown(&self) {
+        Self::push_event(&self.msg_queue, ModuleEvent::Shutdown);
+        // a device which timed out waiting for events may be held back by the gate
+        if let Some(gate) = self.gate.get() {
+            gate.wake();
+        }
+    }
 
-        queue.pop_front().unwrap()
+    // Only the first tracer set is kept, `device` is how the device shows up in the trace.
+    pub fn set_tracer(&self, tracer: Tracer, device: MacAddress) {
+        let _ = self.trace.tracer.set((tracer, device));
+    }
+
+    // only the first gate set is kept
+    pub fn set_gate(&self, gate: Arc<Gate>) {
+        let _ = self.gate.set(gate);
+    }
+
+    pub fn stats(&self) -> DeviceStats {
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.queued = self.msg_queue.0.lock().unwrap().len();
+        stats
     }
 }
 
@@ -152,3 +645,143 @@ impl<F: FnMut(MacAddress, &mut Module) + Send> Device for ProgrammableDevice<F>
         (self.program)(self.address, &mut self.module);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{DropReason, Gate, Module, ModuleEvent};
+    use crate::links;
+    use std::{
+        sync::{mpsc, Arc},
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn reattach_interface() {
+        let mut module = Module::new(1);
+        let handle = module.handle();
+
+        let (end_1, peer) = links::create_link();
+        module.attach_link(0, end_1);
+        peer.attach_receiver(|_| {}).unwrap();
+        assert!(module.get_interface(0).unwrap().is_up());
+
+        drop(handle.detach_link(0));
+        assert!(!module.get_interface(0).unwrap().is_up());
+        assert!(!peer.is_up());
+        assert!(matches!(
+            module.wait_for_event(),
+            ModuleEvent::LinkStateChanged {
+                interface_id: 0,
+                up: false
+            }
+        ));
+
+        let (end_1, peer) = links::create_link();
+        peer.attach_receiver(|_| {}).unwrap();
+        handle.attach_link(0, end_1);
+        assert!(matches!(
+            module.wait_for_event(),
+            ModuleEvent::LinkStateChanged {
+                interface_id: 0,
+                up: true
+            }
+        ));
+
+        peer.send([1, 2, 3]).unwrap();
+        let msg = module.wait_for_msg().unwrap();
+        assert_eq!((msg.interface_id, msg.data.as_ref()), (0, &[1, 2, 3][..]));
+
+        handle.shutdown();
+        assert!(module.wait_for_msg().is_none());
+    }
+
+    #[test]
+    fn interface_counters() {
+        let mut module = Module::new(2);
+        let handle = module.handle();
+        let (end, peer) = links::create_link();
+        module.attach_link(0, end);
+        peer.attach_receiver(|_| {}).unwrap();
+
+        peer.send([1, 2, 3]).unwrap();
+        peer.send([4, 5]).unwrap();
+        let interface = module.get_interface(0).unwrap();
+        interface.send([6; 10]).unwrap();
+        interface.count_parse_error();
+        assert!(module.get_interface(1).unwrap().send([7]).is_err());
+        module
+            .get_interface(1)
+            .unwrap()
+            .count_drop(DropReason::NoRoute);
+        module.count("things");
+
+        let stats = handle.stats();
+        let interface = &stats.interfaces[0];
+        assert_eq!((interface.rx_frames, interface.rx_bytes), (2, 5));
+        assert_eq!((interface.tx_frames, interface.tx_bytes), (1, 10));
+        assert_eq!((interface.parse_errors, interface.dropped()), (1, 0));
+        let drops: Vec<_> = stats.interfaces[1].drops.iter().collect();
+        assert_eq!(
+            drops,
+            [(&DropReason::LinkDown, &1), (&DropReason::NoRoute, &1)]
+        );
+        assert_eq!(stats.counters["things"], 1);
+        assert_eq!((stats.queued, stats.max_queued), (2, 2));
+    }
+
+    #[test]
+    fn pause_and_step() {
+        let mut module = Module::new(1);
+        let handle = module.handle();
+        let gate = Arc::new(Gate::default());
+        handle.set_gate(Arc::clone(&gate));
+        let (end, peer) = links::create_link();
+        module.attach_link(0, end);
+        peer.attach_receiver(|_| {}).unwrap();
+
+        gate.pause();
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            while let Some(msg) = module.wait_for_msg() {
+                sender.send(msg.data[0]).unwrap();
+            }
+        });
+        peer.send([1].as_slice()).unwrap();
+        peer.send([2].as_slice()).unwrap();
+        let wait = Duration::from_millis(50);
+        assert!(receiver.recv_timeout(wait).is_err());
+
+        gate.step();
+        assert_eq!(receiver.recv_timeout(wait), Ok(1));
+        assert!(receiver.recv_timeout(wait).is_err());
+
+        gate.resume();
+        assert_eq!(receiver.recv_timeout(wait), Ok(2));
+
+        // isn't held back by a pause
+        gate.pause();
+        handle.shutdown();
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn shutdown_while_paused() {
+        // a device with timers goes through the gate without any events
+        let mut module = Module::new(1);
+        let handle = module.handle();
+        let gate = Arc::new(Gate::default());
+        handle.set_gate(Arc::clone(&gate));
+
+        let thread = thread::spawn(move || {
+            while !matches!(
+                module.wait_for_event_timeout(Duration::from_millis(5)),
+                Some(ModuleEvent::Shutdown)
+            ) {}
+        });
+        gate.pause();
+        thread::sleep(Duration::from_millis(50));
+        handle.shutdown();
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+use super::{
+    endpoint::{IpEndpoint, IpPacket},
+    Device, DropReason, Module, ModuleEvent, WireMsg,
+};
+use crate::{
+    nat::{Nat, NatConfig},
+    protocols::{
+        ethernet::MacAddress,
+        icmp::{self, IcmpPacket, IcmpType},
+        ipv4::{IpProtocol, Ipv4Packet},
+        Packet,
+    },
+    trace::TraceEvent,
+};
+use std::{
+    net::{Ipv4Addr, SocketAddrV4},
+    time::Instant,
+};
+
+pub const INSIDE_INTERFACE: u32 = 0;
+pub const OUTSIDE_INTERFACE: u32 = 1;
+
+// A NAT gateway between a private network on its inside interface and the rest of the network
+// on its outside one, where everything seems to come from its outside address.
+pub struct NatGateway {
+    address: MacAddress,
+    module: Module,
+    inside: IpEndpoint,
+    outside: IpEndpoint,
+    nat: Nat,
+}
+
+impl NatGateway {
+    pub fn new(
+        address: MacAddress,
+        inside_address: Ipv4Addr,
+        inside_prefix_len: u8,
+        outside_address: Ipv4Addr,
+        outside_prefix_len: u8,
+        gateway: Option<Ipv4Addr>,
+        config: NatConfig,
+    ) -> Self {
+        let mut inside = IpEndpoint::new(address);
+        inside.configure(inside_address, inside_prefix_len, None);
+        inside.set_forwarding(true);
+        let mut outside = IpEndpoint::new(address);
+        outside.configure(outside_address, outside_prefix_len, gateway);
+
+        Self {
+            address,
+            module: Module::new(2),
+            inside,
+            outside,
+            nat: Nat::new(outside_address, config),
+        }
+    }
+
+    pub fn add_port_forward(
+        &mut self,
+        protocol: IpProtocol,
+        external_port: u16,
+        internal: SocketAddrV4,
+    ) {
+        self.nat.add_port_forward(protocol, external_port, internal);
+    }
+
+    fn handle_frame(&mut self, msg: WireMsg, now: Instant) {
+        let interface = self.module.get_interface(msg.interface_id).unwrap();
+        let endpoint = match msg.interface_id {
+            INSIDE_INTERFACE => &mut self.inside,
+            _ => &mut self.outside,
+        };
+        let Some(IpPacket::V4(packet)) = endpoint.receive(interface, &msg.data, now) else {
+            return;
+        };
+
+        match msg.interface_id {
+            INSIDE_INTERFACE => self.handle_inside(packet, now),
+            _ => self.handle_outside(packet, now),
+        }
+    }
+
+    fn handle_inside(&mut self, mut packet: Ipv4Packet, now: Instant) {
+        let prefix = self.inside.prefix().unwrap();
+        if prefix.contains(packet.destin)
+            || packet.destin.is_broadcast()
+            || packet.destin.is_multicast()
+        {
+            return;
+        }
+
+        if packet.ttl <= 1 {
+            return self.time_exceeded(INSIDE_INTERFACE, &packet, now);
+        }
+        packet.ttl -= 1;
+
+        let (source, destin) = (packet.source, packet.destin);
+        let Some(packet) = self.nat.translate_outbound(packet, now) else {
+            let interface = self.module.get_interface(INSIDE_INTERFACE).unwrap();
+            interface.count_drop(DropReason::Filtered);
+            log::debug!(
+                "NAT gateway {}: dropping {source} -> {destin}",
+                self.address
+            );
+            return;
+        };
+        self.module
+            .trace_decision(TraceEvent::Forward, Some(OUTSIDE_INTERFACE), "translated");
+        let interface = self.module.get_interface(OUTSIDE_INTERFACE).unwrap();
+        if !self.outside.send(interface, packet, now) {
+            log::debug!("NAT gateway {}: no route to {destin}", self.address);
+        }
+    }
+
+    // like a router would, so traceroute works through the gateway from either side
+    fn time_exceeded(&mut self, interface_id: u32, packet: &Ipv4Packet, now: Instant) {
+        let interface = self.module.get_interface(interface_id).unwrap();
+        interface.count_drop(DropReason::TtlExpired);
+
+        let endpoint = match interface_id {
+            INSIDE_INTERFACE => &mut self.inside,
+            _ => &mut self.outside,
+        };
+        let error = IcmpPacket::error(
+            IcmpType::TimeExceeded,
+            icmp::ICMP_TTL_EXCEEDED,
+            &packet.to_bytes(),
+        );
+        let error = Ipv4Packet::new(
+            endpoint.address().unwrap(),
+            packet.source,
+            IpProtocol::Icmp,
+            error.to_bytes(),
+        );
+        endpoint.send(interface, error, now);
+    }
+
+    fn handle_outside(&mut self, mut packet: Ipv4Packet, now: Instant) {
+        if packet.ttl <= 1 {
+            return self.time_exceeded(OUTSIDE_INTERFACE, &packet, now);
+        }
+        packet.ttl -= 1;
+
+        match self.nat.translate_inbound(packet, now) {
+            Some(packet) => {
+                self.module.trace_decision(
+                    TraceEvent::Forward,
+                    Some(INSIDE_INTERFACE),
+                    "translated",
+                );
+                let interface = self.module.get_interface(INSIDE_INTERFACE).unwrap();
+                self.inside.send(interface, packet, now);
+            }
+            None => {
+                let interface = self.module.get_interface(OUTSIDE_INTERFACE).unwrap();
+                interface.count_drop(DropReason::Filtered);
+            }
+        }
+    }
+}
+
+impl Device for NatGateway {
+    fn get_mac_address(&self) -> MacAddress {
+        self.address
+    }
+
+    fn get_module(&mut self) -> &mut Module {
+        &mut self.module
+    }
+
+    fn kind(&self) -> &'static str {
+        "nat"
+    }
+
+    fn addresses(&self) -> Vec<String> {
+        [("inside", &self.inside), ("outside", &self.outside)]
+            .into_iter()
+            .filter_map(|(side, endpoint)| Some(format!("{side}: {}", endpoint.cidr()?)))
+            .collect()
+    }
+
+    fn run(&mut self) {
+        log::debug!("NAT gateway {} running...", self.address);
+
+        loop {
+            let event = match self.nat.next_deadline() {
+                Some(deadline) => self
+                    .module
+                    .wait_for_event_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => Some(self.module.wait_for_event()),
+            };
+
+            let now = Instant::now();
+            match event {
+                Some(ModuleEvent::Msg(msg)) => self.handle_frame(msg, now),
+                Some(ModuleEvent::Shutdown) => {
+                    log::debug!("NAT gateway {} shutting down...", self.address);
+                    return;
+                }
+                _ => {}
+            }
+
+            self.nat.expire(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NatGateway, OUTSIDE_INTERFACE};
+    use crate::{
+        devices::{
+            endpoint::test::{attach, ipv4_host, receive, send_udp, udp_addresses},
+            Device, DropReason,
+        },
+        nat::NatConfig,
+        protocols::{
+            ethernet::MacAddress,
+            icmp::IcmpType,
+            ipv4::{IpProtocol, Ipv4Packet},
+        },
+    };
+    use std::{
+        net::{Ipv4Addr, SocketAddrV4},
+        thread,
+        time::Instant,
+    };
+
+    const INSIDE: Ipv4Addr = Ipv4Addr::new(192, 168, 0, 2);
+    const EXTERNAL: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+    const REMOTE: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 2);
+
+    #[test]
+    fn translates_between_networks() {
+        let mut gateway = NatGateway::new(
+            MacAddress::new([1; 6]),
+            Ipv4Addr::new(192, 168, 0, 1),
+            24,
+            EXTERNAL,
+            24,
+            None,
+            NatConfig::default(),
+        );
+        gateway.add_port_forward(IpProtocol::Udp, 8080, SocketAddrV4::new(INSIDE, 80));
+
+        // in the order of INSIDE_INTERFACE and OUTSIDE_INTERFACE
+        let mut hosts = [
+            ipv4_host(INSIDE, Some(Ipv4Addr::new(192, 168, 0, 1))),
+            ipv4_host(REMOTE, Some(EXTERNAL)),
+        ];
+        attach(&mut hosts, gateway.get_module());
+        let handle = gateway.get_module().handle();
+        let thread = thread::spawn(move || gateway.run());
+
+        // the reply reaches the inside host through the mapping of its request
+        send_udp(&mut hosts[0], 5000, SocketAddrV4::new(REMOTE, 53));
+        let (source, destin) = udp_addresses(&receive(&mut hosts, 1).unwrap());
+        assert_eq!(source, SocketAddrV4::new(EXTERNAL, 1024));
+        send_udp(&mut hosts[1], destin.port(), source);
+        let (source, destin) = udp_addresses(&receive(&mut hosts, 0).unwrap());
+        assert_eq!(source, SocketAddrV4::new(REMOTE, 53));
+        assert_eq!(destin, SocketAddrV4::new(INSIDE, 5000));
+
+        send_udp(&mut hosts[1], 6000, SocketAddrV4::new(EXTERNAL, 8080));
+        let (source, destin) = udp_addresses(&receive(&mut hosts, 0).unwrap());
+        assert_eq!(source, SocketAddrV4::new(REMOTE, 6000));
+        assert_eq!(destin, SocketAddrV4::new(INSIDE, 80));
+
+        // packets that expire at the gateway are reported back from the outside as well
+        let (module, endpoint) = &mut hosts[1];
+        let mut expired = Ipv4Packet::new(REMOTE, EXTERNAL, IpProtocol::Udp, Box::new([]));
+        expired.ttl = 1;
+        assert!(endpoint.send(module.get_interface(0).unwrap(), expired, Instant::now()));
+        let error = receive(&mut hosts, 1).unwrap();
+        assert_eq!(error.source, EXTERNAL);
+        assert_eq!(error.protocol, IpProtocol::Icmp);
+        assert_eq!(error.data[0], IcmpType::TimeExceeded as u8);
+        let drops = &handle.stats().interfaces[OUTSIDE_INTERFACE as usize].drops;
+        assert_eq!(drops.get(&DropReason::TtlExpired), Some(&1));
+
+        handle.shutdown();
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,1206 @@
+use super::{Device, DropReason, Interface, Module, ModuleEvent, SharedTable, WireMsg};
+use crate::{
+    ndp::{ndp_packet, NeighborCache},
+    protocols::{
+        arp::{ArpOperation, ArpPacket},
+        ethernet::{
+            EthernetFrame, EthernetFrameRef, FrameProtocol, MacAddress, ETHERNET_BROADCAST_MAC_ADDR,
+        },
+        icmp::{self, IcmpPacket, IcmpType},
+        icmpv6::{self, Icmpv6Packet, PrefixInformation, RouterAdvertisement},
+        ipv4::{IpProtocol, Ipv4Packet, Ipv4Prefix},
+        ipv6::{
+            link_local_address, solicited_node_address, Ipv6Packet, Ipv6Prefix, NextHeader,
+            ALL_NODES, ALL_ROUTERS, IPV6_DEFAULT_HOP_LIMIT,
+        },
+        Packet,
+    },
+    routing::{InterfaceConfig, Outgoing, Route, RouteSource, RoutingProtocol, RoutingTable},
+    trace::TraceEvent,
+};
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, Ipv6Addr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// how long to wait for an ARP reply before asking again
+const ARP_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+// packets queued per unresolved address, the oldest ones are dropped
+const ARP_QUEUE_SIZE: usize = 16;
+
+// unsolicited router advertisements, with the lifetimes of RFC 4861's defaults
+const ADVERTISEMENT_INTERVAL: Duration = Duration::from_secs(200);
+const ROUTER_LIFETIME: u16 = 1800;
+const PREFIX_VALID_LIFETIME: u32 = 30 * 24 * 3600;
+const PREFIX_PREFERRED_LIFETIME: u32 = 7 * 24 * 3600;
+
+struct PendingResolution {
+    requested_at: Instant,
+    packets: Vec<Ipv4Packet>,
+}
+
+// IPv6 routes are only connected or static
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Ipv6Route {
+    prefix: Ipv6Prefix,
+    interface_id: u32,
+    next_hop: Option<Ipv6Addr>,
+}
+
+// A dual-stack router: it answers ARP and neighbor solicitations for its interfaces' addresses,
+// forwards packets through its routing tables and runs whatever routing protocols it was given.
+// IPv6 is enabled on the interfaces with an IPv6 address, whose prefixes it advertises so hosts can
+// configure themselves.
+pub struct Router {
+    address: MacAddress,
+    module: Module,
+    interfaces: Vec<Option<InterfaceConfig>>,
+    static_routes: Vec<Route>,
+    protocols: Vec<Box<dyn RoutingProtocol>>,
+    routing_table: Arc<Mutex<RoutingTable>>,
+    arp_cache: HashMap<Ipv4Addr, MacAddress>,
+    pending: HashMap<(u32, Ipv4Addr), PendingResolution>,
+    ipv6_interfaces: Vec<Option<(Ipv6Addr, Ipv6Prefix)>>,
+    ipv6_static_routes: Vec<Ipv6Route>,
+    ipv6_routes: Vec<Ipv6Route>,
+    neighbors: Vec<NeighborCache>,
+    next_advertisement: Option<Instant>,
+}
+
+impl Router {
+    pub fn new(address: MacAddress, interface_nr: u32) -> Self {
+        Self {
+            address,
+            module: Module::new(interface_nr),
+            interfaces: vec![None; interface_nr as usize],
+            static_routes: Vec::new(),
+            protocols: Vec::new(),
+            routing_table: Arc::new(Mutex::new(RoutingTable::new())),
+            arp_cache: HashMap::new(),
+            pending: HashMap::new(),
+            ipv6_interfaces: vec![None; interface_nr as usize],
+            ipv6_static_routes: Vec::new(),
+            ipv6_routes: Vec::new(),
+            neighbors: (0..interface_nr)
+                .map(|_| NeighborCache::new(address))
+                .collect(),
+            next_advertisement: None,
+        }
+    }
+
+    pub fn set_interface_address(&mut self, interface_id: u32, address: Ipv4Addr, prefix_len: u8) {
+        self.interfaces[interface_id as usize] =
+            Some(InterfaceConfig::new(interface_id, address, prefix_len));
+    }
+
+    pub fn add_static_route(&mut self, prefix: Ipv4Prefix, interface_id: u32, next_hop: Ipv4Addr) {
+        self.static_routes.push(Route {
+            prefix,
+            interface_id,
+            next_hop: Some(next_hop),
+            metric: 1,
+            source: RouteSource::Static,
+        });
+    }
+
+    pub fn set_interface_ipv6_address(
+        &mut self,
+        interface_id: u32,
+        address: Ipv6Addr,
+        prefix_len: u8,
+    ) {
+        self.ipv6_interfaces[interface_id as usize] =
+            Some((address, Ipv6Prefix::new(address, prefix_len)));
+    }
+
+    pub fn add_static_ipv6_route(
+        &mut self,
+        prefix: Ipv6Prefix,
+        interface_id: u32,
+        next_hop: Ipv6Addr,
+    ) {
+        self.ipv6_static_routes.push(Ipv6Route {
+            prefix,
+            interface_id,
+            next_hop: Some(next_hop),
+        });
+    }
+
+    pub fn add_protocol(&mut self, protocol: impl RoutingProtocol + 'static) {
+        self.protocols.push(Box::new(protocol));
+    }
+
+    // Lets whoever built the router look at its routes while it runs.
+    pub fn routing_table(&self) -> Arc<Mutex<RoutingTable>> {
+        Arc::clone(&self.routing_table)
+    }
+
+    fn configured_interfaces(&self) -> impl Iterator<Item = &InterfaceConfig> {
+        self.interfaces.iter().flatten()
+    }
+
+    fn set_connected(&mut self, interface_id: u32, up: bool) {
+        if let Some((_, prefix)) = self.ipv6_interfaces[interface_id as usize] {
+            self.ipv6_routes
+                .retain(|route| route.next_hop.is_some() || route.prefix != prefix);
+            if up {
+                self.ipv6_routes.push(Ipv6Route {
+                    prefix,
+                    interface_id,
+                    next_hop: None,
+                });
+            }
+        }
+
+        let Some(interface) = self.interfaces[interface_id as usize] else {
+            return;
+        };
+
+        let mut table = self.routing_table.lock().unwrap();
+        if up {
+            table.add(Route {
+                prefix: interface.prefix,
+                interface_id,
+                next_hop: None,
+                metric: 0,
+                source: RouteSource::Connected,
+            });
+        } else {
+            table.remove(interface.prefix, RouteSource::Connected);
+        }
+    }
+
+    fn start(&mut self, now: Instant) {
+        // the rest are added once their link comes up
+        let up: Vec<_> = self.module.interfaces().map(Interface::is_up).collect();
+        for (interface_id, up) in up.iter().enumerate() {
+            self.set_connected(interface_id as u32, *up);
+        }
+
+        {
+            let mut table = self.routing_table.lock().unwrap();
+            self.static_routes
+                .iter()
+                .for_each(|route| table.add(route.clone()));
+        }
+        self.ipv6_routes
+            .extend(self.ipv6_static_routes.iter().copied());
+        self.next_advertisement = Some(now);
+
+        let interfaces: Vec<_> = self.configured_interfaces().copied().collect();
+        let mut out = Vec::new();
+        for protocol in self.protocols.iter_mut() {
+            protocol.start(&interfaces, now, &mut out);
+            // they start with every interface up
+            for interface in interfaces
+                .iter()
+                .filter(|config| !up[config.interface_id as usize])
+            {
+                protocol.link_state_changed(interface.interface_id, false, now, &mut out);
+            }
+        }
+        out.retain(|outgoing| {
+            !matches!(outgoing, Outgoing::OnInterface(interface_id, _) if !up[*interface_id as usize])
+        });
+        self.send_all(out);
+        self.poll_protocols(now);
+    }
+
+    fn poll_protocols(&mut self, now: Instant) {
+        let mut out = Vec::new();
+        for protocol in self.protocols.iter_mut() {
+            if protocol
+                .next_deadline()
+                .is_some_and(|deadline| deadline <= now)
+            {
+                protocol.poll(now, &mut out);
+            }
+        }
+        self.send_all(out);
+        self.install_routes();
+    }
+
+    fn install_routes(&mut self) {
+        let mut table = self.routing_table.lock().unwrap();
+        let mut changed = false;
+        for protocol in self.protocols.iter() {
+            changed |= table.replace_source(protocol.source(), protocol.routes());
+        }
+
+        if changed {
+            log::info!("Router {}: routing table changed", self.address);
+            log::debug!("Router {}: routing table\n{table}", self.address);
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.protocols
+            .iter()
+            .filter_map(|protocol| protocol.next_deadline())
+            .chain(self.next_advertisement)
+            .min()
+    }
+
+    fn send_all(&mut self, out: Vec<Outgoing>) {
+        for outgoing in out {
+            match outgoing {
+                Outgoing::OnInterface(interface_id, packet) => {
+                    self.send_packet(interface_id, packet.destin, packet)
+                }
+                Outgoing::Routed(packet) => self.route(packet, None),
+            }
+        }
+    }
+
+    fn handle_frame(&mut self, msg: WireMsg, now: Instant) {
+        let frame = match EthernetFrameRef::new(&msg.data) {
+            Ok(frame) => frame,
+            Err(err) => {
+                log::error!(
+                    "Router {}: parsing ethernet frame from interface {}: {err:?}",
+                    self.address,
+                    msg.interface_id
+                );
+                let interface = self.module.get_interface(msg.interface_id).unwrap();
+                interface.count_parse_error();
+                return;
+            }
+        };
+
+        if frame.destin() != self.address && !frame.destin().is_multicast() {
+            return;
+        }
+
+        match frame.protocol() {
+            Ok(FrameProtocol::Apr) => match ArpPacket::decode(frame.payload()) {
+                Ok(arp) => self.handle_arp(msg.interface_id, arp),
+                Err(err) => log::warn!("Router {}: invalid ARP packet: {err:?}", self.address),
+            },
+            Ok(FrameProtocol::Ipv4) => match Ipv4Packet::decode(frame.payload()) {
+                Ok(packet) => self.handle_packet(msg.interface_id, packet, now),
+                Err(err) => log::warn!("Router {}: invalid IPv4 packet: {err:?}", self.address),
+            },
+            Ok(FrameProtocol::Ipv6) => match Ipv6Packet::decode(frame.payload()) {
+                Ok(packet) => self.handle_ipv6_packet(msg.interface_id, packet, now),
+                Err(err) => log::warn!("Router {}: invalid IPv6 packet: {err:?}", self.address),
+            },
+            _ => log::debug!("Router {}: dropping {frame:?}", self.address),
+        }
+    }
+
+    fn handle_arp(&mut self, interface_id: u32, arp: ArpPacket) {
+        let Some(interface) = self.interfaces[interface_id as usize] else {
+            return;
+        };
+
+        self.arp_cache.insert(arp.sender_ip, arp.sender_mac);
+        if let Some(pending) = self.pending.remove(&(interface_id, arp.sender_ip)) {
+            for packet in pending.packets {
+                self.send_frame(interface_id, arp.sender_mac, packet);
+            }
+        }
+
+        if arp.operation == ArpOperation::Request && arp.target_ip == interface.address {
+            let reply = arp.reply_to(self.address);
+            self.send_ethernet(
+                interface_id,
+                arp.sender_mac,
+                FrameProtocol::Apr,
+                reply.to_bytes(),
+            );
+        }
+    }
+
+    fn is_local(&self, destin: Ipv4Addr) -> bool {
+        destin.is_broadcast()
+            || destin.is_multicast()
+            || self.configured_interfaces().any(|interface| {
+                interface.address == destin || interface.prefix.broadcast() == destin
+            })
+    }
+
+    fn handle_packet(&mut self, interface_id: u32, packet: Ipv4Packet, now: Instant) {
+        if !self.is_local(packet.destin) {
+            return self.route(packet, Some(interface_id));
+        }
+
+        let mut out = Vec::new();
+        let consumed = self
+            .protocols
+            .iter_mut()
+            .any(|protocol| protocol.handle_packet(interface_id, &packet, now, &mut out));
+        self.send_all(out);
+
+        if consumed {
+            self.install_routes();
+            return;
+        }
+
+        if packet.destin.is_broadcast() || packet.destin.is_multicast() {
+            return;
+        }
+
+        let reply = match packet.protocol {
+            IpProtocol::Icmp => match IcmpPacket::decode(&packet.data) {
+                Ok(echo) if echo.message_type == IcmpType::EchoRequest => echo.echo_reply(),
+                _ => return,
+            },
+            IpProtocol::Tcp | IpProtocol::Udp => IcmpPacket::error(
+                IcmpType::DestinationUnreachable,
+                icmp::ICMP_PORT_UNREACHABLE,
+                &packet.to_bytes(),
+            ),
+            _ => IcmpPacket::error(
+                IcmpType::DestinationUnreachable,
+                icmp::ICMP_PROTOCOL_UNREACHABLE,
+                &packet.to_bytes(),
+            ),
+        };
+
+        self.route(
+            Ipv4Packet::new(
+                packet.destin,
+                packet.source,
+                IpProtocol::Icmp,
+                reply.to_bytes(),
+            ),
+            None,
+        );
+    }
+
+    // `from` is the interface the packet arrived on, None for the ones the router sends itself
+    fn route(&mut self, mut packet: Ipv4Packet, from: Option<u32>) {
+        if let Some(from) = from {
+            if packet.ttl <= 1 {
+                let interface = self.module.get_interface(from).unwrap();
+                interface.count_drop(DropReason::TtlExpired);
+                return self.send_icmp_error(
+                    from,
+                    IcmpType::TimeExceeded,
+                    icmp::ICMP_TTL_EXCEEDED,
+                    &packet,
+                );
+            }
+            packet.ttl -= 1;
+        }
+
+        let route = self
+            .routing_table
+            .lock()
+            .unwrap()
+            .lookup(packet.destin)
+            .cloned();
+        match route {
+            Some(route) => {
+                let next_hop = route.next_hop.unwrap_or(packet.destin);
+                log::debug!(
+                    "Router {}: forwarding {} -> {} through interface {} via {next_hop}",
+                    self.address,
+                    packet.source,
+                    packet.destin,
+                    route.interface_id
+                );
+                if from.is_some() {
+                    self.module.trace_decision(
+                        TraceEvent::Forward,
+                        Some(route.interface_id),
+                        "route",
+                    );
+                }
+                self.send_packet(route.interface_id, next_hop, packet)
+            }
+            None => {
+                log::debug!("Router {}: no route to {}", self.address, packet.destin);
+                if let Some(from) = from {
+                    let interface = self.module.get_interface(from).unwrap();
+                    interface.count_drop(DropReason::NoRoute);
+                    self.send_icmp_error(
+                        from,
+                        IcmpType::DestinationUnreachable,
+                        icmp::ICMP_NET_UNREACHABLE,
+                        &packet,
+                    );
+                }
+            }
+        }
+    }
+
+    fn send_icmp_error(
+        &mut self,
+        interface_id: u32,
+        message_type: IcmpType,
+        code: u8,
+        packet: &Ipv4Packet,
+    ) {
+        // errors are never sent about errors, otherwise two routers could bounce them forever
+        let is_error = packet.protocol == IpProtocol::Icmp
+            && IcmpPacket::decode(&packet.data).is_ok_and(|icmp| {
+                !matches!(
+                    icmp.message_type,
+                    IcmpType::EchoRequest | IcmpType::EchoReply
+                )
+            });
+
+        let Some(interface) = self.interfaces[interface_id as usize] else {
+            return;
+        };
+        if is_error {
+            return;
+        }
+
+        let error = IcmpPacket::error(message_type, code, &packet.to_bytes());
+        self.route(
+            Ipv4Packet::new(
+                interface.address,
+                packet.source,
+                IpProtocol::Icmp,
+                error.to_bytes(),
+            ),
+            None,
+        );
+    }
+
+    fn send_packet(&mut self, interface_id: u32, next_hop: Ipv4Addr, packet: Ipv4Packet) {
+        let Some(interface) = self.interfaces[interface_id as usize] else {
+            return;
+        };
+
+        if next_hop.is_broadcast()
+            || next_hop.is_multicast()
+            || next_hop == interface.prefix.broadcast()
+        {
+            return self.send_frame(interface_id, ETHERNET_BROADCAST_MAC_ADDR, packet);
+        }
+
+        if let Some(mac) = self.arp_cache.get(&next_hop).copied() {
+            return self.send_frame(interface_id, mac, packet);
+        }
+
+        let now = Instant::now();
+        let pending = self
+            .pending
+            .entry((interface_id, next_hop))
+            .or_insert(PendingResolution {
+                requested_at: now,
+                packets: Vec::new(),
+            });
+
+        if pending.packets.len() == ARP_QUEUE_SIZE {
+            pending.packets.remove(0);
+        }
+        pending.packets.push(packet);
+
+        let first = pending.packets.len() == 1;
+        if first || now.duration_since(pending.requested_at) >= ARP_RETRY_INTERVAL {
+            pending.requested_at = now;
+            let request = ArpPacket::request(self.address, interface.address, next_hop);
+            self.send_ethernet(
+                interface_id,
+                ETHERNET_BROADCAST_MAC_ADDR,
+                FrameProtocol::Apr,
+                request.to_bytes(),
+            );
+        }
+    }
+
+    // the link-local address first, which is the same on every interface
+    fn ipv6_addresses(&self, interface_id: u32) -> Vec<Ipv6Addr> {
+        match self.ipv6_interfaces[interface_id as usize] {
+            Some((address, _)) => vec![link_local_address(self.address), address],
+            None => Vec::new(),
+        }
+    }
+
+    fn is_local_ipv6(&self, interface_id: u32, destin: Ipv6Addr) -> bool {
+        destin == ALL_NODES
+            || destin == ALL_ROUTERS
+            || destin == link_local_address(self.address)
+            || self
+                .ipv6_interfaces
+                .iter()
+                .flatten()
+                .any(|(address, _)| *address == destin)
+            || self
+                .ipv6_addresses(interface_id)
+                .into_iter()
+                .any(|address| solicited_node_address(address) == destin)
+    }
+
+    fn handle_ipv6_packet(&mut self, interface_id: u32, packet: Ipv6Packet, now: Instant) {
+        if self.ipv6_interfaces[interface_id as usize].is_none() {
+            return;
+        }
+
+        if !self.is_local_ipv6(interface_id, packet.destin) {
+            // link-local and multicast traffic stays on its link
+            let link_scoped = packet.destin.is_multicast()
+                || packet.destin.is_unicast_link_local()
+                || packet.source.is_unicast_link_local();
+            if !link_scoped {
+                self.route_ipv6(packet, Some(interface_id), now);
+            }
+            return;
+        }
+
+        let reply = match packet.protocol {
+            NextHeader::Icmpv6 => match Icmpv6Packet::decode(&packet.data) {
+                Ok(
+                    message @ (Icmpv6Packet::NeighborSolicitation { .. }
+                    | Icmpv6Packet::NeighborAdvertisement(_)),
+                ) => {
+                    let own = self.ipv6_addresses(interface_id);
+                    let out =
+                        self.neighbors[interface_id as usize].handle(&packet, &message, &own, true);
+                    for (mac, packet) in out {
+                        self.send_ethernet(
+                            interface_id,
+                            mac,
+                            FrameProtocol::Ipv6,
+                            packet.to_bytes(),
+                        );
+                    }
+                    return;
+                }
+                Ok(Icmpv6Packet::RouterSolicitation { .. }) => {
+                    return self.advertise(interface_id, now)
+                }
+                Ok(Icmpv6Packet::EchoRequest {
+                    identifier,
+                    sequence,
+                    data,
+                }) if !packet.destin.is_multicast() => Icmpv6Packet::EchoReply {
+                    identifier,
+                    sequence,
+                    data,
+                },
+                _ => return,
+            },
+            NextHeader::Tcp | NextHeader::Udp if !packet.destin.is_multicast() => {
+                Icmpv6Packet::DestinationUnreachable {
+                    code: icmpv6::ICMPV6_PORT_UNREACHABLE,
+                    data: Icmpv6Packet::quote(&packet.to_bytes()),
+                }
+            }
+            _ => return,
+        };
+
+        let reply = Ipv6Packet::new(
+            packet.destin,
+            packet.source,
+            NextHeader::Icmpv6,
+            reply.to_bytes(),
+        );
+        self.reply_ipv6(interface_id, reply, now);
+    }
+
+    // `from` is the interface the packet arrived on, None for the ones the router sends itself
+    fn route_ipv6(&mut self, mut packet: Ipv6Packet, from: Option<u32>, now: Instant) {
+        if let Some(from) = from {
+            if packet.hop_limit <= 1 {
+                let interface = self.module.get_interface(from).unwrap();
+                interface.count_drop(DropReason::TtlExpired);
+                let error = Icmpv6Packet::TimeExceeded {
+                    code: icmpv6::ICMPV6_HOP_LIMIT_EXCEEDED,
+                    data: Icmpv6Packet::quote(&packet.to_bytes()),
+                };
+                return self.send_icmpv6_error(from, error, &packet, now);
+            }
+            packet.hop_limit -= 1;
+        }
+
+        let route = self
+            .ipv6_routes
+            .iter()
+            .filter(|route| route.prefix.contains(packet.destin))
+            .max_by_key(|route| route.prefix.prefix_len())
+            .copied();
+        match route {
+            Some(route) => {
+                let next_hop = route.next_hop.unwrap_or(packet.destin);
+                log::debug!(
+                    "Router {}: forwarding {} -> {} through interface {} via {next_hop}",
+                    self.address,
+                    packet.source,
+                    packet.destin,
+                    route.interface_id
+                );
+                if from.is_some() {
+                    self.module.trace_decision(
+                        TraceEvent::Forward,
+                        Some(route.interface_id),
+                        "route",
+                    );
+                }
+                self.send_ipv6_packet(route.interface_id, next_hop, packet, now)
+            }
+            None => {
+                log::debug!("Router {}: no route to {}", self.address, packet.destin);
+                if let Some(from) = from {
+                    let interface = self.module.get_interface(from).unwrap();
+                    interface.count_drop(DropReason::NoRoute);
+                    let error = Icmpv6Packet::DestinationUnreachable {
+                        code: icmpv6::ICMPV6_NO_ROUTE,
+                        data: Icmpv6Packet::quote(&packet.to_bytes()),
+                    };
+                    self.send_icmpv6_error(from, error, &packet, now);
+                }
+            }
+        }
+    }
+
+    // replies to link-local addresses can't be routed, they go back where the packet came from
+    fn reply_ipv6(&mut self, interface_id: u32, packet: Ipv6Packet, now: Instant) {
+        if packet.destin.is_unicast_link_local() {
+            self.send_ipv6_packet(interface_id, packet.destin, packet, now)
+        } else {
+            self.route_ipv6(packet, None, now)
+        }
+    }
+
+    fn send_icmpv6_error(
+        &mut self,
+        interface_id: u32,
+        error: Icmpv6Packet,
+        packet: &Ipv6Packet,
+        now: Instant,
+    ) {
+        // same as for IPv4, and nobody answers to the unspecified address
+        let is_error = packet.protocol == NextHeader::Icmpv6
+            && Icmpv6Packet::decode(&packet.data).is_ok_and(|icmp| {
+                !matches!(
+                    icmp,
+                    Icmpv6Packet::EchoRequest { .. } | Icmpv6Packet::EchoReply { .. }
+                )
+            });
+        if is_error || packet.source.is_unspecified() || packet.source.is_multicast() {
+            return;
+        }
+
+        let Some((address, _)) = self.ipv6_interfaces[interface_id as usize] else {
+            return;
+        };
+        let source = match packet.source.is_unicast_link_local() {
+            true => link_local_address(self.address),
+            false => address,
+        };
+        let error = Ipv6Packet::new(source, packet.source, NextHeader::Icmpv6, error.to_bytes());
+        self.reply_ipv6(interface_id, error, now);
+    }
+
+    fn send_ipv6_packet(
+        &mut self,
+        interface_id: u32,
+        next_hop: Ipv6Addr,
+        packet: Ipv6Packet,
+        now: Instant,
+    ) {
+        let source = link_local_address(self.address);
+        let out = self.neighbors[interface_id as usize].send(source, next_hop, packet, now);
+        if let Some((mac, packet)) = out {
+            self.send_ethernet(interface_id, mac, FrameProtocol::Ipv6, packet.to_bytes());
+        }
+    }
+
+    fn advertise(&mut self, interface_id: u32, now: Instant) {
+        let Some((_, prefix)) = self.ipv6_interfaces[interface_id as usize] else {
+            return;
+        };
+
+        let advertisement = Icmpv6Packet::RouterAdvertisement(RouterAdvertisement {
+            hop_limit: IPV6_DEFAULT_HOP_LIMIT,
+            managed: false,
+            other: false,
+            router_lifetime: ROUTER_LIFETIME,
+            reachable_time: 0,
+            retransmit_timer: 0,
+            source_mac: Some(self.address),
+            mtu: None,
+            prefixes: vec![PrefixInformation {
+                prefix,
+                on_link: true,
+                // hosts can only build addresses from /64s
+                autonomous: prefix.prefix_len() == 64,
+                valid_lifetime: PREFIX_VALID_LIFETIME,
+                preferred_lifetime: PREFIX_PREFERRED_LIFETIME,
+            }],
+        });
+        let packet = ndp_packet(link_local_address(self.address), ALL_NODES, &advertisement);
+        self.send_ipv6_packet(interface_id, ALL_NODES, packet, now);
+    }
+
+    fn poll_advertisements(&mut self, now: Instant) {
+        if self.next_advertisement.is_some_and(|next| next <= now) {
+            for interface_id in 0..self.module.get_interface_nr() {
+                self.advertise(interface_id, now);
+            }
+            self.next_advertisement = Some(now + ADVERTISEMENT_INTERVAL);
+        }
+    }
+
+    fn send_frame(&self, interface_id: u32, destin: MacAddress, packet: Ipv4Packet) {
+        self.send_ethernet(interface_id, destin, FrameProtocol::Ipv4, packet.to_bytes())
+    }
+
+    fn send_ethernet(
+        &self,
+        interface_id: u32,
+        destin: MacAddress,
+        protocol: FrameProtocol,
+        data: Box<[u8]>,
+    ) {
+        let frame = EthernetFrame {
+            source: self.address,
+            destin,
+            protocol,
+            data,
+        };
+
+        let interface = self.module.get_interface(interface_id).unwrap();
+        if let Err(err) = interface.send(frame.to_bytes()) {
+            log::debug!(
+                "Router {}: failed to send frame to interface {interface_id}: {err:?}",
+                self.address
+            );
+        }
+    }
+}
+
+impl Device for Router {
+    fn get_mac_address(&self) -> MacAddress {
+        self.address
+    }
+
+    fn get_module(&mut self) -> &mut Module {
+        &mut self.module
+    }
+
+    fn kind(&self) -> &'static str {
+        "router"
+    }
+
+    fn tables(&self) -> Vec<(&'static str, SharedTable)> {
+        vec![("routing table", self.routing_table() as SharedTable)]
+    }
+
+    fn addresses(&self) -> Vec<String> {
+        self.interfaces
+            .iter()
+            .flatten()
+            .map(|config| {
+                format!(
+                    "{}: {}/{}",
+                    config.interface_id,
+                    config.address,
+                    config.prefix.prefix_len()
+                )
+            })
+            .collect()
+    }
+
+    fn run(&mut self) {
+        log::debug!("Router {} running...", self.address);
+        self.start(Instant::now());
+
+        loop {
+            let event = match self.next_deadline() {
+                Some(deadline) => self
+                    .module
+                    .wait_for_event_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => Some(self.module.wait_for_event()),
+            };
+
+            let now = Instant::now();
+            match event {
+                Some(ModuleEvent::Msg(msg)) => self.handle_frame(msg, now),
+                Some(ModuleEvent::LinkStateChanged { interface_id, up }) => {
+                    log::info!(
+                        "Router {}: interface {interface_id} went {}",
+                        self.address,
+                        if up { "up" } else { "down" }
+                    );
+                    self.set_connected(interface_id, up);
+                    if up {
+                        self.advertise(interface_id, now);
+                    } else {
+                        self.pending
+                            .retain(|(pending_on, _), _| *pending_on != interface_id);
+                        self.neighbors[interface_id as usize].clear_pending();
+                    }
+
+                    let mut out = Vec::new();
+                    for protocol in self.protocols.iter_mut() {
+                        protocol.link_state_changed(interface_id, up, now, &mut out);
+                    }
+                    self.send_all(out);
+                    self.install_routes();
+                }
+                Some(ModuleEvent::Shutdown) => {
+                    log::debug!("Router {} shutting down...", self.address);
+                    return;
+                }
+                None => {}
+            }
+
+            self.poll_protocols(now);
+            self.poll_advertisements(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Router;
+    use crate::{
+        devices::{
+            endpoint::{
+                test::{attach, host, step},
+                IpPacket,
+            },
+            Device, WireMsg,
+        },
+        links::{self, LinkEnd},
+        protocols::{
+            arp::{ArpOperation, ArpPacket},
+            ethernet::{EthernetFrame, FrameProtocol, MacAddress},
+            icmp::{IcmpPacket, IcmpType},
+            icmpv6::Icmpv6Packet,
+            ipv4::{IpProtocol, Ipv4Packet},
+            ipv6::{Ipv6Packet, Ipv6Prefix, NextHeader, IPV6_DEFAULT_HOP_LIMIT},
+            Packet,
+        },
+        routing::{
+            ospf::{Ospf, OspfConfig},
+            rip::{Rip, RipConfig},
+            RoutingProtocol,
+        },
+    };
+    use std::{
+        net::{Ipv4Addr, Ipv6Addr},
+        sync::mpsc::{self, Receiver},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    const ROUTER_MAC: MacAddress = MacAddress::new([1; 6]);
+    const HOST_MAC: MacAddress = MacAddress::new([2; 6]);
+
+    fn connect(router: &mut Router, interface_id: u32) -> (LinkEnd, Receiver<EthernetFrame>) {
+        let (end, peer) = links::create_link();
+        let (sender, receiver) = mpsc::channel();
+        peer.attach_receiver(move |data| {
+            sender
+                .send(EthernetFrame::from_raw_bytes(&data).unwrap())
+                .unwrap()
+        })
+        .unwrap();
+        router.get_module().attach_link(interface_id, end);
+        (peer, receiver)
+    }
+
+    fn receive(router: &mut Router, interface_id: u32, frame: EthernetFrame) {
+        router.handle_frame(
+            WireMsg {
+                interface_id,
+                data: frame.to_bytes().into(),
+            },
+            Instant::now(),
+        );
+    }
+
+    fn ipv4(frame: EthernetFrame) -> Ipv4Packet {
+        assert_eq!(frame.protocol, FrameProtocol::Ipv4);
+        Ipv4Packet::decode(&frame.data).unwrap()
+    }
+
+    #[test]
+    fn forwards_and_answers() {
+        let mut router = Router::new(ROUTER_MAC, 2);
+        router.set_interface_address(0, Ipv4Addr::new(10, 0, 0, 1), 24);
+        router.set_interface_address(1, Ipv4Addr::new(10, 0, 1, 1), 24);
+        let (_left, left) = connect(&mut router, 0);
+        let (_right, right) = connect(&mut router, 1);
+        router.start(Instant::now());
+
+        let host = Ipv4Addr::new(10, 0, 0, 2);
+        let echo = IcmpPacket::echo_request(1, 1, Box::new([]));
+        let mut packet = Ipv4Packet::new(
+            host,
+            Ipv4Addr::new(10, 0, 1, 2),
+            IpProtocol::Icmp,
+            echo.to_bytes(),
+        );
+        packet.ttl = 2;
+        let frame = EthernetFrame {
+            source: HOST_MAC,
+            destin: ROUTER_MAC,
+            protocol: FrameProtocol::Ipv4,
+            data: packet.to_bytes(),
+        };
+        receive(&mut router, 0, frame.clone());
+
+        // the next hop has to be resolved first
+        let request = right.recv_timeout(Duration::from_secs(1)).unwrap();
+        let request = ArpPacket::decode(&request.data).unwrap();
+        assert_eq!(request.operation, ArpOperation::Request);
+        assert_eq!(request.target_ip, Ipv4Addr::new(10, 0, 1, 2));
+
+        let reply = request.reply_to(MacAddress::new([3; 6]));
+        receive(
+            &mut router,
+            1,
+            EthernetFrame {
+                source: reply.sender_mac,
+                destin: ROUTER_MAC,
+                protocol: FrameProtocol::Apr,
+                data: reply.to_bytes(),
+            },
+        );
+        let forwarded = right.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(forwarded.destin, MacAddress::new([3; 6]));
+        assert_eq!(ipv4(forwarded).ttl, 1);
+
+        // expired packets are reported back to their source, whose address is resolved first
+        let mut expired = packet.clone();
+        expired.ttl = 1;
+        receive(
+            &mut router,
+            0,
+            EthernetFrame {
+                data: expired.to_bytes(),
+                ..frame
+            },
+        );
+        let request =
+            ArpPacket::decode(&left.recv_timeout(Duration::from_secs(1)).unwrap().data).unwrap();
+        let reply = request.reply_to(HOST_MAC);
+        receive(
+            &mut router,
+            0,
+            EthernetFrame {
+                source: HOST_MAC,
+                destin: ROUTER_MAC,
+                protocol: FrameProtocol::Apr,
+                data: reply.to_bytes(),
+            },
+        );
+
+        let error = ipv4(left.recv_timeout(Duration::from_secs(1)).unwrap());
+        assert_eq!(error.destin, host);
+        assert_eq!(error.source, Ipv4Addr::new(10, 0, 0, 1));
+        let error = IcmpPacket::decode(&error.data).unwrap();
+        assert_eq!(error.message_type, IcmpType::TimeExceeded);
+
+        // and there is no route to anything else
+        let mut lost = packet;
+        lost.destin = Ipv4Addr::new(8, 8, 8, 8);
+        receive(
+            &mut router,
+            0,
+            EthernetFrame {
+                source: HOST_MAC,
+                destin: ROUTER_MAC,
+                protocol: FrameProtocol::Ipv4,
+                data: lost.to_bytes(),
+            },
+        );
+        let error =
+            IcmpPacket::decode(&ipv4(left.recv_timeout(Duration::from_secs(1)).unwrap()).data)
+                .unwrap();
+        assert_eq!(error.message_type, IcmpType::DestinationUnreachable);
+    }
+
+    #[test]
+    fn connects_interfaces_once_up() {
+        let mut router = Router::new(ROUTER_MAC, 2);
+        router.set_interface_address(0, Ipv4Addr::new(10, 0, 0, 1), 24);
+        router.set_interface_address(1, Ipv4Addr::new(10, 0, 1, 1), 24);
+        let _left = connect(&mut router, 0);
+        let table = router.routing_table();
+        let handle = router.module.handle();
+        let thread = thread::spawn(move || router.run());
+
+        let connected = |address| {
+            let started = Instant::now();
+            while table.lock().unwrap().lookup(address).is_none() {
+                assert!(started.elapsed() < Duration::from_secs(5));
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+        connected(Ipv4Addr::new(10, 0, 0, 2));
+        // interface 1 has no link yet
+        assert!(table
+            .lock()
+            .unwrap()
+            .lookup(Ipv4Addr::new(10, 0, 1, 2))
+            .is_none());
+
+        let (end, peer) = links::create_link();
+        peer.attach_receiver(|_| {}).unwrap();
+        handle.attach_link(1, end);
+        connected(Ipv4Addr::new(10, 0, 1, 2));
+
+        handle.shutdown();
+        thread.join().unwrap();
+    }
+
+    // r0 -- r1 -- r2, every router with a stub network on interface 2
+    fn chain_converges<P: RoutingProtocol + 'static>(protocol: impl Fn() -> P) {
+        let mut routers: Vec<_> = (0..3u8)
+            .map(|i| {
+                let mut router = Router::new(MacAddress::new([i + 1; 6]), 3);
+                router.set_interface_address(2, Ipv4Addr::new(192, 168, i, 1), 24);
+                router.add_protocol(protocol());
+                router
+            })
+            .collect();
+        let source = routers[0].protocols[0].source();
+
+        for i in 0..2 {
+            routers[i].set_interface_address(1, Ipv4Addr::new(10, 0, i as u8, 1), 24);
+            routers[i + 1].set_interface_address(0, Ipv4Addr::new(10, 0, i as u8, 2), 24);
+            let (left, right) = links::create_link();
+            routers[i].get_module().attach_link(1, left);
+            routers[i + 1].get_module().attach_link(0, right);
+        }
+        // the stubs are only connected while their link is up
+        let _stubs: Vec<_> = routers
+            .iter_mut()
+            .map(|router| connect(router, 2))
+            .collect();
+
+        let table = routers[0].routing_table();
+        let handles: Vec<_> = routers
+            .iter()
+            .map(|router| router.module.handle())
+            .collect();
+        let threads: Vec<_> = routers
+            .into_iter()
+            .map(|mut router| thread::spawn(move || router.run()))
+            .collect();
+
+        let stub = Ipv4Addr::new(192, 168, 2, 1);
+        let started = Instant::now();
+        let route = loop {
+            if let Some(route) = table.lock().unwrap().lookup(stub).cloned() {
+                break route;
+            }
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "{source:?} didn't converge"
+            );
+            thread::sleep(Duration::from_millis(10));
+        };
+
+        assert_eq!(route.source, source);
+        assert_eq!(route.metric, 3);
+        assert_eq!(route.next_hop, Some(Ipv4Addr::new(10, 0, 0, 2)));
+
+        handles.iter().for_each(|handle| handle.shutdown());
+        threads
+            .into_iter()
+            .for_each(|thread| thread.join().unwrap());
+    }
+
+    #[test]
+    fn rip_converges_between_running_routers() {
+        chain_converges(|| {
+            Rip::new(RipConfig {
+                update_interval: Duration::from_millis(100),
+                triggered_update_delay: Duration::from_millis(10),
+                ..RipConfig::default()
+            })
+        });
+    }
+
+    #[test]
+    fn ospf_converges_between_running_routers() {
+        chain_converges(|| {
+            Ospf::new(OspfConfig {
+                spf_delay: Duration::from_millis(10),
+                ..OspfConfig::default()
+            })
+        });
+    }
+
+    fn echo_request(source: Ipv6Addr, destin: Ipv6Addr) -> Ipv6Packet {
+        let echo = Icmpv6Packet::EchoRequest {
+            identifier: 1,
+            sequence: 1,
+            data: Box::new([1, 2, 3]),
+        };
+        Ipv6Packet::new(source, destin, NextHeader::Icmpv6, echo.to_bytes())
+    }
+
+    #[test]
+    fn forwards_ipv6_between_autoconfigured_hosts() {
+        let prefixes: [Ipv6Prefix; 2] = [
+            "2001:db8:0::/64".parse().unwrap(),
+            "2001:db8:1::/64".parse().unwrap(),
+        ];
+        let mut router = Router::new(ROUTER_MAC, 2);
+        for (i, prefix) in prefixes.iter().enumerate() {
+            router.set_interface_ipv6_address(i as u32, prefix.with_interface_id(ROUTER_MAC), 64);
+            // dual-stack
+            router.set_interface_address(i as u32, Ipv4Addr::new(10, 0, i as u8, 1), 24);
+        }
+        let mut hosts = [2, 3].map(|mac| host(MacAddress::new([mac; 6])));
+        attach(&mut hosts, router.get_module());
+        let handle = router.get_module().handle();
+        let thread = thread::spawn(move || router.run());
+
+        let started = Instant::now();
+        for (module, endpoint) in hosts.iter_mut() {
+            endpoint.enable_ipv6(module.get_interface(0).unwrap(), started);
+        }
+        let addresses: Vec<_> = hosts
+            .iter()
+            .zip(prefixes)
+            .map(|((_, endpoint), prefix)| prefix.with_interface_id(endpoint.mac()))
+            .collect();
+        while !hosts
+            .iter()
+            .zip(&addresses)
+            .all(|((_, endpoint), address)| endpoint.ipv6_addresses().contains(address))
+        {
+            assert!(started.elapsed() < Duration::from_secs(5), "no addresses");
+            step(&mut hosts);
+        }
+
+        // the neighbors are solicited on both sides of the router
+        let (module, endpoint) = &mut hosts[0];
+        let request = echo_request(addresses[0], addresses[1]);
+        assert!(endpoint.send_ipv6(module.get_interface(0).unwrap(), request, Instant::now()));
+        let (receiver, forwarded) = loop {
+            assert!(started.elapsed() < Duration::from_secs(5), "not forwarded");
+            if let Some((i, IpPacket::V6(packet))) = step(&mut hosts).pop() {
+                break (i, packet);
+            }
+        };
+        assert_eq!(receiver, 1);
+        assert_eq!(forwarded.source, addresses[0]);
+        assert_eq!(forwarded.hop_limit, IPV6_DEFAULT_HOP_LIMIT - 1);
+
+        // expired packets are reported by the router
+        let (module, endpoint) = &mut hosts[0];
+        let mut expired = echo_request(addresses[0], addresses[1]);
+        expired.hop_limit = 1;
+        endpoint.send_ipv6(module.get_interface(0).unwrap(), expired, Instant::now());
+        let (receiver, error) = loop {
+            assert!(started.elapsed() < Duration::from_secs(5), "no error");
+            if let Some((i, IpPacket::V6(packet))) = step(&mut hosts).pop() {
+                break (i, packet);
+            }
+        };
+        assert_eq!(receiver, 0);
+        assert_eq!(error.source, prefixes[0].with_interface_id(ROUTER_MAC));
+        assert!(matches!(
+            Icmpv6Packet::decode(&error.data).unwrap(),
+            Icmpv6Packet::TimeExceeded { .. }
+        ));
+
+        handle.shutdown();
+        thread.join().unwrap();
+    }
+}
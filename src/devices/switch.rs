@@ -1,12 +1,48 @@
-use super::{Device, Module};
-use crate::protocols::ethernet::{self, EthernetFrame, MacAddress};
-use std::collections::HashMap;
+use super::{Device, DropReason, Module, ModuleEvent, SharedTable, WireMsg};
+use crate::{
+    protocols::{
+        dissector,
+        ethernet::{self, EthernetFrameRef, MacAddress},
+    },
+    trace::TraceEvent,
+};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    sync::{Arc, Mutex},
+};
+
+// the counters of the switch in its DeviceStats
+pub const LEARNS: &str = "learns";
+pub const FLOODS: &str = "floods";
+// the floods of unicast frames, their destination just wasn't learned yet
+pub const UNKNOWN_UNICAST: &str = "unknown_unicast";
+// the floods of multicast frames other than broadcasts, e.g. IPv6 neighbor discovery
+pub const MULTICAST: &str = "multicast";
+
+// The interface each address was last seen on.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LearnTable {
+    // TODO: limit the size of this and start using an array
+    pub entries: HashMap<MacAddress, u32>,
+}
+
+impl Display for LearnTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+        for (mac, interface_id) in entries {
+            writeln!(f, "{mac}  interface {interface_id}")?;
+        }
+        Ok(())
+    }
+}
 
 pub struct Layer2Switch {
     address: MacAddress,
     module: Module,
-    // TODO: limit the size of this and start using an array
-    learn_table: HashMap<MacAddress, u32>,
+    // shared so it can be looked at while the switch runs
+    learn_table: Arc<Mutex<LearnTable>>,
 }
 
 impl Layer2Switch {
@@ -14,7 +50,84 @@ impl Layer2Switch {
         Self {
             address,
             module: Module::new(interface_nr),
-            learn_table: HashMap::new(),
+            learn_table: Arc::new(Mutex::new(LearnTable::default())),
+        }
+    }
+
+    pub fn learn_table(&self) -> Arc<Mutex<LearnTable>> {
+        Arc::clone(&self.learn_table)
+    }
+
+    fn forward(&mut self, msg: WireMsg) {
+        let frame = match EthernetFrameRef::new(&msg.data) {
+            Ok(frame) => frame,
+            Err(err) => {
+                log::error!(
+                    "Layer2Switch {}: parsing ethernet frame from interface {}: {err:?}",
+                    self.address,
+                    msg.interface_id
+                );
+                let interface = self.module.get_interface(msg.interface_id).unwrap();
+                interface.count_parse_error();
+                return;
+            }
+        };
+
+        log::debug!(
+            "received ethernet frame {}",
+            dissector::dissect(&msg.data).summary()
+        );
+
+        let mut learn_table = self.learn_table.lock().unwrap();
+        if frame.source() != ethernet::ETHERNET_BROADCAST_MAC_ADDR
+            && learn_table.entries.insert(frame.source(), msg.interface_id)
+                != Some(msg.interface_id)
+        {
+            self.module.count(LEARNS);
+        }
+        let learned = learn_table.entries.get(&frame.destin()).copied();
+        drop(learn_table);
+
+        match learned {
+            Some(interface_id) if msg.interface_id != interface_id => {
+                log::debug!("Sending frame to interface {interface_id}");
+                self.module
+                    .trace_decision(TraceEvent::Forward, Some(interface_id), "learned");
+                if let Err(err) = self
+                    .module
+                    .get_interface(interface_id)
+                    .unwrap()
+                    .send(msg.data.clone())
+                {
+                    log::debug!("Failed to send frame to interface {interface_id}: {err:?}");
+                }
+            }
+            None => {
+                log::debug!("Broadcasting frame...");
+                self.module.count(FLOODS);
+                let destin = frame.destin();
+                let reason = if destin == ethernet::ETHERNET_BROADCAST_MAC_ADDR {
+                    "broadcast"
+                } else if destin.is_multicast() {
+                    self.module.count(MULTICAST);
+                    "multicast"
+                } else {
+                    self.module.count(UNKNOWN_UNICAST);
+                    "unknown unicast"
+                };
+                self.module.trace_decision(TraceEvent::Flood, None, reason);
+                self.module.interfaces().for_each(|interface| {
+                    if interface.interface_id != msg.interface_id && interface.is_up() {
+                        // the link might still go down in the meantime
+                        let _ = interface.send(msg.data.clone());
+                    }
+                });
+            }
+            _ => {
+                log::warn!("Dropping frame: {frame:?}");
+                let interface = self.module.get_interface(msg.interface_id).unwrap();
+                interface.count_drop(DropReason::SameInterface);
+            }
         }
     }
 }
@@ -28,49 +141,124 @@ impl Device for Layer2Switch {
         &mut self.module
     }
 
+    fn kind(&self) -> &'static str {
+        "switch"
+    }
+
+    fn tables(&self) -> Vec<(&'static str, SharedTable)> {
+        vec![("learning table", self.learn_table() as SharedTable)]
+    }
+
     fn run(&mut self) {
         log::debug!("Layer2Switch {} running...", self.address);
         loop {
-            let msg = self.module.wait_for_msg();
-            // TODO: Do not parse the frame just check the first 6 bytes c:
-            let frame = match EthernetFrame::from_raw_bytes(msg.data.as_ref()) {
-                Ok(frame) => frame,
-                Err(err) => {
-                    log::error!(
-                        "Layer2Switch {}: parsing ethernet frame from interface {}: {err:?}",
+            let msg = match self.module.wait_for_event() {
+                ModuleEvent::Msg(msg) => msg,
+                ModuleEvent::LinkStateChanged { interface_id, up } => {
+                    log::info!(
+                        "Layer2Switch {}: interface {interface_id} went {}",
                         self.address,
-                        msg.interface_id
+                        if up { "up" } else { "down" }
                     );
+                    if !up {
+                        // whatever was learned there is no longer reachable through it
+                        self.learn_table
+                            .lock()
+                            .unwrap()
+                            .entries
+                            .retain(|_, learned| *learned != interface_id);
+                    }
                     continue;
                 }
+                ModuleEvent::Shutdown => {
+                    log::debug!("Layer2Switch {} shutting down...", self.address);
+                    return;
+                }
             };
+            self.forward(msg);
+        }
+    }
+}
 
-            log::debug!("received ethernet frame {frame:?}");
+#[cfg(test)]
+mod test {
+    use super::{Layer2Switch, FLOODS, LEARNS, MULTICAST, UNKNOWN_UNICAST};
+    use crate::{
+        devices::{Device, DropReason, WireMsg},
+        links::{self, LinkData, LinkEnd},
+        protocols::{
+            ethernet::{EthernetFrame, FrameProtocol, MacAddress, ETHERNET_BROADCAST_MAC_ADDR},
+            Packet,
+        },
+    };
+    use test::Bencher;
 
-            if frame.source != ethernet::ETHERNET_BROADCAST_MAC_ADDR {
-                self.learn_table.insert(frame.source, msg.interface_id);
-            }
+    const PORTS: u32 = 48;
 
-            match self.learn_table.get(&frame.destin).copied() {
-                Some(interface_id) if msg.interface_id != interface_id => {
-                    log::debug!("Sending frame to interface {interface_id}");
-                    self.module
-                        .get_interface(interface_id)
-                        .unwrap()
-                        .send(msg.data.as_ref());
-                }
-                None => {
-                    log::debug!("Broadcasting frame...");
-                    self.module.interfaces().for_each(|interface| {
-                        if interface.interface_id != msg.interface_id && interface.is_up() {
-                            interface.send(&msg.data)
-                        }
-                    });
-                }
-                _ => {
-                    log::warn!("Dropping frame: {frame:?}")
-                }
-            }
-        }
+    // a switch whose every port leads to a receiver that just drops whatever it gets
+    fn switch_with_sinks() -> (Layer2Switch, Vec<LinkEnd>) {
+        let mut switch = Layer2Switch::new(MacAddress::new([1; 6]), PORTS);
+        let peers = (0..PORTS)
+            .map(|interface_id| {
+                let (end, peer) = links::create_link();
+                peer.attach_receiver(|_| {}).unwrap();
+                switch.get_module().attach_link(interface_id, end);
+                peer
+            })
+            .collect();
+        (switch, peers)
+    }
+
+    fn frame(source: u8, destin: MacAddress) -> LinkData {
+        let frame = EthernetFrame {
+            source: MacAddress::new([source; 6]),
+            destin,
+            protocol: FrameProtocol::Ipv4,
+            data: Box::new([0; 46]),
+        };
+        LinkData::from(frame.to_bytes())
+    }
+
+    #[test]
+    fn counts_learns_and_floods() {
+        let (mut switch, _peers) = switch_with_sinks();
+        let handle = switch.get_module().handle();
+        let mut forward = |data, interface_id| switch.forward(WireMsg { data, interface_id });
+
+        forward(frame(2, ETHERNET_BROADCAST_MAC_ADDR), 0);
+        forward(frame(3, MacAddress::new([2; 6])), 1);
+        forward(frame(2, MacAddress::new([4; 6])), 0);
+        // an IPv6 all-nodes frame is flooded without being unknown unicast
+        forward(frame(2, MacAddress::new([0x33, 0x33, 0, 0, 0, 1])), 0);
+        // 3 moved, and 2 is where it came from
+        forward(frame(3, MacAddress::new([2; 6])), 0);
+
+        let stats = handle.stats();
+        assert_eq!(stats.counters[LEARNS], 3);
+        assert_eq!(stats.counters[FLOODS], 3);
+        assert_eq!(stats.counters[UNKNOWN_UNICAST], 1);
+        assert_eq!(stats.counters[MULTICAST], 1);
+        assert_eq!(stats.interfaces[0].drops[&DropReason::SameInterface], 1);
+        assert_eq!(stats.interfaces[0].tx_frames, 1);
+        assert_eq!(stats.interfaces[1].tx_frames, 3);
+    }
+
+    #[bench]
+    fn flood_to_all_ports(b: &mut Bencher) {
+        let (mut switch, _peers) = switch_with_sinks();
+        let frame = EthernetFrame {
+            source: MacAddress::new([2; 6]),
+            destin: ETHERNET_BROADCAST_MAC_ADDR,
+            protocol: FrameProtocol::Ipv4,
+            data: Box::new([0; 1500]),
+        };
+        let data = LinkData::from(frame.to_bytes());
+
+        b.iter(|| {
+            switch.forward(WireMsg {
+                data: data.clone(),
+                interface_id: 0,
+            })
+        });
     }
 }
@@ -0,0 +1,303 @@
+use super::host::{AddressConfig, Host, NetStack, ProbeProtocol, ProbeReply, SocketError};
+use crate::protocols::{ethernet::MacAddress, icmp, icmp::IcmpType};
+use std::{
+    fmt::{self, Display},
+    net::Ipv4Addr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TracerouteConfig {
+    pub protocol: ProbeProtocol,
+    pub max_hops: u8,
+    pub probes_per_hop: usize,
+    // how long to wait for each probe, it shows up as `*` after that
+    pub timeout: Duration,
+}
+
+impl Default for TracerouteConfig {
+    fn default() -> Self {
+        Self {
+            protocol: ProbeProtocol::Udp,
+            max_hops: 30,
+            probes_per_hop: 3,
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hop {
+    pub ttl: u8,
+    // None for the probes nothing answered
+    pub replies: Vec<Option<ProbeReply>>,
+}
+
+impl Hop {
+    // whether the probes made it to the destination, or couldn't go any further
+    pub fn is_last(&self) -> bool {
+        self.replies
+            .iter()
+            .flatten()
+            .any(|reply| reply.message_type != IcmpType::TimeExceeded)
+    }
+}
+
+// how traceroute flags the probes that were stopped before getting to the destination
+fn annotation(reply: &ProbeReply) -> Option<String> {
+    if reply.message_type != IcmpType::DestinationUnreachable {
+        return None;
+    }
+    Some(match reply.code {
+        // what UDP probes get from the destination
+        icmp::ICMP_PORT_UNREACHABLE => return None,
+        icmp::ICMP_NET_UNREACHABLE => "!N".into(),
+        icmp::ICMP_HOST_UNREACHABLE => "!H".into(),
+        icmp::ICMP_PROTOCOL_UNREACHABLE => "!P".into(),
+        icmp::ICMP_ADMIN_PROHIBITED => "!X".into(),
+        code => format!("!<{code}>"),
+    })
+}
+
+// like traceroute prints it: the address again whenever another router answers
+impl Display for Hop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>2} ", self.ttl)?;
+        let mut last_from = None;
+        for reply in &self.replies {
+            let Some(reply) = reply else {
+                write!(f, " *")?;
+                continue;
+            };
+            if last_from != Some(reply.from) {
+                write!(f, " {}", reply.from)?;
+                last_from = Some(reply.from);
+            }
+            write!(f, "  {:.3} ms", reply.rtt.as_secs_f64() * 1000.0)?;
+            if let Some(annotation) = annotation(reply) {
+                write!(f, " {annotation}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Finds the routers on the way to a destination by sending probes that live one hop longer each
+// time. The hops are shared so they can be read while it runs.
+pub struct Traceroute {
+    destin: Ipv4Addr,
+    config: TracerouteConfig,
+    hops: Arc<Mutex<Vec<Hop>>>,
+}
+
+impl Traceroute {
+    pub fn new(destin: Ipv4Addr, config: TracerouteConfig) -> Self {
+        Self {
+            destin,
+            config,
+            hops: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn hops(&self) -> Arc<Mutex<Vec<Hop>>> {
+        Arc::clone(&self.hops)
+    }
+
+    pub fn run(&mut self, stack: &mut NetStack) -> Result<(), SocketError> {
+        stack.wait_for_address(None)?;
+        log::info!(
+            "Host {}: traceroute to {}, {} hops max",
+            stack.mac(),
+            self.destin,
+            self.config.max_hops
+        );
+        for ttl in 1..=self.config.max_hops {
+            let replies = (0..self.config.probes_per_hop)
+                .map(|_| stack.probe(self.destin, self.config.protocol, ttl, self.config.timeout))
+                .collect::<Result<_, _>>()?;
+            let hop = Hop { ttl, replies };
+            log::info!("Host {}: {hop}", stack.mac());
+
+            let is_last = hop.is_last();
+            self.hops.lock().unwrap().push(hop);
+            if is_last {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn into_host(mut self, address: MacAddress, config: AddressConfig) -> Host {
+        Host::with_program(address, config, move |stack| {
+            if let Err(err) = self.run(stack) {
+                log::warn!("Host {address}: traceroute stopped: {err:?}");
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Hop, Traceroute, TracerouteConfig};
+    use crate::{
+        devices::{
+            firewall::Firewall,
+            host::{AddressConfig, Host, ProbeProtocol},
+            router::Router,
+            Device,
+        },
+        firewall::rules::RuleSet,
+        links,
+        protocols::{ethernet::MacAddress, icmp::IcmpType},
+    };
+    use std::{net::Ipv4Addr, thread, time::Duration};
+
+    const SOURCE: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+    const DESTIN: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 2);
+
+    // source -- r0 -- r1 -- firewall -- destin, returns the hops the source found
+    fn trace(config: TracerouteConfig, rules: &str) -> Vec<Hop> {
+        let traceroute = Traceroute::new(DESTIN, config);
+        let hops = traceroute.hops();
+        let mut source = traceroute.into_host(
+            MacAddress::new([1; 6]),
+            AddressConfig::Static {
+                address: SOURCE,
+                prefix_len: 24,
+                gateway: Some(Ipv4Addr::new(10, 0, 0, 1)),
+            },
+        );
+        let mut destin = Host::new(
+            MacAddress::new([2; 6]),
+            AddressConfig::Static {
+                address: DESTIN,
+                prefix_len: 24,
+                gateway: Some(Ipv4Addr::new(10, 0, 2, 1)),
+            },
+        );
+        let mut r0 = Router::new(MacAddress::new([3; 6]), 2);
+        r0.set_interface_address(0, Ipv4Addr::new(10, 0, 0, 1), 24);
+        r0.set_interface_address(1, Ipv4Addr::new(10, 0, 1, 1), 24);
+        r0.add_static_route(
+            "10.0.2.0/24".parse().unwrap(),
+            1,
+            Ipv4Addr::new(10, 0, 1, 2),
+        );
+        let mut r1 = Router::new(MacAddress::new([4; 6]), 2);
+        r1.set_interface_address(0, Ipv4Addr::new(10, 0, 1, 2), 24);
+        r1.set_interface_address(1, Ipv4Addr::new(10, 0, 2, 1), 24);
+        r1.add_static_route(
+            "10.0.0.0/24".parse().unwrap(),
+            0,
+            Ipv4Addr::new(10, 0, 1, 1),
+        );
+        let mut firewall =
+            Firewall::new(MacAddress::new([5; 6]), 2, RuleSet::parse(rules).unwrap());
+
+        connect(&mut source, 0, &mut r0, 0);
+        connect(&mut r0, 1, &mut r1, 0);
+        connect(&mut r1, 1, &mut firewall, 0);
+        connect(&mut firewall, 1, &mut destin, 0);
+
+        let mut devices: Vec<Box<dyn Device + Send>> = vec![
+            Box::new(source),
+            Box::new(destin),
+            Box::new(r0),
+            Box::new(r1),
+            Box::new(firewall),
+        ];
+        let handles: Vec<_> = devices
+            .iter_mut()
+            .map(|device| device.get_module().handle())
+            .collect();
+        let threads: Vec<_> = devices
+            .into_iter()
+            .map(|mut device| thread::spawn(move || device.run()))
+            .collect();
+
+        let max_hops = config.max_hops as usize;
+        let done = || {
+            let hops = hops.lock().unwrap();
+            hops.len() == max_hops || hops.last().is_some_and(|hop| hop.is_last())
+        };
+        for _ in 0..500 {
+            if done() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        for handle in handles {
+            handle.shutdown();
+        }
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let hops = hops.lock().unwrap().clone();
+        hops
+    }
+
+    fn connect(left: &mut dyn Device, left_id: u32, right: &mut dyn Device, right_id: u32) {
+        let (left_end, right_end) = links::create_link();
+        left.get_module().attach_link(left_id, left_end);
+        right.get_module().attach_link(right_id, right_end);
+    }
+
+    // the addresses that answered each hop, if any
+    fn addresses(hops: &[Hop]) -> Vec<Option<Ipv4Addr>> {
+        hops.iter()
+            .map(|hop| hop.replies[0].map(|reply| reply.from))
+            .collect()
+    }
+
+    #[test]
+    fn finds_the_routers_on_the_way() {
+        let config = TracerouteConfig {
+            max_hops: 5,
+            probes_per_hop: 2,
+            timeout: Duration::from_millis(500),
+            ..TracerouteConfig::default()
+        };
+        let hops = trace(config, "default accept");
+
+        assert_eq!(
+            addresses(&hops),
+            [
+                Some(Ipv4Addr::new(10, 0, 0, 1)),
+                Some(Ipv4Addr::new(10, 0, 1, 2)),
+                Some(DESTIN)
+            ]
+        );
+        assert!(hops
+            .iter()
+            .all(|hop| hop.replies.iter().all(Option::is_some)));
+        assert_eq!(
+            hops[2].replies[0].unwrap().message_type,
+            IcmpType::DestinationUnreachable
+        );
+        assert!(hops[2].to_string().starts_with(" 3  10.0.2.2  "));
+    }
+
+    #[test]
+    fn shows_dropped_probes() {
+        let config = TracerouteConfig {
+            protocol: ProbeProtocol::Icmp,
+            max_hops: 4,
+            probes_per_hop: 1,
+            timeout: Duration::from_millis(200),
+        };
+        let hops = trace(config, "default drop");
+
+        assert_eq!(
+            addresses(&hops),
+            [
+                Some(Ipv4Addr::new(10, 0, 0, 1)),
+                Some(Ipv4Addr::new(10, 0, 1, 2)),
+                None,
+                None
+            ]
+        );
+        assert_eq!(hops[3].to_string(), " 4  *");
+    }
+}
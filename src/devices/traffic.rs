@@ -0,0 +1,275 @@
+use super::host::{AddressConfig, Host, NetStack, SocketError};
+use crate::{
+    protocols::ethernet::MacAddress,
+    traffic::{self, Generator, Pattern, SinkStats, SourceStats},
+};
+use std::{
+    net::SocketAddrV4,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// what a bulk sender writes at once
+const BULK_CHUNK_SIZE: usize = 64 * 1024;
+
+// Sends UDP traffic following a pattern for a while. The statistics are shared so they can be
+// read while the host runs.
+pub struct UdpSource {
+    destin: SocketAddrV4,
+    duration: Duration,
+    generator: Generator,
+    stats: Arc<Mutex<SourceStats>>,
+}
+
+impl UdpSource {
+    pub fn new(destin: SocketAddrV4, pattern: Pattern, duration: Duration) -> Self {
+        Self::with_seed(destin, pattern, duration, 1)
+    }
+
+    pub fn with_seed(
+        destin: SocketAddrV4,
+        pattern: Pattern,
+        duration: Duration,
+        seed: u64,
+    ) -> Self {
+        Self {
+            destin,
+            duration,
+            generator: Generator::new(pattern, seed),
+            stats: Arc::new(Mutex::new(SourceStats::default())),
+        }
+    }
+
+    pub fn stats(&self) -> Arc<Mutex<SourceStats>> {
+        Arc::clone(&self.stats)
+    }
+
+    pub fn run(&mut self, stack: &mut NetStack) -> Result<(), SocketError> {
+        stack.wait_for_address(None)?;
+        let socket = stack.udp_bind(0)?;
+        let packet_size = self.generator.pattern().packet_size();
+
+        let start = Instant::now();
+        let end = start + self.duration;
+        let mut next = start;
+        for sequence in 0.. {
+            if next >= end {
+                break;
+            }
+            stack.sleep(next.saturating_duration_since(Instant::now()))?;
+
+            let data = traffic::encode_probe(sequence, Instant::now(), packet_size);
+            stack.send_to(&socket, &data, self.destin)?;
+            let mut stats = self.stats.lock().unwrap();
+            stats.packets += 1;
+            stats.bytes += data.len() as u64;
+            drop(stats);
+
+            next += self.generator.next_gap();
+        }
+        stack.udp_close(socket);
+        Ok(())
+    }
+
+    pub fn into_host(mut self, address: MacAddress, config: AddressConfig) -> Host {
+        Host::with_program(address, config, move |stack| {
+            if let Err(err) = self.run(stack) {
+                log::warn!("Host {address}: traffic source stopped: {err:?}");
+            }
+        })
+    }
+}
+
+// Receives the traffic of UdpSources on a port.
+pub struct UdpSink {
+    port: u16,
+    stats: Arc<Mutex<SinkStats>>,
+}
+
+impl UdpSink {
+    pub fn new(port: u16) -> Self {
+        Self {
+            port,
+            stats: Arc::new(Mutex::new(SinkStats::default())),
+        }
+    }
+
+    pub fn stats(&self) -> Arc<Mutex<SinkStats>> {
+        Arc::clone(&self.stats)
+    }
+
+    // runs until the host shuts down
+    pub fn run(&mut self, stack: &mut NetStack) -> Result<(), SocketError> {
+        let socket = stack.udp_bind(self.port)?;
+        loop {
+            let (data, _) = stack.recv_from(&socket, None)?;
+            self.stats.lock().unwrap().record(&data, Instant::now());
+        }
+    }
+
+    pub fn into_host(mut self, address: MacAddress, config: AddressConfig) -> Host {
+        Host::with_program(address, config, move |stack| {
+            let _ = self.run(stack);
+        })
+    }
+}
+
+// Sends that many bytes over a TCP connection as fast as it goes.
+pub struct BulkSender {
+    destin: SocketAddrV4,
+    bytes: usize,
+    stats: Arc<Mutex<SourceStats>>,
+}
+
+impl BulkSender {
+    pub fn new(destin: SocketAddrV4, bytes: usize) -> Self {
+        Self {
+            destin,
+            bytes,
+            stats: Arc::new(Mutex::new(SourceStats::default())),
+        }
+    }
+
+    pub fn stats(&self) -> Arc<Mutex<SourceStats>> {
+        Arc::clone(&self.stats)
+    }
+
+    pub fn run(&mut self, stack: &mut NetStack) -> Result<(), SocketError> {
+        stack.wait_for_address(None)?;
+        let stream = stack.tcp_connect(self.destin, Some(Duration::from_secs(10)))?;
+
+        let chunk = [0; BULK_CHUNK_SIZE];
+        let mut left = self.bytes;
+        while left > 0 {
+            let len = left.min(BULK_CHUNK_SIZE);
+            stack.tcp_send(&stream, &chunk[..len])?;
+            // no point in buffering everything at once
+            stack.tcp_flush(&stream, None)?;
+            left -= len;
+
+            let mut stats = self.stats.lock().unwrap();
+            stats.packets += 1;
+            stats.bytes += len as u64;
+        }
+        stack.tcp_close(stream);
+        Ok(())
+    }
+
+    pub fn into_host(mut self, address: MacAddress, config: AddressConfig) -> Host {
+        Host::with_program(address, config, move |stack| {
+            if let Err(err) = self.run(stack) {
+                log::warn!("Host {address}: bulk transfer stopped: {err:?}");
+            }
+        })
+    }
+}
+
+// Accepts the connections of BulkSenders one after the other, counting what they send.
+pub struct BulkSink {
+    port: u16,
+    stats: Arc<Mutex<SinkStats>>,
+}
+
+impl BulkSink {
+    pub fn new(port: u16) -> Self {
+        Self {
+            port,
+            stats: Arc::new(Mutex::new(SinkStats::default())),
+        }
+    }
+
+    pub fn stats(&self) -> Arc<Mutex<SinkStats>> {
+        Arc::clone(&self.stats)
+    }
+
+    // runs until the host shuts down
+    pub fn run(&mut self, stack: &mut NetStack) -> Result<(), SocketError> {
+        let listener = stack.tcp_listen(self.port)?;
+        loop {
+            let stream = stack.tcp_accept(&listener, None)?;
+            loop {
+                match stack.tcp_recv(&stream, None) {
+                    Ok(data) if data.is_empty() => break,
+                    Ok(data) => {
+                        let mut stats = self.stats.lock().unwrap();
+                        stats.record_bytes(data.len(), Instant::now());
+                    }
+                    Err(SocketError::ConnectionReset) => break,
+                    Err(err) => return Err(err),
+                }
+            }
+            stack.tcp_close(stream);
+        }
+    }
+
+    pub fn into_host(mut self, address: MacAddress, config: AddressConfig) -> Host {
+        Host::with_program(address, config, move |stack| {
+            let _ = self.run(stack);
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BulkSender, BulkSink, UdpSink, UdpSource};
+    use crate::{
+        devices::host::test::{run_pair, static_config},
+        protocols::ethernet::MacAddress,
+        traffic::Pattern,
+    };
+    use std::{
+        net::{Ipv4Addr, SocketAddrV4},
+        time::Duration,
+    };
+
+    const SOURCE: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const SINK: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+    #[test]
+    fn constant_bit_rate() {
+        let pattern = Pattern::Cbr {
+            rate: 1_000_000,
+            packet_size: 500,
+        };
+        let source = UdpSource::new(
+            SocketAddrV4::new(SINK, 9000),
+            pattern,
+            Duration::from_millis(200),
+        );
+        let sink = UdpSink::new(9000);
+        let (sent, received) = (source.stats(), sink.stats());
+
+        let hosts = [
+            source.into_host(MacAddress::new([1; 6]), static_config(SOURCE)),
+            sink.into_host(MacAddress::new([2; 6]), static_config(SINK)),
+        ];
+        run_pair(hosts, || {
+            let sent = sent.lock().unwrap().packets;
+            sent == 50 && received.lock().unwrap().packets == sent
+        });
+
+        let received = *received.lock().unwrap();
+        assert_eq!(sent.lock().unwrap().packets, 50);
+        assert_eq!(received.packets, 50);
+        assert_eq!(received.bytes, 50 * 500);
+        assert_eq!(received.lost, 0);
+        assert!(received.mean_latency().unwrap() < Duration::from_millis(100));
+        let rate = received.throughput();
+        assert!((800_000.0..1_500_000.0).contains(&rate), "{rate}");
+    }
+
+    #[test]
+    fn bulk_transfer() {
+        let sender = BulkSender::new(SocketAddrV4::new(SINK, 5001), 200_000);
+        let sink = BulkSink::new(5001);
+        let received = sink.stats();
+
+        let hosts = [
+            sender.into_host(MacAddress::new([1; 6]), static_config(SOURCE)),
+            sink.into_host(MacAddress::new([2; 6]), static_config(SINK)),
+        ];
+        run_pair(hosts, || received.lock().unwrap().bytes == 200_000);
+
+        assert_eq!(received.lock().unwrap().bytes, 200_000);
+    }
+}
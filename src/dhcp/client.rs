@@ -0,0 +1,372 @@
+use crate::protocols::{
+    dhcp::{DhcpMessage, DhcpMessageType, DhcpOp, DHCP_CLIENT_PORT, DHCP_SERVER_PORT},
+    ethernet::MacAddress,
+    ipv4::{IpProtocol, Ipv4Packet, Ipv4Prefix},
+    udp::UdpDatagram,
+    Packet,
+};
+use std::{
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+const INITIAL_RETRANSMIT: Duration = Duration::from_secs(1);
+const MAX_RETRANSMIT: Duration = Duration::from_secs(16);
+// requests sent before going back to discovering servers
+const MAX_REQUESTS: u32 = 4;
+const DEFAULT_LEASE_TIME: u32 = 3600;
+// used when the server doesn't tell
+const DEFAULT_PREFIX_LEN: u8 = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpState {
+    Init,
+    Selecting,
+    Requesting,
+    Bound,
+    Renewing,
+    Rebinding,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhcpLease {
+    pub address: Ipv4Addr,
+    pub prefix_len: u8,
+    pub gateway: Option<Ipv4Addr>,
+    pub dns_servers: Vec<Ipv4Addr>,
+    pub server: Ipv4Addr,
+    pub renew_at: Instant,
+    pub rebind_at: Instant,
+    pub expires_at: Instant,
+}
+
+// The client side of DHCP (https://www.rfc-editor.org/rfc/rfc2131) without any IO, run by host
+// stacks to get their address. It hands out the packets it wants sent through `poll`, the ones
+// to 255.255.255.255 have to go to the broadcast MAC address.
+pub struct DhcpClient {
+    mac: MacAddress,
+    state: DhcpState,
+    xid: u32,
+    lease: Option<DhcpLease>,
+    // the offer being requested
+    offer: Option<DhcpMessage>,
+    requests: u32,
+    retransmit_interval: Duration,
+    retransmit_at: Option<Instant>,
+    outgoing: Vec<Ipv4Packet>,
+}
+
+impl DhcpClient {
+    pub fn new(mac: MacAddress) -> Self {
+        let bytes = mac.as_bytes();
+        Self {
+            mac,
+            state: DhcpState::Init,
+            xid: u32::from_be_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]),
+            lease: None,
+            offer: None,
+            requests: 0,
+            retransmit_interval: INITIAL_RETRANSMIT,
+            retransmit_at: None,
+            outgoing: Vec::new(),
+        }
+    }
+
+    pub fn state(&self) -> DhcpState {
+        self.state
+    }
+
+    pub fn lease(&self) -> Option<&DhcpLease> {
+        self.lease.as_ref()
+    }
+
+    pub fn start(&mut self, now: Instant) {
+        self.lease = None;
+        self.offer = None;
+        self.state = DhcpState::Selecting;
+        self.xid = self.xid.wrapping_add(1);
+        self.retransmit_interval = INITIAL_RETRANSMIT;
+        self.discover(now);
+    }
+
+    // gives the address back to the server, the client stays idle until started again
+    pub fn release(&mut self) {
+        if let Some(lease) = self.lease.take() {
+            let mut release = DhcpMessage::new(DhcpMessageType::Release, self.xid, self.mac);
+            release.ciaddr = lease.address;
+            release.server_id = Some(lease.server);
+            self.send(lease.address, lease.server, release);
+        }
+        self.state = DhcpState::Init;
+        self.retransmit_at = None;
+    }
+
+    fn send(&mut self, source: Ipv4Addr, destin: Ipv4Addr, message: DhcpMessage) {
+        let datagram = UdpDatagram {
+            source_port: DHCP_CLIENT_PORT,
+            destin_port: DHCP_SERVER_PORT,
+            data: message.to_bytes(),
+        };
+        self.outgoing.push(Ipv4Packet::new(
+            source,
+            destin,
+            IpProtocol::Udp,
+            datagram.to_bytes(),
+        ));
+    }
+
+    // exponential backoff, as the RFC asks for
+    fn backoff(&mut self, now: Instant) {
+        self.retransmit_at = Some(now + self.retransmit_interval);
+        self.retransmit_interval = (self.retransmit_interval * 2).min(MAX_RETRANSMIT);
+    }
+
+    fn discover(&mut self, now: Instant) {
+        let mut discover = DhcpMessage::new(DhcpMessageType::Discover, self.xid, self.mac);
+        discover.broadcast = true;
+        self.send(Ipv4Addr::UNSPECIFIED, Ipv4Addr::BROADCAST, discover);
+        self.backoff(now);
+    }
+
+    fn request(&mut self, now: Instant) {
+        let mut request = DhcpMessage::new(DhcpMessageType::Request, self.xid, self.mac);
+
+        match (self.state, self.lease.clone()) {
+            (DhcpState::Requesting, _) => {
+                let offer = self.offer.as_ref().unwrap();
+                request.broadcast = true;
+                request.requested_address = Some(offer.yiaddr);
+                request.server_id = offer.server_id;
+                self.send(Ipv4Addr::UNSPECIFIED, Ipv4Addr::BROADCAST, request);
+                self.requests += 1;
+                self.backoff(now);
+            }
+            (DhcpState::Renewing, Some(lease)) => {
+                request.ciaddr = lease.address;
+                self.send(lease.address, lease.server, request);
+                // half of the time left, but not too often
+                let left = lease.rebind_at.saturating_duration_since(now);
+                self.retransmit_at = Some(now + (left / 2).max(INITIAL_RETRANSMIT).min(left));
+            }
+            (DhcpState::Rebinding, Some(lease)) => {
+                request.ciaddr = lease.address;
+                self.send(lease.address, Ipv4Addr::BROADCAST, request);
+                let left = lease.expires_at.saturating_duration_since(now);
+                self.retransmit_at = Some(now + (left / 2).max(INITIAL_RETRANSMIT).min(left));
+            }
+            _ => {}
+        }
+    }
+
+    fn bind(&mut self, ack: &DhcpMessage, source: Ipv4Addr, now: Instant) {
+        let lease_time = ack.lease_time.unwrap_or(DEFAULT_LEASE_TIME);
+        let renewal_time = ack.renewal_time.unwrap_or(lease_time / 2);
+        let rebinding_time = ack.rebinding_time.unwrap_or(lease_time / 8 * 7);
+        let seconds = |seconds: u32| now + Duration::from_secs(seconds as u64);
+
+        let lease = DhcpLease {
+            address: ack.yiaddr,
+            prefix_len: ack
+                .subnet_mask
+                .and_then(|mask| Ipv4Prefix::from_mask(ack.yiaddr, mask))
+                .map_or(DEFAULT_PREFIX_LEN, |prefix| prefix.prefix_len()),
+            gateway: ack.router,
+            dns_servers: ack.dns_servers.clone(),
+            server: ack.server_id.unwrap_or(source),
+            renew_at: seconds(renewal_time),
+            rebind_at: seconds(rebinding_time),
+            expires_at: seconds(lease_time),
+        };
+
+        if self.state == DhcpState::Requesting {
+            log::info!(
+                "DHCP client {}: got {}/{} from {} for {lease_time} seconds",
+                self.mac,
+                lease.address,
+                lease.prefix_len,
+                lease.server
+            );
+        }
+        self.lease = Some(lease);
+        self.offer = None;
+        self.state = DhcpState::Bound;
+        self.retransmit_at = None;
+    }
+
+    // returns whether the packet was for the client
+    pub fn handle_packet(&mut self, packet: &Ipv4Packet, now: Instant) -> bool {
+        if packet.protocol != IpProtocol::Udp {
+            return false;
+        }
+        let Ok(datagram) = UdpDatagram::decode(&packet.data) else {
+            return false;
+        };
+        if datagram.destin_port != DHCP_CLIENT_PORT {
+            return false;
+        }
+
+        let message = match DhcpMessage::decode(&datagram.data) {
+            Ok(message) => message,
+            Err(err) => {
+                log::warn!("DHCP client {}: invalid message: {err:?}", self.mac);
+                return true;
+            }
+        };
+        if message.op != DhcpOp::Reply || message.xid != self.xid || message.chaddr != self.mac {
+            return true;
+        }
+
+        match (self.state, message.message_type) {
+            (DhcpState::Selecting, DhcpMessageType::Offer) => {
+                self.offer = Some(message);
+                self.state = DhcpState::Requesting;
+                self.requests = 0;
+                self.retransmit_interval = INITIAL_RETRANSMIT;
+                self.request(now);
+            }
+            (
+                DhcpState::Requesting | DhcpState::Renewing | DhcpState::Rebinding,
+                DhcpMessageType::Ack,
+            ) => self.bind(&message, packet.source, now),
+            (
+                DhcpState::Requesting | DhcpState::Renewing | DhcpState::Rebinding,
+                DhcpMessageType::Nak,
+            ) => {
+                log::info!("DHCP client {}: the server refused the address", self.mac);
+                self.start(now);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    // Returns the packets to send.
+    pub fn poll(&mut self, now: Instant) -> Vec<Ipv4Packet> {
+        if let Some(lease) = self.lease.clone() {
+            if lease.expires_at <= now {
+                log::info!(
+                    "DHCP client {}: lease of {} expired",
+                    self.mac,
+                    lease.address
+                );
+                self.start(now);
+            } else if self.state == DhcpState::Bound && lease.renew_at <= now {
+                self.state = DhcpState::Renewing;
+                self.xid = self.xid.wrapping_add(1);
+                self.request(now);
+            } else if self.state == DhcpState::Renewing && lease.rebind_at <= now {
+                self.state = DhcpState::Rebinding;
+                self.request(now);
+            }
+        }
+
+        if self.retransmit_at.is_some_and(|at| at <= now) {
+            match self.state {
+                DhcpState::Selecting => self.discover(now),
+                DhcpState::Requesting if self.requests >= MAX_REQUESTS => self.start(now),
+                _ => self.request(now),
+            }
+        }
+        std::mem::take(&mut self.outgoing)
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let lease = self.lease.as_ref().map(|lease| match self.state {
+            DhcpState::Bound => lease.renew_at,
+            DhcpState::Renewing => lease.rebind_at,
+            _ => lease.expires_at,
+        });
+        [self.retransmit_at, lease].into_iter().flatten().min()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DhcpClient, DhcpState};
+    use crate::{
+        dhcp::server::{DhcpServer, DhcpServerConfig},
+        protocols::ethernet::MacAddress,
+    };
+    use std::{
+        net::Ipv4Addr,
+        time::{Duration, Instant},
+    };
+
+    // Runs the client until `end`, answered by `server` while it is reachable.
+    fn run(
+        client: &mut DhcpClient,
+        server: &mut DhcpServer,
+        reachable: bool,
+        now: &mut Instant,
+        end: Instant,
+    ) {
+        loop {
+            // replies make the client send more right away
+            let mut packets = client.poll(*now);
+            while !packets.is_empty() {
+                for packet in packets {
+                    if !reachable {
+                        continue;
+                    }
+                    if let Some((_, reply)) = server.handle_packet(&packet, *now) {
+                        assert!(client.handle_packet(&reply, *now));
+                    }
+                }
+                packets = client.poll(*now);
+            }
+
+            match client.next_deadline() {
+                Some(deadline) if deadline <= end => *now = deadline.max(*now),
+                _ => break,
+            }
+        }
+        *now = end;
+    }
+
+    #[test]
+    fn acquires_renews_and_rebinds() {
+        let mut config = DhcpServerConfig::new(
+            Ipv4Addr::new(10, 0, 0, 2),
+            24,
+            Ipv4Addr::new(10, 0, 0, 100),
+            Ipv4Addr::new(10, 0, 0, 200),
+        );
+        config.lease_time = Duration::from_secs(800);
+        config.gateway = Some(Ipv4Addr::new(10, 0, 0, 1));
+        config.dns_servers = vec![Ipv4Addr::new(10, 0, 0, 53)];
+        let mut server = DhcpServer::new(config);
+
+        let mut client = DhcpClient::new(MacAddress::new([0x11; 6]));
+        let start = Instant::now();
+        let mut now = start;
+        client.start(now);
+
+        let secs = |seconds| start + Duration::from_secs(seconds);
+        run(&mut client, &mut server, true, &mut now, secs(1));
+        let lease = client.lease().unwrap().clone();
+        assert_eq!(client.state(), DhcpState::Bound);
+        assert_eq!(lease.address, Ipv4Addr::new(10, 0, 0, 100));
+        assert_eq!(lease.prefix_len, 24);
+        assert_eq!(lease.gateway, Some(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(lease.dns_servers, vec![Ipv4Addr::new(10, 0, 0, 53)]);
+
+        // renewed at half the lease time
+        run(&mut client, &mut server, true, &mut now, secs(450));
+        assert_eq!(client.state(), DhcpState::Bound);
+        assert_eq!(client.lease().unwrap().expires_at, secs(400 + 800));
+
+        // the server goes away: renewing until 7/8 of the lease, then rebinding until it expires
+        run(&mut client, &mut server, false, &mut now, secs(1000));
+        assert_eq!(client.state(), DhcpState::Renewing);
+        run(&mut client, &mut server, false, &mut now, secs(1150));
+        assert_eq!(client.state(), DhcpState::Rebinding);
+        run(&mut client, &mut server, false, &mut now, secs(1250));
+        assert_eq!(client.state(), DhcpState::Selecting);
+        assert_eq!(client.lease(), None);
+
+        // and when it comes back the client gets its address back
+        run(&mut client, &mut server, true, &mut now, secs(1300));
+        assert_eq!(client.state(), DhcpState::Bound);
+        assert_eq!(client.lease().unwrap().address, lease.address);
+    }
+}
@@ -0,0 +1,376 @@
+use crate::protocols::{
+    dhcp::{DhcpMessage, DhcpMessageType, DhcpOp, DHCP_CLIENT_PORT, DHCP_SERVER_PORT},
+    ethernet::{MacAddress, ETHERNET_BROADCAST_MAC_ADDR},
+    ipv4::{IpProtocol, Ipv4Packet, Ipv4Prefix},
+    udp::UdpDatagram,
+    Packet,
+};
+use std::{
+    collections::BTreeMap,
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+// how long an offered address is kept for the client to request it
+const OFFER_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct DhcpServerConfig {
+    // the server's own address, which is also its identifier
+    pub address: Ipv4Addr,
+    pub prefix_len: u8,
+    // addresses handed out, both included
+    pub pool_start: Ipv4Addr,
+    pub pool_end: Ipv4Addr,
+    pub lease_time: Duration,
+    pub gateway: Option<Ipv4Addr>,
+    pub dns_servers: Vec<Ipv4Addr>,
+}
+
+impl DhcpServerConfig {
+    pub fn new(
+        address: Ipv4Addr,
+        prefix_len: u8,
+        pool_start: Ipv4Addr,
+        pool_end: Ipv4Addr,
+    ) -> Self {
+        Self {
+            address,
+            prefix_len,
+            pool_start,
+            pool_end,
+            lease_time: Duration::from_secs(3600),
+            gateway: None,
+            dns_servers: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lease {
+    pub mac: MacAddress,
+    pub expires_at: Instant,
+    // false while the address was only offered
+    pub bound: bool,
+}
+
+// The server side of DHCP without any IO: requests go in and the replies come out with the MAC
+// address they should be sent to. Expired leases are only reclaimed when addresses run out.
+pub struct DhcpServer {
+    config: DhcpServerConfig,
+    leases: BTreeMap<Ipv4Addr, Lease>,
+}
+
+impl DhcpServer {
+    pub fn new(config: DhcpServerConfig) -> Self {
+        Self {
+            config,
+            leases: BTreeMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &DhcpServerConfig {
+        &self.config
+    }
+
+    // the addresses currently bound to a client
+    pub fn leases(&self, now: Instant) -> impl Iterator<Item = (Ipv4Addr, &Lease)> {
+        self.leases
+            .iter()
+            .filter(move |(_, lease)| lease.bound && lease.expires_at > now)
+            .map(|(address, lease)| (*address, lease))
+    }
+
+    fn in_pool(&self, address: Ipv4Addr) -> bool {
+        (self.config.pool_start..=self.config.pool_end).contains(&address)
+            && address != self.config.address
+    }
+
+    fn is_available(&self, address: Ipv4Addr, mac: MacAddress, now: Instant) -> bool {
+        self.in_pool(address)
+            && self
+                .leases
+                .get(&address)
+                .is_none_or(|lease| lease.mac == mac || lease.expires_at <= now)
+    }
+
+    fn allocate(
+        &self,
+        mac: MacAddress,
+        requested: Option<Ipv4Addr>,
+        now: Instant,
+    ) -> Option<Ipv4Addr> {
+        // clients get the address they had before whenever possible
+        let previous = self
+            .leases
+            .iter()
+            .find(|(address, lease)| lease.mac == mac && self.in_pool(**address))
+            .map(|(address, _)| *address);
+
+        let pool = u32::from(self.config.pool_start)..=u32::from(self.config.pool_end);
+        previous
+            .into_iter()
+            .chain(requested.filter(|address| self.is_available(*address, mac, now)))
+            .chain(
+                pool.map(Ipv4Addr::from)
+                    .filter(|address| !self.leases.contains_key(address)),
+            )
+            .chain(
+                self.leases
+                    .iter()
+                    .filter(|(_, lease)| lease.expires_at <= now)
+                    .map(|(address, _)| *address),
+            )
+            .find(|address| self.is_available(*address, mac, now))
+    }
+
+    fn reply(&self, message_type: DhcpMessageType, request: &DhcpMessage) -> DhcpMessage {
+        let mut reply = DhcpMessage::new(message_type, request.xid, request.chaddr);
+        reply.broadcast = request.broadcast;
+        reply.giaddr = request.giaddr;
+        reply.server_id = Some(self.config.address);
+        if message_type == DhcpMessageType::Nak {
+            return reply;
+        }
+
+        let lease_time = self.config.lease_time.as_secs() as u32;
+        reply.siaddr = self.config.address;
+        reply.subnet_mask =
+            Some(Ipv4Prefix::new(self.config.address, self.config.prefix_len).mask());
+        reply.router = self.config.gateway;
+        reply.dns_servers = self.config.dns_servers.clone();
+        reply.lease_time = Some(lease_time);
+        reply.renewal_time = Some(lease_time / 2);
+        reply.rebinding_time = Some(lease_time / 8 * 7);
+        reply
+    }
+
+    pub fn handle_message(&mut self, message: &DhcpMessage, now: Instant) -> Option<DhcpMessage> {
+        if message.op != DhcpOp::Request {
+            return None;
+        }
+        let mac = message.chaddr;
+
+        match message.message_type {
+            DhcpMessageType::Discover => {
+                let Some(address) = self.allocate(mac, message.requested_address, now) else {
+                    log::warn!(
+                        "DHCP server {}: no address left for {mac}",
+                        self.config.address
+                    );
+                    return None;
+                };
+
+                let lease = self.leases.entry(address).or_insert(Lease {
+                    mac,
+                    expires_at: now,
+                    bound: false,
+                });
+                if lease.mac != mac || lease.expires_at <= now {
+                    lease.bound = false;
+                }
+                lease.mac = mac;
+                lease.expires_at = lease.expires_at.max(now + OFFER_TIMEOUT);
+
+                let mut offer = self.reply(DhcpMessageType::Offer, message);
+                offer.yiaddr = address;
+                Some(offer)
+            }
+            DhcpMessageType::Request => {
+                // the client picked another server
+                if message
+                    .server_id
+                    .is_some_and(|server| server != self.config.address)
+                {
+                    self.leases
+                        .retain(|_, lease| lease.mac != mac || lease.bound);
+                    return None;
+                }
+
+                let address = message
+                    .requested_address
+                    .or(Some(message.ciaddr).filter(|address| !address.is_unspecified()))?;
+
+                if !self.is_available(address, mac, now) {
+                    return Some(self.reply(DhcpMessageType::Nak, message));
+                }
+
+                log::info!(
+                    "DHCP server {}: leasing {address} to {mac}",
+                    self.config.address
+                );
+                // a client only holds one address
+                self.leases
+                    .retain(|other, lease| lease.mac != mac || *other == address);
+                self.leases.insert(
+                    address,
+                    Lease {
+                        mac,
+                        expires_at: now + self.config.lease_time,
+                        bound: true,
+                    },
+                );
+
+                let mut ack = self.reply(DhcpMessageType::Ack, message);
+                ack.yiaddr = address;
+                ack.ciaddr = message.ciaddr;
+                Some(ack)
+            }
+            DhcpMessageType::Release => {
+                if self
+                    .leases
+                    .get(&message.ciaddr)
+                    .is_some_and(|lease| lease.mac == mac)
+                {
+                    log::info!(
+                        "DHCP server {}: {mac} released {}",
+                        self.config.address,
+                        message.ciaddr
+                    );
+                    self.leases.remove(&message.ciaddr);
+                }
+                None
+            }
+            DhcpMessageType::Decline => {
+                // someone else uses the address, it is left alone for a while
+                let address = message.requested_address?;
+                log::warn!(
+                    "DHCP server {}: {address} is already in use",
+                    self.config.address
+                );
+                self.leases.insert(
+                    address,
+                    Lease {
+                        mac: ETHERNET_BROADCAST_MAC_ADDR,
+                        expires_at: now + self.config.lease_time,
+                        bound: false,
+                    },
+                );
+                None
+            }
+            _ => None,
+        }
+    }
+
+    // Returns the reply along with the MAC address it is for, None when the packet wasn't a
+    // request for the server or doesn't need an answer.
+    pub fn handle_packet(
+        &mut self,
+        packet: &Ipv4Packet,
+        now: Instant,
+    ) -> Option<(MacAddress, Ipv4Packet)> {
+        if packet.protocol != IpProtocol::Udp {
+            return None;
+        }
+
+        let datagram = UdpDatagram::decode(&packet.data).ok()?;
+        if datagram.destin_port != DHCP_SERVER_PORT {
+            return None;
+        }
+
+        let message = match DhcpMessage::decode(&datagram.data) {
+            Ok(message) => message,
+            Err(err) => {
+                log::warn!(
+                    "DHCP server {}: invalid message from {}: {err:?}",
+                    self.config.address,
+                    packet.source
+                );
+                return None;
+            }
+        };
+        let reply = self.handle_message(&message, now)?;
+
+        // clients without an address can't answer ARP, so unicast goes straight to their MAC
+        let (mac, destin) = if reply.broadcast || reply.message_type == DhcpMessageType::Nak {
+            (ETHERNET_BROADCAST_MAC_ADDR, Ipv4Addr::BROADCAST)
+        } else if !message.ciaddr.is_unspecified() {
+            (message.chaddr, message.ciaddr)
+        } else {
+            (message.chaddr, reply.yiaddr)
+        };
+
+        let datagram = UdpDatagram {
+            source_port: DHCP_SERVER_PORT,
+            destin_port: DHCP_CLIENT_PORT,
+            data: reply.to_bytes(),
+        };
+        let packet = Ipv4Packet::new(
+            self.config.address,
+            destin,
+            IpProtocol::Udp,
+            datagram.to_bytes(),
+        );
+        Some((mac, packet))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DhcpServer, DhcpServerConfig};
+    use crate::protocols::{
+        dhcp::{DhcpMessage, DhcpMessageType},
+        ethernet::MacAddress,
+    };
+    use std::{
+        net::Ipv4Addr,
+        time::{Duration, Instant},
+    };
+
+    fn request(
+        server: &mut DhcpServer,
+        mac: MacAddress,
+        now: Instant,
+    ) -> Option<(DhcpMessageType, Ipv4Addr)> {
+        let discover = DhcpMessage::new(DhcpMessageType::Discover, 1, mac);
+        let offer = server.handle_message(&discover, now)?;
+
+        let mut request = DhcpMessage::new(DhcpMessageType::Request, 1, mac);
+        request.requested_address = Some(offer.yiaddr);
+        request.server_id = offer.server_id;
+        let reply = server.handle_message(&request, now).unwrap();
+        Some((reply.message_type, reply.yiaddr))
+    }
+
+    #[test]
+    fn allocates_from_the_pool() {
+        let mut config = DhcpServerConfig::new(
+            Ipv4Addr::new(10, 0, 0, 2),
+            24,
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 3),
+        );
+        config.lease_time = Duration::from_secs(60);
+        let mut server = DhcpServer::new(config);
+        let now = Instant::now();
+        let macs: Vec<_> = (1..=3).map(|i| MacAddress::new([i; 6])).collect();
+
+        let first = (DhcpMessageType::Ack, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(request(&mut server, macs[0], now), Some(first));
+        // the server's own address is skipped
+        let second = (DhcpMessageType::Ack, Ipv4Addr::new(10, 0, 0, 3));
+        assert_eq!(request(&mut server, macs[1], now), Some(second));
+        // the same client gets the same address back
+        assert_eq!(request(&mut server, macs[0], now), Some(first));
+
+        assert_eq!(request(&mut server, macs[2], now), None);
+        assert_eq!(server.leases(now).count(), 2);
+
+        // the first lease is renewed, the other expires and is handed out again
+        let later = now + Duration::from_secs(40);
+        let mut renew = DhcpMessage::new(DhcpMessageType::Request, 2, macs[0]);
+        renew.ciaddr = first.1;
+        let ack = server.handle_message(&renew, later).unwrap();
+        assert_eq!((ack.message_type, ack.yiaddr), first);
+
+        let later = now + Duration::from_secs(70);
+        let third = (DhcpMessageType::Ack, Ipv4Addr::new(10, 0, 0, 3));
+        assert_eq!(request(&mut server, macs[2], later), Some(third));
+
+        // too late to renew
+        let mut renew = DhcpMessage::new(DhcpMessageType::Request, 3, macs[1]);
+        renew.ciaddr = second.1;
+        let nak = server.handle_message(&renew, later).unwrap();
+        assert_eq!(nak.message_type, DhcpMessageType::Nak);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod resolver;
+pub mod zone;
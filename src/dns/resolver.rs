@@ -0,0 +1,517 @@
+use crate::protocols::dns::{
+    in_zone, DnsMessage, DnsQuestion, DnsRecord, RecordData, RecordType, ResponseCode,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+pub type LookupId = u32;
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+// a stub asks each of its servers that many times, a recursive resolver only once
+const STUB_ATTEMPTS: usize = 3;
+// referrals and CNAMEs followed by a lookup before giving up
+const MAX_STEPS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    pub id: LookupId,
+    pub question: DnsQuestion,
+    // the CNAMEs that were followed and the records of the final name, possibly none
+    pub result: Result<Vec<DnsRecord>, ResponseCode>,
+}
+
+impl Resolution {
+    pub fn ipv4_addresses(&self) -> Vec<Ipv4Addr> {
+        self.result
+            .iter()
+            .flatten()
+            .filter_map(|record| match record.data {
+                RecordData::A(address) => Some(address),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+struct Lookup {
+    question: DnsQuestion,
+    // the name being resolved, the target of the last CNAME once one was followed
+    name: String,
+    chain: Vec<DnsRecord>,
+    // the zone the servers are authoritative for, recursive lookups only
+    zone: String,
+    servers: Vec<Ipv4Addr>,
+    attempts: usize,
+    steps: usize,
+    message_id: u16,
+    // None until the query is sent
+    deadline: Option<Instant>,
+}
+
+impl Lookup {
+    fn server(&self) -> Ipv4Addr {
+        self.servers[self.attempts % self.servers.len()]
+    }
+}
+
+// A resolver without any IO: questions go in, the queries to send come out of `poll` and the
+// answers are collected with `take_results`. As a stub it asks its servers to recurse. As a
+// recursive resolver it starts from the root hints and follows the referrals itself, caching the
+// answers and the delegations on the way.
+pub struct Resolver {
+    recursive: bool,
+    // the servers of a stub, the root servers otherwise
+    servers: Vec<Ipv4Addr>,
+    lookups: BTreeMap<LookupId, Lookup>,
+    next_lookup: LookupId,
+    next_message_id: u16,
+    cache: HashMap<(String, RecordType), (Vec<DnsRecord>, Instant)>,
+    delegations: HashMap<String, (Vec<Ipv4Addr>, Instant)>,
+    outgoing: Vec<(Ipv4Addr, DnsMessage)>,
+    results: Vec<Resolution>,
+}
+
+impl Resolver {
+    fn new(recursive: bool, servers: Vec<Ipv4Addr>) -> Self {
+        assert!(!servers.is_empty(), "a resolver needs servers to ask");
+        Self {
+            recursive,
+            servers,
+            lookups: BTreeMap::new(),
+            next_lookup: 0,
+            next_message_id: 1,
+            cache: HashMap::new(),
+            delegations: HashMap::new(),
+            outgoing: Vec::new(),
+            results: Vec::new(),
+        }
+    }
+
+    pub fn stub(servers: Vec<Ipv4Addr>) -> Self {
+        Self::new(false, servers)
+    }
+
+    pub fn recursive(root_hints: Vec<Ipv4Addr>) -> Self {
+        Self::new(true, root_hints)
+    }
+
+    pub fn is_recursive(&self) -> bool {
+        self.recursive
+    }
+
+    pub fn resolve(&mut self, question: DnsQuestion, now: Instant) -> LookupId {
+        let id = self.next_lookup;
+        self.next_lookup += 1;
+
+        let key = (question.name.clone(), question.record_type);
+        if let Some((records, expires_at)) = self.cache.get(&key) {
+            if *expires_at > now {
+                log::debug!("resolver: {} {:?} from the cache", key.0, key.1);
+                self.results.push(Resolution {
+                    id,
+                    question,
+                    result: Ok(records.clone()),
+                });
+                return id;
+            }
+        }
+
+        let name = question.name.clone();
+        self.lookups.insert(
+            id,
+            Lookup {
+                question,
+                name,
+                chain: Vec::new(),
+                zone: String::new(),
+                servers: Vec::new(),
+                attempts: 0,
+                steps: 0,
+                message_id: 0,
+                deadline: None,
+            },
+        );
+        self.restart(id, now);
+        id
+    }
+
+    // starts over from the closest servers known for the current name
+    fn restart(&mut self, id: LookupId, now: Instant) {
+        let (zone, servers) = match self.recursive {
+            false => (String::new(), self.servers.clone()),
+            true => {
+                let name = &self.lookups[&id].name;
+                self.delegations
+                    .iter()
+                    .filter(|(zone, (_, expires_at))| *expires_at > now && in_zone(name, zone))
+                    .max_by_key(|(zone, _)| zone.len())
+                    .map(|(zone, (servers, _))| (zone.clone(), servers.clone()))
+                    .unwrap_or((String::new(), self.servers.clone()))
+            }
+        };
+
+        let lookup = self.lookups.get_mut(&id).unwrap();
+        lookup.zone = zone;
+        lookup.servers = servers;
+        lookup.attempts = 0;
+        lookup.deadline = None;
+    }
+
+    fn finish(&mut self, id: LookupId, result: Result<Vec<DnsRecord>, ResponseCode>, now: Instant) {
+        let lookup = self.lookups.remove(&id).unwrap();
+        let result = result.map(|records| {
+            let mut chain = lookup.chain;
+            chain.extend(records);
+            chain
+        });
+
+        if let Ok(records) = &result {
+            if let Some(ttl) = records.iter().map(|record| record.ttl).min() {
+                let key = (lookup.question.name.clone(), lookup.question.record_type);
+                let expires_at = now + Duration::from_secs(ttl as u64);
+                self.cache.insert(key, (records.clone(), expires_at));
+            }
+        }
+        self.results.push(Resolution {
+            id,
+            question: lookup.question,
+            result,
+        });
+    }
+
+    // the server didn't answer or couldn't help, the next one is asked
+    fn retry(&mut self, id: LookupId, now: Instant) {
+        let per_server = if self.recursive { 1 } else { STUB_ATTEMPTS };
+        let lookup = self.lookups.get_mut(&id).unwrap();
+        lookup.attempts += 1;
+        lookup.deadline = None;
+        if lookup.attempts >= lookup.servers.len() * per_server {
+            log::warn!("resolver: no server answered for {}", lookup.name);
+            self.finish(id, Err(ResponseCode::ServerFailure), now);
+        }
+    }
+
+    // Returns false when the message isn't the response to one of the queries.
+    pub fn handle_response(
+        &mut self,
+        server: Ipv4Addr,
+        message: &DnsMessage,
+        now: Instant,
+    ) -> bool {
+        let Some((&id, lookup)) = self.lookups.iter_mut().find(|(_, lookup)| {
+            lookup.deadline.is_some()
+                && lookup.message_id == message.id
+                && lookup.server() == server
+        }) else {
+            return false;
+        };
+        if !message.response
+            || message.questions.first().is_none_or(|question| {
+                question.name != lookup.name || question.record_type != lookup.question.record_type
+            })
+        {
+            return false;
+        }
+        lookup.deadline = None;
+
+        match message.response_code {
+            ResponseCode::NoError => {}
+            ResponseCode::NameError => {
+                self.finish(id, Err(ResponseCode::NameError), now);
+                return true;
+            }
+            _ => {
+                self.retry(id, now);
+                return true;
+            }
+        }
+
+        // follows the CNAMEs in the answer
+        let record_type = lookup.question.record_type;
+        let mut name = lookup.name.clone();
+        loop {
+            let records: Vec<_> = message
+                .answers
+                .iter()
+                .filter(|record| record.name == name && record.record_type() == record_type)
+                .cloned()
+                .collect();
+            if !records.is_empty() {
+                self.finish(id, Ok(records), now);
+                return true;
+            }
+
+            let cname = message.answers.iter().find(|record| {
+                record.name == name
+                    && record.record_type() == RecordType::Cname
+                    && !lookup.chain.contains(record)
+            });
+            match cname {
+                Some(
+                    record @ DnsRecord {
+                        data: RecordData::Cname(target),
+                        ..
+                    },
+                ) => {
+                    lookup.chain.push(record.clone());
+                    name = target.clone();
+                }
+                _ => break,
+            }
+        }
+
+        if name != lookup.name {
+            lookup.steps += 1;
+            lookup.name = name;
+            if !self.recursive || lookup.steps > MAX_STEPS {
+                // all a stub gets from its server
+                self.finish(id, Ok(Vec::new()), now);
+            } else {
+                self.restart(id, now);
+            }
+            return true;
+        }
+
+        if !self.recursive || message.authoritative {
+            // the name exists without records of that type
+            self.finish(id, Ok(Vec::new()), now);
+            return true;
+        }
+
+        // a referral to the servers of a zone closer to the name
+        let zone = message
+            .authorities
+            .iter()
+            .find_map(|record| match record.data {
+                RecordData::Ns(_)
+                    if in_zone(&lookup.name, &record.name)
+                        && record.name.len() > lookup.zone.len()
+                        && in_zone(&record.name, &lookup.zone) =>
+                {
+                    Some(record.name.clone())
+                }
+                _ => None,
+            });
+        let Some(zone) = zone else {
+            log::warn!(
+                "resolver: {server} neither answered nor referred for {}",
+                lookup.name
+            );
+            self.retry(id, now);
+            return true;
+        };
+
+        let name_servers: Vec<_> = message
+            .authorities
+            .iter()
+            .filter(|record| record.name == zone)
+            .filter_map(|record| match &record.data {
+                RecordData::Ns(name) => Some((name, record.ttl)),
+                _ => None,
+            })
+            .collect();
+        let servers: Vec<_> = message
+            .additionals
+            .iter()
+            .filter_map(|record| match record.data {
+                RecordData::A(address)
+                    if name_servers.iter().any(|(name, _)| **name == record.name) =>
+                {
+                    Some(address)
+                }
+                _ => None,
+            })
+            .collect();
+
+        lookup.steps += 1;
+        if servers.is_empty() || lookup.steps > MAX_STEPS {
+            log::warn!(
+                "resolver: can't follow the referral to {zone} for {}, glueless delegations aren't supported",
+                lookup.name
+            );
+            self.finish(id, Err(ResponseCode::ServerFailure), now);
+            return true;
+        }
+
+        log::debug!("resolver: {} is delegated to {servers:?}", zone);
+        let ttl = name_servers.iter().map(|(_, ttl)| *ttl).min().unwrap_or(0);
+        self.delegations
+            .insert(zone, (servers, now + Duration::from_secs(ttl as u64)));
+        self.restart(id, now);
+        true
+    }
+
+    // Returns the queries to send along with the server they are for.
+    pub fn poll(&mut self, now: Instant) -> Vec<(Ipv4Addr, DnsMessage)> {
+        let expired: Vec<_> = self
+            .lookups
+            .iter()
+            .filter(|(_, lookup)| lookup.deadline.is_some_and(|deadline| deadline <= now))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            self.retry(id, now);
+        }
+
+        for lookup in self.lookups.values_mut() {
+            if lookup.deadline.is_some() {
+                continue;
+            }
+            lookup.message_id = self.next_message_id;
+            self.next_message_id = self.next_message_id.wrapping_add(1).max(1);
+            lookup.deadline = Some(now + QUERY_TIMEOUT);
+
+            let question = DnsQuestion::new(&lookup.name, lookup.question.record_type);
+            let query = DnsMessage::query(lookup.message_id, question, !self.recursive);
+            self.outgoing.push((lookup.server(), query));
+        }
+
+        std::mem::take(&mut self.outgoing)
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.lookups
+            .values()
+            .filter_map(|lookup| lookup.deadline)
+            .min()
+    }
+
+    pub fn take_results(&mut self) -> Vec<Resolution> {
+        std::mem::take(&mut self.results)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Resolver;
+    use crate::{
+        dns::zone::Zone,
+        protocols::dns::{DnsMessage, DnsQuestion, RecordData, RecordType, ResponseCode},
+    };
+    use std::{
+        collections::HashMap,
+        net::Ipv4Addr,
+        time::{Duration, Instant},
+    };
+
+    const ROOT: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const COM: Ipv4Addr = Ipv4Addr::new(10, 0, 1, 1);
+    const EXAMPLE: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 1);
+
+    fn servers() -> HashMap<Ipv4Addr, Zone> {
+        let zones = [
+            (
+                ROOT,
+                "$ORIGIN .
+                 com.             NS  a.gtld-servers.net.
+                 net.             NS  a.gtld-servers.net.
+                 a.gtld-servers.net. A 10.0.1.1",
+            ),
+            (
+                COM,
+                "$ORIGIN com.
+                 example          NS  ns1.example
+                 ns1.example      A   10.0.2.1",
+            ),
+            (
+                EXAMPLE,
+                "$ORIGIN example.com.
+                 @                NS  ns1
+                 ns1              A   10.0.2.1
+                 www              CNAME web
+                 web              A   10.0.2.80",
+            ),
+        ];
+        zones
+            .into_iter()
+            .map(|(address, text)| {
+                // the indentation would make every record reuse the previous name
+                let text: Vec<_> = text.lines().map(str::trim).collect();
+                (address, Zone::parse(&text.join("\n")).unwrap())
+            })
+            .collect()
+    }
+
+    // answers every query, returns the servers that were asked
+    fn run(
+        resolver: &mut Resolver,
+        servers: &HashMap<Ipv4Addr, Zone>,
+        now: Instant,
+    ) -> Vec<Ipv4Addr> {
+        let mut asked = Vec::new();
+        loop {
+            let queries = resolver.poll(now);
+            if queries.is_empty() {
+                return asked;
+            }
+            for (server, query) in queries {
+                asked.push(server);
+                if let Some(zone) = servers.get(&server) {
+                    assert!(resolver.handle_response(server, &zone.answer(&query), now));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn iterative_resolution() {
+        let servers = servers();
+        let mut resolver = Resolver::recursive(vec![ROOT]);
+        let now = Instant::now();
+
+        let id = resolver.resolve(DnsQuestion::new("www.example.com", RecordType::A), now);
+        assert_eq!(run(&mut resolver, &servers, now), [ROOT, COM, EXAMPLE]);
+        let results = resolver.take_results();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, id);
+        let records = results[0].result.as_ref().unwrap();
+        assert_eq!(records[0].data, RecordData::Cname("web.example.com".into()));
+        assert_eq!(results[0].ipv4_addresses(), [Ipv4Addr::new(10, 0, 2, 80)]);
+
+        // the answer is cached and the delegation to example.com too
+        resolver.resolve(DnsQuestion::new("www.example.com", RecordType::A), now);
+        assert!(run(&mut resolver, &servers, now).is_empty());
+        resolver.resolve(DnsQuestion::new("nothing.example.com", RecordType::A), now);
+        assert_eq!(run(&mut resolver, &servers, now), [EXAMPLE]);
+        let results = resolver.take_results();
+        assert_eq!(results[0].ipv4_addresses(), [Ipv4Addr::new(10, 0, 2, 80)]);
+        assert_eq!(results[1].result, Err(ResponseCode::NameError));
+
+        // until the delegation expires
+        let later = now + Duration::from_secs(3601);
+        resolver.resolve(DnsQuestion::new("web.example.com", RecordType::A), later);
+        assert_eq!(run(&mut resolver, &servers, later), [ROOT, COM, EXAMPLE]);
+    }
+
+    #[test]
+    fn unreachable_servers() {
+        let servers = servers();
+        let mut resolver = Resolver::stub(vec![Ipv4Addr::new(10, 0, 9, 9)]);
+        let mut now = Instant::now();
+
+        resolver.resolve(DnsQuestion::new("www.example.com", RecordType::A), now);
+        for _ in 0..3 {
+            assert_eq!(resolver.poll(now).len(), 1);
+            assert!(resolver.take_results().is_empty());
+            now = resolver.next_deadline().unwrap();
+        }
+        assert!(resolver.poll(now).is_empty());
+        let results = resolver.take_results();
+        assert_eq!(results[0].result, Err(ResponseCode::ServerFailure));
+
+        // a stub gets the whole chain from its recursive server
+        let mut resolver = Resolver::stub(vec![EXAMPLE]);
+        resolver.resolve(DnsQuestion::new("www.example.com", RecordType::A), now);
+        let (server, query) = resolver.poll(now).pop().unwrap();
+        assert!(query.recursion_desired);
+        let response = servers[&server].answer(&query);
+        assert!(resolver.handle_response(server, &response, now));
+        assert_eq!(
+            resolver.take_results()[0].ipv4_addresses(),
+            [Ipv4Addr::new(10, 0, 2, 80)]
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use crate::protocols::dns::{
+    in_zone, normalize_name, DnsMessage, DnsRecord, RecordData, RecordType, ResponseCode,
+};
+use std::{
+    fs,
+    net::{Ipv4Addr, Ipv6Addr},
+    path::Path,
+};
+
+const DEFAULT_TTL: u32 = 3600;
+// a CNAME pointing at a CNAME, and so on
+const MAX_CNAME_CHAIN: usize = 8;
+
+#[derive(Debug)]
+pub enum ZoneError {
+    Io(std::io::Error),
+    Syntax { line: usize, reason: &'static str },
+}
+
+// The records a server is authoritative for, below `origin`. Records below a delegation (a name
+// other than the origin with NS records) only serve as glue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zone {
+    origin: String,
+    records: Vec<DnsRecord>,
+}
+
+fn syntax(line: usize, reason: &'static str) -> ZoneError {
+    ZoneError::Syntax { line, reason }
+}
+
+impl Zone {
+    pub fn new(origin: &str) -> Self {
+        Self {
+            origin: normalize_name(origin),
+            records: Vec::new(),
+        }
+    }
+
+    // The master file format of https://www.rfc-editor.org/rfc/rfc1035#section-5 restricted to one
+    // record per line: `$ORIGIN` and `$TTL`, `@`, names relative to the origin, `;` comments and
+    // `name [ttl] [IN] type data`. Lines starting with a blank reuse the previous name.
+    pub fn parse(text: &str) -> Result<Self, ZoneError> {
+        let mut zone: Option<Zone> = None;
+        let mut origin = String::new();
+        let mut ttl = DEFAULT_TTL;
+        let mut previous_name: Option<String> = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let line_nr = index + 1;
+            let content = line.split(';').next().unwrap();
+            let mut fields: Vec<_> = content.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+
+            match fields[0] {
+                "$ORIGIN" => {
+                    let name = fields.get(1).ok_or(syntax(line_nr, "missing origin"))?;
+                    origin = absolute_name(name, &origin);
+                    zone.get_or_insert_with(|| Zone::new(&origin));
+                    continue;
+                }
+                "$TTL" => {
+                    let value = fields.get(1).ok_or(syntax(line_nr, "missing TTL"))?;
+                    ttl = value.parse().map_err(|_| syntax(line_nr, "invalid TTL"))?;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let name = if content.starts_with(char::is_whitespace) {
+                previous_name
+                    .clone()
+                    .ok_or(syntax(line_nr, "no previous name"))?
+            } else {
+                absolute_name(fields.remove(0), &origin)
+            };
+
+            let mut record_ttl = ttl;
+            if let Some(value) = fields.first().and_then(|field| field.parse().ok()) {
+                record_ttl = value;
+                fields.remove(0);
+            }
+            if fields
+                .first()
+                .is_some_and(|field| field.eq_ignore_ascii_case("IN"))
+            {
+                fields.remove(0);
+            }
+
+            let [record_type, data] = fields[..] else {
+                return Err(syntax(line_nr, "expected a type and its data"));
+            };
+            let data = match record_type.to_ascii_uppercase().as_str() {
+                "A" => RecordData::A(
+                    data.parse::<Ipv4Addr>()
+                        .map_err(|_| syntax(line_nr, "invalid IPv4 address"))?,
+                ),
+                "AAAA" => RecordData::Aaaa(
+                    data.parse::<Ipv6Addr>()
+                        .map_err(|_| syntax(line_nr, "invalid IPv6 address"))?,
+                ),
+                "NS" => RecordData::Ns(absolute_name(data, &origin)),
+                "CNAME" => RecordData::Cname(absolute_name(data, &origin)),
+                _ => return Err(syntax(line_nr, "unsupported record type")),
+            };
+
+            let zone = zone
+                .as_mut()
+                .ok_or(syntax(line_nr, "record before $ORIGIN"))?;
+            if !in_zone(&name, &zone.origin) {
+                return Err(syntax(line_nr, "name outside of the zone"));
+            }
+            zone.add_record(DnsRecord::new(&name, record_ttl, data));
+            previous_name = Some(name);
+        }
+
+        zone.ok_or(syntax(0, "missing $ORIGIN"))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ZoneError> {
+        let text = fs::read_to_string(path).map_err(ZoneError::Io)?;
+        Self::parse(&text)
+    }
+
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    pub fn records(&self) -> &[DnsRecord] {
+        &self.records
+    }
+
+    pub fn add_record(&mut self, record: DnsRecord) {
+        self.records.push(record);
+    }
+
+    fn find<'a>(
+        &'a self,
+        name: &'a str,
+        record_type: RecordType,
+    ) -> impl Iterator<Item = &'a DnsRecord> {
+        self.records
+            .iter()
+            .filter(move |record| record.name == name && record.record_type() == record_type)
+    }
+
+    // the NS records of the closest delegation above or at `name`
+    fn delegation(&self, name: &str) -> Option<Vec<DnsRecord>> {
+        let mut current = name;
+        while current != self.origin {
+            let servers: Vec<_> = self.find(current, RecordType::Ns).cloned().collect();
+            if !servers.is_empty() {
+                return Some(servers);
+            }
+            current = current.split_once('.').map_or("", |(_, parent)| parent);
+        }
+        None
+    }
+
+    // the addresses of the name servers that are inside of the zone
+    fn glue(&self, servers: &[DnsRecord]) -> Vec<DnsRecord> {
+        servers
+            .iter()
+            .filter_map(|server| match &server.data {
+                RecordData::Ns(name) => Some(name),
+                _ => None,
+            })
+            .flat_map(|name| {
+                self.find(name, RecordType::A)
+                    .chain(self.find(name, RecordType::Aaaa))
+            })
+            .cloned()
+            .collect()
+    }
+
+    // Answers the query as the authoritative server of the zone: with the records, a referral to
+    // the servers of a subdomain or a name error.
+    pub fn answer(&self, query: &DnsMessage) -> DnsMessage {
+        let question = &query.questions[0];
+        let mut response = DnsMessage::response_to(query, ResponseCode::NoError);
+        response.authoritative = true;
+
+        let mut name = question.name.clone();
+        for _ in 0..MAX_CNAME_CHAIN {
+            if !in_zone(&name, &self.origin) {
+                break;
+            }
+
+            if let Some(servers) = self.delegation(&name) {
+                // only the answers found so far are authoritative
+                response.authoritative = !response.answers.is_empty();
+                response.additionals = self.glue(&servers);
+                response.authorities = servers;
+                break;
+            }
+
+            let records: Vec<_> = self.find(&name, question.record_type).cloned().collect();
+            if !records.is_empty() {
+                if question.record_type == RecordType::Ns {
+                    response.additionals = self.glue(&records);
+                }
+                response.answers.extend(records);
+                break;
+            }
+
+            let cname = self.find(&name, RecordType::Cname).next().cloned();
+            match cname {
+                Some(record) => {
+                    let RecordData::Cname(target) = &record.data else {
+                        unreachable!()
+                    };
+                    name = target.clone();
+                    response.answers.push(record);
+                }
+                None => {
+                    if !self
+                        .records
+                        .iter()
+                        .any(|record| in_zone(&record.name, &name))
+                    {
+                        response.response_code = ResponseCode::NameError;
+                    }
+                    break;
+                }
+            }
+        }
+        response
+    }
+}
+
+fn absolute_name(name: &str, origin: &str) -> String {
+    if name == "@" {
+        origin.to_string()
+    } else if name.ends_with('.') || origin.is_empty() {
+        normalize_name(name)
+    } else {
+        normalize_name(&format!("{name}.{origin}"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Zone, ZoneError};
+    use crate::protocols::dns::{DnsMessage, DnsQuestion, RecordData, RecordType, ResponseCode};
+    use std::net::Ipv4Addr;
+
+    const ZONE: &str = "
+$ORIGIN example.com.
+$TTL 600
+@           IN NS    ns1
+ns1         IN A     10.0.2.53
+www    300  IN CNAME web
+web            A     10.0.2.80 ; the web server
+               AAAA  2001:db8::80
+lab         IN NS    ns.lab
+ns.lab      IN A     10.0.3.53
+";
+
+    fn query(zone: &Zone, name: &str, record_type: RecordType) -> DnsMessage {
+        let query = DnsMessage::query(1, DnsQuestion::new(name, record_type), false);
+        zone.answer(&query)
+    }
+
+    #[test]
+    fn parse() {
+        let zone = Zone::parse(ZONE).unwrap();
+        assert_eq!(zone.origin(), "example.com");
+        assert_eq!(zone.records().len(), 7);
+
+        let web = &zone.records()[3];
+        assert_eq!(web.name, "web.example.com");
+        assert_eq!(web.ttl, 600);
+        assert_eq!(web.data, RecordData::A(Ipv4Addr::new(10, 0, 2, 80)));
+        assert_eq!(zone.records()[4].name, "web.example.com");
+        assert_eq!(zone.records()[2].ttl, 300);
+
+        assert!(matches!(
+            Zone::parse("$ORIGIN example.com.\nwww IN MX 10 mail"),
+            Err(ZoneError::Syntax { line: 2, .. })
+        ));
+        assert!(matches!(
+            Zone::parse("$ORIGIN example.com.\nwww.example.org. IN A 10.0.0.1"),
+            Err(ZoneError::Syntax { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn answers() {
+        let zone = Zone::parse(ZONE).unwrap();
+
+        // the CNAME is followed inside of the zone
+        let response = query(&zone, "WWW.example.com", RecordType::A);
+        assert!(response.authoritative);
+        let answers: Vec<_> = response.answers.iter().map(|record| &record.data).collect();
+        assert_eq!(
+            answers,
+            [
+                &RecordData::Cname("web.example.com".into()),
+                &RecordData::A(Ipv4Addr::new(10, 0, 2, 80))
+            ]
+        );
+
+        // the name exists, but without records of that type
+        let response = query(&zone, "ns1.example.com", RecordType::Aaaa);
+        assert_eq!(response.response_code, ResponseCode::NoError);
+        assert!(response.answers.is_empty());
+
+        let response = query(&zone, "mail.example.com", RecordType::A);
+        assert_eq!(response.response_code, ResponseCode::NameError);
+
+        // a referral with glue
+        let response = query(&zone, "host.lab.example.com", RecordType::A);
+        assert!(!response.authoritative);
+        assert!(response.answers.is_empty());
+        assert_eq!(
+            response.authorities[0].data,
+            RecordData::Ns("ns.lab.example.com".into())
+        );
+        assert_eq!(
+            response.additionals[0].data,
+            RecordData::A(Ipv4Addr::new(10, 0, 3, 53))
+        );
+
+        // the servers of the zone itself aren't a referral
+        let response = query(&zone, "example.com", RecordType::Ns);
+        assert!(response.authoritative);
+        assert_eq!(response.answers.len(), 1);
+        assert_eq!(response.additionals.len(), 1);
+    }
+}
@@ -0,0 +1,295 @@
+pub mod rules;
+
+use crate::protocols::{
+    ethernet::{EthernetFrameRef, FrameProtocol},
+    icmp::{IcmpType, ICMP_HEADER_SIZE},
+    ipv4::{IpProtocol, Ipv4Packet},
+    tcp::TcpFlags,
+    Packet,
+};
+use rules::{Action, ConnectionState, FrameFields, RuleSet};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    net::{Ipv4Addr, SocketAddrV4},
+    time::{Duration, Instant},
+};
+
+// how long flows are remembered after their last packet
+const TCP_TIMEOUT: Duration = Duration::from_secs(3600);
+const TCP_CLOSING_TIMEOUT: Duration = Duration::from_secs(10);
+const UDP_TIMEOUT: Duration = Duration::from_secs(60);
+const ICMP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// (protocol, source, destination), ICMP queries use their identifier as the port of the side that
+// sent the request
+type FlowKey = (IpProtocol, SocketAddrV4, SocketAddrV4);
+
+fn reversed((protocol, source, destin): FlowKey) -> FlowKey {
+    (protocol, destin, source)
+}
+
+fn flow_key(
+    protocol: IpProtocol,
+    source: Ipv4Addr,
+    destin: Ipv4Addr,
+    data: &[u8],
+) -> Option<FlowKey> {
+    let port = |offset: usize| {
+        Some(u16::from_be_bytes([
+            *data.get(offset)?,
+            *data.get(offset + 1)?,
+        ]))
+    };
+    let (source_port, destin_port) = match protocol {
+        IpProtocol::Tcp | IpProtocol::Udp => (port(0)?, port(2)?),
+        IpProtocol::Icmp => match IcmpType::try_from(*data.first()?) {
+            Ok(IcmpType::EchoRequest) => (port(4)?, 0),
+            Ok(IcmpType::EchoReply) => (0, port(4)?),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some((
+        protocol,
+        SocketAddrV4::new(source, source_port),
+        SocketAddrV4::new(destin, destin_port),
+    ))
+}
+
+// the flow of the packet quoted by an ICMP error
+fn related_flow(packet: &Ipv4Packet) -> Option<FlowKey> {
+    if packet.protocol != IpProtocol::Icmp {
+        return None;
+    }
+    let error = matches!(
+        IcmpType::try_from(*packet.data.first()?),
+        Ok(IcmpType::DestinationUnreachable | IcmpType::TimeExceeded)
+    );
+    let quote = packet.data.get(ICMP_HEADER_SIZE..).filter(|_| error)?;
+
+    let header_len = (*quote.first()? & 0x0F) as usize * 4;
+    let protocol = IpProtocol::from(*quote.get(9)?);
+    let source: [u8; 4] = quote.get(12..16)?.try_into().unwrap();
+    let destin: [u8; 4] = quote.get(16..20)?.try_into().unwrap();
+    flow_key(
+        protocol,
+        source.into(),
+        destin.into(),
+        quote.get(header_len..)?,
+    )
+}
+
+fn timeout(key: &FlowKey, data: &[u8]) -> Duration {
+    match key.0 {
+        IpProtocol::Tcp => {
+            let flags = TcpFlags(data.get(13).copied().unwrap_or_default());
+            if flags.contains(TcpFlags::FIN) || flags.contains(TcpFlags::RST) {
+                TCP_CLOSING_TIMEOUT
+            } else {
+                TCP_TIMEOUT
+            }
+        }
+        IpProtocol::Icmp => ICMP_TIMEOUT,
+        _ => UDP_TIMEOUT,
+    }
+}
+
+// Stateful filtering of frames: the rules decide on each of them, knowing whether it belongs to a
+// flow that was accepted before. Only the IPv4 flows are tracked.
+pub struct PacketFilter {
+    rules: RuleSet,
+    // when each flow expires
+    connections: HashMap<FlowKey, Instant>,
+}
+
+impl PacketFilter {
+    pub fn new(rules: RuleSet) -> Self {
+        Self {
+            rules,
+            connections: HashMap::new(),
+        }
+    }
+
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    pub fn tracked_connections(&self) -> usize {
+        self.connections.len()
+    }
+
+    fn is_tracked(&self, key: FlowKey, now: Instant) -> bool {
+        [key, reversed(key)].iter().any(|key| {
+            self.connections
+                .get(key)
+                .is_some_and(|expires_at| *expires_at > now)
+        })
+    }
+
+    // whether the frame going from one interface to the other (or flooded) may pass
+    pub fn check(
+        &mut self,
+        data: &[u8],
+        in_interface: u32,
+        out_interface: Option<u32>,
+        now: Instant,
+    ) -> Action {
+        let Ok(frame) = EthernetFrameRef::new(data) else {
+            return Action::Drop;
+        };
+        let mut fields = FrameFields {
+            in_interface,
+            out_interface,
+            source_mac: frame.source(),
+            destin_mac: frame.destin(),
+            ether_type: frame.protocol().ok(),
+            source: None,
+            destin: None,
+            protocol: None,
+            source_port: None,
+            destin_port: None,
+            state: ConnectionState::New,
+        };
+
+        let packet = match fields.ether_type {
+            Some(FrameProtocol::Ipv4) => Ipv4Packet::decode(frame.payload()).ok(),
+            _ => None,
+        };
+        let mut flow = None;
+        if let Some(packet) = &packet {
+            fields.source = Some(packet.source);
+            fields.destin = Some(packet.destin);
+            fields.protocol = Some(packet.protocol);
+
+            flow = flow_key(packet.protocol, packet.source, packet.destin, &packet.data);
+            if let Some((IpProtocol::Tcp | IpProtocol::Udp, source, destin)) = flow {
+                fields.source_port = Some(source.port());
+                fields.destin_port = Some(destin.port());
+            }
+            let tracked = flow
+                .or_else(|| related_flow(packet))
+                .is_some_and(|key| self.is_tracked(key, now));
+            if tracked {
+                fields.state = ConnectionState::Established;
+            }
+        }
+
+        let action = self.rules.evaluate(&fields);
+        if let (Action::Accept, Some(key), Some(packet)) = (action, flow, packet) {
+            // whichever direction was seen first
+            let key = match self.connections.contains_key(&reversed(key)) {
+                true => reversed(key),
+                false => key,
+            };
+            self.connections
+                .insert(key, now + timeout(&key, &packet.data));
+        }
+        action
+    }
+
+    pub fn expire(&mut self, now: Instant) {
+        self.connections.retain(|_, expires_at| *expires_at > now);
+    }
+}
+
+impl Display for PacketFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.rules)?;
+        writeln!(f, "{} connections tracked", self.connections.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        rules::{Action, RuleSet},
+        PacketFilter,
+    };
+    use crate::protocols::{
+        ethernet::{EthernetFrame, FrameProtocol, MacAddress},
+        icmp::{IcmpPacket, IcmpType, ICMP_PORT_UNREACHABLE},
+        ipv4::{IpProtocol, Ipv4Packet},
+        udp::UdpDatagram,
+        Packet,
+    };
+    use std::{
+        net::Ipv4Addr,
+        time::{Duration, Instant},
+    };
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 1, 2);
+
+    fn frame(packet: &Ipv4Packet) -> Box<[u8]> {
+        EthernetFrame {
+            source: MacAddress::new([1; 6]),
+            destin: MacAddress::new([2; 6]),
+            protocol: FrameProtocol::Ipv4,
+            data: packet.to_bytes(),
+        }
+        .to_bytes()
+    }
+
+    fn udp(source: Ipv4Addr, destin: Ipv4Addr, source_port: u16, destin_port: u16) -> Ipv4Packet {
+        let datagram = UdpDatagram {
+            source_port,
+            destin_port,
+            data: Box::new([]),
+        };
+        Ipv4Packet::new(source, destin, IpProtocol::Udp, datagram.to_bytes())
+    }
+
+    #[test]
+    fn tracks_connections() {
+        let rules = RuleSet::parse(
+            "accept state established\naccept in 0 proto udp dst-port 53\ndefault drop",
+        )
+        .unwrap();
+        let mut filter = PacketFilter::new(rules);
+        let now = Instant::now();
+
+        // replies only get in once the request went out
+        let reply = udp(SERVER, CLIENT, 53, 40000);
+        assert_eq!(filter.check(&frame(&reply), 1, Some(0), now), Action::Drop);
+        let request = udp(CLIENT, SERVER, 40000, 53);
+        assert_eq!(
+            filter.check(&frame(&request), 0, Some(1), now),
+            Action::Accept
+        );
+        assert_eq!(
+            filter.check(&frame(&reply), 1, Some(0), now),
+            Action::Accept
+        );
+        assert_eq!(filter.tracked_connections(), 1);
+
+        // and so do the errors about the flow
+        let error = IcmpPacket::error(
+            IcmpType::DestinationUnreachable,
+            ICMP_PORT_UNREACHABLE,
+            &request.to_bytes(),
+        );
+        let error = Ipv4Packet::new(SERVER, CLIENT, IpProtocol::Icmp, error.to_bytes());
+        assert_eq!(
+            filter.check(&frame(&error), 1, Some(0), now),
+            Action::Accept
+        );
+
+        let hits: Vec<_> = filter
+            .rules()
+            .rules()
+            .iter()
+            .map(|rule| rule.hits)
+            .collect();
+        assert_eq!(hits, [2, 1]);
+        assert_eq!(filter.rules().default_hits(), 1);
+
+        let later = now + Duration::from_secs(60);
+        assert_eq!(
+            filter.check(&frame(&reply), 1, Some(0), later),
+            Action::Drop
+        );
+        filter.expire(later);
+        assert_eq!(filter.tracked_connections(), 0);
+    }
+}
@@ -0,0 +1,456 @@
+use crate::protocols::{
+    ethernet::{FrameProtocol, MacAddress},
+    ipv4::{IpProtocol, Ipv4Prefix},
+};
+use std::{fmt::Display, fs, net::Ipv4Addr, ops::RangeInclusive, path::Path};
+
+#[derive(Debug)]
+pub enum RuleError {
+    Io(std::io::Error),
+    Syntax { line: usize, reason: &'static str },
+}
+
+fn syntax(line: usize, reason: &'static str) -> RuleError {
+    RuleError::Syntax { line, reason }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Accept,
+    Drop,
+    // drops, letting the source know
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    New,
+    // a packet of a tracked flow, in either direction, or an ICMP error about one
+    Established,
+}
+
+// What the rules look at in a frame. The IPv4 fields are None for the other protocols, the ports
+// for the packets without any (ICMP errors, OSPF...).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameFields {
+    pub in_interface: u32,
+    // None for the frames flooded to every interface, which rules about it don't match
+    pub out_interface: Option<u32>,
+    pub source_mac: MacAddress,
+    pub destin_mac: MacAddress,
+    pub ether_type: Option<FrameProtocol>,
+    pub source: Option<Ipv4Addr>,
+    pub destin: Option<Ipv4Addr>,
+    pub protocol: Option<IpProtocol>,
+    pub source_port: Option<u16>,
+    pub destin_port: Option<u16>,
+    pub state: ConnectionState,
+}
+
+// Every condition present has to hold for the rule to match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub action: Action,
+    pub in_interface: Option<u32>,
+    pub out_interface: Option<u32>,
+    pub source_mac: Option<MacAddress>,
+    pub destin_mac: Option<MacAddress>,
+    pub ether_type: Option<FrameProtocol>,
+    pub source: Option<Ipv4Prefix>,
+    pub destin: Option<Ipv4Prefix>,
+    pub protocol: Option<IpProtocol>,
+    pub source_ports: Option<RangeInclusive<u16>>,
+    pub destin_ports: Option<RangeInclusive<u16>>,
+    pub state: Option<ConnectionState>,
+    pub hits: u64,
+}
+
+fn holds<T: PartialEq>(condition: &Option<T>, value: T) -> bool {
+    condition
+        .as_ref()
+        .is_none_or(|condition| *condition == value)
+}
+
+fn holds_for<C, T>(condition: &Option<C>, value: Option<T>, test: impl Fn(&C, T) -> bool) -> bool {
+    match (condition, value) {
+        (None, _) => true,
+        (Some(condition), Some(value)) => test(condition, value),
+        (Some(_), None) => false,
+    }
+}
+
+impl Rule {
+    pub fn new(action: Action) -> Self {
+        Self {
+            action,
+            in_interface: None,
+            out_interface: None,
+            source_mac: None,
+            destin_mac: None,
+            ether_type: None,
+            source: None,
+            destin: None,
+            protocol: None,
+            source_ports: None,
+            destin_ports: None,
+            state: None,
+            hits: 0,
+        }
+    }
+
+    pub fn matches(&self, fields: &FrameFields) -> bool {
+        holds(&self.in_interface, fields.in_interface)
+            && holds_for(&self.out_interface, fields.out_interface, |a, b| *a == b)
+            && holds(&self.source_mac, fields.source_mac)
+            && holds(&self.destin_mac, fields.destin_mac)
+            && holds(&self.state, fields.state)
+            && holds_for(&self.ether_type, fields.ether_type, |a, b| *a == b)
+            && holds_for(&self.source, fields.source, |prefix, address| {
+                prefix.contains(address)
+            })
+            && holds_for(&self.destin, fields.destin, |prefix, address| {
+                prefix.contains(address)
+            })
+            && holds_for(&self.protocol, fields.protocol, |a, b| *a == b)
+            && holds_for(&self.source_ports, fields.source_port, |ports, port| {
+                ports.contains(&port)
+            })
+            && holds_for(&self.destin_ports, fields.destin_port, |ports, port| {
+                ports.contains(&port)
+            })
+    }
+}
+
+fn ether_type_name(ether_type: FrameProtocol) -> &'static str {
+    match ether_type {
+        FrameProtocol::Ipv4 => "ipv4",
+        FrameProtocol::Apr => "arp",
+        FrameProtocol::Vlan => "vlan",
+        FrameProtocol::Ipv6 => "ipv6",
+    }
+}
+
+// the others go by their number
+fn protocol_name(protocol: IpProtocol) -> String {
+    match protocol {
+        IpProtocol::Icmp => "icmp".into(),
+        IpProtocol::Tcp => "tcp".into(),
+        IpProtocol::Udp => "udp".into(),
+        IpProtocol::Ospf => "ospf".into(),
+        IpProtocol::Other(number) => number.to_string(),
+    }
+}
+
+fn parse_ports(value: &str) -> Option<RangeInclusive<u16>> {
+    match value.split_once('-') {
+        Some((first, last)) => {
+            let (first, last) = (first.parse().ok()?, last.parse().ok()?);
+            (first <= last).then_some(first..=last)
+        }
+        None => value.parse().ok().map(|port| port..=port),
+    }
+}
+
+fn action_name(action: Action) -> &'static str {
+    match action {
+        Action::Accept => "accept",
+        Action::Drop => "drop",
+        Action::Reject => "reject",
+    }
+}
+
+fn parse_action(value: &str) -> Option<Action> {
+    Some(match value {
+        "accept" => Action::Accept,
+        "drop" => Action::Drop,
+        "reject" => Action::Reject,
+        _ => return None,
+    })
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", action_name(self.action))?;
+
+        let ports = |ports: &RangeInclusive<u16>| match ports.start() == ports.end() {
+            true => ports.start().to_string(),
+            false => format!("{}-{}", ports.start(), ports.end()),
+        };
+        let conditions = [
+            self.in_interface.map(|id| format!("in {id}")),
+            self.out_interface.map(|id| format!("out {id}")),
+            self.source_mac.map(|mac| format!("src-mac {mac}")),
+            self.destin_mac.map(|mac| format!("dst-mac {mac}")),
+            self.ether_type
+                .map(|ether_type| format!("ether {}", ether_type_name(ether_type))),
+            self.source.map(|prefix| format!("src {prefix}")),
+            self.destin.map(|prefix| format!("dst {prefix}")),
+            self.protocol
+                .map(|protocol| format!("proto {}", protocol_name(protocol))),
+            self.source_ports
+                .as_ref()
+                .map(|range| format!("src-port {}", ports(range))),
+            self.destin_ports
+                .as_ref()
+                .map(|range| format!("dst-port {}", ports(range))),
+            self.state.map(|state| match state {
+                ConnectionState::New => "state new".to_string(),
+                ConnectionState::Established => "state established".to_string(),
+            }),
+        ];
+        for condition in conditions.into_iter().flatten() {
+            write!(f, " {condition}")?;
+        }
+        Ok(())
+    }
+}
+
+// The ordered rules of a firewall, the first matching one decides and the default action is
+// taken when none does. Each of them counts the frames it decided on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+    default: Action,
+    default_hits: u64,
+}
+
+impl RuleSet {
+    pub fn new(default: Action) -> Self {
+        Self {
+            rules: Vec::new(),
+            default,
+            default_hits: 0,
+        }
+    }
+
+    // One rule per line, `#` starting a comment:
+    //   accept state established
+    //   accept in 0 proto tcp dst 10.0.1.0/24 dst-port 80
+    //   reject proto udp dst-port 1000-2000
+    //   accept ether arp
+    //   default drop
+    // The conditions are `in`, `out`, `src-mac`, `dst-mac`, `ether` (arp, ipv4, ipv6, vlan), `src`,
+    // `dst`, `proto` (icmp, tcp, udp, ospf), `src-port`, `dst-port` and `state` (new,
+    // established). Without a `default` line everything else is accepted.
+    pub fn parse(text: &str) -> Result<Self, RuleError> {
+        let mut rule_set = Self::new(Action::Accept);
+
+        for (index, line) in text.lines().enumerate() {
+            let line_nr = index + 1;
+            let content = line.split('#').next().unwrap();
+            let mut fields = content.split_whitespace();
+            let Some(first) = fields.next() else {
+                continue;
+            };
+
+            if first == "default" {
+                let action = fields.next().and_then(parse_action);
+                rule_set.default = action.ok_or(syntax(line_nr, "invalid default action"))?;
+                if fields.next().is_some() {
+                    return Err(syntax(line_nr, "unexpected field after the default action"));
+                }
+                continue;
+            }
+
+            let action = parse_action(first).ok_or(syntax(line_nr, "invalid action"))?;
+            let mut rule = Rule::new(action);
+            while let Some(key) = fields.next() {
+                let value = fields.next().ok_or(syntax(line_nr, "missing value"))?;
+                let invalid = || syntax(line_nr, "invalid value");
+                match key {
+                    "in" => rule.in_interface = Some(value.parse().map_err(|_| invalid())?),
+                    "out" => rule.out_interface = Some(value.parse().map_err(|_| invalid())?),
+                    "src-mac" => {
+                        rule.source_mac = Some(MacAddress::from(value).map_err(|_| invalid())?)
+                    }
+                    "dst-mac" => {
+                        rule.destin_mac = Some(MacAddress::from(value).map_err(|_| invalid())?)
+                    }
+                    "ether" => {
+                        let ether_type = [
+                            FrameProtocol::Ipv4,
+                            FrameProtocol::Apr,
+                            FrameProtocol::Vlan,
+                            FrameProtocol::Ipv6,
+                        ]
+                        .into_iter()
+                        .find(|ether_type| ether_type_name(*ether_type) == value);
+                        rule.ether_type = Some(ether_type.ok_or_else(invalid)?);
+                    }
+                    "src" => rule.source = Some(value.parse().map_err(|_| invalid())?),
+                    "dst" => rule.destin = Some(value.parse().map_err(|_| invalid())?),
+                    "proto" => {
+                        let protocol = [
+                            IpProtocol::Icmp,
+                            IpProtocol::Tcp,
+                            IpProtocol::Udp,
+                            IpProtocol::Ospf,
+                        ]
+                        .into_iter()
+                        .find(|protocol| protocol_name(*protocol) == value)
+                        .or_else(|| value.parse::<u8>().ok().map(IpProtocol::from));
+                        rule.protocol = Some(protocol.ok_or_else(invalid)?);
+                    }
+                    "src-port" => rule.source_ports = Some(parse_ports(value).ok_or_else(invalid)?),
+                    "dst-port" => rule.destin_ports = Some(parse_ports(value).ok_or_else(invalid)?),
+                    "state" => {
+                        rule.state = Some(match value {
+                            "new" => ConnectionState::New,
+                            "established" => ConnectionState::Established,
+                            _ => return Err(invalid()),
+                        })
+                    }
+                    _ => return Err(syntax(line_nr, "unknown condition")),
+                }
+            }
+            rule_set.rules.push(rule);
+        }
+        Ok(rule_set)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RuleError> {
+        let text = fs::read_to_string(path).map_err(RuleError::Io)?;
+        Self::parse(&text)
+    }
+
+    pub fn add_rule(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn default_action(&self) -> Action {
+        self.default
+    }
+
+    pub fn default_hits(&self) -> u64 {
+        self.default_hits
+    }
+
+    // the action of the first matching rule, counting the hit
+    pub fn evaluate(&mut self, fields: &FrameFields) -> Action {
+        match self.rules.iter_mut().find(|rule| rule.matches(fields)) {
+            Some(rule) => {
+                rule.hits += 1;
+                rule.action
+            }
+            None => {
+                self.default_hits += 1;
+                self.default
+            }
+        }
+    }
+}
+
+// the rules with their counters, like `iptables -L -v`
+impl Display for RuleSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for rule in self.rules.iter() {
+            writeln!(f, "{:>8} {rule}", rule.hits)?;
+        }
+        writeln!(
+            f,
+            "{:>8} default {}",
+            self.default_hits,
+            action_name(self.default)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Action, ConnectionState, FrameFields, RuleError, RuleSet};
+    use crate::protocols::{
+        ethernet::{FrameProtocol, MacAddress},
+        ipv4::IpProtocol,
+    };
+    use std::net::Ipv4Addr;
+
+    const RULES: &str = "
+# the replies of whatever was let through
+accept state established
+accept in 0 proto tcp dst 10.0.1.0/24 dst-port 80-81
+reject proto udp   # no UDP at all
+accept ether arp
+default drop
+";
+
+    fn fields() -> FrameFields {
+        FrameFields {
+            in_interface: 0,
+            out_interface: Some(1),
+            source_mac: MacAddress::new([1; 6]),
+            destin_mac: MacAddress::new([2; 6]),
+            ether_type: Some(FrameProtocol::Ipv4),
+            source: Some(Ipv4Addr::new(10, 0, 0, 2)),
+            destin: Some(Ipv4Addr::new(10, 0, 1, 2)),
+            protocol: Some(IpProtocol::Tcp),
+            source_port: Some(40000),
+            destin_port: Some(80),
+            state: ConnectionState::New,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let rule_set = RuleSet::parse(RULES).unwrap();
+        assert_eq!(rule_set.rules().len(), 4);
+        assert_eq!(rule_set.default_action(), Action::Drop);
+        assert_eq!(
+            rule_set.rules()[1].to_string(),
+            "accept in 0 dst 10.0.1.0/24 proto tcp dst-port 80-81"
+        );
+
+        for (text, line) in [
+            ("accept\nallow in 0", 2),
+            ("accept dst-port 90-80", 1),
+            ("accept proto sctp", 1),
+            ("accept in", 1),
+            ("default maybe", 1),
+        ] {
+            assert!(
+                matches!(RuleSet::parse(text), Err(RuleError::Syntax { line: l, .. }) if l == line),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn first_match_wins() {
+        let mut rule_set = RuleSet::parse(RULES).unwrap();
+
+        assert_eq!(rule_set.evaluate(&fields()), Action::Accept);
+        let wrong_port = FrameFields {
+            destin_port: Some(22),
+            ..fields()
+        };
+        assert_eq!(rule_set.evaluate(&wrong_port), Action::Drop);
+        let udp = FrameFields {
+            protocol: Some(IpProtocol::Udp),
+            ..fields()
+        };
+        assert_eq!(rule_set.evaluate(&udp), Action::Reject);
+        let reply = FrameFields {
+            in_interface: 1,
+            state: ConnectionState::Established,
+            ..fields()
+        };
+        assert_eq!(rule_set.evaluate(&reply), Action::Accept);
+        let arp = FrameFields {
+            ether_type: Some(FrameProtocol::Apr),
+            source: None,
+            destin: None,
+            protocol: None,
+            source_port: None,
+            destin_port: None,
+            ..fields()
+        };
+        assert_eq!(rule_set.evaluate(&arp), Action::Accept);
+
+        let hits: Vec<_> = rule_set.rules().iter().map(|rule| rule.hits).collect();
+        assert_eq!(hits, [1, 1, 1, 1]);
+        assert_eq!(rule_set.default_hits(), 1);
+    }
+}
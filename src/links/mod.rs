@@ -0,0 +1,369 @@
+#![allow(unused)]
+pub mod wireless;
+
+use std::sync::{Arc, Mutex};
+
+// Frames are immutable once sent, so every copy of a flooded frame shares one buffer.
+pub type LinkData = Arc<[u8]>;
+
+type Locked<T> = Arc<Mutex<T>>;
+#[derive(PartialEq, Eq, Debug)]
+pub enum LinkError {
+    ReceiverAlreadyAttached,
+    LinkIsDown,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    // delivered, in both directions
+    pub frames: u64,
+    // sent while the link was down or nothing was attached to the other end
+    pub lost: u64,
+    // the frames waiting to be sent and the most there ever were, wired links send at once
+    pub queued: usize,
+    pub max_queued: usize,
+}
+
+pub struct LinkEnd {
+    link: Locked<dyn Link>,
+    end_id: LinkEndId,
+}
+
+// Allows bringing a link down (or back up) without owning any of its ends.
+#[derive(Clone)]
+pub struct LinkHandle {
+    link: Locked<dyn Link>,
+}
+
+impl LinkHandle {
+    pub fn set_up(&self, up: bool) {
+        self.link.lock().unwrap().set_up(up)
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.link.lock().unwrap().is_up()
+    }
+
+    pub fn stats(&self) -> LinkStats {
+        self.link.lock().unwrap().stats()
+    }
+}
+
+impl LinkEnd {
+    fn new(end_id: LinkEndId, link: Locked<dyn Link>) -> Self {
+        Self { link, end_id }
+    }
+    fn get_link_id(&self) -> LinkEndId {
+        self.end_id
+    }
+    pub fn send(&self, data: impl Into<LinkData>) -> Result<(), LinkError> {
+        let mut link = self.link.lock().unwrap();
+        link.send(self.end_id, data.into())
+    }
+    pub fn attach_receiver<F>(&self, handler: F) -> Result<(), LinkError>
+    where
+        F: FnMut(LinkData) + Send + Sync + 'static, // TODO: make it so the lifetime of this as to
+                                                    // be as long as this function lives
+    {
+        let mut link = self.link.lock().unwrap();
+        link.attach_receiver(self.end_id, Box::new(handler))
+    }
+    pub fn attach_state_listener<F>(&self, listener: F) -> Result<(), LinkError>
+    where
+        F: FnMut(bool) + Send + Sync + 'static,
+    {
+        let mut link = self.link.lock().unwrap();
+        link.attach_state_listener(self.end_id, Box::new(listener))
+    }
+    // whether frames sent through this end can currently reach the other one
+    pub fn is_up(&self) -> bool {
+        let link = self.link.lock().unwrap();
+        link.has_carrier(self.end_id)
+    }
+    pub fn handle(&self) -> LinkHandle {
+        LinkHandle {
+            link: Arc::clone(&self.link),
+        }
+    }
+}
+
+impl Drop for LinkEnd {
+    fn drop(&mut self) {
+        let mut link = self.link.lock().unwrap();
+        link.detach(self.end_id)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum LinkEndId {
+    First = 0,
+    Second = 1,
+}
+
+impl LinkEndId {
+    #[inline]
+    pub fn get_other_end(self) -> Self {
+        match self {
+            Self::First => Self::Second,
+            Self::Second => Self::First,
+        }
+    }
+}
+
+type LinkEndHandler = Box<dyn FnMut(LinkData) + Send + Sync>;
+type LinkStateListener = Box<dyn FnMut(bool) + Send + Sync>;
+pub trait Link: Send {
+    fn send(&mut self, from: LinkEndId, data: LinkData) -> Result<(), LinkError>;
+    fn attach_receiver(
+        &mut self,
+        link_end: LinkEndId,
+        handler: LinkEndHandler,
+    ) -> Result<(), LinkError>;
+    fn attach_state_listener(
+        &mut self,
+        link_end: LinkEndId,
+        listener: LinkStateListener,
+    ) -> Result<(), LinkError>;
+    // removes the handler and listener of that end, letting the other end know it lost carrier
+    fn detach(&mut self, link_end: LinkEndId);
+    // listeners of both ends are notified when the state actually changes
+    fn set_up(&mut self, up: bool);
+    fn is_up(&self) -> bool;
+    fn has_carrier(&self, from: LinkEndId) -> bool;
+    fn stats(&self) -> LinkStats;
+}
+
+pub fn create_link() -> (LinkEnd, LinkEnd) {
+    let link = Arc::new(Mutex::new(SimpleLink::new()));
+    (
+        LinkEnd::new(LinkEndId::First, link.clone()),
+        LinkEnd::new(LinkEndId::Second, link),
+    )
+}
+
+pub struct SimpleLink {
+    receivers: [Option<LinkEndHandler>; 2],
+    listeners: [Option<LinkStateListener>; 2],
+    up: bool,
+    stats: LinkStats,
+}
+
+impl SimpleLink {
+    fn new() -> Self {
+        Self {
+            receivers: [None, None],
+            listeners: [None, None],
+            up: true,
+            stats: LinkStats::default(),
+        }
+    }
+}
+
+impl Link for SimpleLink {
+    fn send(&mut self, from: LinkEndId, data: LinkData) -> Result<(), LinkError> {
+        let to = from.get_other_end();
+        let idx = to as usize;
+
+        match &mut self.receivers[idx] {
+            Some(handler) if self.up => {
+                handler(data);
+                self.stats.frames += 1;
+                Ok(())
+            }
+            _ => {
+                self.stats.lost += 1;
+                Err(LinkError::LinkIsDown)
+            }
+        }
+    }
+
+    fn attach_receiver(
+        &mut self,
+        link_end: LinkEndId,
+        handler: LinkEndHandler,
+    ) -> Result<(), LinkError> {
+        let idx = link_end as usize;
+        match self.receivers[idx] {
+            Some(_) => Err(LinkError::ReceiverAlreadyAttached),
+            None => {
+                self.receivers[idx] = Some(handler);
+                Ok(())
+            }
+        }
+    }
+
+    fn attach_state_listener(
+        &mut self,
+        link_end: LinkEndId,
+        listener: LinkStateListener,
+    ) -> Result<(), LinkError> {
+        let idx = link_end as usize;
+        match self.listeners[idx] {
+            Some(_) => Err(LinkError::ReceiverAlreadyAttached),
+            None => {
+                self.listeners[idx] = Some(listener);
+                Ok(())
+            }
+        }
+    }
+
+    fn detach(&mut self, link_end: LinkEndId) {
+        let idx = link_end as usize;
+        self.receivers[idx] = None;
+        self.listeners[idx] = None;
+
+        if self.up {
+            if let Some(listener) = &mut self.listeners[link_end.get_other_end() as usize] {
+                listener(false)
+            }
+        }
+    }
+
+    fn set_up(&mut self, up: bool) {
+        if self.up != up {
+            self.up = up;
+            self.listeners
+                .iter_mut()
+                .flatten()
+                .for_each(|listener| listener(up));
+        }
+    }
+
+    fn is_up(&self) -> bool {
+        self.up
+    }
+
+    fn has_carrier(&self, from: LinkEndId) -> bool {
+        self.up && self.receivers[from.get_other_end() as usize].is_some()
+    }
+
+    fn stats(&self) -> LinkStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LinkEnd, LinkError};
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    fn attach_receiver(link_end: &LinkEnd) -> Arc<AtomicU32> {
+        let value = Arc::new(AtomicU32::new(0));
+        let copy = Arc::clone(&value);
+        link_end
+            .attach_receiver(move |data| {
+                copy.store(
+                    u32::from_ne_bytes(
+                        data.as_ref()
+                            .try_into()
+                            .expect("Failed to parse bytes as u32"),
+                    ),
+                    Ordering::Relaxed,
+                )
+            })
+            .expect("Failed to attach receiver");
+        value
+    }
+
+    #[test]
+    fn creation_and_attachment() {
+        let (end_1, end_2) = super::create_link();
+
+        assert_ne!(end_1.get_link_id(), end_2.get_link_id());
+
+        assert_eq!(Ok(()), end_1.attach_receiver(|_| {}));
+        assert_eq!(Ok(()), end_2.attach_receiver(|_| {}));
+
+        assert_eq!(
+            Err(LinkError::ReceiverAlreadyAttached),
+            end_1.attach_receiver(|_| {})
+        );
+        assert_eq!(
+            Err(LinkError::ReceiverAlreadyAttached),
+            end_2.attach_receiver(|_| {})
+        );
+    }
+
+    #[test]
+    fn send_and_receive() {
+        let (end_1, end_2) = super::create_link();
+
+        assert_eq!(Err(LinkError::LinkIsDown), end_1.send(0u32.to_ne_bytes()));
+        assert_eq!(Err(LinkError::LinkIsDown), end_2.send(0u32.to_ne_bytes()));
+
+        let v1 = attach_receiver(&end_1);
+        let v2 = attach_receiver(&end_2);
+
+        assert_eq!(v1.load(Ordering::Relaxed), 0);
+        assert_eq!(v2.load(Ordering::Relaxed), 0);
+
+        assert_eq!(Ok(()), end_1.send(10u32.to_ne_bytes()));
+
+        assert_eq!(v1.load(Ordering::Relaxed), 0);
+        assert_eq!(v2.load(Ordering::Relaxed), 10);
+
+        assert_eq!(Ok(()), end_2.send(11u32.to_ne_bytes()));
+
+        assert_eq!(v1.load(Ordering::Relaxed), 11);
+        assert_eq!(v2.load(Ordering::Relaxed), 10);
+    }
+
+    #[test]
+    fn link_down_and_up() {
+        let (end_1, end_2) = super::create_link();
+
+        assert!(!end_1.is_up());
+        let v2 = attach_receiver(&end_2);
+        assert!(end_1.is_up());
+        assert!(!end_2.is_up());
+        attach_receiver(&end_1);
+
+        let changes = Arc::new(AtomicU32::new(0));
+        let copy = Arc::clone(&changes);
+        end_2
+            .attach_state_listener(move |_| {
+                copy.fetch_add(1, Ordering::Relaxed);
+            })
+            .expect("Failed to attach state listener");
+
+        let handle = end_1.handle();
+        handle.set_up(false);
+        handle.set_up(false);
+
+        assert!(!end_1.is_up());
+        assert!(!end_2.is_up());
+        assert_eq!(changes.load(Ordering::Relaxed), 1);
+        assert_eq!(Err(LinkError::LinkIsDown), end_1.send(1u32.to_ne_bytes()));
+
+        handle.set_up(true);
+
+        assert!(end_1.is_up() && end_2.is_up());
+        assert_eq!(changes.load(Ordering::Relaxed), 2);
+        assert_eq!(Ok(()), end_1.send(1u32.to_ne_bytes()));
+        assert_eq!(v2.load(Ordering::Relaxed), 1);
+        assert_eq!((handle.stats().frames, handle.stats().lost), (1, 1));
+    }
+
+    #[test]
+    fn dropping_an_end_detaches_it() {
+        let (end_1, end_2) = super::create_link();
+        attach_receiver(&end_1);
+        attach_receiver(&end_2);
+
+        let changes = Arc::new(AtomicU32::new(1));
+        let copy = Arc::clone(&changes);
+        end_1
+            .attach_state_listener(move |up| copy.store(up as u32, Ordering::Relaxed))
+            .expect("Failed to attach state listener");
+
+        drop(end_2);
+
+        assert!(!end_1.is_up());
+        assert_eq!(changes.load(Ordering::Relaxed), 0);
+        assert_eq!(Err(LinkError::LinkIsDown), end_1.send(1u32.to_ne_bytes()));
+    }
+}
@@ -0,0 +1,905 @@
+// A shared wireless medium (something like 802.11 in ad-hoc mode). Unlike a `SimpleLink` the
+// medium is not point to point: a transmission reaches every station within the sender's radio
+// range, and two transmissions overlapping at some receiver destroy each other there. Stations
+// can only sense the transmissions they can hear, which is exactly what makes hidden terminals
+// possible (https://en.wikipedia.org/wiki/Hidden_node_problem).
+//
+// Time is divided in slots and the MAC of every station (DCF with optional RTS/CTS) is simulated
+// by the medium itself, one slot at a time. Devices keep using their interfaces as usual, they
+// just enqueue frames here.
+
+use super::{
+    Link, LinkData, LinkEnd, LinkEndHandler, LinkEndId, LinkError, LinkStateListener, LinkStats,
+};
+use crate::protocols::ethernet::{MacAddress, ETHERNET_BROADCAST_MAC_ADDR, ETHERNET_MAC_ADDR_SIZE};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+type StationId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Position {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self { x, y }
+    }
+
+    pub fn distance(&self, other: &Position) -> f64 {
+        ((self.x - other.x).powi(2) + (self.y - other.y).powi(2)).sqrt()
+    }
+}
+
+// All durations are in slots.
+#[derive(Debug, Clone)]
+pub struct WirelessConfig {
+    pub slot_time: Duration,
+    pub sifs: u64,
+    pub difs: u64,
+    pub cw_min: u32,
+    pub cw_max: u32,
+    pub retry_limit: u32,
+    pub bytes_per_slot: usize,
+    pub control_frame_slots: u64,
+    pub rts_cts: bool,
+    // frames smaller than this are sent without RTS/CTS even if it is enabled
+    pub rts_threshold: usize,
+    pub seed: u64,
+}
+
+impl Default for WirelessConfig {
+    fn default() -> Self {
+        // roughly 802.11b: 20us slots, SIFS of 10us (rounded up) and DIFS = SIFS + 2 slots
+        Self {
+            slot_time: Duration::from_micros(20),
+            sifs: 1,
+            difs: 3,
+            cw_min: 31,
+            cw_max: 1023,
+            retry_limit: 7,
+            bytes_per_slot: 32,
+            control_frame_slots: 1,
+            rts_cts: false,
+            rts_threshold: 0,
+            seed: 0x5eed,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StationStats {
+    pub sent: u64,
+    pub received: u64,
+    pub retries: u64,
+    pub dropped: u64,
+    // frames addressed to this station that were destroyed by a collision
+    pub collisions: u64,
+    // the most frames there ever were waiting to be sent
+    pub max_queued: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    Rts,
+    Cts,
+    Data { seq: u16 },
+    Ack,
+}
+
+#[derive(Clone)]
+struct MacFrame {
+    kind: FrameKind,
+    source: StationId,
+    destin: Option<StationId>, // None means broadcast
+    // how long (after this frame) the medium is reserved, used to set the NAV of other stations
+    duration: u64,
+    payload: Option<LinkData>,
+}
+
+struct Transmission {
+    frame: MacFrame,
+    end: u64,
+    // stations at which this transmission overlapped with some other one
+    corrupted_at: Vec<StationId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MacState {
+    Idle,
+    Contending,
+    Transmitting,
+    WaitingCts { deadline: u64 },
+    WaitingAck { deadline: u64 },
+}
+
+struct Station {
+    address: MacAddress,
+    position: Position,
+    range: f64,
+    // a station whose radio is off neither hears nor is heard by anyone
+    up: bool,
+    handler: Option<LinkEndHandler>,
+    listener: Option<LinkStateListener>,
+    queue: VecDeque<LinkData>,
+    state: MacState,
+    difs_left: u64,
+    backoff: u32,
+    cw: u32,
+    retries: u32,
+    nav_until: u64,
+    next_seq: u16,
+    last_seqs: HashMap<StationId, u16>,
+    response: Option<(u64, MacFrame)>,
+    stats: StationStats,
+}
+
+impl Station {
+    fn hears(&self, other: &Station) -> bool {
+        self.up && other.up && self.position.distance(&other.position) <= other.range
+    }
+}
+
+struct Medium {
+    config: WirelessConfig,
+    now: u64,
+    stations: Vec<Station>,
+    ongoing: Vec<Transmission>,
+    rng: u64,
+    // makes run() return
+    stopped: bool,
+}
+
+impl Medium {
+    fn new(config: WirelessConfig) -> Self {
+        Self {
+            rng: config.seed.max(1),
+            config,
+            now: 0,
+            stations: Vec::new(),
+            ongoing: Vec::new(),
+            stopped: false,
+        }
+    }
+
+    // xorshift64, good enough for backoff counters and it keeps runs reproducible
+    fn random(&mut self, max: u32) -> u32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng % (max as u64 + 1)) as u32
+    }
+
+    fn is_active(&self) -> bool {
+        !self.ongoing.is_empty()
+            || self
+                .stations
+                .iter()
+                .any(|station| station.state != MacState::Idle || station.response.is_some())
+    }
+
+    fn find_station(&self, address: MacAddress) -> Option<StationId> {
+        self.stations
+            .iter()
+            .position(|station| station.address == address)
+    }
+
+    fn enqueue(&mut self, station_id: StationId, data: LinkData) {
+        let station = &mut self.stations[station_id];
+        station.queue.push_back(data);
+        station.stats.max_queued = station.stats.max_queued.max(station.queue.len());
+        if self.stations[station_id].state == MacState::Idle {
+            self.start_contending(station_id);
+        }
+    }
+
+    fn start_contending(&mut self, station_id: StationId) {
+        let cw = self.stations[station_id].cw;
+        let backoff = self.random(cw);
+        let station = &mut self.stations[station_id];
+        station.state = MacState::Contending;
+        station.difs_left = self.config.difs;
+        station.backoff = backoff;
+    }
+
+    fn next_frame(&mut self, station_id: StationId) {
+        let station = &mut self.stations[station_id];
+        station.queue.pop_front();
+        station.cw = self.config.cw_min;
+        station.retries = 0;
+        if station.queue.is_empty() {
+            station.state = MacState::Idle;
+        } else {
+            self.start_contending(station_id);
+        }
+    }
+
+    fn transmission_failed(&mut self, station_id: StationId) {
+        let cw_max = self.config.cw_max;
+        let retry_limit = self.config.retry_limit;
+        let station = &mut self.stations[station_id];
+
+        station.retries += 1;
+        station.stats.retries += 1;
+        if station.retries > retry_limit {
+            station.stats.dropped += 1;
+            self.next_frame(station_id);
+        } else {
+            station.cw = (station.cw * 2 + 1).min(cw_max);
+            self.start_contending(station_id);
+        }
+    }
+
+    fn frame_slots(&self, frame: &MacFrame) -> u64 {
+        match &frame.payload {
+            Some(data) => data.len().div_ceil(self.config.bytes_per_slot).max(1) as u64,
+            None => self.config.control_frame_slots,
+        }
+    }
+
+    fn destin_of(&self, data: &[u8]) -> Option<StationId> {
+        let address = MacAddress::build(data.get(..ETHERNET_MAC_ADDR_SIZE)?).ok()?;
+        if address == ETHERNET_BROADCAST_MAC_ADDR {
+            None
+        } else {
+            self.find_station(address)
+        }
+    }
+
+    // the frame the station sends once it wins the contention for the medium
+    fn head_of_line_frame(&mut self, station_id: StationId) -> MacFrame {
+        let data = self.stations[station_id].queue.front().unwrap().clone();
+        let destin = self.destin_of(&data);
+        let control = self.config.control_frame_slots;
+        let sifs = self.config.sifs;
+
+        let data_frame = MacFrame {
+            kind: FrameKind::Data {
+                seq: self.stations[station_id].next_seq,
+            },
+            source: station_id,
+            destin,
+            duration: if destin.is_some() { sifs + control } else { 0 },
+            payload: Some(data),
+        };
+
+        if self.config.rts_cts
+            && destin.is_some()
+            && data_frame.payload.as_ref().unwrap().len() >= self.config.rts_threshold
+        {
+            let data_slots = self.frame_slots(&data_frame);
+            MacFrame {
+                kind: FrameKind::Rts,
+                source: station_id,
+                destin,
+                duration: 3 * sifs + 2 * control + data_slots,
+                payload: None,
+            }
+        } else {
+            data_frame
+        }
+    }
+
+    fn medium_busy(&self, station_id: StationId) -> bool {
+        let station = &self.stations[station_id];
+        station.nav_until > self.now
+            || self.ongoing.iter().any(|transmission| {
+                let sender = transmission.frame.source;
+                sender == station_id || station.hears(&self.stations[sender])
+            })
+    }
+
+    fn transmit(&mut self, frame: MacFrame) {
+        let sender = frame.source;
+        let mut transmission = Transmission {
+            end: self.now + self.frame_slots(&frame),
+            frame,
+            corrupted_at: Vec::new(),
+        };
+
+        for receiver in 0..self.stations.len() {
+            if receiver == sender || !self.stations[receiver].hears(&self.stations[sender]) {
+                continue;
+            }
+
+            for other in self.ongoing.iter_mut() {
+                let other_sender = other.frame.source;
+                // a half duplex radio can't listen while talking
+                let receiver_talking = other_sender == receiver;
+                if receiver_talking || self.stations[receiver].hears(&self.stations[other_sender]) {
+                    transmission.corrupted_at.push(receiver);
+                    if !receiver_talking {
+                        other.corrupted_at.push(receiver);
+                    }
+                }
+            }
+        }
+
+        // whatever the sender was hearing is lost as well
+        for other in self.ongoing.iter_mut() {
+            if self.stations[sender].hears(&self.stations[other.frame.source]) {
+                other.corrupted_at.push(sender);
+            }
+        }
+
+        self.ongoing.push(transmission);
+    }
+
+    fn deliver(&mut self, station_id: StationId, frame: &MacFrame) {
+        let FrameKind::Data { seq } = frame.kind else {
+            return;
+        };
+
+        let station = &mut self.stations[station_id];
+        if frame.destin.is_some() && station.last_seqs.insert(frame.source, seq) == Some(seq) {
+            // retransmission of a frame whose ACK got lost
+            return;
+        }
+
+        station.stats.received += 1;
+        if let Some(handler) = station.handler.as_mut() {
+            handler(frame.payload.clone().unwrap());
+        }
+    }
+
+    fn receive(&mut self, station_id: StationId, frame: &MacFrame) {
+        let now = self.now;
+        let sifs = self.config.sifs;
+        let control = self.config.control_frame_slots;
+
+        if frame.destin != Some(station_id) {
+            let station = &mut self.stations[station_id];
+            match frame.destin {
+                None => self.deliver(station_id, frame),
+                Some(_) => station.nav_until = station.nav_until.max(now + frame.duration),
+            }
+            return;
+        }
+
+        match frame.kind {
+            FrameKind::Rts => {
+                let station = &mut self.stations[station_id];
+                if station.nav_until <= now {
+                    let cts = MacFrame {
+                        kind: FrameKind::Cts,
+                        source: station_id,
+                        destin: Some(frame.source),
+                        duration: frame.duration - sifs - control,
+                        payload: None,
+                    };
+                    station.response = Some((now + sifs, cts));
+                }
+            }
+            FrameKind::Cts => {
+                if let MacState::WaitingCts { .. } = self.stations[station_id].state {
+                    let data = self.stations[station_id].queue.front().unwrap().clone();
+                    let station = &mut self.stations[station_id];
+                    let frame = MacFrame {
+                        kind: FrameKind::Data {
+                            seq: station.next_seq,
+                        },
+                        source: station_id,
+                        destin: Some(frame.source),
+                        duration: sifs + control,
+                        payload: Some(data),
+                    };
+                    station.state = MacState::Transmitting;
+                    station.response = Some((now + sifs, frame));
+                }
+            }
+            FrameKind::Data { .. } => {
+                let ack = MacFrame {
+                    kind: FrameKind::Ack,
+                    source: station_id,
+                    destin: Some(frame.source),
+                    duration: 0,
+                    payload: None,
+                };
+                self.stations[station_id].response = Some((now + sifs, ack));
+                self.deliver(station_id, frame);
+            }
+            FrameKind::Ack => {
+                if let MacState::WaitingAck { .. } = self.stations[station_id].state {
+                    let station = &mut self.stations[station_id];
+                    station.stats.sent += 1;
+                    station.next_seq = station.next_seq.wrapping_add(1);
+                    self.next_frame(station_id);
+                }
+            }
+        }
+    }
+
+    fn finish_transmission(&mut self, transmission: Transmission) {
+        let frame = &transmission.frame;
+        let sender = frame.source;
+
+        for receiver in 0..self.stations.len() {
+            if receiver == sender || !self.stations[receiver].hears(&self.stations[sender]) {
+                continue;
+            }
+
+            if transmission.corrupted_at.contains(&receiver) {
+                if frame.destin == Some(receiver) {
+                    self.stations[receiver].stats.collisions += 1;
+                }
+            } else {
+                self.receive(receiver, frame);
+            }
+        }
+
+        // a station only stays in the Transmitting state while sending its own RTS or DATA
+        if self.stations[sender].state != MacState::Transmitting {
+            return;
+        }
+
+        let sifs = self.config.sifs;
+        let control = self.config.control_frame_slots;
+        let deadline = self.now + sifs + control;
+        let station = &mut self.stations[sender];
+
+        match (frame.kind, frame.destin) {
+            (FrameKind::Rts, _) => station.state = MacState::WaitingCts { deadline },
+            (FrameKind::Data { .. }, Some(_)) => station.state = MacState::WaitingAck { deadline },
+            (FrameKind::Data { .. }, None) => {
+                station.stats.sent += 1;
+                station.next_seq = station.next_seq.wrapping_add(1);
+                self.next_frame(sender);
+            }
+            _ => {}
+        }
+    }
+
+    // advances the whole medium by one slot
+    fn step(&mut self) {
+        let now = self.now;
+
+        let (finished, ongoing) = self
+            .ongoing
+            .drain(..)
+            .partition(|transmission| transmission.end <= now);
+        self.ongoing = ongoing;
+
+        for transmission in finished {
+            self.finish_transmission(transmission);
+        }
+
+        for station_id in 0..self.stations.len() {
+            match self.stations[station_id].state {
+                MacState::WaitingCts { deadline } | MacState::WaitingAck { deadline }
+                    if deadline <= now =>
+                {
+                    self.transmission_failed(station_id)
+                }
+                _ => {}
+            }
+        }
+
+        // decide who starts talking before anyone does so that stations whose backoff
+        // reaches zero in the same slot do collide
+        let mut starting = Vec::new();
+        for station_id in 0..self.stations.len() {
+            if let Some((at, _)) = &self.stations[station_id].response {
+                if *at <= now {
+                    let (_, frame) = self.stations[station_id].response.take().unwrap();
+                    starting.push(frame);
+                }
+                continue;
+            }
+
+            if self.stations[station_id].state != MacState::Contending {
+                continue;
+            }
+
+            let busy = self.medium_busy(station_id);
+            let difs = self.config.difs;
+            let station = &mut self.stations[station_id];
+
+            if busy {
+                station.difs_left = difs;
+            } else if station.difs_left > 0 {
+                station.difs_left -= 1;
+            } else if station.backoff > 0 {
+                station.backoff -= 1;
+            } else {
+                station.state = MacState::Transmitting;
+                starting.push(self.head_of_line_frame(station_id));
+            }
+        }
+
+        for frame in starting {
+            self.transmit(frame);
+        }
+
+        self.now += 1;
+    }
+}
+
+// Handle to a wireless medium. Stations are attached to it as if they were the
+// ends of a link.
+#[derive(Clone)]
+pub struct WirelessMedium {
+    inner: Arc<(Mutex<Medium>, Condvar)>,
+}
+
+impl WirelessMedium {
+    pub fn new(config: WirelessConfig) -> Self {
+        Self {
+            inner: Arc::new((Mutex::new(Medium::new(config)), Condvar::new())),
+        }
+    }
+
+    pub fn add_station(&self, address: MacAddress, position: Position, range: f64) -> LinkEnd {
+        let (lock, _) = &*self.inner;
+        let mut medium = lock.lock().unwrap();
+        assert!(
+            medium.find_station(address).is_none(),
+            "Station '{address}' already in the wireless medium!"
+        );
+
+        let station_id = medium.stations.len();
+        let cw = medium.config.cw_min;
+        medium.stations.push(Station {
+            address,
+            position,
+            range,
+            up: true,
+            handler: None,
+            listener: None,
+            queue: VecDeque::new(),
+            state: MacState::Idle,
+            difs_left: 0,
+            backoff: 0,
+            cw,
+            retries: 0,
+            nav_until: 0,
+            next_seq: 0,
+            last_seqs: HashMap::new(),
+            response: None,
+            stats: StationStats::default(),
+        });
+
+        let link = StationLink {
+            medium: Arc::clone(&self.inner),
+            station_id,
+        };
+        LinkEnd::new(LinkEndId::First, Arc::new(Mutex::new(link)))
+    }
+
+    pub fn station_stats(&self, address: MacAddress) -> Option<StationStats> {
+        let medium = self.inner.0.lock().unwrap();
+        let station_id = medium.find_station(address)?;
+        Some(medium.stations[station_id].stats)
+    }
+
+    // simulated time in slots
+    pub fn now(&self) -> u64 {
+        self.inner.0.lock().unwrap().now
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.inner.0.lock().unwrap().is_active()
+    }
+
+    pub fn step(&self) {
+        self.inner.0.lock().unwrap().step()
+    }
+
+    // Runs the medium until stopped, sleeping while no station has anything to do.
+    pub fn run(&self) {
+        let (lock, condvar) = &*self.inner;
+        loop {
+            let slot_time = {
+                let mut medium = condvar
+                    .wait_while(lock.lock().unwrap(), |medium| {
+                        !medium.is_active() && !medium.stopped
+                    })
+                    .unwrap();
+                if medium.stopped {
+                    return;
+                }
+                medium.step();
+                medium.config.slot_time
+            };
+
+            if !slot_time.is_zero() {
+                thread::sleep(slot_time);
+            }
+        }
+    }
+
+    // frames still in the air are dropped
+    pub fn stop(&self) {
+        let (lock, condvar) = &*self.inner;
+        lock.lock().unwrap().stopped = true;
+        condvar.notify_all();
+    }
+}
+
+// The device side of a station. Only the first end of it is ever handed out.
+struct StationLink {
+    medium: Arc<(Mutex<Medium>, Condvar)>,
+    station_id: StationId,
+}
+
+impl Link for StationLink {
+    fn send(&mut self, from: LinkEndId, data: LinkData) -> Result<(), LinkError> {
+        let (lock, condvar) = &*self.medium;
+        let mut medium = lock.lock().unwrap();
+        if !medium.stations[self.station_id].up {
+            return Err(LinkError::LinkIsDown);
+        }
+        medium.enqueue(self.station_id, data);
+        condvar.notify_one();
+        Ok(())
+    }
+
+    fn attach_receiver(
+        &mut self,
+        link_end: LinkEndId,
+        handler: LinkEndHandler,
+    ) -> Result<(), LinkError> {
+        let mut medium = self.medium.0.lock().unwrap();
+        let station = &mut medium.stations[self.station_id];
+        match station.handler {
+            Some(_) => Err(LinkError::ReceiverAlreadyAttached),
+            None => {
+                station.handler = Some(handler);
+                Ok(())
+            }
+        }
+    }
+
+    fn attach_state_listener(
+        &mut self,
+        link_end: LinkEndId,
+        listener: LinkStateListener,
+    ) -> Result<(), LinkError> {
+        let mut medium = self.medium.0.lock().unwrap();
+        let station = &mut medium.stations[self.station_id];
+        match station.listener {
+            Some(_) => Err(LinkError::ReceiverAlreadyAttached),
+            None => {
+                station.listener = Some(listener);
+                Ok(())
+            }
+        }
+    }
+
+    fn detach(&mut self, link_end: LinkEndId) {
+        let mut medium = self.medium.0.lock().unwrap();
+        let station = &mut medium.stations[self.station_id];
+        // the station stays around (ids are indexes) but it goes silent for good
+        station.up = false;
+        station.handler = None;
+        station.listener = None;
+        station.queue.clear();
+        station.state = MacState::Idle;
+        station.response = None;
+    }
+
+    fn set_up(&mut self, up: bool) {
+        let mut medium = self.medium.0.lock().unwrap();
+        let station = &mut medium.stations[self.station_id];
+        if station.up != up {
+            station.up = up;
+            if let Some(listener) = station.listener.as_mut() {
+                listener(up)
+            }
+        }
+    }
+
+    fn is_up(&self) -> bool {
+        self.medium.0.lock().unwrap().stations[self.station_id].up
+    }
+
+    fn has_carrier(&self, from: LinkEndId) -> bool {
+        self.is_up()
+    }
+
+    fn stats(&self) -> LinkStats {
+        let medium = self.medium.0.lock().unwrap();
+        let station = &medium.stations[self.station_id];
+        LinkStats {
+            frames: station.stats.sent,
+            lost: station.stats.dropped,
+            queued: station.queue.len(),
+            max_queued: station.stats.max_queued,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Position, WirelessConfig, WirelessMedium};
+    use crate::{
+        devices::{ModuleEvent, ProgrammableDevice},
+        protocols::ethernet::{MacAddress, ETHERNET_BROADCAST_MAC_ADDR},
+        simulator::{InterfaceSpec, SimEvent, Simulator},
+    };
+    use std::{
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            mpsc, Arc,
+        },
+        time::Duration,
+    };
+
+    const A: MacAddress = MacAddress::new([0xA; 6]);
+    const B: MacAddress = MacAddress::new([0xB; 6]);
+    const C: MacAddress = MacAddress::new([0xC; 6]);
+
+    fn frame(destin: MacAddress, size: usize) -> Vec<u8> {
+        let mut frame = destin.as_bytes().to_vec();
+        frame.resize(size, 0);
+        frame
+    }
+
+    fn run_until_idle(medium: &WirelessMedium) {
+        while medium.is_active() {
+            medium.step();
+            assert!(medium.now() < 10_000_000, "Wireless medium never went idle");
+        }
+    }
+
+    fn counter(end: &super::LinkEnd) -> Arc<AtomicU32> {
+        let count = Arc::new(AtomicU32::new(0));
+        let copy = Arc::clone(&count);
+        end.attach_receiver(move |_| {
+            copy.fetch_add(1, Ordering::Relaxed);
+        })
+        .unwrap();
+        count
+    }
+
+    #[test]
+    fn only_stations_in_range_receive() {
+        let medium = WirelessMedium::new(WirelessConfig::default());
+        let a = medium.add_station(A, Position::new(0.0, 0.0), 12.0);
+        let b = medium.add_station(B, Position::new(10.0, 0.0), 12.0);
+        let c = medium.add_station(C, Position::new(20.0, 0.0), 12.0);
+
+        let (_, at_b, at_c) = (counter(&a), counter(&b), counter(&c));
+
+        a.send(frame(ETHERNET_BROADCAST_MAC_ADDR, 100)).unwrap();
+        run_until_idle(&medium);
+
+        assert_eq!(at_b.load(Ordering::Relaxed), 1);
+        assert_eq!(at_c.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn unicast_is_acknowledged() {
+        let medium = WirelessMedium::new(WirelessConfig::default());
+        let a = medium.add_station(A, Position::new(0.0, 0.0), 12.0);
+        let b = medium.add_station(B, Position::new(10.0, 0.0), 12.0);
+        let at_b = counter(&b);
+
+        for _ in 0..10 {
+            a.send(frame(B, 200)).unwrap();
+        }
+        run_until_idle(&medium);
+
+        let stats = medium.station_stats(A).unwrap();
+        assert_eq!(at_b.load(Ordering::Relaxed), 10);
+        assert_eq!(stats.sent, 10);
+        assert_eq!(stats.retries, 0);
+    }
+
+    // A and C can't hear each other but both reach B
+    fn hidden_terminal(rts_cts: bool) -> (u64, u32) {
+        let medium = WirelessMedium::new(WirelessConfig {
+            rts_cts,
+            ..Default::default()
+        });
+        let a = medium.add_station(A, Position::new(0.0, 0.0), 12.0);
+        let b = medium.add_station(B, Position::new(10.0, 0.0), 12.0);
+        let c = medium.add_station(C, Position::new(20.0, 0.0), 12.0);
+        let at_b = counter(&b);
+
+        for _ in 0..50 {
+            a.send(frame(B, 1500)).unwrap();
+            c.send(frame(B, 1500)).unwrap();
+        }
+        run_until_idle(&medium);
+
+        let collisions = medium.station_stats(B).unwrap().collisions;
+        (collisions, at_b.load(Ordering::Relaxed))
+    }
+
+    #[test]
+    fn rts_cts_mitigates_hidden_terminals() {
+        let (collisions, received) = hidden_terminal(false);
+        let (rts_collisions, rts_received) = hidden_terminal(true);
+
+        assert!(collisions > 0);
+        assert!(
+            rts_received > received,
+            "RTS/CTS delivered {rts_received} frames, basic access delivered {received}"
+        );
+        assert_eq!(rts_received, 100);
+        assert!(rts_collisions < collisions);
+    }
+
+    #[test]
+    fn simulation_ends() {
+        let mut sim = Simulator::new();
+        let medium = sim.add_wireless_medium(WirelessConfig::default());
+        // a's station goes away with it, so it waits for b to get the frame
+        let (received, done) = mpsc::channel();
+        sim.add_device(ProgrammableDevice::new(A, 2, move |_, module| {
+            let interface = module.get_interface(0).unwrap();
+            interface.send(frame(B, 64)).unwrap();
+            done.recv().unwrap();
+        }));
+        sim.add_device(ProgrammableDevice::new(B, 2, move |_, module| {
+            module.wait_for_msg();
+            received.send(()).unwrap();
+        }));
+        sim.add_wireless_station(
+            medium,
+            InterfaceSpec::new(A, 0),
+            Position::new(0.0, 0.0),
+            10.0,
+        );
+        sim.add_wireless_station(
+            medium,
+            InterfaceSpec::new(B, 0),
+            Position::new(5.0, 0.0),
+            10.0,
+        );
+        // an event long after the devices are done doesn't hold the simulation back either
+        let link = sim.add_link(InterfaceSpec::new(A, 1), InterfaceSpec::new(B, 1));
+        sim.schedule(Duration::from_secs(3600), SimEvent::LinkDown(link));
+
+        let stats = sim.run().wait();
+        let (_, station) = stats.stations.iter().find(|(mac, _)| *mac == B).unwrap();
+        assert_eq!(station.received, 1);
+    }
+
+    #[test]
+    fn removes_wireless_devices() {
+        let mut sim = Simulator::new();
+        let medium = sim.add_wireless_medium(WirelessConfig::default());
+        for mac in [A, B] {
+            sim.add_device(ProgrammableDevice::new(mac, 2, |_, module| {
+                while module.wait_for_msg().is_some() {}
+            }));
+            sim.add_wireless_station(
+                medium,
+                InterfaceSpec::new(mac, 0),
+                Position::new(0.0, 0.0),
+                10.0,
+            );
+        }
+        // c is wired to a, and tells how its link changes
+        let (changes, changed) = mpsc::channel();
+        sim.add_device(ProgrammableDevice::new(C, 1, move |_, module| loop {
+            match module.wait_for_event() {
+                ModuleEvent::LinkStateChanged { up, .. } => changes.send(up).unwrap(),
+                ModuleEvent::Shutdown => break,
+                ModuleEvent::Msg(_) => {}
+            }
+        }));
+        sim.add_link(InterfaceSpec::new(A, 1), InterfaceSpec::new(C, 0));
+
+        let mut network = sim.run();
+        network.remove_device(A);
+        assert_eq!(network.topology().media, [vec![(B, 0)]]);
+        let stations: Vec<_> = network
+            .stats()
+            .stations
+            .iter()
+            .map(|(mac, _)| *mac)
+            .collect();
+        assert_eq!(stations, [B]);
+
+        network.shutdown();
+        assert_eq!(changed.iter().collect::<Vec<_>>(), [false]);
+    }
+}
@@ -1,21 +1,59 @@
 #![feature(iter_map_windows, iter_next_chunk)]
 #![allow(unused)]
+#![cfg_attr(test, feature(test))]
+
+#[cfg(test)]
+extern crate test;
 
 mod devices;
+mod dhcp;
+mod dns;
+mod firewall;
 mod links;
+mod metrics;
+mod nat;
+mod ndp;
 mod protocols;
+mod routing;
 mod simulator;
+mod topology;
+mod trace;
+mod traffic;
+mod transport;
+mod tui;
 
 use devices::{
+    host::{AddressConfig, Host},
     switch::{self, Layer2Switch},
-    ProgrammableDevice,
 };
-use protocols::ethernet::{self, EthernetFrame, FrameProtocol, MacAddress};
+use metrics::TimeSeries;
+use protocols::{
+    dissector,
+    ethernet::{self, EthernetFrame, FrameProtocol, MacAddress},
+    Packet,
+};
 use simulator::{InterfaceSpec, Simulator};
-use std::env;
+use std::{
+    env,
+    fs::File,
+    io::{self, BufWriter, Read},
+    net::{Ipv4Addr, SocketAddrV4},
+    thread,
+    time::Duration,
+};
+use topology::ExportOptions;
+use trace::Tracer;
 
 fn main() {
-    init_log();
+    let args: Vec<_> = env::args().skip(1).collect();
+    // `tui` shows the network live in the terminal, the logs would be all over it
+    let tui = args.first().map(String::as_str) == Some("tui");
+    if !tui {
+        init_log();
+    }
+    if args.first().map(String::as_str) == Some("dissect") {
+        return dissect_command(&args[1..]);
+    }
 
     let addresses: Vec<_> = [
         "11:11:11:11:11:11",
@@ -28,69 +66,63 @@ fn main() {
     .collect();
 
     let switch_addr = addresses[0];
-    let source = addresses[1];
-    let destin = addresses[2];
+    let server = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 7);
 
     let mut sim = Simulator::new();
+    // `--trace <file>` writes what happens to every frame as JSON lines, `--paths` prints the
+    // path every frame took once the simulation is over
+    let show_paths = args.iter().any(|arg| arg == "--paths");
+    let tracer = match args.iter().position(|arg| arg == "--trace") {
+        Some(position) => {
+            let path = args.get(position + 1).expect("--trace needs a file");
+            Some(Tracer::create(path).expect("Failed to create the trace file"))
+        }
+        None => show_paths.then(|| Tracer::new(io::sink())),
+    };
+    let tracer = match show_paths {
+        true => tracer.map(Tracer::collect_paths),
+        false => tracer,
+    };
+    if let Some(tracer) = &tracer {
+        sim.set_tracer(tracer.clone());
+    }
+    // `--metrics <prefix>` samples the network while it runs, writing every sample to
+    // `<prefix>.csv` and the last one to `<prefix>.prom` in the Prometheus text format
+    let metrics_prefix = args
+        .iter()
+        .position(|arg| arg == "--metrics")
+        .map(|position| {
+            args.get(position + 1)
+                .expect("--metrics needs a file prefix")
+        });
     sim.add_device(Layer2Switch::new(switch_addr, 3));
 
     for i in 0..3 {
         let addr = addresses[i + 1];
-        let device = ProgrammableDevice::new(addr, 1, move |addr, module| {
-            log::debug!("Device {addr} running...");
-
-            if addr == source {
-                let frame = EthernetFrame {
-                    source,
-                    destin,
-                    protocol: FrameProtocol::Ipv4, // just a joke
-                    data: Box::from("Hello, world".as_bytes()),
-                };
-
-                let interface = module.get_interface(0).unwrap();
-                interface.send(frame.to_bytes().as_ref())
+        let config = AddressConfig::Static {
+            address: Ipv4Addr::new(10, 0, 0, i as u8 + 1),
+            prefix_len: 24,
+            gateway: None,
+        };
+        let device = Host::with_program(addr, config, move |stack| match i {
+            0 => {
+                let socket = stack.udp_bind(0).unwrap();
+                stack.send_to(&socket, b"Hello, world", server).unwrap();
+                if let Ok((data, source)) = stack.recv_from(&socket, None) {
+                    let data = String::from_utf8_lossy(&data);
+                    log::info!("Device {addr}: Received '{data}' from {source}");
+                }
             }
-
-            let mut replied = false;
-
-            loop {
-                let msg = module.wait_for_msg();
-                let frame = match EthernetFrame::from_raw_bytes(msg.data.as_ref()) {
-                    Err(error) => {
-                        log::error!("Device {addr}: Error parsing ethernet frame: {error:?}");
-                        continue;
-                    }
-                    Ok(frame) => frame,
-                };
-
-                if frame.destin == addr {
-                    log::info!(
-                        "Device {addr}: Received a ethernet frame for me from {} with msg: '{}'",
-                        frame.source,
-                        String::from_utf8(frame.data.to_vec()).unwrap()
-                    );
-
-                    if !replied {
-                        let frame = EthernetFrame {
-                            source: addr,
-                            destin: frame.source,
-                            protocol: FrameProtocol::Ipv4, // just joking
-                            data: format!("Hi {}", frame.source).into_bytes().into(),
-                        };
-
-                        let interface = module.get_interface(0).unwrap();
-                        interface.send(frame.to_bytes().as_ref());
-
-                        replied = true;
-                    }
-                } else {
-                    log::debug!(
-                        "Device {addr}: Received an ethernet frame from {} to {}. Dropping...",
-                        frame.source,
-                        frame.destin
-                    )
+            1 => {
+                let socket = stack.udp_bind(server.port()).unwrap();
+                while let Ok((data, source)) = stack.recv_from(&socket, None) {
+                    let data = String::from_utf8_lossy(&data);
+                    log::info!("Device {addr}: Received '{data}' from {source}");
+                    let reply = format!("Hi {source}");
+                    stack.send_to(&socket, reply.as_bytes(), source).unwrap();
                 }
             }
+            _ => {}
         });
         sim.add_device(device);
         sim.add_link(
@@ -99,15 +131,84 @@ fn main() {
         );
     }
 
-    sim.run();
+    if tui {
+        match tui::run(sim) {
+            Ok(stats) => println!("{stats}"),
+            Err(err) => eprintln!("Terminal error: {err}"),
+        }
+        return;
+    }
+
+    let network = sim.run();
+    match metrics_prefix {
+        Some(prefix) => {
+            let series =
+                metrics::record(&network, Duration::from_secs(1), Duration::from_millis(100));
+            if let Err(err) = write_metrics(&series, prefix) {
+                eprintln!("Failed to write the metrics: {err}");
+            }
+        }
+        None => thread::sleep(Duration::from_secs(1)),
+    }
+    // `--dot` or `--mermaid` print the topology, its links coloured by what they carried
+    let options = ExportOptions { utilization: true };
+    if args.iter().any(|arg| arg == "--dot") {
+        print!("{}", network.export_dot(&options));
+    }
+    if args.iter().any(|arg| arg == "--mermaid") {
+        print!("{}", network.export_mermaid(&options));
+    }
+    log::info!("Network statistics:\n{}", network.shutdown());
+
+    if let (true, Some(tracer)) = (show_paths, tracer) {
+        for path in tracer.paths() {
+            println!("{path}");
+        }
+    }
+}
+
+fn write_metrics(series: &TimeSeries, prefix: &str) -> io::Result<()> {
+    series.write_csv(BufWriter::new(File::create(format!("{prefix}.csv"))?))?;
+    series.write_prometheus(BufWriter::new(File::create(format!("{prefix}.prom"))?))
+}
+
+// Prints the frame given in hex (as arguments or through stdin), e.g.:
+//   the-internet dissect 222222222222 111111111111 0800 ...
+fn dissect_command(args: &[String]) {
+    let mut input = args.join("");
+    if input.is_empty() {
+        std::io::stdin()
+            .read_to_string(&mut input)
+            .expect("Failed to read stdin");
+    }
+
+    let digits: Vec<_> = input
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .collect();
+    // from_str_radix would take a sign too
+    if let Some(c) = digits.iter().find(|c| !c.is_ascii_hexdigit()) {
+        eprintln!("Invalid hex input: {c:?} is not a hex digit");
+        return;
+    }
+    if digits.len() % 2 != 0 {
+        eprintln!("Invalid hex input: odd number of digits");
+        return;
+    }
+
+    let bytes: Vec<_> = digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(&pair.iter().collect::<String>(), 16).unwrap())
+        .collect();
+    print!("{}", dissector::dissect(&bytes));
 }
 
 pub fn init_log() {
-    // TODO: ADD TIME TO LOG LINES
     if env::var_os("RUST_LOG").is_none() {
         unsafe {
             env::set_var("RUST_LOG", "info");
         }
     }
-    pretty_env_logger::init();
+    // timestamps make it possible to tell how long routing protocols take to converge
+    pretty_env_logger::init_timed();
 }
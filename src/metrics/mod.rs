@@ -0,0 +1,372 @@
+use crate::simulator::{NetworkStats, SimulatorHandle};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write as _,
+    io::{self, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+// every metric name starts with it
+const NAMESPACE: &str = "sim";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    // only ever goes up, rates (e.g. the throughput) come from its differences
+    Counter,
+    Gauge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    pub name: String,
+    pub kind: MetricKind,
+    pub labels: Vec<(&'static str, String)>,
+    pub value: f64,
+}
+
+impl Metric {
+    fn new(name: &str, kind: MetricKind, labels: &[(&'static str, &str)], value: u64) -> Self {
+        let name = match kind {
+            MetricKind::Counter => format!("{NAMESPACE}_{name}_total"),
+            MetricKind::Gauge => format!("{NAMESPACE}_{name}"),
+        };
+        Self {
+            name,
+            kind,
+            labels: labels
+                .iter()
+                .map(|(label, value)| (*label, value.to_string()))
+                .collect(),
+            value: value as f64,
+        }
+    }
+
+    // how Prometheus names it, e.g. `sim_link_frames_total{link="0"}`
+    pub fn series(&self) -> String {
+        let mut series = self.name.clone();
+        if !self.labels.is_empty() {
+            let labels: Vec<_> = self
+                .labels
+                .iter()
+                .map(|(label, value)| format!("{label}=\"{}\"", escape_label(value)))
+                .collect();
+            write!(series, "{{{}}}", labels.join(",")).unwrap();
+        }
+        series
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Everything in the statistics as metrics, labelled by device, interface, link...
+pub fn network_metrics(stats: &NetworkStats) -> Vec<Metric> {
+    use MetricKind::{Counter, Gauge};
+
+    let mut metrics = Vec::new();
+    for (mac, device) in &stats.devices {
+        let device_label = mac.to_string();
+        let device_label = device_label.as_str();
+        for (interface_id, interface) in device.interfaces.iter().enumerate() {
+            let interface_label = interface_id.to_string();
+            let labels = [("device", device_label), ("interface", &interface_label)];
+            metrics.extend([
+                Metric::new("interface_rx_frames", Counter, &labels, interface.rx_frames),
+                Metric::new("interface_rx_bytes", Counter, &labels, interface.rx_bytes),
+                Metric::new("interface_tx_frames", Counter, &labels, interface.tx_frames),
+                Metric::new("interface_tx_bytes", Counter, &labels, interface.tx_bytes),
+                Metric::new(
+                    "interface_parse_errors",
+                    Counter,
+                    &labels,
+                    interface.parse_errors,
+                ),
+            ]);
+            for (reason, count) in &interface.drops {
+                let labels = [labels[0], labels[1], ("reason", reason.name())];
+                metrics.push(Metric::new("interface_drops", Counter, &labels, *count));
+            }
+        }
+
+        let labels = [("device", device_label)];
+        for (counter, count) in &device.counters {
+            metrics.push(Metric::new(counter, Counter, &labels, *count));
+        }
+        for ((gauge, instance), value) in &device.gauges {
+            let labels = [labels[0], ("id", instance)];
+            metrics.push(Metric::new(gauge, Gauge, &labels, *value));
+        }
+        metrics.extend([
+            Metric::new("device_queue_length", Gauge, &labels, device.queued as u64),
+            Metric::new("device_queue_max", Gauge, &labels, device.max_queued as u64),
+        ]);
+    }
+
+    for link in &stats.links {
+        let link_label = link.link.index().to_string();
+        let labels = [("link", link_label.as_str())];
+        metrics.extend([
+            Metric::new("link_frames", Counter, &labels, link.stats.frames),
+            Metric::new("link_lost", Counter, &labels, link.stats.lost),
+            Metric::new(
+                "link_queue_length",
+                Gauge,
+                &labels,
+                link.stats.queued as u64,
+            ),
+            Metric::new(
+                "link_queue_max",
+                Gauge,
+                &labels,
+                link.stats.max_queued as u64,
+            ),
+        ]);
+    }
+
+    for (mac, station) in &stats.stations {
+        let station_label = mac.to_string();
+        let labels = [("station", station_label.as_str())];
+        metrics.extend([
+            Metric::new("station_sent", Counter, &labels, station.sent),
+            Metric::new("station_received", Counter, &labels, station.received),
+            Metric::new("station_retries", Counter, &labels, station.retries),
+            Metric::new("station_dropped", Counter, &labels, station.dropped),
+            Metric::new("station_collisions", Counter, &labels, station.collisions),
+            Metric::new(
+                "station_queue_max",
+                Gauge,
+                &labels,
+                station.max_queued as u64,
+            ),
+        ]);
+    }
+    metrics
+}
+
+// Snapshots of the metrics, by the time since the sampling started.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimeSeries {
+    pub samples: Vec<(Duration, Vec<Metric>)>,
+}
+
+impl TimeSeries {
+    pub fn push(&mut self, at: Duration, metrics: Vec<Metric>) {
+        self.samples.push((at, metrics));
+    }
+
+    // One row per sample and one column per series, empty where a series didn't exist yet (or
+    // anymore).
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        let series: BTreeSet<String> = self
+            .samples
+            .iter()
+            .flat_map(|(_, metrics)| metrics.iter().map(Metric::series))
+            .collect();
+
+        write!(writer, "time")?;
+        for name in &series {
+            // series have commas and quotes in them
+            write!(writer, ",\"{}\"", name.replace('"', "\"\""))?;
+        }
+        writeln!(writer)?;
+
+        for (at, metrics) in &self.samples {
+            let values: HashMap<_, _> = metrics
+                .iter()
+                .map(|metric| (metric.series(), metric.value))
+                .collect();
+            write!(writer, "{:.3}", at.as_secs_f64())?;
+            for name in &series {
+                match values.get(name) {
+                    Some(value) => write!(writer, ",{value}")?,
+                    None => write!(writer, ",")?,
+                }
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    // the last sample, in the Prometheus text exposition format
+    pub fn write_prometheus(&self, writer: impl Write) -> io::Result<()> {
+        match self.samples.last() {
+            Some((_, metrics)) => write_prometheus(metrics, writer),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn write_prometheus(metrics: &[Metric], mut writer: impl Write) -> io::Result<()> {
+    let mut names: Vec<_> = metrics.iter().map(|metric| metric.name.as_str()).collect();
+    names.sort();
+    names.dedup();
+
+    for name in names {
+        let mut family = metrics
+            .iter()
+            .filter(|metric| metric.name == name)
+            .peekable();
+        let kind = match family.peek().unwrap().kind {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+        };
+        writeln!(writer, "# TYPE {name} {kind}")?;
+        for metric in family {
+            writeln!(writer, "{} {}", metric.series(), metric.value)?;
+        }
+    }
+    Ok(())
+}
+
+// Samples the metrics of the running network every `interval` until `duration` has passed.
+pub fn record(network: &SimulatorHandle, duration: Duration, interval: Duration) -> TimeSeries {
+    assert!(!interval.is_zero(), "sampling needs an interval");
+    let start = Instant::now();
+    let mut series = TimeSeries::default();
+    let mut at = Duration::ZERO;
+    while at <= duration {
+        thread::sleep((start + at).saturating_duration_since(Instant::now()));
+        series.push(at, network_metrics(&network.stats()));
+        at += interval;
+    }
+    series
+}
+
+#[cfg(test)]
+mod test {
+    use super::{network_metrics, record, write_prometheus, TimeSeries};
+    use crate::{
+        devices::{DeviceStats, DropReason, InterfaceStats, ProgrammableDevice},
+        protocols::ethernet::MacAddress,
+        simulator::{InterfaceSpec, NetworkStats, Simulator},
+    };
+    use std::time::Duration;
+
+    fn stats(rx_frames: u64, in_flight: Option<u64>) -> NetworkStats {
+        let mut interface = InterfaceStats {
+            rx_frames,
+            ..Default::default()
+        };
+        interface.drops.insert(DropReason::NoRoute, 2);
+        let mut device = DeviceStats {
+            interfaces: vec![interface],
+            ..Default::default()
+        };
+        device.counters.insert("floods", 3);
+        if let Some(in_flight) = in_flight {
+            device
+                .gauges
+                .insert(("tcp_in_flight_bytes", "a b".into()), in_flight);
+        }
+        NetworkStats {
+            devices: vec![(MacAddress::new([1; 6]), device)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prometheus_exposition() {
+        let mut output = Vec::new();
+        write_prometheus(&network_metrics(&stats(5, Some(1460))), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        let device = "device=\"01:01:01:01:01:01\"";
+        let interface = format!("{device},interface=\"0\"");
+        for line in [
+            "# TYPE sim_floods_total counter".to_string(),
+            format!("sim_floods_total{{{device}}} 3"),
+            "# TYPE sim_interface_rx_frames_total counter".into(),
+            format!("sim_interface_rx_frames_total{{{interface}}} 5"),
+            format!("sim_interface_drops_total{{{interface},reason=\"NoRoute\"}} 2"),
+            "# TYPE sim_tcp_in_flight_bytes gauge".into(),
+            format!("sim_tcp_in_flight_bytes{{{device},id=\"a b\"}} 1460"),
+        ] {
+            assert!(output.lines().any(|output| output == line), "{line}");
+        }
+        // every family is typed once
+        assert_eq!(
+            output.matches("# TYPE sim_interface_drops_total").count(),
+            1
+        );
+    }
+
+    #[test]
+    fn csv_time_series() {
+        let mut series = TimeSeries::default();
+        series.push(Duration::ZERO, network_metrics(&stats(0, None)));
+        series.push(
+            Duration::from_millis(500),
+            network_metrics(&stats(7, Some(10))),
+        );
+
+        let mut output = Vec::new();
+        series.write_csv(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let rows: Vec<Vec<_>> = output
+            .lines()
+            .map(|line| line.split(",\"").collect())
+            .collect();
+
+        assert_eq!(rows.len(), 3);
+        let column = |name: &str| {
+            rows[0]
+                .iter()
+                .position(|header| header.starts_with(name))
+                .unwrap()
+        };
+        let rx = column("sim_interface_rx_frames_total");
+        let in_flight = column("sim_tcp_in_flight_bytes");
+
+        let values: Vec<Vec<_>> = output
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').collect())
+            .collect();
+        assert_eq!(values[0][0], "0.000");
+        assert_eq!(values[1][0], "0.500");
+        assert_eq!((values[0][rx], values[1][rx]), ("0", "7"));
+        // the connection didn't exist yet
+        assert_eq!((values[0][in_flight], values[1][in_flight]), ("", "10"));
+    }
+
+    #[test]
+    fn records_a_running_network() {
+        let (a, b) = (MacAddress::new([0xa; 6]), MacAddress::new([0xb; 6]));
+        let mut sim = Simulator::new();
+        sim.add_device(ProgrammableDevice::new(a, 1, |_, module| {
+            let interface = module.get_interface(0).unwrap();
+            interface.send([0; 64].as_slice()).unwrap();
+            while module.wait_for_msg().is_some() {}
+        }));
+        sim.add_device(ProgrammableDevice::new(b, 1, |_, module| {
+            while module.wait_for_msg().is_some() {}
+        }));
+        sim.add_link(InterfaceSpec::new(a, 0), InterfaceSpec::new(b, 0));
+
+        let network = sim.run();
+        let series = record(
+            &network,
+            Duration::from_millis(100),
+            Duration::from_millis(25),
+        );
+        network.shutdown();
+
+        let times: Vec<_> = series
+            .samples
+            .iter()
+            .map(|(at, _)| at.as_millis())
+            .collect();
+        assert_eq!(times, [0, 25, 50, 75, 100]);
+        let (_, last) = series.samples.last().unwrap();
+        let frames = last
+            .iter()
+            .find(|metric| metric.series() == "sim_link_frames_total{link=\"0\"}")
+            .unwrap();
+        assert_eq!(frames.value, 1.0);
+    }
+}
@@ -0,0 +1,665 @@
+use crate::protocols::{
+    adjust_checksum,
+    icmp::{IcmpType, ICMP_HEADER_SIZE},
+    internet_checksum,
+    ipv4::{IpProtocol, Ipv4Packet},
+    tcp::TcpFlags,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{Ipv4Addr, SocketAddrV4},
+    ops::RangeInclusive,
+    time::{Duration, Instant},
+};
+
+// How the NAT maps and filters, in the classic STUN terms
+// (https://www.rfc-editor.org/rfc/rfc3489#section-5). The first three keep the same external port
+// for an internal endpoint whoever it talks to, the symmetric one uses a new port per remote
+// endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NatType {
+    // anyone can send to a mapped port
+    FullCone,
+    // only the addresses the internal endpoint sent something to
+    AddressRestricted,
+    // only the address and port pairs the internal endpoint sent something to
+    PortRestricted,
+    // only the remote endpoint of the mapping
+    Symmetric,
+}
+
+#[derive(Debug, Clone)]
+pub struct NatConfig {
+    pub nat_type: NatType,
+    pub udp_timeout: Duration,
+    pub tcp_timeout: Duration,
+    // for connections being opened or closed
+    pub tcp_transitory_timeout: Duration,
+    pub icmp_timeout: Duration,
+    // where the external ports are taken from
+    pub ports: RangeInclusive<u16>,
+}
+
+impl Default for NatConfig {
+    // the timeouts recommended by RFC 4787, RFC 5382 and RFC 5508
+    fn default() -> Self {
+        Self {
+            nat_type: NatType::PortRestricted,
+            udp_timeout: Duration::from_secs(300),
+            tcp_timeout: Duration::from_secs(7440),
+            tcp_transitory_timeout: Duration::from_secs(240),
+            icmp_timeout: Duration::from_secs(60),
+            ports: 1024..=65535,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    pub protocol: IpProtocol,
+    pub internal: SocketAddrV4,
+    pub external_port: u16,
+    // the only remote endpoint the mapping is for, with a symmetric NAT
+    pub remote: Option<SocketAddrV4>,
+    // None for the static port forwards
+    pub expires_at: Option<Instant>,
+    // where the internal endpoint sent packets to, for the restricted NATs
+    contacted: HashSet<SocketAddrV4>,
+}
+
+// where the port of each side, or the identifier of ICMP queries, and the checksum sit
+struct Layout {
+    source_port: usize,
+    destin_port: usize,
+    checksum: usize,
+    // whether the checksum covers the addresses too
+    pseudo_header: bool,
+}
+
+fn layout(protocol: IpProtocol, data: &[u8]) -> Option<Layout> {
+    quoted_layout(protocol, data).filter(|layout| data.len() >= layout.checksum + 2)
+}
+
+// the same for a packet quoted by an ICMP error, whose quote may end before the checksum
+fn quoted_layout(protocol: IpProtocol, data: &[u8]) -> Option<Layout> {
+    let echo = [IcmpType::EchoRequest as u8, IcmpType::EchoReply as u8];
+    let layout = match protocol {
+        IpProtocol::Udp => Layout {
+            source_port: 0,
+            destin_port: 2,
+            checksum: 6,
+            pseudo_header: true,
+        },
+        IpProtocol::Tcp => Layout {
+            source_port: 0,
+            destin_port: 2,
+            checksum: 16,
+            pseudo_header: true,
+        },
+        IpProtocol::Icmp if data.first().is_some_and(|kind| echo.contains(kind)) => Layout {
+            source_port: 4,
+            destin_port: 4,
+            checksum: 2,
+            pseudo_header: false,
+        },
+        _ => return None,
+    };
+    (data.len() >= layout.destin_port + 2).then_some(layout)
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Side {
+    Source,
+    Destin,
+}
+
+// replaces the address and port of one side of the packet, keeping the checksum right
+fn rewrite(packet: &mut Ipv4Packet, layout: &Layout, side: Side, endpoint: SocketAddrV4) {
+    let (address, port) = match side {
+        Side::Source => (&mut packet.source, layout.source_port),
+        Side::Destin => (&mut packet.destin, layout.destin_port),
+    };
+    let mut data = packet.data.to_vec();
+    rewrite_port(packet.protocol, &mut data, layout, port, *address, endpoint);
+    *address = *endpoint.ip();
+    packet.data = data.into();
+}
+
+// Replaces the port at `port` of the transport header in `data`, and adjusts its checksum for the
+// address that is replaced along with it. A quoted header may end before the checksum.
+fn rewrite_port(
+    protocol: IpProtocol,
+    data: &mut [u8],
+    layout: &Layout,
+    port: usize,
+    address: Ipv4Addr,
+    endpoint: SocketAddrV4,
+) {
+    let (mut old, mut new) = (Vec::new(), Vec::new());
+    if layout.pseudo_header {
+        old.extend(address.octets());
+        new.extend(endpoint.ip().octets());
+    }
+    old.extend(&data[port..port + 2]);
+    new.extend(endpoint.port().to_be_bytes());
+
+    if let Some(checksum) = data.get_mut(layout.checksum..layout.checksum + 2) {
+        let value = u16::from_be_bytes([checksum[0], checksum[1]]);
+        // a UDP datagram without checksum stays without one
+        if protocol != IpProtocol::Udp || value != 0 {
+            let mut adjusted = adjust_checksum(value, &old, &new);
+            if protocol == IpProtocol::Udp && adjusted == 0 {
+                adjusted = 0xFFFF;
+            }
+            checksum.copy_from_slice(&adjusted.to_be_bytes());
+        }
+    }
+    data[port..port + 2].copy_from_slice(&endpoint.port().to_be_bytes());
+}
+
+// Network address and port translation (https://www.rfc-editor.org/rfc/rfc3022) for UDP, TCP and
+// ICMP queries: the packets going out get the external address and a port of the NAT, the ones
+// coming back are sent to the internal endpoint if the NAT type lets them in. ICMP errors about
+// translated packets are translated too. Time is given by the caller, mappings expire in `expire`.
+pub struct Nat {
+    config: NatConfig,
+    external_address: Ipv4Addr,
+    mappings: HashMap<(IpProtocol, u16), Mapping>,
+    // the external port of (protocol, internal endpoint, remote endpoint for symmetric NATs)
+    outbound: HashMap<(IpProtocol, SocketAddrV4, Option<SocketAddrV4>), u16>,
+    next_port: u16,
+}
+
+impl Nat {
+    pub fn new(external_address: Ipv4Addr, config: NatConfig) -> Self {
+        Self {
+            next_port: *config.ports.start(),
+            config,
+            external_address,
+            mappings: HashMap::new(),
+            outbound: HashMap::new(),
+        }
+    }
+
+    pub fn external_address(&self) -> Ipv4Addr {
+        self.external_address
+    }
+
+    pub fn mappings(&self) -> impl Iterator<Item = &Mapping> {
+        self.mappings.values()
+    }
+
+    // Sends whatever reaches `external_port` to `internal`, from anyone and forever. The internal
+    // endpoint's own packets go out from that port too.
+    pub fn add_port_forward(
+        &mut self,
+        protocol: IpProtocol,
+        external_port: u16,
+        internal: SocketAddrV4,
+    ) {
+        self.outbound
+            .insert((protocol, internal, None), external_port);
+        self.mappings.insert(
+            (protocol, external_port),
+            Mapping {
+                protocol,
+                internal,
+                external_port,
+                remote: None,
+                expires_at: None,
+                contacted: HashSet::new(),
+            },
+        );
+    }
+
+    fn timeout(&self, packet: &Ipv4Packet) -> Duration {
+        match packet.protocol {
+            IpProtocol::Tcp => {
+                let flags = TcpFlags(packet.data[13]);
+                let transitory = flags.contains(TcpFlags::FIN)
+                    || flags.contains(TcpFlags::RST)
+                    || (flags.contains(TcpFlags::SYN) && !flags.contains(TcpFlags::ACK));
+                if transitory {
+                    self.config.tcp_transitory_timeout
+                } else {
+                    self.config.tcp_timeout
+                }
+            }
+            IpProtocol::Icmp => self.config.icmp_timeout,
+            _ => self.config.udp_timeout,
+        }
+    }
+
+    fn allocate_port(&mut self, protocol: IpProtocol) -> Option<u16> {
+        let (first, last) = (*self.config.ports.start(), *self.config.ports.end());
+        let count = (last - first) as usize + 1;
+        for _ in 0..count {
+            let port = self.next_port;
+            self.next_port = if port == last { first } else { port + 1 };
+            if !self.mappings.contains_key(&(protocol, port)) {
+                return Some(port);
+            }
+        }
+        None
+    }
+
+    // None when the packet can't be translated or there is no port left
+    pub fn translate_outbound(
+        &mut self,
+        mut packet: Ipv4Packet,
+        now: Instant,
+    ) -> Option<Ipv4Packet> {
+        let protocol = packet.protocol;
+        let layout = layout(protocol, &packet.data)?;
+        let internal = SocketAddrV4::new(packet.source, read_u16(&packet.data, layout.source_port));
+        // ICMP queries have a single identifier and no port on the remote side
+        let remote_port = match protocol {
+            IpProtocol::Icmp => 0,
+            _ => read_u16(&packet.data, layout.destin_port),
+        };
+        let remote = SocketAddrV4::new(packet.destin, remote_port);
+
+        let key = match self.config.nat_type {
+            NatType::Symmetric => (protocol, internal, Some(remote)),
+            _ => (protocol, internal, None),
+        };
+        let external_port = match self
+            .outbound
+            .get(&(protocol, internal, None))
+            .or_else(|| self.outbound.get(&key))
+        {
+            Some(port) => *port,
+            None => {
+                let Some(port) = self.allocate_port(protocol) else {
+                    log::warn!("NAT {}: out of {protocol:?} ports", self.external_address);
+                    return None;
+                };
+                log::debug!(
+                    "NAT {}: mapping {protocol:?} {internal} to port {port}",
+                    self.external_address
+                );
+                self.outbound.insert(key, port);
+                self.mappings.insert(
+                    (protocol, port),
+                    Mapping {
+                        protocol,
+                        internal,
+                        external_port: port,
+                        remote: key.2,
+                        // refreshed below
+                        expires_at: Some(now),
+                        contacted: HashSet::new(),
+                    },
+                );
+                port
+            }
+        };
+
+        let timeout = self.timeout(&packet);
+        let mapping = self.mappings.get_mut(&(protocol, external_port)).unwrap();
+        if mapping.expires_at.is_some() {
+            mapping.expires_at = Some(now + timeout);
+        }
+        mapping.contacted.insert(remote);
+
+        rewrite(
+            &mut packet,
+            &layout,
+            Side::Source,
+            SocketAddrV4::new(self.external_address, external_port),
+        );
+        Some(packet)
+    }
+
+    // None when the packet isn't for a mapping or is filtered out
+    pub fn translate_inbound(
+        &mut self,
+        mut packet: Ipv4Packet,
+        now: Instant,
+    ) -> Option<Ipv4Packet> {
+        if packet.destin != self.external_address {
+            return None;
+        }
+        if packet.protocol == IpProtocol::Icmp {
+            let errors = [
+                IcmpType::DestinationUnreachable as u8,
+                IcmpType::TimeExceeded as u8,
+            ];
+            if packet
+                .data
+                .first()
+                .is_some_and(|kind| errors.contains(kind))
+            {
+                return self.translate_error(packet);
+            }
+        }
+
+        let protocol = packet.protocol;
+        let layout = layout(protocol, &packet.data)?;
+        let external_port = read_u16(&packet.data, layout.destin_port);
+        let remote_port = match protocol {
+            IpProtocol::Icmp => 0,
+            _ => read_u16(&packet.data, layout.source_port),
+        };
+        let remote = SocketAddrV4::new(packet.source, remote_port);
+
+        let nat_type = self.config.nat_type;
+        let timeout = self.timeout(&packet);
+        let mapping = self.mappings.get_mut(&(protocol, external_port))?;
+        let allowed = mapping.expires_at.is_none()
+            || match nat_type {
+                NatType::FullCone => true,
+                NatType::AddressRestricted => mapping
+                    .contacted
+                    .iter()
+                    .any(|contacted| contacted.ip() == remote.ip()),
+                NatType::PortRestricted => mapping.contacted.contains(&remote),
+                NatType::Symmetric => mapping.remote == Some(remote),
+            };
+        if !allowed {
+            log::debug!(
+                "NAT {}: filtered {protocol:?} from {remote} to port {external_port}",
+                self.external_address
+            );
+            return None;
+        }
+
+        if mapping.expires_at.is_some() {
+            mapping.expires_at = Some(now + timeout);
+        }
+        let internal = mapping.internal;
+        rewrite(&mut packet, &layout, Side::Destin, internal);
+        Some(packet)
+    }
+
+    // the error quotes a packet the NAT translated on its way out, both have to be translated back
+    fn translate_error(&self, mut packet: Ipv4Packet) -> Option<Ipv4Packet> {
+        let mut data = packet.data.to_vec();
+        let quote = data.get_mut(ICMP_HEADER_SIZE..)?;
+        let header_len = (*quote.first()? & 0x0F) as usize * 4;
+        let protocol = IpProtocol::from(*quote.get(9)?);
+        let source: [u8; 4] = quote.get(12..16)?.try_into().unwrap();
+        if Ipv4Addr::from(source) != self.external_address {
+            return None;
+        }
+
+        // the quoted packet went out translated, so its source is the mapped side
+        let (header, transport) = quote.split_at_mut_checked(header_len)?;
+        let layout = quoted_layout(protocol, transport)?;
+        let external_port = read_u16(transport, layout.source_port);
+        let internal = self.mappings.get(&(protocol, external_port))?.internal;
+
+        let header_checksum = read_u16(header, 10);
+        let header_checksum = adjust_checksum(header_checksum, &source, &internal.ip().octets());
+        header[10..12].copy_from_slice(&header_checksum.to_be_bytes());
+        header[12..16].copy_from_slice(&internal.ip().octets());
+        rewrite_port(
+            protocol,
+            transport,
+            &layout,
+            layout.source_port,
+            source.into(),
+            internal,
+        );
+
+        data[2..4].copy_from_slice(&[0, 0]);
+        let checksum = internet_checksum(&data);
+        data[2..4].copy_from_slice(&checksum.to_be_bytes());
+        packet.destin = *internal.ip();
+        packet.data = data.into();
+        Some(packet)
+    }
+
+    pub fn expire(&mut self, now: Instant) {
+        let expired: Vec<_> = self
+            .mappings
+            .iter()
+            .filter(|(_, mapping)| mapping.expires_at.is_some_and(|at| at <= now))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired {
+            let mapping = self.mappings.remove(&key).unwrap();
+            log::debug!(
+                "NAT {}: {:?} mapping of {} to port {} expired",
+                self.external_address,
+                mapping.protocol,
+                mapping.internal,
+                mapping.external_port
+            );
+            self.outbound
+                .retain(|(protocol, _, _), port| (*protocol, *port) != key);
+        }
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.mappings
+            .values()
+            .filter_map(|mapping| mapping.expires_at)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Nat, NatConfig, NatType};
+    use crate::protocols::{
+        icmp::{IcmpPacket, IcmpType, ICMP_TTL_EXCEEDED},
+        internet_checksum,
+        ipv4::{IpProtocol, Ipv4Packet},
+        tcp::{TcpFlags, TcpSegment},
+        udp::UdpDatagram,
+        Packet,
+    };
+    use std::{
+        net::{Ipv4Addr, SocketAddrV4},
+        time::{Duration, Instant},
+    };
+
+    const EXTERNAL: Ipv4Addr = Ipv4Addr::new(203, 0, 113, 1);
+    const INTERNAL: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(192, 168, 0, 2), 5000);
+    const REMOTE: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(198, 51, 100, 1), 3478);
+
+    fn pseudo_header(packet: &Ipv4Packet) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(packet.source.octets());
+        bytes.extend(packet.destin.octets());
+        bytes.extend([0, packet.protocol.into()]);
+        bytes.extend((packet.data.len() as u16).to_be_bytes());
+        bytes.extend(&packet.data);
+        bytes
+    }
+
+    // with a checksum, which the datagrams of the simulator don't have
+    fn udp(source: SocketAddrV4, destin: SocketAddrV4) -> Ipv4Packet {
+        let datagram = UdpDatagram {
+            source_port: source.port(),
+            destin_port: destin.port(),
+            data: Box::new([1, 2, 3]),
+        };
+        let mut packet = Ipv4Packet::new(
+            *source.ip(),
+            *destin.ip(),
+            IpProtocol::Udp,
+            datagram.to_bytes(),
+        );
+        let checksum = internet_checksum(&pseudo_header(&packet));
+        let mut data = packet.data.to_vec();
+        data[6..8].copy_from_slice(&checksum.to_be_bytes());
+        packet.data = data.into();
+        packet
+    }
+
+    // the source and destination of the datagram, after checking its checksum
+    fn endpoints(packet: &Ipv4Packet) -> (SocketAddrV4, SocketAddrV4) {
+        assert_eq!(internet_checksum(&pseudo_header(packet)), 0);
+        let datagram = UdpDatagram::decode(&packet.data).unwrap();
+        (
+            SocketAddrV4::new(packet.source, datagram.source_port),
+            SocketAddrV4::new(packet.destin, datagram.destin_port),
+        )
+    }
+
+    fn nat(nat_type: NatType) -> Nat {
+        Nat::new(
+            EXTERNAL,
+            NatConfig {
+                nat_type,
+                ..NatConfig::default()
+            },
+        )
+    }
+
+    #[test]
+    fn cone_nats() {
+        let other_port = SocketAddrV4::new(*REMOTE.ip(), 3479);
+        let other_host = SocketAddrV4::new(Ipv4Addr::new(198, 51, 100, 2), 3478);
+        let mapped = SocketAddrV4::new(EXTERNAL, 1024);
+        let now = Instant::now();
+
+        for (nat_type, allowed) in [
+            (NatType::FullCone, [true, true]),
+            (NatType::AddressRestricted, [true, false]),
+            (NatType::PortRestricted, [false, false]),
+        ] {
+            let mut nat = nat(nat_type);
+            let packet = nat.translate_outbound(udp(INTERNAL, REMOTE), now).unwrap();
+            assert_eq!(endpoints(&packet), (mapped, REMOTE));
+
+            let inbound = nat.translate_inbound(udp(REMOTE, mapped), now).unwrap();
+            assert_eq!(endpoints(&inbound), (REMOTE, INTERNAL));
+            for (remote, allowed) in [other_port, other_host].into_iter().zip(allowed) {
+                let inbound = nat.translate_inbound(udp(remote, mapped), now);
+                assert_eq!(inbound.is_some(), allowed, "{nat_type:?} from {remote}");
+            }
+
+            // the mapping doesn't depend on the destination
+            let packet = nat
+                .translate_outbound(udp(INTERNAL, other_host), now)
+                .unwrap();
+            assert_eq!(endpoints(&packet).0, mapped);
+            assert!(nat
+                .translate_inbound(udp(other_host, mapped), now)
+                .is_some());
+        }
+    }
+
+    #[test]
+    fn symmetric_nat() {
+        let mut nat = nat(NatType::Symmetric);
+        let other = SocketAddrV4::new(Ipv4Addr::new(198, 51, 100, 2), 3478);
+        let now = Instant::now();
+
+        let first = nat.translate_outbound(udp(INTERNAL, REMOTE), now).unwrap();
+        let second = nat.translate_outbound(udp(INTERNAL, other), now).unwrap();
+        let (first, second) = (endpoints(&first).0, endpoints(&second).0);
+        assert_eq!(first.port(), 1024);
+        assert_eq!(second.port(), 1025);
+
+        assert!(nat.translate_inbound(udp(other, first), now).is_none());
+        assert!(nat.translate_inbound(udp(REMOTE, first), now).is_some());
+        assert!(nat.translate_inbound(udp(other, second), now).is_some());
+    }
+
+    #[test]
+    fn forwards_and_timeouts() {
+        let mut nat = nat(NatType::PortRestricted);
+        let server = SocketAddrV4::new(*INTERNAL.ip(), 80);
+        nat.add_port_forward(IpProtocol::Tcp, 8080, server);
+        let now = Instant::now();
+
+        let syn = TcpSegment {
+            source_port: REMOTE.port(),
+            destin_port: 8080,
+            seq: 1,
+            ack: 0,
+            flags: TcpFlags::SYN,
+            window: 65535,
+            data: Box::new([]),
+        };
+        let packet = Ipv4Packet::new(*REMOTE.ip(), EXTERNAL, IpProtocol::Tcp, syn.to_bytes());
+        let inbound = nat.translate_inbound(packet, now).unwrap();
+        assert_eq!(inbound.destin, *INTERNAL.ip());
+        assert_eq!(TcpSegment::decode(&inbound.data).unwrap().destin_port, 80);
+
+        // pings are mapped by their identifier
+        let echo = IcmpPacket::echo_request(7, 1, Box::new([]));
+        let packet = Ipv4Packet::new(
+            *INTERNAL.ip(),
+            *REMOTE.ip(),
+            IpProtocol::Icmp,
+            echo.to_bytes(),
+        );
+        let outbound = nat.translate_outbound(packet, now).unwrap();
+        let echo = IcmpPacket::decode(&outbound.data).unwrap();
+        assert_eq!(echo.identifier(), 1024);
+        let reply = Ipv4Packet::new(
+            *REMOTE.ip(),
+            EXTERNAL,
+            IpProtocol::Icmp,
+            echo.echo_reply().to_bytes(),
+        );
+        let inbound = nat.translate_inbound(reply, now).unwrap();
+        assert_eq!(IcmpPacket::decode(&inbound.data).unwrap().identifier(), 7);
+
+        // errors about translated packets reach the internal host
+        let outbound = nat.translate_outbound(udp(INTERNAL, REMOTE), now).unwrap();
+        let error = IcmpPacket::error(
+            IcmpType::TimeExceeded,
+            ICMP_TTL_EXCEEDED,
+            &outbound.to_bytes(),
+        );
+        let router = Ipv4Addr::new(198, 51, 100, 254);
+        let packet = Ipv4Packet::new(router, EXTERNAL, IpProtocol::Icmp, error.to_bytes());
+        let inbound = nat.translate_inbound(packet, now).unwrap();
+        assert_eq!(inbound.destin, *INTERNAL.ip());
+        let error = IcmpPacket::decode(&inbound.data).unwrap();
+        assert_eq!(internet_checksum(&error.data[..20]), 0);
+        assert_eq!(error.data[12..16], INTERNAL.ip().octets());
+        assert_eq!(error.data[20..22], INTERNAL.port().to_be_bytes());
+
+        assert_eq!(nat.next_deadline(), Some(now + Duration::from_secs(60)));
+        nat.expire(now + Duration::from_secs(300));
+        assert_eq!(nat.mappings().count(), 1);
+        let mapped = endpoints(&outbound).0;
+        assert!(nat.translate_inbound(udp(REMOTE, mapped), now).is_none());
+    }
+
+    #[test]
+    fn translates_quoted_packets() {
+        let mut nat = nat(NatType::PortRestricted);
+        let now = Instant::now();
+        let echo = IcmpPacket::echo_request(7, 1, Box::new([1, 2, 3, 4]));
+        let ping = Ipv4Packet::new(
+            *INTERNAL.ip(),
+            *REMOTE.ip(),
+            IpProtocol::Icmp,
+            echo.to_bytes(),
+        );
+
+        for sent in [udp(INTERNAL, REMOTE), ping] {
+            let outbound = nat.translate_outbound(sent.clone(), now).unwrap();
+            let error = IcmpPacket::error(
+                IcmpType::TimeExceeded,
+                ICMP_TTL_EXCEEDED,
+                &outbound.to_bytes(),
+            );
+            let router = Ipv4Addr::new(198, 51, 100, 254);
+            let packet = Ipv4Packet::new(router, EXTERNAL, IpProtocol::Icmp, error.to_bytes());
+            let inbound = nat.translate_inbound(packet, now).unwrap();
+
+            // the quote is the start of what the internal host sent, checksums included
+            let error = IcmpPacket::decode(&inbound.data).unwrap();
+            let sent = sent.to_bytes();
+            assert_eq!(
+                error.data[..],
+                sent[..error.data.len()],
+                "{:?}",
+                outbound.protocol
+            );
+        }
+    }
+}
@@ -0,0 +1,221 @@
+pub mod slaac;
+
+use crate::protocols::{
+    ethernet::MacAddress,
+    icmpv6::{Icmpv6Packet, NeighborAdvertisement},
+    ipv6::{multicast_mac_address, solicited_node_address, Ipv6Packet, NextHeader, ALL_NODES},
+    Packet,
+};
+use std::{
+    collections::HashMap,
+    net::Ipv6Addr,
+    time::{Duration, Instant},
+};
+
+// neighbor discovery messages are only accepted when they weren't forwarded
+pub const NDP_HOP_LIMIT: u8 = 255;
+
+// how long to wait for an advertisement before soliciting again
+const SOLICIT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+// packets queued per unresolved address, the oldest ones are dropped
+const QUEUE_SIZE: usize = 16;
+
+struct PendingResolution {
+    requested_at: Instant,
+    packets: Vec<Ipv6Packet>,
+}
+
+pub fn ndp_packet(source: Ipv6Addr, destin: Ipv6Addr, message: &Icmpv6Packet) -> Ipv6Packet {
+    let mut packet = Ipv6Packet::new(source, destin, NextHeader::Icmpv6, message.to_bytes());
+    packet.hop_limit = NDP_HOP_LIMIT;
+    packet
+}
+
+// The IPv6 replacement of ARP (https://www.rfc-editor.org/rfc/rfc4861#section-7): the link
+// addresses of the neighbors and the packets waiting for one to be resolved. Entries never become
+// stale, like in the ARP caches of the simulator.
+pub struct NeighborCache {
+    mac: MacAddress,
+    entries: HashMap<Ipv6Addr, MacAddress>,
+    pending: HashMap<Ipv6Addr, PendingResolution>,
+}
+
+impl NeighborCache {
+    pub fn new(mac: MacAddress) -> Self {
+        Self {
+            mac,
+            entries: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn lookup(&self, address: Ipv6Addr) -> Option<MacAddress> {
+        self.entries.get(&address).copied()
+    }
+
+    pub fn clear_pending(&mut self) {
+        self.pending.clear();
+    }
+
+    // Returns what can be sent right away with the MAC address it is for: the packet when the
+    // link address of `next_hop` is known, otherwise a solicitation for it from `source` if it is
+    // time for one. The packet then waits for the neighbor to answer.
+    pub fn send(
+        &mut self,
+        source: Ipv6Addr,
+        next_hop: Ipv6Addr,
+        packet: Ipv6Packet,
+        now: Instant,
+    ) -> Option<(MacAddress, Ipv6Packet)> {
+        if next_hop.is_multicast() {
+            return Some((multicast_mac_address(next_hop), packet));
+        }
+        if let Some(mac) = self.lookup(next_hop) {
+            return Some((mac, packet));
+        }
+
+        let pending = self.pending.entry(next_hop).or_insert(PendingResolution {
+            requested_at: now,
+            packets: Vec::new(),
+        });
+        if pending.packets.len() == QUEUE_SIZE {
+            pending.packets.remove(0);
+        }
+        pending.packets.push(packet);
+
+        let first = pending.packets.len() == 1;
+        if !first && now.duration_since(pending.requested_at) < SOLICIT_RETRY_INTERVAL {
+            return None;
+        }
+        pending.requested_at = now;
+
+        let solicitation = Icmpv6Packet::NeighborSolicitation {
+            target: next_hop,
+            source_mac: Some(self.mac),
+        };
+        let group = solicited_node_address(next_hop);
+        Some((
+            multicast_mac_address(group),
+            ndp_packet(source, group, &solicitation),
+        ))
+    }
+
+    fn learn(&mut self, address: Ipv6Addr, mac: MacAddress) -> Vec<(MacAddress, Ipv6Packet)> {
+        self.entries.insert(address, mac);
+        self.pending
+            .remove(&address)
+            .map(|pending| pending.packets)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|packet| (mac, packet))
+            .collect()
+    }
+
+    // Handles the solicitations and advertisements, returning the frames to send: the packets that
+    // waited for the neighbor and the answer to a solicitation for one of the `own` addresses.
+    pub fn handle(
+        &mut self,
+        packet: &Ipv6Packet,
+        message: &Icmpv6Packet,
+        own: &[Ipv6Addr],
+        router: bool,
+    ) -> Vec<(MacAddress, Ipv6Packet)> {
+        if packet.hop_limit != NDP_HOP_LIMIT {
+            return Vec::new();
+        }
+
+        match message {
+            Icmpv6Packet::NeighborSolicitation { target, source_mac } => {
+                let mut out = Vec::new();
+                // solicitations from the unspecified address come from duplicate address detection
+                let dad = packet.source.is_unspecified();
+                if let (Some(mac), false) = (source_mac, dad) {
+                    out = self.learn(packet.source, *mac);
+                }
+                if !own.contains(target) {
+                    return out;
+                }
+
+                let advertisement = Icmpv6Packet::NeighborAdvertisement(NeighborAdvertisement {
+                    target: *target,
+                    router,
+                    solicited: !dad,
+                    override_cache: true,
+                    target_mac: Some(self.mac),
+                });
+                let (destin, destin_mac) = match (dad, source_mac) {
+                    (false, Some(mac)) => (packet.source, *mac),
+                    (false, None) => match self.lookup(packet.source) {
+                        Some(mac) => (packet.source, mac),
+                        None => (ALL_NODES, multicast_mac_address(ALL_NODES)),
+                    },
+                    (true, _) => (ALL_NODES, multicast_mac_address(ALL_NODES)),
+                };
+                out.push((destin_mac, ndp_packet(*target, destin, &advertisement)));
+                out
+            }
+            Icmpv6Packet::NeighborAdvertisement(advertisement) => match advertisement.target_mac {
+                Some(mac) => self.learn(advertisement.target, mac),
+                None => Vec::new(),
+            },
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::NeighborCache;
+    use crate::protocols::{
+        ethernet::MacAddress,
+        icmpv6::Icmpv6Packet,
+        ipv6::{link_local_address, multicast_mac_address, Ipv6Packet, NextHeader},
+        Packet,
+    };
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn resolves_neighbors() {
+        let macs = [MacAddress::new([1; 6]), MacAddress::new([2; 6])];
+        let addresses = macs.map(link_local_address);
+        let mut caches = macs.map(NeighborCache::new);
+        let now = Instant::now();
+
+        let packet = Ipv6Packet::new(addresses[0], addresses[1], NextHeader::Udp, Box::new([]));
+        let (mac, solicitation) = caches[0]
+            .send(addresses[0], addresses[1], packet.clone(), now)
+            .unwrap();
+        assert_eq!(mac, multicast_mac_address(solicitation.destin));
+        // no new solicitation until the retry interval passed
+        assert!(caches[0]
+            .send(addresses[0], addresses[1], packet.clone(), now)
+            .is_none());
+        let later = now + Duration::from_secs(1);
+        assert!(caches[0]
+            .send(addresses[0], addresses[1], packet.clone(), later)
+            .is_some());
+
+        let message = Icmpv6Packet::decode(&solicitation.data).unwrap();
+        let out = caches[1].handle(&solicitation, &message, &[addresses[1]], false);
+        assert_eq!(out.len(), 1);
+        let (mac, advertisement) = &out[0];
+        assert_eq!(*mac, macs[0]);
+        assert_eq!(caches[1].lookup(addresses[0]), Some(macs[0]));
+
+        let message = Icmpv6Packet::decode(&advertisement.data).unwrap();
+        let out = caches[0].handle(advertisement, &message, &[addresses[0]], false);
+        // every packet queued while waiting
+        assert_eq!(out, vec![(macs[1], packet.clone()); 3]);
+        assert_eq!(
+            caches[0].send(addresses[0], addresses[1], packet.clone(), later),
+            Some((macs[1], packet))
+        );
+
+        // forwarded messages are ignored
+        let mut forwarded = advertisement.clone();
+        forwarded.hop_limit -= 1;
+        let mut cache = NeighborCache::new(macs[0]);
+        assert!(cache.handle(&forwarded, &message, &[], false).is_empty());
+        assert_eq!(cache.lookup(addresses[1]), None);
+    }
+}
@@ -0,0 +1,444 @@
+use super::{ndp_packet, NDP_HOP_LIMIT};
+use crate::protocols::{
+    ethernet::MacAddress,
+    icmpv6::{Icmpv6Packet, RouterAdvertisement},
+    ipv6::{
+        link_local_address, solicited_node_address, Ipv6Packet, Ipv6Prefix, ALL_NODES, ALL_ROUTERS,
+    },
+};
+use std::{
+    net::Ipv6Addr,
+    time::{Duration, Instant},
+};
+
+// how long duplicate address detection waits for someone to claim the address
+const DAD_DELAY: Duration = Duration::from_secs(1);
+const SOLICITATION_INTERVAL: Duration = Duration::from_secs(4);
+const MAX_SOLICITATIONS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressState {
+    // until duplicate address detection is done
+    Tentative(Instant),
+    Preferred,
+    // still usable, but not for new connections
+    Deprecated,
+    Duplicate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutoAddress {
+    pub address: Ipv6Addr,
+    pub prefix: Ipv6Prefix,
+    pub state: AddressState,
+    // None being forever
+    pub preferred_until: Option<Instant>,
+    pub valid_until: Option<Instant>,
+}
+
+impl AutoAddress {
+    pub fn is_usable(&self) -> bool {
+        matches!(
+            self.state,
+            AddressState::Preferred | AddressState::Deprecated
+        )
+    }
+}
+
+fn lifetime_end(now: Instant, seconds: u32) -> Option<Instant> {
+    (seconds != u32::MAX).then(|| now + Duration::from_secs(seconds.into()))
+}
+
+// Stateless address autoconfiguration (https://www.rfc-editor.org/rfc/rfc4862) of a host: a
+// link-local address, then one per prefix the routers advertise, all derived from the MAC address
+// and checked for duplicates first. Packets to send are returned by `poll`, which has to be called
+// after `start` and `handle` too.
+pub struct Slaac {
+    mac: MacAddress,
+    addresses: Vec<AutoAddress>,
+    // the routers and when their advertised lifetime ends
+    routers: Vec<(Ipv6Addr, Instant)>,
+    on_link: Vec<(Ipv6Prefix, Option<Instant>)>,
+    solicitations: u32,
+    next_solicitation: Option<Instant>,
+    outgoing: Vec<Ipv6Packet>,
+}
+
+impl Slaac {
+    pub fn new(mac: MacAddress) -> Self {
+        Self {
+            mac,
+            addresses: Vec::new(),
+            routers: Vec::new(),
+            on_link: Vec::new(),
+            solicitations: 0,
+            next_solicitation: None,
+            outgoing: Vec::new(),
+        }
+    }
+
+    pub fn start(&mut self, now: Instant) {
+        self.addresses.clear();
+        self.routers.clear();
+        self.on_link.clear();
+
+        let link_local = link_local_address(self.mac);
+        self.add_address(link_local, Ipv6Prefix::new(link_local, 64), None, None, now);
+        self.solicitations = 0;
+        self.next_solicitation = Some(now);
+    }
+
+    fn add_address(
+        &mut self,
+        address: Ipv6Addr,
+        prefix: Ipv6Prefix,
+        valid_until: Option<Instant>,
+        preferred_until: Option<Instant>,
+        now: Instant,
+    ) {
+        self.addresses.push(AutoAddress {
+            address,
+            prefix,
+            state: AddressState::Tentative(now + DAD_DELAY),
+            preferred_until,
+            valid_until,
+        });
+
+        let solicitation = Icmpv6Packet::NeighborSolicitation {
+            target: address,
+            source_mac: None,
+        };
+        self.outgoing.push(ndp_packet(
+            Ipv6Addr::UNSPECIFIED,
+            solicited_node_address(address),
+            &solicitation,
+        ));
+    }
+
+    pub fn all_addresses(&self) -> &[AutoAddress] {
+        &self.addresses
+    }
+
+    // the addresses that passed duplicate address detection
+    pub fn addresses(&self) -> Vec<Ipv6Addr> {
+        self.addresses
+            .iter()
+            .filter(|address| address.is_usable())
+            .map(|address| address.address)
+            .collect()
+    }
+
+    pub fn link_local_address(&self) -> Option<Ipv6Addr> {
+        let address = link_local_address(self.mac);
+        self.addresses().contains(&address).then_some(address)
+    }
+
+    pub fn default_router(&self) -> Option<Ipv6Addr> {
+        self.routers.first().map(|(router, _)| *router)
+    }
+
+    // whether packets to `destin` are for the host, including the solicitations for its
+    // tentative addresses
+    pub fn listens_to(&self, destin: Ipv6Addr) -> bool {
+        destin == ALL_NODES
+            || self.addresses.iter().any(|address| {
+                (address.is_usable() && address.address == destin)
+                    || (address.state != AddressState::Duplicate
+                        && solicited_node_address(address.address) == destin)
+            })
+    }
+
+    // where to send packets for `destin`: straight to it when it is on the link, otherwise to
+    // the default router
+    pub fn next_hop(&self, destin: Ipv6Addr) -> Option<Ipv6Addr> {
+        let on_link = destin.is_multicast()
+            || destin.is_unicast_link_local()
+            || self
+                .on_link
+                .iter()
+                .any(|(prefix, _)| prefix.contains(destin));
+        if on_link {
+            Some(destin)
+        } else {
+            self.default_router()
+        }
+    }
+
+    // the address to send from, preferring the global ones that aren't deprecated
+    pub fn source_address(&self, destin: Ipv6Addr) -> Option<Ipv6Addr> {
+        if destin.is_unicast_link_local() || destin.is_multicast() {
+            return self.link_local_address();
+        }
+
+        let global = |state| {
+            self.addresses
+                .iter()
+                .find(|address| address.state == state && !address.address.is_unicast_link_local())
+        };
+        global(AddressState::Preferred)
+            .or_else(|| global(AddressState::Deprecated))
+            .map(|address| address.address)
+    }
+
+    pub fn handle(&mut self, packet: &Ipv6Packet, message: &Icmpv6Packet, now: Instant) {
+        if packet.hop_limit != NDP_HOP_LIMIT {
+            return;
+        }
+
+        match message {
+            Icmpv6Packet::RouterAdvertisement(advertisement)
+                if packet.source.is_unicast_link_local() =>
+            {
+                self.handle_advertisement(packet.source, advertisement, now)
+            }
+            // someone else uses or is trying to use one of the tentative addresses
+            Icmpv6Packet::NeighborSolicitation { target, .. } if packet.source.is_unspecified() => {
+                self.mark_duplicate(*target)
+            }
+            Icmpv6Packet::NeighborAdvertisement(advertisement) => {
+                self.mark_duplicate(advertisement.target)
+            }
+            _ => {}
+        }
+    }
+
+    fn mark_duplicate(&mut self, target: Ipv6Addr) {
+        for address in self.addresses.iter_mut() {
+            if address.address == target && matches!(address.state, AddressState::Tentative(_)) {
+                log::warn!("SLAAC {}: {target} is a duplicate", self.mac);
+                address.state = AddressState::Duplicate;
+            }
+        }
+    }
+
+    fn handle_advertisement(
+        &mut self,
+        router: Ipv6Addr,
+        advertisement: &RouterAdvertisement,
+        now: Instant,
+    ) {
+        self.next_solicitation = None;
+
+        self.routers.retain(|(known, _)| *known != router);
+        if advertisement.router_lifetime > 0 {
+            let lifetime = Duration::from_secs(advertisement.router_lifetime.into());
+            self.routers.push((router, now + lifetime));
+        }
+
+        for information in advertisement.prefixes.iter() {
+            let prefix = information.prefix;
+            if prefix.address().is_unicast_link_local()
+                || information.preferred_lifetime > information.valid_lifetime
+            {
+                continue;
+            }
+            let valid_until = lifetime_end(now, information.valid_lifetime);
+            let preferred_until = lifetime_end(now, information.preferred_lifetime);
+
+            if information.on_link {
+                self.on_link.retain(|(known, _)| *known != prefix);
+                if information.valid_lifetime > 0 {
+                    self.on_link.push((prefix, valid_until));
+                }
+            }
+
+            if !information.autonomous || prefix.prefix_len() != 64 {
+                continue;
+            }
+            let address = prefix.with_interface_id(self.mac);
+            match self
+                .addresses
+                .iter_mut()
+                .find(|known| known.address == address)
+            {
+                Some(known) => {
+                    known.valid_until = valid_until;
+                    known.preferred_until = preferred_until;
+                    if known.state == AddressState::Deprecated && information.preferred_lifetime > 0
+                    {
+                        known.state = AddressState::Preferred;
+                    }
+                }
+                None if information.valid_lifetime > 0 => {
+                    self.add_address(address, prefix, valid_until, preferred_until, now)
+                }
+                None => {}
+            }
+        }
+    }
+
+    pub fn poll(&mut self, now: Instant) -> Vec<Ipv6Packet> {
+        let expired = |until: Option<Instant>| until.is_some_and(|until| until <= now);
+
+        for address in self.addresses.iter_mut() {
+            match address.state {
+                AddressState::Tentative(until) if until <= now => {
+                    log::info!("SLAAC {}: configured {}", self.mac, address.address);
+                    address.state = AddressState::Preferred;
+                }
+                AddressState::Preferred if expired(address.preferred_until) => {
+                    address.state = AddressState::Deprecated;
+                }
+                _ => {}
+            }
+        }
+        self.addresses.retain(|address| {
+            let keep = !expired(address.valid_until);
+            if !keep {
+                log::info!("SLAAC {}: {} expired", self.mac, address.address);
+            }
+            keep
+        });
+        self.routers.retain(|(_, until)| *until > now);
+        self.on_link.retain(|(_, until)| !expired(*until));
+
+        if self.next_solicitation.is_some_and(|next| next <= now) {
+            // without an address yet the solicitation comes from nowhere and can't carry the MAC
+            let source = self.link_local_address();
+            let solicitation = Icmpv6Packet::RouterSolicitation {
+                source_mac: source.map(|_| self.mac),
+            };
+            self.outgoing.push(ndp_packet(
+                source.unwrap_or(Ipv6Addr::UNSPECIFIED),
+                ALL_ROUTERS,
+                &solicitation,
+            ));
+
+            self.solicitations += 1;
+            self.next_solicitation =
+                (self.solicitations < MAX_SOLICITATIONS).then_some(now + SOLICITATION_INTERVAL);
+        }
+
+        std::mem::take(&mut self.outgoing)
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let addresses = self.addresses.iter().flat_map(|address| {
+            let tentative = match address.state {
+                AddressState::Tentative(until) => Some(until),
+                _ => None,
+            };
+            [tentative, address.preferred_until, address.valid_until]
+        });
+        addresses
+            .chain(self.routers.iter().map(|(_, until)| Some(*until)))
+            .chain(self.on_link.iter().map(|(_, until)| *until))
+            .chain([self.next_solicitation])
+            .flatten()
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AddressState, Slaac};
+    use crate::{
+        ndp::ndp_packet,
+        protocols::{
+            ethernet::MacAddress,
+            icmpv6::{Icmpv6Packet, PrefixInformation, RouterAdvertisement},
+            ipv6::{
+                link_local_address, solicited_node_address, Ipv6Prefix, ALL_NODES, ALL_ROUTERS,
+            },
+        },
+    };
+    use std::{
+        net::Ipv6Addr,
+        time::{Duration, Instant},
+    };
+
+    const MAC: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 1]);
+
+    fn advertisement(prefix: Ipv6Prefix, valid_lifetime: u32) -> Icmpv6Packet {
+        Icmpv6Packet::RouterAdvertisement(RouterAdvertisement {
+            hop_limit: 64,
+            managed: false,
+            other: false,
+            router_lifetime: 1800,
+            reachable_time: 0,
+            retransmit_timer: 0,
+            source_mac: Some(MacAddress::new([1; 6])),
+            mtu: None,
+            prefixes: vec![PrefixInformation {
+                prefix,
+                on_link: true,
+                autonomous: true,
+                valid_lifetime,
+                preferred_lifetime: valid_lifetime / 2,
+            }],
+        })
+    }
+
+    #[test]
+    fn configures_addresses() {
+        let mut slaac = Slaac::new(MAC);
+        let now = Instant::now();
+        slaac.start(now);
+
+        // the duplicate address detection and the first router solicitation
+        let out = slaac.poll(now);
+        assert_eq!(out.len(), 2);
+        assert!(out.iter().all(|packet| packet.source.is_unspecified()));
+        assert_eq!(out[1].destin, ALL_ROUTERS);
+        assert!(slaac.addresses().is_empty());
+        assert_eq!(slaac.next_deadline(), Some(now + Duration::from_secs(1)));
+
+        let now = now + Duration::from_secs(1);
+        assert!(slaac.poll(now).is_empty());
+        let link_local = link_local_address(MAC);
+        assert_eq!(slaac.addresses(), [link_local]);
+
+        let router = link_local_address(MacAddress::new([1; 6]));
+        let prefix: Ipv6Prefix = "2001:db8:1::/64".parse().unwrap();
+        let message = advertisement(prefix, 60);
+        slaac.handle(&ndp_packet(router, ALL_NODES, &message), &message, now);
+        assert_eq!(slaac.default_router(), Some(router));
+        let out = slaac.poll(now);
+        assert_eq!(out.len(), 1);
+        let global = prefix.with_interface_id(MAC);
+        assert!(slaac.listens_to(out[0].destin));
+        assert_eq!(slaac.source_address(global), None);
+
+        let now = now + Duration::from_secs(1);
+        slaac.poll(now);
+        assert_eq!(slaac.addresses(), [link_local, global]);
+        assert_eq!(
+            slaac.source_address("2001:db8:2::1".parse().unwrap()),
+            Some(global)
+        );
+        assert_eq!(slaac.next_hop(global), Some(global));
+        let remote: Ipv6Addr = "2001:db8:2::1".parse().unwrap();
+        assert_eq!(slaac.next_hop(remote), Some(router));
+
+        // the address is deprecated, then forgotten
+        let now = now + Duration::from_secs(30);
+        slaac.poll(now);
+        assert_eq!(slaac.all_addresses()[1].state, AddressState::Deprecated);
+        assert_eq!(slaac.source_address(remote), Some(global));
+        let now = now + Duration::from_secs(30);
+        slaac.poll(now);
+        assert_eq!(slaac.addresses(), [link_local]);
+        assert_eq!(slaac.next_hop(global), Some(router));
+    }
+
+    #[test]
+    fn detects_duplicates() {
+        let mut slaac = Slaac::new(MAC);
+        let now = Instant::now();
+        slaac.start(now);
+        slaac.poll(now);
+
+        let link_local = link_local_address(MAC);
+        let message = Icmpv6Packet::NeighborSolicitation {
+            target: link_local,
+            source_mac: None,
+        };
+        let packet = ndp_packet(Ipv6Addr::UNSPECIFIED, ALL_NODES, &message);
+        slaac.handle(&packet, &message, now);
+
+        slaac.poll(now + Duration::from_secs(1));
+        assert_eq!(slaac.all_addresses()[0].state, AddressState::Duplicate);
+        assert!(slaac.addresses().is_empty());
+        assert!(!slaac.listens_to(solicited_node_address(link_local)));
+    }
+}
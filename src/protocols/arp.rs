@@ -0,0 +1,149 @@
+use super::{
+    ethernet::{MacAddress, ETHERNET_MAC_ADDR_SIZE},
+    Packet, ParseError, Parser,
+};
+use std::{io::Write, net::Ipv4Addr};
+
+pub const ARP_PACKET_SIZE: usize = 28;
+
+const ARP_HARDWARE_ETHERNET: u16 = 1;
+const ARP_PROTOCOL_IPV4: u16 = 0x0800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum ArpOperation {
+    Request = 1,
+    Reply = 2,
+}
+
+impl TryFrom<u16> for ArpOperation {
+    type Error = ();
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::Request,
+            2 => Self::Reply,
+            _ => return Err(()),
+        })
+    }
+}
+
+// Only ARP for IPv4 over Ethernet is supported (https://www.rfc-editor.org/rfc/rfc826).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArpPacket {
+    pub operation: ArpOperation,
+    pub sender_mac: MacAddress,
+    pub sender_ip: Ipv4Addr,
+    pub target_mac: MacAddress,
+    pub target_ip: Ipv4Addr,
+}
+
+impl ArpPacket {
+    pub fn request(sender_mac: MacAddress, sender_ip: Ipv4Addr, target_ip: Ipv4Addr) -> Self {
+        Self {
+            operation: ArpOperation::Request,
+            sender_mac,
+            sender_ip,
+            target_mac: MacAddress::new([0; ETHERNET_MAC_ADDR_SIZE]),
+            target_ip,
+        }
+    }
+
+    pub fn reply_to(&self, sender_mac: MacAddress) -> Self {
+        Self {
+            operation: ArpOperation::Reply,
+            sender_mac,
+            sender_ip: self.target_ip,
+            target_mac: self.sender_mac,
+            target_ip: self.sender_ip,
+        }
+    }
+}
+
+impl Packet for ArpPacket {
+    // ARP doesn't carry anything else
+    type Payload = ();
+
+    fn header_len(&self) -> usize {
+        ARP_PACKET_SIZE
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        super::write_u16(writer, ARP_HARDWARE_ETHERNET);
+        super::write_u16(writer, ARP_PROTOCOL_IPV4);
+        super::write_u8(writer, ETHERNET_MAC_ADDR_SIZE as u8);
+        super::write_u8(writer, 4);
+        super::write_u16(writer, self.operation as u16);
+        super::write_bytes(writer, self.sender_mac.as_bytes());
+        super::write_bytes(writer, &self.sender_ip.octets());
+        super::write_bytes(writer, self.target_mac.as_bytes());
+        super::write_bytes(writer, &self.target_ip.octets());
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        let mut parser = Parser::build(data);
+
+        let hardware = parser.parse_u16()?;
+        if hardware != ARP_HARDWARE_ETHERNET {
+            return Err(ParseError::InvalidFieldValue {
+                field: "arp_hardware_type",
+                value: hardware as usize,
+            });
+        }
+
+        let protocol = parser.parse_u16()?;
+        if protocol != ARP_PROTOCOL_IPV4 {
+            return Err(ParseError::InvalidFieldValue {
+                field: "arp_protocol_type",
+                value: protocol as usize,
+            });
+        }
+
+        let _lengths = parser.parse_u16()?;
+        let operation = parser.parse_u16()?;
+
+        Ok(Self {
+            operation: operation
+                .try_into()
+                .map_err(|_| ParseError::InvalidFieldValue {
+                    field: "arp_operation",
+                    value: operation as usize,
+                })?,
+            sender_mac: MacAddress::new(parser.parse_chunk()?),
+            sender_ip: Ipv4Addr::from(parser.parse_chunk::<4>()?),
+            target_mac: MacAddress::new(parser.parse_chunk()?),
+            target_ip: Ipv4Addr::from(parser.parse_chunk::<4>()?),
+        })
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ArpOperation, ArpPacket, ARP_PACKET_SIZE};
+    use crate::protocols::{ethernet::MacAddress, Packet, ParseError};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn request_and_reply() {
+        let request = ArpPacket::request(
+            MacAddress::new([1; 6]),
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+        );
+        let bytes = request.to_bytes();
+        assert_eq!(bytes.len(), ARP_PACKET_SIZE);
+        assert_eq!(Ok(request.clone()), ArpPacket::decode(&bytes));
+
+        let reply = request.reply_to(MacAddress::new([2; 6]));
+        assert_eq!(reply.operation, ArpOperation::Reply);
+        assert_eq!(reply.target_mac, request.sender_mac);
+        assert_eq!(reply.sender_ip, request.target_ip);
+        assert_eq!(
+            Err(ParseError::MissingBytes),
+            ArpPacket::decode(&reply.to_bytes()[..20])
+        );
+    }
+}
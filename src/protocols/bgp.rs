@@ -0,0 +1,471 @@
+use super::{ipv4::Ipv4Prefix, Packet, ParseError, Parser};
+use std::{io::Write, net::Ipv4Addr};
+
+pub const BGP_PORT: u16 = 179;
+pub const BGP_HEADER_SIZE: usize = 19;
+pub const BGP_MAX_MESSAGE_SIZE: usize = 4096;
+
+const BGP_VERSION: u8 = 4;
+const BGP_MARKER: [u8; 16] = [0xFF; 16];
+
+// attribute flags
+const ATTR_OPTIONAL: u8 = 0x80;
+const ATTR_TRANSITIVE: u8 = 0x40;
+// attribute type codes
+const ATTR_ORIGIN: u8 = 1;
+const ATTR_AS_PATH: u8 = 2;
+const ATTR_NEXT_HOP: u8 = 3;
+const ATTR_MED: u8 = 4;
+const ATTR_LOCAL_PREF: u8 = 5;
+const AS_SEQUENCE: u8 = 2;
+
+// NOTIFICATION error codes
+pub const BGP_MESSAGE_HEADER_ERROR: u8 = 1;
+pub const BGP_OPEN_MESSAGE_ERROR: u8 = 2;
+pub const BGP_UPDATE_MESSAGE_ERROR: u8 = 3;
+pub const BGP_HOLD_TIMER_EXPIRED: u8 = 4;
+pub const BGP_FSM_ERROR: u8 = 5;
+pub const BGP_CEASE: u8 = 6;
+// OPEN_MESSAGE_ERROR subcodes
+pub const BGP_BAD_PEER_AS: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BgpMessageType {
+    Open = 1,
+    Update = 2,
+    Notification = 3,
+    Keepalive = 4,
+}
+
+impl TryFrom<u8> for BgpMessageType {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::Open,
+            2 => Self::Update,
+            3 => Self::Notification,
+            4 => Self::Keepalive,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Origin {
+    Igp = 0,
+    Egp = 1,
+    Incomplete = 2,
+}
+
+impl TryFrom<u8> for Origin {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::Igp,
+            1 => Self::Egp,
+            2 => Self::Incomplete,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathAttributes {
+    pub origin: Origin,
+    // only AS_SEQUENCE segments, the neighbour's AS first
+    pub as_path: Vec<u16>,
+    pub next_hop: Ipv4Addr,
+    pub med: Option<u32>,
+    pub local_pref: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BgpOpen {
+    pub my_as: u16,
+    // seconds
+    pub hold_time: u16,
+    pub bgp_identifier: Ipv4Addr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BgpUpdate {
+    pub withdrawn: Vec<Ipv4Prefix>,
+    // None when the update only withdraws routes
+    pub attributes: Option<PathAttributes>,
+    pub nlri: Vec<Ipv4Prefix>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BgpNotification {
+    pub code: u8,
+    pub subcode: u8,
+    pub data: Box<[u8]>,
+}
+
+// BGP-4 (https://www.rfc-editor.org/rfc/rfc4271) with 2 byte AS numbers and no capabilities.
+// Unknown path attributes are skipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BgpMessage {
+    Open(BgpOpen),
+    Update(BgpUpdate),
+    Notification(BgpNotification),
+    Keepalive,
+}
+
+impl BgpMessage {
+    pub fn message_type(&self) -> BgpMessageType {
+        match self {
+            Self::Open(_) => BgpMessageType::Open,
+            Self::Update(_) => BgpMessageType::Update,
+            Self::Notification(_) => BgpMessageType::Notification,
+            Self::Keepalive => BgpMessageType::Keepalive,
+        }
+    }
+
+    // BGP runs over a byte stream, this returns the length of the first message in `data` if it
+    // is all there
+    pub fn message_len(data: &[u8]) -> Option<usize> {
+        let length = u16::from_be_bytes(data.get(16..18)?.try_into().unwrap()) as usize;
+        (data.len() >= length).then_some(length)
+    }
+}
+
+fn encode_prefixes(writer: &mut impl Write, prefixes: &[Ipv4Prefix]) {
+    for prefix in prefixes {
+        let len = prefix.prefix_len() as usize;
+        super::write_u8(writer, prefix.prefix_len());
+        super::write_bytes(writer, &prefix.address().octets()[..len.div_ceil(8)]);
+    }
+}
+
+fn parse_prefixes(data: &[u8]) -> Result<Vec<Ipv4Prefix>, ParseError> {
+    let mut parser = Parser::build(data);
+    let mut prefixes = Vec::new();
+
+    while !parser.remaining().is_empty() {
+        let prefix_len = parser.parse_u8()?;
+        if prefix_len > 32 {
+            return Err(ParseError::InvalidFieldValue {
+                field: "bgp_prefix_length",
+                value: prefix_len as usize,
+            });
+        }
+
+        let mut octets = [0; 4];
+        for octet in octets.iter_mut().take((prefix_len as usize).div_ceil(8)) {
+            *octet = parser.parse_u8()?;
+        }
+        prefixes.push(Ipv4Prefix::new(Ipv4Addr::from(octets), prefix_len));
+    }
+    Ok(prefixes)
+}
+
+fn encode_attribute(writer: &mut impl Write, flags: u8, code: u8, value: &[u8]) {
+    super::write_u8(writer, flags);
+    super::write_u8(writer, code);
+    super::write_u8(writer, value.len() as u8);
+    super::write_bytes(writer, value);
+}
+
+fn encode_attributes(attributes: &PathAttributes) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    encode_attribute(
+        &mut buffer,
+        ATTR_TRANSITIVE,
+        ATTR_ORIGIN,
+        &[attributes.origin as u8],
+    );
+
+    let mut as_path = Vec::new();
+    if !attributes.as_path.is_empty() {
+        super::write_u8(&mut as_path, AS_SEQUENCE);
+        super::write_u8(&mut as_path, attributes.as_path.len() as u8);
+        for asn in attributes.as_path.iter() {
+            super::write_u16(&mut as_path, *asn);
+        }
+    }
+    encode_attribute(&mut buffer, ATTR_TRANSITIVE, ATTR_AS_PATH, &as_path);
+    encode_attribute(
+        &mut buffer,
+        ATTR_TRANSITIVE,
+        ATTR_NEXT_HOP,
+        &attributes.next_hop.octets(),
+    );
+
+    if let Some(med) = attributes.med {
+        encode_attribute(&mut buffer, ATTR_OPTIONAL, ATTR_MED, &med.to_be_bytes());
+    }
+    if let Some(local_pref) = attributes.local_pref {
+        encode_attribute(
+            &mut buffer,
+            ATTR_TRANSITIVE,
+            ATTR_LOCAL_PREF,
+            &local_pref.to_be_bytes(),
+        );
+    }
+    buffer
+}
+
+fn invalid_attribute(code: u8) -> ParseError {
+    ParseError::InvalidFieldValue {
+        field: "bgp_path_attribute",
+        value: code as usize,
+    }
+}
+
+fn parse_attributes(data: &[u8]) -> Result<PathAttributes, ParseError> {
+    let mut parser = Parser::build(data);
+    let (mut origin, mut as_path, mut next_hop) = (None, None, None);
+    let (mut med, mut local_pref) = (None, None);
+
+    while !parser.remaining().is_empty() {
+        let flags = parser.parse_u8()?;
+        let code = parser.parse_u8()?;
+        let length = if flags & 0x10 != 0 {
+            parser.parse_u16()? as usize
+        } else {
+            parser.parse_u8()? as usize
+        };
+
+        let value = parser
+            .remaining()
+            .get(..length)
+            .ok_or(ParseError::MissingBytes)?;
+        parser.skip(length)?;
+        let mut value_parser = Parser::build(value);
+
+        match code {
+            ATTR_ORIGIN => {
+                let value = value_parser.parse_u8()?;
+                origin = Some(Origin::try_from(value).map_err(|_| invalid_attribute(code))?);
+            }
+            ATTR_AS_PATH => {
+                let mut path = Vec::new();
+                while !value_parser.remaining().is_empty() {
+                    let _segment_type = value_parser.parse_u8()?;
+                    let asn_nr = value_parser.parse_u8()?;
+                    for _ in 0..asn_nr {
+                        path.push(value_parser.parse_u16()?);
+                    }
+                }
+                as_path = Some(path);
+            }
+            ATTR_NEXT_HOP => next_hop = Some(Ipv4Addr::from(value_parser.parse_chunk::<4>()?)),
+            ATTR_MED => med = Some(value_parser.parse_u32()?),
+            ATTR_LOCAL_PREF => local_pref = Some(value_parser.parse_u32()?),
+            _ => {}
+        }
+    }
+
+    Ok(PathAttributes {
+        origin: origin.ok_or(invalid_attribute(ATTR_ORIGIN))?,
+        as_path: as_path.ok_or(invalid_attribute(ATTR_AS_PATH))?,
+        next_hop: next_hop.ok_or(invalid_attribute(ATTR_NEXT_HOP))?,
+        med,
+        local_pref,
+    })
+}
+
+impl Packet for BgpMessage {
+    type Payload = ();
+
+    fn header_len(&self) -> usize {
+        BGP_HEADER_SIZE
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        let mut body = Vec::new();
+        match self {
+            Self::Open(open) => {
+                super::write_u8(&mut body, BGP_VERSION);
+                super::write_u16(&mut body, open.my_as);
+                super::write_u16(&mut body, open.hold_time);
+                super::write_bytes(&mut body, &open.bgp_identifier.octets());
+                super::write_u8(&mut body, 0); // no optional parameters
+            }
+            Self::Update(update) => {
+                let mut withdrawn = Vec::new();
+                encode_prefixes(&mut withdrawn, &update.withdrawn);
+                let attributes = update
+                    .attributes
+                    .as_ref()
+                    .map(encode_attributes)
+                    .unwrap_or_default();
+
+                super::write_u16(&mut body, withdrawn.len() as u16);
+                super::write_bytes(&mut body, &withdrawn);
+                super::write_u16(&mut body, attributes.len() as u16);
+                super::write_bytes(&mut body, &attributes);
+                encode_prefixes(&mut body, &update.nlri);
+            }
+            Self::Notification(notification) => {
+                super::write_u8(&mut body, notification.code);
+                super::write_u8(&mut body, notification.subcode);
+                super::write_bytes(&mut body, &notification.data);
+            }
+            Self::Keepalive => {}
+        }
+
+        super::write_bytes(writer, &BGP_MARKER);
+        super::write_u16(writer, (BGP_HEADER_SIZE + body.len()) as u16);
+        super::write_u8(writer, self.message_type() as u8);
+        super::write_bytes(writer, &body);
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        let mut parser = Parser::build(data);
+
+        let marker = parser.parse_chunk::<16>()?;
+        let length = parser.parse_u16()? as usize;
+        let message_type = parser.parse_u8()?;
+
+        if marker != BGP_MARKER {
+            return Err(ParseError::InvalidFieldValue {
+                field: "bgp_marker",
+                value: 0,
+            });
+        }
+
+        if !(BGP_HEADER_SIZE..=BGP_MAX_MESSAGE_SIZE).contains(&length) {
+            return Err(ParseError::InvalidFieldValue {
+                field: "bgp_length",
+                value: length,
+            });
+        }
+
+        let body = data
+            .get(BGP_HEADER_SIZE..length)
+            .ok_or(ParseError::MissingBytes)?;
+        let mut parser = Parser::build(body);
+
+        let message_type: BgpMessageType =
+            message_type
+                .try_into()
+                .map_err(|_| ParseError::InvalidFieldValue {
+                    field: "bgp_type",
+                    value: message_type as usize,
+                })?;
+
+        Ok(match message_type {
+            BgpMessageType::Open => {
+                let version = parser.parse_u8()?;
+                let my_as = parser.parse_u16()?;
+                let hold_time = parser.parse_u16()?;
+                let bgp_identifier = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+                let parameters_len = parser.parse_u8()? as usize;
+                parser.skip(parameters_len)?;
+
+                if version != BGP_VERSION {
+                    return Err(ParseError::InvalidFieldValue {
+                        field: "bgp_version",
+                        value: version as usize,
+                    });
+                }
+
+                Self::Open(BgpOpen {
+                    my_as,
+                    hold_time,
+                    bgp_identifier,
+                })
+            }
+            BgpMessageType::Update => {
+                let withdrawn_len = parser.parse_u16()? as usize;
+                let withdrawn = parser
+                    .remaining()
+                    .get(..withdrawn_len)
+                    .ok_or(ParseError::MissingBytes)?;
+                parser.skip(withdrawn_len)?;
+
+                let attributes_len = parser.parse_u16()? as usize;
+                let attributes = parser
+                    .remaining()
+                    .get(..attributes_len)
+                    .ok_or(ParseError::MissingBytes)?;
+                parser.skip(attributes_len)?;
+
+                Self::Update(BgpUpdate {
+                    withdrawn: parse_prefixes(withdrawn)?,
+                    attributes: (!attributes.is_empty())
+                        .then(|| parse_attributes(attributes))
+                        .transpose()?,
+                    nlri: parse_prefixes(parser.remaining())?,
+                })
+            }
+            BgpMessageType::Notification => Self::Notification(BgpNotification {
+                code: parser.parse_u8()?,
+                subcode: parser.parse_u8()?,
+                data: parser.remaining().into(),
+            }),
+            BgpMessageType::Keepalive => Self::Keepalive,
+        })
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        BgpMessage, BgpNotification, BgpOpen, BgpUpdate, Origin, PathAttributes,
+        BGP_HOLD_TIMER_EXPIRED,
+    };
+    use crate::protocols::{Packet, ParseError};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn marshall_and_unmarshall() {
+        let messages = [
+            BgpMessage::Open(BgpOpen {
+                my_as: 65001,
+                hold_time: 90,
+                bgp_identifier: Ipv4Addr::new(1, 1, 1, 1),
+            }),
+            BgpMessage::Update(BgpUpdate {
+                withdrawn: vec!["10.1.0.0/16".parse().unwrap()],
+                attributes: Some(PathAttributes {
+                    origin: Origin::Igp,
+                    as_path: vec![65001, 65002],
+                    next_hop: Ipv4Addr::new(10, 0, 0, 1),
+                    med: Some(10),
+                    local_pref: None,
+                }),
+                nlri: vec![
+                    "192.168.0.0/24".parse().unwrap(),
+                    "0.0.0.0/0".parse().unwrap(),
+                    "172.16.0.0/12".parse().unwrap(),
+                ],
+            }),
+            BgpMessage::Update(BgpUpdate {
+                withdrawn: vec!["10.1.2.3/32".parse().unwrap()],
+                attributes: None,
+                nlri: Vec::new(),
+            }),
+            BgpMessage::Notification(BgpNotification {
+                code: BGP_HOLD_TIMER_EXPIRED,
+                subcode: 0,
+                data: Box::new([]),
+            }),
+            BgpMessage::Keepalive,
+        ];
+
+        for message in messages {
+            let bytes = message.to_bytes();
+            assert_eq!(BgpMessage::message_len(&bytes), Some(bytes.len()));
+            assert_eq!(BgpMessage::message_len(&bytes[..bytes.len() - 1]), None);
+            assert_eq!(Ok(&message), BgpMessage::decode(&bytes).as_ref());
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = BgpMessage::Keepalive.to_bytes();
+        assert_eq!(
+            Err(ParseError::MissingBytes),
+            BgpMessage::decode(&bytes[..10])
+        );
+    }
+}
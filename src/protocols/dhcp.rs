@@ -0,0 +1,345 @@
+use super::{ethernet::MacAddress, Packet, ParseError, Parser};
+use std::{io::Write, net::Ipv4Addr};
+
+pub const DHCP_SERVER_PORT: u16 = 67;
+pub const DHCP_CLIENT_PORT: u16 = 68;
+
+const DHCP_MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+// everything up to the magic cookie, sname and file included
+const BOOTP_HEADER_SIZE: usize = 236;
+const HARDWARE_TYPE_ETHERNET: u8 = 1;
+const FLAG_BROADCAST: u16 = 0x8000;
+
+// option codes
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS_SERVERS: u8 = 6;
+const OPTION_REQUESTED_ADDRESS: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_RENEWAL_TIME: u8 = 58;
+const OPTION_REBINDING_TIME: u8 = 59;
+const OPTION_END: u8 = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DhcpOp {
+    Request = 1,
+    Reply = 2,
+}
+
+impl TryFrom<u8> for DhcpOp {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::Request,
+            2 => Self::Reply,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DhcpMessageType {
+    Discover = 1,
+    Offer = 2,
+    Request = 3,
+    Decline = 4,
+    Ack = 5,
+    Nak = 6,
+    Release = 7,
+    Inform = 8,
+}
+
+impl TryFrom<u8> for DhcpMessageType {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::Discover,
+            2 => Self::Offer,
+            3 => Self::Request,
+            4 => Self::Decline,
+            5 => Self::Ack,
+            6 => Self::Nak,
+            7 => Self::Release,
+            8 => Self::Inform,
+            _ => return Err(()),
+        })
+    }
+}
+
+// A DHCP message (https://www.rfc-editor.org/rfc/rfc2131) with the options of
+// https://www.rfc-editor.org/rfc/rfc2132 the simulator uses, the others are skipped when decoding.
+// Times are in seconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhcpMessage {
+    pub op: DhcpOp,
+    pub message_type: DhcpMessageType,
+    pub xid: u32,
+    pub secs: u16,
+    // asks the server to broadcast its replies, for clients that can't receive unicast yet
+    pub broadcast: bool,
+    pub ciaddr: Ipv4Addr,
+    pub yiaddr: Ipv4Addr,
+    pub siaddr: Ipv4Addr,
+    pub giaddr: Ipv4Addr,
+    pub chaddr: MacAddress,
+    pub subnet_mask: Option<Ipv4Addr>,
+    pub router: Option<Ipv4Addr>,
+    pub dns_servers: Vec<Ipv4Addr>,
+    pub requested_address: Option<Ipv4Addr>,
+    pub lease_time: Option<u32>,
+    pub server_id: Option<Ipv4Addr>,
+    pub renewal_time: Option<u32>,
+    pub rebinding_time: Option<u32>,
+}
+
+impl DhcpMessage {
+    pub fn new(message_type: DhcpMessageType, xid: u32, chaddr: MacAddress) -> Self {
+        let op = match message_type {
+            DhcpMessageType::Offer | DhcpMessageType::Ack | DhcpMessageType::Nak => DhcpOp::Reply,
+            _ => DhcpOp::Request,
+        };
+
+        Self {
+            op,
+            message_type,
+            xid,
+            secs: 0,
+            broadcast: false,
+            ciaddr: Ipv4Addr::UNSPECIFIED,
+            yiaddr: Ipv4Addr::UNSPECIFIED,
+            siaddr: Ipv4Addr::UNSPECIFIED,
+            giaddr: Ipv4Addr::UNSPECIFIED,
+            chaddr,
+            subnet_mask: None,
+            router: None,
+            dns_servers: Vec::new(),
+            requested_address: None,
+            lease_time: None,
+            server_id: None,
+            renewal_time: None,
+            rebinding_time: None,
+        }
+    }
+}
+
+fn write_option(writer: &mut impl Write, code: u8, value: &[u8]) {
+    super::write_u8(writer, code);
+    super::write_u8(writer, value.len() as u8);
+    super::write_bytes(writer, value);
+}
+
+fn parse_address(value: &[u8]) -> Result<Ipv4Addr, ParseError> {
+    Ok(Ipv4Addr::from(Parser::build(value).parse_chunk::<4>()?))
+}
+
+impl Packet for DhcpMessage {
+    type Payload = ();
+
+    fn header_len(&self) -> usize {
+        BOOTP_HEADER_SIZE + DHCP_MAGIC_COOKIE.len()
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        super::write_u8(writer, self.op as u8);
+        super::write_u8(writer, HARDWARE_TYPE_ETHERNET);
+        super::write_u8(writer, self.chaddr.as_bytes().len() as u8);
+        super::write_u8(writer, 0); // hops
+        super::write_u32(writer, self.xid);
+        super::write_u16(writer, self.secs);
+        super::write_u16(writer, if self.broadcast { FLAG_BROADCAST } else { 0 });
+        for address in [self.ciaddr, self.yiaddr, self.siaddr, self.giaddr] {
+            super::write_bytes(writer, &address.octets());
+        }
+        let mut chaddr = [0; 16];
+        chaddr[..6].copy_from_slice(self.chaddr.as_bytes());
+        super::write_bytes(writer, &chaddr);
+        super::write_bytes(writer, &[0; 64 + 128]); // sname and file
+        super::write_bytes(writer, &DHCP_MAGIC_COOKIE);
+
+        write_option(writer, OPTION_MESSAGE_TYPE, &[self.message_type as u8]);
+        let addresses = [
+            (OPTION_SUBNET_MASK, self.subnet_mask),
+            (OPTION_ROUTER, self.router),
+            (OPTION_REQUESTED_ADDRESS, self.requested_address),
+            (OPTION_SERVER_ID, self.server_id),
+        ];
+        for (code, address) in addresses {
+            if let Some(address) = address {
+                write_option(writer, code, &address.octets());
+            }
+        }
+        if !self.dns_servers.is_empty() {
+            let servers: Vec<_> = self
+                .dns_servers
+                .iter()
+                .flat_map(|server| server.octets())
+                .collect();
+            write_option(writer, OPTION_DNS_SERVERS, &servers);
+        }
+        let times = [
+            (OPTION_LEASE_TIME, self.lease_time),
+            (OPTION_RENEWAL_TIME, self.renewal_time),
+            (OPTION_REBINDING_TIME, self.rebinding_time),
+        ];
+        for (code, time) in times {
+            if let Some(time) = time {
+                write_option(writer, code, &time.to_be_bytes());
+            }
+        }
+        super::write_u8(writer, OPTION_END);
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        let mut parser = Parser::build(data);
+
+        let op = parser.parse_u8()?;
+        let hardware_type = parser.parse_u8()?;
+        let hardware_len = parser.parse_u8()?;
+        let _hops = parser.parse_u8()?;
+        let xid = parser.parse_u32()?;
+        let secs = parser.parse_u16()?;
+        let flags = parser.parse_u16()?;
+        let ciaddr = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+        let yiaddr = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+        let siaddr = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+        let giaddr = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+        let chaddr = parser.parse_chunk::<16>()?;
+        parser.skip(64 + 128)?;
+        let cookie = parser.parse_chunk::<4>()?;
+
+        let op = DhcpOp::try_from(op).map_err(|_| ParseError::InvalidFieldValue {
+            field: "dhcp_op",
+            value: op as usize,
+        })?;
+
+        if hardware_type != HARDWARE_TYPE_ETHERNET || hardware_len != 6 {
+            return Err(ParseError::InvalidFieldValue {
+                field: "dhcp_hardware_type",
+                value: hardware_type as usize,
+            });
+        }
+
+        if cookie != DHCP_MAGIC_COOKIE {
+            return Err(ParseError::InvalidFieldValue {
+                field: "dhcp_magic_cookie",
+                value: u32::from_be_bytes(cookie) as usize,
+            });
+        }
+
+        let mut message_type = None;
+        let mut message = DhcpMessage::new(
+            DhcpMessageType::Discover,
+            xid,
+            MacAddress::build(&chaddr[..6]).unwrap(),
+        );
+
+        loop {
+            let code = parser.parse_u8()?;
+            match code {
+                OPTION_PAD => continue,
+                OPTION_END => break,
+                _ => {}
+            }
+
+            let len = parser.parse_u8()? as usize;
+            let value = parser
+                .remaining()
+                .get(..len)
+                .ok_or(ParseError::MissingBytes)?;
+            parser.skip(len)?;
+            let mut value_parser = Parser::build(value);
+
+            match code {
+                OPTION_MESSAGE_TYPE => {
+                    let value = value_parser.parse_u8()?;
+                    message_type = Some(DhcpMessageType::try_from(value).map_err(|_| {
+                        ParseError::InvalidFieldValue {
+                            field: "dhcp_message_type",
+                            value: value as usize,
+                        }
+                    })?);
+                }
+                OPTION_SUBNET_MASK => message.subnet_mask = Some(parse_address(value)?),
+                OPTION_ROUTER => message.router = Some(parse_address(value)?),
+                OPTION_REQUESTED_ADDRESS => message.requested_address = Some(parse_address(value)?),
+                OPTION_SERVER_ID => message.server_id = Some(parse_address(value)?),
+                OPTION_DNS_SERVERS => {
+                    message.dns_servers = value
+                        .chunks(4)
+                        .map(parse_address)
+                        .collect::<Result<_, _>>()?
+                }
+                OPTION_LEASE_TIME => message.lease_time = Some(value_parser.parse_u32()?),
+                OPTION_RENEWAL_TIME => message.renewal_time = Some(value_parser.parse_u32()?),
+                OPTION_REBINDING_TIME => message.rebinding_time = Some(value_parser.parse_u32()?),
+                _ => {}
+            }
+        }
+
+        // plain BOOTP isn't supported
+        message.message_type = message_type.ok_or(ParseError::InvalidFieldValue {
+            field: "dhcp_message_type",
+            value: 0,
+        })?;
+        message.op = op;
+        message.secs = secs;
+        message.broadcast = flags & FLAG_BROADCAST != 0;
+        message.ciaddr = ciaddr;
+        message.yiaddr = yiaddr;
+        message.siaddr = siaddr;
+        message.giaddr = giaddr;
+        Ok(message)
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DhcpMessage, DhcpMessageType, DhcpOp};
+    use crate::protocols::{ethernet::MacAddress, Packet, ParseError};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn marshall_and_unmarshall() {
+        let mut message =
+            DhcpMessage::new(DhcpMessageType::Ack, 0xdeadbeef, MacAddress::new([0x11; 6]));
+        message.broadcast = true;
+        message.yiaddr = Ipv4Addr::new(10, 0, 0, 100);
+        message.subnet_mask = Some(Ipv4Addr::new(255, 255, 255, 0));
+        message.router = Some(Ipv4Addr::new(10, 0, 0, 1));
+        message.dns_servers = vec![Ipv4Addr::new(10, 0, 0, 53), Ipv4Addr::new(8, 8, 8, 8)];
+        message.server_id = Some(Ipv4Addr::new(10, 0, 0, 2));
+        message.lease_time = Some(3600);
+        message.renewal_time = Some(1800);
+
+        let bytes = message.to_bytes();
+        assert_eq!(bytes[0], DhcpOp::Reply as u8);
+        assert_eq!(Ok(&message), DhcpMessage::decode(&bytes).as_ref());
+    }
+
+    #[test]
+    fn without_message_type() {
+        let message = DhcpMessage::new(DhcpMessageType::Discover, 1, MacAddress::new([0x11; 6]));
+        let mut bytes = message.to_bytes().to_vec();
+        // the message type is the first option
+        bytes[240] = 0;
+        bytes[241] = 0;
+        bytes[242] = 0;
+
+        assert!(matches!(
+            DhcpMessage::decode(&bytes),
+            Err(ParseError::InvalidFieldValue {
+                field: "dhcp_message_type",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,911 @@
+// Turns raw frames into a tree of layers with their fields, similar to what `tshark -V` prints.
+// Unlike the decoders it never fails: whatever can't be decoded shows up as malformed or as plain
+// data, so it is safe to use on anything that comes out of a link.
+
+use super::{
+    arp::{ArpOperation, ArpPacket},
+    bgp::{BgpMessage, BGP_PORT},
+    dhcp::{DhcpMessage, DHCP_CLIENT_PORT, DHCP_SERVER_PORT},
+    dns::{DnsMessage, DnsRecord, RecordData, DNS_PORT},
+    ethernet::{EthernetFrameRef, FrameProtocol, VlanFrame},
+    icmp::{IcmpPacket, IcmpType},
+    icmpv6::Icmpv6Packet,
+    ipv4::{IpProtocol, Ipv4Packet},
+    ipv6::{Ipv6Packet, NextHeader},
+    ospf::{OspfBody, OspfPacket, RouterLinkType},
+    rip::{RipCommand, RipMessage, RIP_PORT},
+    tcp::TcpSegment,
+    udp::UdpDatagram,
+    Packet, ParseError,
+};
+use std::fmt::{Display, Write};
+
+const HEXDUMP_WIDTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layer {
+    pub summary: String,
+    pub fields: Vec<Field>,
+}
+
+impl Layer {
+    fn new(summary: impl Into<String>) -> Self {
+        Self {
+            summary: summary.into(),
+            fields: Vec::new(),
+        }
+    }
+
+    fn field(mut self, name: &'static str, value: impl Display) -> Self {
+        self.fields.push(Field {
+            name,
+            value: value.to_string(),
+        });
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Dissection<'a> {
+    pub layers: Vec<Layer>,
+    pub bytes: &'a [u8],
+}
+
+impl Dissection<'_> {
+    // one line with the summary of every layer, handy for logs
+    pub fn summary(&self) -> String {
+        self.layers
+            .iter()
+            .map(|layer| layer.summary.as_str())
+            .collect::<Vec<_>>()
+            .join(" / ")
+    }
+}
+
+impl Display for Dissection<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for layer in self.layers.iter() {
+            writeln!(f, "{}", layer.summary)?;
+            for field in layer.fields.iter() {
+                writeln!(f, "    {}: {}", field.name, field.value)?;
+            }
+        }
+        writeln!(f)?;
+        write!(f, "{}", hexdump(self.bytes))
+    }
+}
+
+pub fn dissect(bytes: &[u8]) -> Dissection<'_> {
+    let mut layers = Vec::new();
+    dissect_ethernet(bytes, &mut layers);
+    Dissection { layers, bytes }
+}
+
+// dissects what an ethernet frame with the given protocol carries
+pub fn dissect_payload(protocol: FrameProtocol, bytes: &[u8]) -> Dissection<'_> {
+    let mut layers = Vec::new();
+    dissect_ether_type(protocol as u16, bytes, &mut layers);
+    Dissection { layers, bytes }
+}
+
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut output = String::new();
+    for (line, chunk) in bytes.chunks(HEXDUMP_WIDTH).enumerate() {
+        let mut hex = String::new();
+        for (idx, byte) in chunk.iter().enumerate() {
+            if idx == HEXDUMP_WIDTH / 2 {
+                hex.push(' ');
+            }
+            write!(hex, "{byte:02x} ").unwrap();
+        }
+
+        let ascii: String = chunk
+            .iter()
+            .map(|byte| match byte {
+                0x20..0x7F => *byte as char,
+                _ => '.',
+            })
+            .collect();
+
+        writeln!(
+            output,
+            "{:04x}  {hex:<width$} {ascii}",
+            line * HEXDUMP_WIDTH,
+            width = 3 * HEXDUMP_WIDTH + 1
+        )
+        .unwrap();
+    }
+    output
+}
+
+fn malformed(layers: &mut Vec<Layer>, protocol: &str, error: ParseError) {
+    layers.push(
+        Layer::new(format!("[Malformed {protocol} packet]")).field("Error", format!("{error:?}")),
+    );
+}
+
+fn dissect_data(bytes: &[u8], layers: &mut Vec<Layer>) {
+    if bytes.is_empty() {
+        return;
+    }
+
+    let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    layers.push(
+        Layer::new(format!("Data ({} bytes)", bytes.len()))
+            .field("Data", hex)
+            .field("Length", bytes.len()),
+    );
+}
+
+fn ether_type_name(ether_type: u16) -> String {
+    let name = match FrameProtocol::try_from(ether_type) {
+        Ok(FrameProtocol::Ipv4) => "IPv4",
+        Ok(FrameProtocol::Apr) => "ARP",
+        Ok(FrameProtocol::Vlan) => "802.1Q Virtual LAN",
+        Ok(FrameProtocol::Ipv6) => "IPv6",
+        Err(_) => "Unknown",
+    };
+    format!("{name} ({ether_type:#06x})")
+}
+
+fn dissect_ethernet(bytes: &[u8], layers: &mut Vec<Layer>) {
+    let frame = match EthernetFrameRef::new(bytes) {
+        Ok(frame) => frame,
+        Err(error) => {
+            malformed(layers, "Ethernet", error);
+            return dissect_data(bytes, layers);
+        }
+    };
+
+    layers.push(
+        Layer::new(format!(
+            "Ethernet II, Src: {}, Dst: {}",
+            frame.source(),
+            frame.destin()
+        ))
+        .field("Destination", frame.destin())
+        .field("Source", frame.source())
+        .field("Type", ether_type_name(frame.ether_type())),
+    );
+
+    dissect_ether_type(frame.ether_type(), frame.payload(), layers);
+}
+
+fn dissect_ether_type(ether_type: u16, bytes: &[u8], layers: &mut Vec<Layer>) {
+    match FrameProtocol::try_from(ether_type) {
+        Ok(FrameProtocol::Ipv4) => dissect_ipv4(bytes, layers),
+        Ok(FrameProtocol::Apr) => dissect_arp(bytes, layers),
+        Ok(FrameProtocol::Vlan) => dissect_vlan(bytes, layers),
+        Ok(FrameProtocol::Ipv6) => dissect_ipv6(bytes, layers),
+        Err(_) => dissect_data(bytes, layers),
+    }
+}
+
+fn dissect_vlan(bytes: &[u8], layers: &mut Vec<Layer>) {
+    // the inner EtherType is not decoded here since we might not know it
+    if bytes.len() < 4 {
+        malformed(layers, "802.1Q", ParseError::MissingBytes);
+        return dissect_data(bytes, layers);
+    }
+
+    let tci = u16::from_be_bytes([bytes[0], bytes[1]]);
+    let ether_type = u16::from_be_bytes([bytes[2], bytes[3]]);
+    let (priority, drop_eligible, vlan_id) = (tci >> 13, (tci >> 12) & 1, tci & 0x0FFF);
+
+    layers.push(
+        Layer::new(format!(
+            "802.1Q Virtual LAN, PRI: {priority}, DEI: {drop_eligible}, ID: {vlan_id}"
+        ))
+        .field("Priority", priority)
+        .field("DEI", drop_eligible)
+        .field("ID", vlan_id)
+        .field("Type", ether_type_name(ether_type)),
+    );
+
+    dissect_ether_type(ether_type, &bytes[4..], layers);
+}
+
+fn dissect_arp(bytes: &[u8], layers: &mut Vec<Layer>) {
+    let packet = match ArpPacket::decode(bytes) {
+        Ok(packet) => packet,
+        Err(error) => return malformed(layers, "ARP", error),
+    };
+
+    let operation = match packet.operation {
+        ArpOperation::Request => "request",
+        ArpOperation::Reply => "reply",
+    };
+
+    layers.push(
+        Layer::new(format!("Address Resolution Protocol ({operation})"))
+            .field("Hardware type", "Ethernet (1)")
+            .field("Protocol type", "IPv4 (0x0800)")
+            .field(
+                "Opcode",
+                format!("{operation} ({})", packet.operation as u16),
+            )
+            .field("Sender MAC address", packet.sender_mac)
+            .field("Sender IP address", packet.sender_ip)
+            .field("Target MAC address", packet.target_mac)
+            .field("Target IP address", packet.target_ip),
+    );
+}
+
+fn dissect_ipv4(bytes: &[u8], layers: &mut Vec<Layer>) {
+    let packet = match Ipv4Packet::decode(bytes) {
+        Ok(packet) => packet,
+        Err(error) => {
+            malformed(layers, "IPv4", error);
+            return dissect_data(bytes, layers);
+        }
+    };
+
+    let protocol = match packet.protocol {
+        IpProtocol::Icmp => "ICMP",
+        IpProtocol::Tcp => "TCP",
+        IpProtocol::Udp => "UDP",
+        IpProtocol::Ospf => "OSPF",
+        IpProtocol::Other(_) => "Unknown",
+    };
+
+    layers.push(
+        Layer::new(format!(
+            "Internet Protocol Version 4, Src: {}, Dst: {}",
+            packet.source, packet.destin
+        ))
+        .field("Version", 4)
+        .field("Header Length", format!("{} bytes", packet.header_len()))
+        .field("Type of Service", format!("{:#04x}", packet.tos))
+        .field("Total Length", packet.header_len() + packet.data.len())
+        .field(
+            "Identification",
+            format!("{:#06x} ({})", packet.identification, packet.identification),
+        )
+        .field("Time to Live", packet.ttl)
+        .field(
+            "Protocol",
+            format!("{protocol} ({})", u8::from(packet.protocol)),
+        )
+        .field("Header Checksum", "[correct]")
+        .field("Source Address", packet.source)
+        .field("Destination Address", packet.destin),
+    );
+
+    match packet.protocol {
+        IpProtocol::Icmp => dissect_icmp(&packet.data, layers),
+        IpProtocol::Tcp => dissect_tcp(&packet.data, layers),
+        IpProtocol::Udp => dissect_udp(&packet.data, layers),
+        IpProtocol::Ospf => dissect_ospf(&packet.data, layers),
+        IpProtocol::Other(_) => dissect_data(&packet.data, layers),
+    }
+}
+
+fn dissect_ipv6(bytes: &[u8], layers: &mut Vec<Layer>) {
+    let packet = match Ipv6Packet::decode(bytes) {
+        Ok(packet) => packet,
+        Err(error) => {
+            malformed(layers, "IPv6", error);
+            return dissect_data(bytes, layers);
+        }
+    };
+
+    let mut layer = Layer::new(format!(
+        "Internet Protocol Version 6, Src: {}, Dst: {}",
+        packet.source, packet.destin
+    ))
+    .field("Version", 6)
+    .field("Traffic Class", format!("{:#04x}", packet.traffic_class))
+    .field("Flow Label", format!("{:#07x}", packet.flow_label))
+    .field(
+        "Payload Length",
+        packet.header_len() - 40 + packet.data.len(),
+    )
+    .field("Hop Limit", packet.hop_limit);
+    for header in &packet.extension_headers {
+        layer = layer.field(
+            "Extension Header",
+            format!("{:?} ({} bytes)", header.header_type, header.data.len()),
+        );
+    }
+    layers.push(
+        layer
+            .field(
+                "Next Header",
+                format!("{:?} ({})", packet.protocol, packet.protocol as u8),
+            )
+            .field("Source Address", packet.source)
+            .field("Destination Address", packet.destin),
+    );
+
+    match packet.protocol {
+        NextHeader::Icmpv6 => dissect_icmpv6(&packet.data, layers),
+        NextHeader::Tcp => dissect_tcp(&packet.data, layers),
+        NextHeader::Udp => dissect_udp(&packet.data, layers),
+        _ => dissect_data(&packet.data, layers),
+    }
+}
+
+fn dissect_icmpv6(bytes: &[u8], layers: &mut Vec<Layer>) {
+    let packet = match Icmpv6Packet::decode(bytes) {
+        Ok(packet) => packet,
+        Err(error) => {
+            malformed(layers, "ICMPv6", error);
+            return dissect_data(bytes, layers);
+        }
+    };
+
+    let name = match &packet {
+        Icmpv6Packet::DestinationUnreachable { .. } => "Destination Unreachable",
+        Icmpv6Packet::PacketTooBig { .. } => "Packet Too Big",
+        Icmpv6Packet::TimeExceeded { .. } => "Time Exceeded",
+        Icmpv6Packet::EchoRequest { .. } => "Echo (ping) request",
+        Icmpv6Packet::EchoReply { .. } => "Echo (ping) reply",
+        Icmpv6Packet::RouterSolicitation { .. } => "Router Solicitation",
+        Icmpv6Packet::RouterAdvertisement(_) => "Router Advertisement",
+        Icmpv6Packet::NeighborSolicitation { .. } => "Neighbor Solicitation",
+        Icmpv6Packet::NeighborAdvertisement(_) => "Neighbor Advertisement",
+    };
+
+    let mut layer = Layer::new(format!("Internet Control Message Protocol v6 ({name})"))
+        .field("Type", format!("{name} ({})", packet.message_type() as u8))
+        .field("Checksum", "[correct]");
+
+    let mut data = None;
+    match packet {
+        Icmpv6Packet::DestinationUnreachable { code, data: quoted }
+        | Icmpv6Packet::TimeExceeded { code, data: quoted } => {
+            layer = layer.field("Code", code);
+            data = Some(quoted);
+        }
+        Icmpv6Packet::PacketTooBig { mtu, data: quoted } => {
+            layer = layer.field("MTU", mtu);
+            data = Some(quoted);
+        }
+        Icmpv6Packet::EchoRequest {
+            identifier,
+            sequence,
+            data: payload,
+        }
+        | Icmpv6Packet::EchoReply {
+            identifier,
+            sequence,
+            data: payload,
+        } => {
+            layer = layer
+                .field("Identifier", identifier)
+                .field("Sequence", sequence);
+            data = Some(payload);
+        }
+        Icmpv6Packet::RouterSolicitation { source_mac } => {
+            if let Some(mac) = source_mac {
+                layer = layer.field("Source link-layer address", mac);
+            }
+        }
+        Icmpv6Packet::RouterAdvertisement(advertisement) => {
+            layer = layer
+                .field("Cur hop limit", advertisement.hop_limit)
+                .field("Managed address configuration", advertisement.managed)
+                .field("Other configuration", advertisement.other)
+                .field("Router lifetime (s)", advertisement.router_lifetime);
+            if let Some(mac) = advertisement.source_mac {
+                layer = layer.field("Source link-layer address", mac);
+            }
+            if let Some(mtu) = advertisement.mtu {
+                layer = layer.field("MTU", mtu);
+            }
+            for info in advertisement.prefixes {
+                layer = layer.field(
+                    "Prefix information",
+                    format!(
+                        "{}, L: {}, A: {}, Valid: {}, Preferred: {}",
+                        info.prefix,
+                        info.on_link as u8,
+                        info.autonomous as u8,
+                        info.valid_lifetime,
+                        info.preferred_lifetime
+                    ),
+                );
+            }
+        }
+        Icmpv6Packet::NeighborSolicitation { target, source_mac } => {
+            layer = layer.field("Target Address", target);
+            if let Some(mac) = source_mac {
+                layer = layer.field("Source link-layer address", mac);
+            }
+        }
+        Icmpv6Packet::NeighborAdvertisement(advertisement) => {
+            layer = layer
+                .field("Router", advertisement.router)
+                .field("Solicited", advertisement.solicited)
+                .field("Override", advertisement.override_cache)
+                .field("Target Address", advertisement.target);
+            if let Some(mac) = advertisement.target_mac {
+                layer = layer.field("Target link-layer address", mac);
+            }
+        }
+    }
+    layers.push(layer);
+    if let Some(data) = data {
+        dissect_data(&data, layers);
+    }
+}
+
+fn dissect_icmp(bytes: &[u8], layers: &mut Vec<Layer>) {
+    let packet = match IcmpPacket::decode(bytes) {
+        Ok(packet) => packet,
+        Err(error) => {
+            malformed(layers, "ICMP", error);
+            return dissect_data(bytes, layers);
+        }
+    };
+
+    let name = match packet.message_type {
+        IcmpType::EchoReply => "Echo (ping) reply",
+        IcmpType::DestinationUnreachable => "Destination unreachable",
+        IcmpType::EchoRequest => "Echo (ping) request",
+        IcmpType::TimeExceeded => "Time-to-live exceeded",
+    };
+
+    let mut layer = Layer::new("Internet Control Message Protocol")
+        .field("Type", format!("{} ({name})", packet.message_type as u8))
+        .field("Code", packet.code)
+        .field("Checksum", "[correct]");
+
+    match packet.message_type {
+        IcmpType::EchoReply | IcmpType::EchoRequest => {
+            layer = layer
+                .field("Identifier", packet.identifier())
+                .field("Sequence Number", packet.sequence());
+            layers.push(layer);
+            dissect_data(&packet.data, layers);
+        }
+        IcmpType::DestinationUnreachable | IcmpType::TimeExceeded => {
+            layers.push(layer);
+            // the quoted datagram is usually cut short, so only its header is shown
+            match Ipv4Packet::decode(&packet.data) {
+                Ok(quoted) => layers.push(
+                    Layer::new(format!(
+                        "Internet Protocol Version 4, Src: {}, Dst: {}",
+                        quoted.source, quoted.destin
+                    ))
+                    .field("Time to Live", quoted.ttl)
+                    .field("Protocol", u8::from(quoted.protocol)),
+                ),
+                Err(_) => dissect_data(&packet.data, layers),
+            }
+        }
+    }
+}
+
+fn dissect_udp(bytes: &[u8], layers: &mut Vec<Layer>) {
+    let datagram = match UdpDatagram::decode(bytes) {
+        Ok(datagram) => datagram,
+        Err(error) => {
+            malformed(layers, "UDP", error);
+            return dissect_data(bytes, layers);
+        }
+    };
+
+    layers.push(
+        Layer::new(format!(
+            "User Datagram Protocol, Src Port: {}, Dst Port: {}",
+            datagram.source_port, datagram.destin_port
+        ))
+        .field("Source Port", datagram.source_port)
+        .field("Destination Port", datagram.destin_port)
+        .field("Length", datagram.header_len() + datagram.data.len()),
+    );
+
+    match (datagram.source_port, datagram.destin_port) {
+        (RIP_PORT, _) | (_, RIP_PORT) => dissect_rip(&datagram.data, layers),
+        (DHCP_SERVER_PORT, DHCP_CLIENT_PORT) | (DHCP_CLIENT_PORT, DHCP_SERVER_PORT) => {
+            dissect_dhcp(&datagram.data, layers)
+        }
+        (DNS_PORT, _) | (_, DNS_PORT) => dissect_dns(&datagram.data, layers),
+        _ => dissect_data(&datagram.data, layers),
+    }
+}
+
+fn dissect_rip(bytes: &[u8], layers: &mut Vec<Layer>) {
+    let message = match RipMessage::decode(bytes) {
+        Ok(message) => message,
+        Err(error) => {
+            malformed(layers, "RIP", error);
+            return dissect_data(bytes, layers);
+        }
+    };
+
+    let command = match message.command {
+        RipCommand::Request => "Request",
+        RipCommand::Response => "Response",
+    };
+
+    let mut layer = Layer::new(format!(
+        "Routing Information Protocol ({command}, {} entries)",
+        message.entries.len()
+    ))
+    .field("Command", format!("{command} ({})", message.command as u8))
+    .field("Version", "RIPv2 (2)");
+
+    for entry in message.entries {
+        layer = layer.field(
+            "IP Address",
+            format!(
+                "{}, Metric: {}, Next Hop: {}",
+                entry.prefix, entry.metric, entry.next_hop
+            ),
+        );
+    }
+    layers.push(layer);
+}
+
+fn dissect_dhcp(bytes: &[u8], layers: &mut Vec<Layer>) {
+    let message = match DhcpMessage::decode(bytes) {
+        Ok(message) => message,
+        Err(error) => {
+            malformed(layers, "DHCP", error);
+            return dissect_data(bytes, layers);
+        }
+    };
+
+    let mut layer = Layer::new(format!(
+        "Dynamic Host Configuration Protocol ({:?})",
+        message.message_type
+    ))
+    .field("Message type", format!("{:?}", message.op))
+    .field("Transaction ID", format!("{:#010x}", message.xid))
+    .field("Broadcast flag", message.broadcast)
+    .field("Client IP address", message.ciaddr)
+    .field("Your (client) IP address", message.yiaddr)
+    .field("Client MAC address", message.chaddr);
+
+    let addresses = [
+        ("Subnet Mask", message.subnet_mask),
+        ("Router", message.router),
+        ("Requested IP Address", message.requested_address),
+        ("DHCP Server Identifier", message.server_id),
+    ];
+    for (name, address) in addresses {
+        if let Some(address) = address {
+            layer = layer.field(name, address);
+        }
+    }
+    for server in message.dns_servers {
+        layer = layer.field("Domain Name Server", server);
+    }
+    if let Some(lease_time) = message.lease_time {
+        layer = layer.field("IP Address Lease Time", format!("{lease_time} seconds"));
+    }
+    layers.push(layer);
+}
+
+fn dissect_dns(bytes: &[u8], layers: &mut Vec<Layer>) {
+    let message = match DnsMessage::decode(bytes) {
+        Ok(message) => message,
+        Err(error) => {
+            malformed(layers, "DNS", error);
+            return dissect_data(bytes, layers);
+        }
+    };
+
+    let kind = if message.response {
+        "response"
+    } else {
+        "query"
+    };
+    let mut summary = format!("Domain Name System ({kind}) {:#06x}", message.id);
+    for question in &message.questions {
+        write!(summary, " {:?} {}", question.record_type, question.name).unwrap();
+    }
+
+    let mut layer = Layer::new(summary)
+        .field("Transaction ID", format!("{:#06x}", message.id))
+        .field("Authoritative", message.authoritative)
+        .field("Recursion desired", message.recursion_desired)
+        .field("Recursion available", message.recursion_available)
+        .field("Reply code", format!("{:?}", message.response_code));
+
+    for question in &message.questions {
+        layer = layer.field(
+            "Query",
+            format!("{}: type {:?}", question.name, question.record_type),
+        );
+    }
+    let sections = [
+        ("Answer", &message.answers),
+        ("Authority", &message.authorities),
+        ("Additional", &message.additionals),
+    ];
+    for (name, records) in sections {
+        for record in records {
+            layer = layer.field(name, format_record(record));
+        }
+    }
+    layers.push(layer);
+}
+
+fn format_record(record: &DnsRecord) -> String {
+    let data = match &record.data {
+        RecordData::A(address) => address.to_string(),
+        RecordData::Aaaa(address) => address.to_string(),
+        RecordData::Ns(name) | RecordData::Cname(name) => name.clone(),
+    };
+    format!(
+        "{}: type {:?}, ttl {}, {data}",
+        record.name,
+        record.record_type(),
+        record.ttl
+    )
+}
+
+fn dissect_ospf(bytes: &[u8], layers: &mut Vec<Layer>) {
+    let packet = match OspfPacket::decode(bytes) {
+        Ok(packet) => packet,
+        Err(error) => {
+            malformed(layers, "OSPF", error);
+            return dissect_data(bytes, layers);
+        }
+    };
+
+    let name = match packet.body {
+        OspfBody::Hello(_) => "Hello Packet",
+        OspfBody::LinkStateUpdate(_) => "LS Update",
+        OspfBody::LinkStateAck(_) => "LS Acknowledge",
+    };
+
+    let mut layer = Layer::new(format!("Open Shortest Path First ({name})"))
+        .field("Version", 2)
+        .field(
+            "Message Type",
+            format!("{name} ({})", packet.packet_type() as u8),
+        )
+        .field("Source OSPF Router", packet.router_id)
+        .field("Area ID", packet.area_id);
+
+    match packet.body {
+        OspfBody::Hello(hello) => {
+            layer = layer
+                .field("Network Mask", hello.network_mask)
+                .field(
+                    "Hello Interval",
+                    format!("{} seconds", hello.hello_interval),
+                )
+                .field(
+                    "Router Dead Interval",
+                    format!("{} seconds", hello.dead_interval),
+                );
+            for neighbor in hello.neighbors {
+                layer = layer.field("Active Neighbor", neighbor);
+            }
+        }
+        OspfBody::LinkStateUpdate(lsas) => {
+            for lsa in lsas {
+                let links: Vec<_> = lsa
+                    .links
+                    .iter()
+                    .map(|link| match link.link_type {
+                        RouterLinkType::PointToPoint => {
+                            format!("router {} cost {}", link.link_id, link.metric)
+                        }
+                        RouterLinkType::Stub => {
+                            format!(
+                                "stub {}/{} cost {}",
+                                link.link_id, link.link_data, link.metric
+                            )
+                        }
+                    })
+                    .collect();
+                layer = layer.field(
+                    "Router-LSA",
+                    format!(
+                        "{}, Seq: {:#010x}, Age: {}, Links: [{}]",
+                        lsa.header.advertising_router,
+                        lsa.header.sequence,
+                        lsa.header.age,
+                        links.join(", ")
+                    ),
+                );
+            }
+        }
+        OspfBody::LinkStateAck(headers) => {
+            for header in headers {
+                layer = layer.field(
+                    "LSA Header",
+                    format!(
+                        "{}, Seq: {:#010x}",
+                        header.advertising_router, header.sequence
+                    ),
+                );
+            }
+        }
+    }
+    layers.push(layer);
+}
+
+fn dissect_tcp(bytes: &[u8], layers: &mut Vec<Layer>) {
+    let segment = match TcpSegment::decode(bytes) {
+        Ok(segment) => segment,
+        Err(error) => {
+            malformed(layers, "TCP", error);
+            return dissect_data(bytes, layers);
+        }
+    };
+
+    layers.push(
+        Layer::new(format!(
+            "Transmission Control Protocol, Src Port: {}, Dst Port: {}, Seq: {}, Ack: {}, Len: {}",
+            segment.source_port,
+            segment.destin_port,
+            segment.seq,
+            segment.ack,
+            segment.data.len()
+        ))
+        .field("Source Port", segment.source_port)
+        .field("Destination Port", segment.destin_port)
+        .field("Sequence Number", segment.seq)
+        .field("Acknowledgment Number", segment.ack)
+        .field(
+            "Flags",
+            format!("{:#04x} {}", segment.flags.0, segment.flags),
+        )
+        .field("Window", segment.window),
+    );
+
+    match (segment.source_port, segment.destin_port) {
+        (BGP_PORT, _) | (_, BGP_PORT) => dissect_bgp(&segment.data, layers),
+        _ => dissect_data(&segment.data, layers),
+    }
+}
+
+// a segment can carry several messages, or only a piece of one
+fn dissect_bgp(mut bytes: &[u8], layers: &mut Vec<Layer>) {
+    while let Some(len) = BgpMessage::message_len(bytes) {
+        let message = match BgpMessage::decode(bytes) {
+            Ok(message) => message,
+            Err(error) => {
+                malformed(layers, "BGP", error);
+                return dissect_data(bytes, layers);
+            }
+        };
+        bytes = &bytes[len..];
+
+        let name = format!("{:?}", message.message_type()).to_uppercase();
+        let mut layer = Layer::new(format!("Border Gateway Protocol - {name} Message"))
+            .field("Length", len)
+            .field(
+                "Type",
+                format!("{name} Message ({})", message.message_type() as u8),
+            );
+
+        match message {
+            BgpMessage::Open(open) => {
+                layer = layer
+                    .field("Version", 4)
+                    .field("My AS", open.my_as)
+                    .field("Hold Time", open.hold_time)
+                    .field("BGP Identifier", open.bgp_identifier);
+            }
+            BgpMessage::Update(update) => {
+                for prefix in update.withdrawn {
+                    layer = layer.field("Withdrawn Route", prefix);
+                }
+                if let Some(attributes) = update.attributes {
+                    let as_path: Vec<_> = attributes
+                        .as_path
+                        .iter()
+                        .map(|asn| asn.to_string())
+                        .collect();
+                    layer = layer
+                        .field("ORIGIN", format!("{:?}", attributes.origin).to_uppercase())
+                        .field("AS_PATH", as_path.join(" "))
+                        .field("NEXT_HOP", attributes.next_hop);
+                    if let Some(med) = attributes.med {
+                        layer = layer.field("MULTI_EXIT_DISC", med);
+                    }
+                    if let Some(local_pref) = attributes.local_pref {
+                        layer = layer.field("LOCAL_PREF", local_pref);
+                    }
+                }
+                for prefix in update.nlri {
+                    layer = layer.field("NLRI", prefix);
+                }
+            }
+            BgpMessage::Notification(notification) => {
+                layer = layer
+                    .field("Major error Code", notification.code)
+                    .field("Minor error Code", notification.subcode);
+            }
+            BgpMessage::Keepalive => {}
+        }
+        layers.push(layer);
+    }
+
+    dissect_data(bytes, layers);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{dissect, hexdump};
+    use crate::protocols::{
+        ethernet::{EthernetFrame, FrameProtocol, MacAddress, VlanFrame},
+        ipv4::{IpProtocol, Ipv4Packet},
+        udp::UdpDatagram,
+        Packet,
+    };
+    use std::net::Ipv4Addr;
+
+    fn frame() -> Box<[u8]> {
+        let datagram = UdpDatagram {
+            source_port: 4000,
+            destin_port: 7,
+            data: "Hello".as_bytes().into(),
+        };
+        let packet = Ipv4Packet::new(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            IpProtocol::Udp,
+            datagram.to_bytes(),
+        );
+        let vlan = VlanFrame {
+            priority: 3,
+            drop_eligible: false,
+            vlan_id: 10,
+            protocol: FrameProtocol::Ipv4,
+            data: packet.to_bytes(),
+        };
+        EthernetFrame {
+            source: MacAddress::new([0x11; 6]),
+            destin: MacAddress::new([0x22; 6]),
+            protocol: FrameProtocol::Vlan,
+            data: vlan.to_bytes(),
+        }
+        .to_bytes()
+    }
+
+    #[test]
+    fn layers() {
+        let bytes = frame();
+        let dissection = dissect(&bytes);
+
+        assert_eq!(
+            dissection.summary(),
+            "Ethernet II, Src: 11:11:11:11:11:11, Dst: 22:22:22:22:22:22 / \
+             802.1Q Virtual LAN, PRI: 3, DEI: 0, ID: 10 / \
+             Internet Protocol Version 4, Src: 10.0.0.1, Dst: 10.0.0.2 / \
+             User Datagram Protocol, Src Port: 4000, Dst Port: 7 / \
+             Data (5 bytes)"
+        );
+
+        let data = dissection.layers.last().unwrap();
+        assert_eq!(data.fields[0].value, "48656c6c6f");
+    }
+
+    #[test]
+    fn malformed() {
+        let bytes = frame();
+        // cut in the middle of the ipv4 header
+        let dissection = dissect(&bytes[..30]);
+
+        assert!(dissection.layers[2].summary.starts_with("[Malformed IPv4"));
+        assert_eq!(dissection.layers[3].summary, "Data (8 bytes)");
+    }
+
+    #[test]
+    fn hexdump_format() {
+        let dump = hexdump("Hello, world! 0123456789".as_bytes());
+        let lines: Vec<_> = dump.lines().collect();
+
+        assert_eq!(
+            lines[0],
+            "0000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 20 30 31  Hello, world! 01"
+        );
+        assert!(lines[1].starts_with("0010  32 33 34 35 36 37 38 39 "));
+        assert!(lines[1].ends_with(" 23456789"));
+    }
+}
@@ -0,0 +1,511 @@
+use super::{Packet, ParseError, Parser};
+use std::{
+    collections::HashMap,
+    io::Write,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+pub const DNS_PORT: u16 = 53;
+pub const DNS_HEADER_SIZE: usize = 12;
+
+const CLASS_IN: u16 = 1;
+const MAX_LABEL_LEN: usize = 63;
+const MAX_NAME_LEN: usize = 255;
+// pointers are the two top bits set followed by a 14 bits offset
+const POINTER_MASK: u8 = 0xC0;
+const MAX_POINTER_OFFSET: usize = 0x3FFF;
+
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_AUTHORITATIVE: u16 = 0x0400;
+const FLAG_TRUNCATED: u16 = 0x0200;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const FLAG_RECURSION_AVAILABLE: u16 = 0x0080;
+const OPCODE_MASK: u16 = 0x7800;
+const RCODE_MASK: u16 = 0x000F;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u16)]
+pub enum RecordType {
+    A = 1,
+    Ns = 2,
+    Cname = 5,
+    Aaaa = 28,
+}
+
+impl TryFrom<u16> for RecordType {
+    type Error = ();
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::A,
+            2 => Self::Ns,
+            5 => Self::Cname,
+            28 => Self::Aaaa,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ResponseCode {
+    NoError = 0,
+    FormatError = 1,
+    ServerFailure = 2,
+    // the name doesn't exist (NXDOMAIN)
+    NameError = 3,
+    NotImplemented = 4,
+    Refused = 5,
+}
+
+impl TryFrom<u8> for ResponseCode {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::NoError,
+            1 => Self::FormatError,
+            2 => Self::ServerFailure,
+            3 => Self::NameError,
+            4 => Self::NotImplemented,
+            5 => Self::Refused,
+            _ => return Err(()),
+        })
+    }
+}
+
+// Names are kept lowercase and without the trailing dot, the root being the empty name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsQuestion {
+    pub name: String,
+    pub record_type: RecordType,
+}
+
+impl DnsQuestion {
+    pub fn new(name: &str, record_type: RecordType) -> Self {
+        Self {
+            name: normalize_name(name),
+            record_type,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Ns(String),
+    Cname(String),
+    Aaaa(Ipv6Addr),
+}
+
+impl RecordData {
+    pub fn record_type(&self) -> RecordType {
+        match self {
+            Self::A(_) => RecordType::A,
+            Self::Ns(_) => RecordType::Ns,
+            Self::Cname(_) => RecordType::Cname,
+            Self::Aaaa(_) => RecordType::Aaaa,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsRecord {
+    pub name: String,
+    // in seconds
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+impl DnsRecord {
+    pub fn new(name: &str, ttl: u32, data: RecordData) -> Self {
+        Self {
+            name: normalize_name(name),
+            ttl,
+            data,
+        }
+    }
+
+    pub fn record_type(&self) -> RecordType {
+        self.data.record_type()
+    }
+}
+
+// A standard query or response (https://www.rfc-editor.org/rfc/rfc1035#section-4) of the IN class.
+// Records of other types are skipped when decoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsMessage {
+    pub id: u16,
+    pub response: bool,
+    pub authoritative: bool,
+    pub truncated: bool,
+    pub recursion_desired: bool,
+    pub recursion_available: bool,
+    pub response_code: ResponseCode,
+    pub questions: Vec<DnsQuestion>,
+    pub answers: Vec<DnsRecord>,
+    pub authorities: Vec<DnsRecord>,
+    pub additionals: Vec<DnsRecord>,
+}
+
+impl DnsMessage {
+    pub fn query(id: u16, question: DnsQuestion, recursion_desired: bool) -> Self {
+        Self {
+            id,
+            response: false,
+            authoritative: false,
+            truncated: false,
+            recursion_desired,
+            recursion_available: false,
+            response_code: ResponseCode::NoError,
+            questions: vec![question],
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+        }
+    }
+
+    // an empty response to the query, with its id, question and RD flag
+    pub fn response_to(query: &DnsMessage, response_code: ResponseCode) -> Self {
+        Self {
+            response: true,
+            response_code,
+            ..Self::query(
+                query.id,
+                query.questions[0].clone(),
+                query.recursion_desired,
+            )
+        }
+    }
+}
+
+pub fn normalize_name(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+// whether `name` is `zone` or one of its subdomains
+pub fn in_zone(name: &str, zone: &str) -> bool {
+    zone.is_empty()
+        || name == zone
+        || name
+            .strip_suffix(zone)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+// Writes the name, reusing a suffix already in the message whenever there is one. `compression`
+// maps the suffixes written so far to their offset.
+fn encode_name(buffer: &mut Vec<u8>, name: &str, compression: &mut HashMap<String, usize>) {
+    let mut suffix = name;
+    while !suffix.is_empty() {
+        if let Some(offset) = compression.get(suffix) {
+            super::write_u16(buffer, 0xC000 | *offset as u16);
+            return;
+        }
+        if buffer.len() <= MAX_POINTER_OFFSET {
+            compression.insert(suffix.to_string(), buffer.len());
+        }
+
+        let (label, rest) = suffix.split_once('.').unwrap_or((suffix, ""));
+        super::write_u8(buffer, label.len() as u8);
+        super::write_bytes(buffer, label.as_bytes());
+        suffix = rest;
+    }
+    super::write_u8(buffer, 0);
+}
+
+// Reads the name at `offset` of the message, returning it along with the number of bytes it takes
+// there. Pointers may only go backwards, which rules out loops.
+fn decode_name(message: &[u8], offset: usize) -> Result<(String, usize), ParseError> {
+    let mut name = String::new();
+    let mut position = offset;
+    let mut len = None;
+
+    loop {
+        let label_len = *message.get(position).ok_or(ParseError::MissingBytes)? as usize;
+        if label_len == 0 {
+            break;
+        }
+
+        if label_len as u8 & POINTER_MASK == POINTER_MASK {
+            let low = *message.get(position + 1).ok_or(ParseError::MissingBytes)? as usize;
+            let target = (label_len & !(POINTER_MASK as usize)) << 8 | low;
+            if target >= position {
+                return Err(ParseError::InvalidFieldValue {
+                    field: "dns_name_pointer",
+                    value: target,
+                });
+            }
+            len.get_or_insert(position + 2 - offset);
+            position = target;
+            continue;
+        }
+        if label_len > MAX_LABEL_LEN {
+            return Err(ParseError::InvalidFieldValue {
+                field: "dns_label_length",
+                value: label_len,
+            });
+        }
+
+        let label = message
+            .get(position + 1..position + 1 + label_len)
+            .ok_or(ParseError::MissingBytes)?;
+        if !name.is_empty() {
+            name.push('.');
+        }
+        name.extend(label.iter().map(|byte| byte.to_ascii_lowercase() as char));
+        if name.len() > MAX_NAME_LEN {
+            return Err(ParseError::InvalidFieldValue {
+                field: "dns_name_length",
+                value: name.len(),
+            });
+        }
+        position += 1 + label_len;
+    }
+
+    Ok((name, len.unwrap_or_else(|| position + 1 - offset)))
+}
+
+fn encode_record(
+    buffer: &mut Vec<u8>,
+    record: &DnsRecord,
+    compression: &mut HashMap<String, usize>,
+) {
+    encode_name(buffer, &record.name, compression);
+    super::write_u16(buffer, record.record_type() as u16);
+    super::write_u16(buffer, CLASS_IN);
+    super::write_u32(buffer, record.ttl);
+
+    // the length is filled in once the data is written
+    let len_offset = buffer.len();
+    super::write_u16(buffer, 0);
+    match &record.data {
+        RecordData::A(address) => super::write_bytes(buffer, &address.octets()),
+        RecordData::Aaaa(address) => super::write_bytes(buffer, &address.octets()),
+        RecordData::Ns(name) | RecordData::Cname(name) => encode_name(buffer, name, compression),
+    }
+    let len = (buffer.len() - len_offset - 2) as u16;
+    buffer[len_offset..len_offset + 2].copy_from_slice(&len.to_be_bytes());
+}
+
+// None for records of a type or class the simulator doesn't know
+fn decode_record(message: &[u8], parser: &mut Parser) -> Result<Option<DnsRecord>, ParseError> {
+    let offset = message.len() - parser.remaining().len();
+    let (name, len) = decode_name(message, offset)?;
+    parser.skip(len)?;
+
+    let record_type = parser.parse_u16()?;
+    let class = parser.parse_u16()?;
+    let ttl = parser.parse_u32()?;
+    let data_len = parser.parse_u16()? as usize;
+    let data_offset = message.len() - parser.remaining().len();
+    let data = parser
+        .remaining()
+        .get(..data_len)
+        .ok_or(ParseError::MissingBytes)?;
+    parser.skip(data_len)?;
+
+    let Ok(record_type) = RecordType::try_from(record_type) else {
+        return Ok(None);
+    };
+    if class != CLASS_IN {
+        return Ok(None);
+    }
+
+    let mut data_parser = Parser::build(data);
+    let data = match record_type {
+        RecordType::A => RecordData::A(Ipv4Addr::from(data_parser.parse_chunk::<4>()?)),
+        RecordType::Aaaa => RecordData::Aaaa(Ipv6Addr::from(data_parser.parse_chunk::<16>()?)),
+        RecordType::Ns => RecordData::Ns(decode_name(message, data_offset)?.0),
+        RecordType::Cname => RecordData::Cname(decode_name(message, data_offset)?.0),
+    };
+    Ok(Some(DnsRecord { name, ttl, data }))
+}
+
+impl Packet for DnsMessage {
+    type Payload = ();
+
+    fn header_len(&self) -> usize {
+        DNS_HEADER_SIZE
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        let mut flags = self.response_code as u16;
+        for (set, flag) in [
+            (self.response, FLAG_RESPONSE),
+            (self.authoritative, FLAG_AUTHORITATIVE),
+            (self.truncated, FLAG_TRUNCATED),
+            (self.recursion_desired, FLAG_RECURSION_DESIRED),
+            (self.recursion_available, FLAG_RECURSION_AVAILABLE),
+        ] {
+            if set {
+                flags |= flag;
+            }
+        }
+
+        // compression pointers are offsets into the message, so it's built in memory first
+        let mut buffer = Vec::new();
+        super::write_u16(&mut buffer, self.id);
+        super::write_u16(&mut buffer, flags);
+        for count in [
+            self.questions.len(),
+            self.answers.len(),
+            self.authorities.len(),
+            self.additionals.len(),
+        ] {
+            super::write_u16(&mut buffer, count as u16);
+        }
+
+        let mut compression = HashMap::new();
+        for question in &self.questions {
+            encode_name(&mut buffer, &question.name, &mut compression);
+            super::write_u16(&mut buffer, question.record_type as u16);
+            super::write_u16(&mut buffer, CLASS_IN);
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authorities)
+            .chain(&self.additionals)
+        {
+            encode_record(&mut buffer, record, &mut compression);
+        }
+        super::write_bytes(writer, &buffer);
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        let mut parser = Parser::build(data);
+
+        let id = parser.parse_u16()?;
+        let flags = parser.parse_u16()?;
+        let question_count = parser.parse_u16()?;
+        let counts = [
+            parser.parse_u16()?,
+            parser.parse_u16()?,
+            parser.parse_u16()?,
+        ];
+
+        let opcode = (flags & OPCODE_MASK) >> 11;
+        if opcode != 0 {
+            return Err(ParseError::InvalidFieldValue {
+                field: "dns_opcode",
+                value: opcode as usize,
+            });
+        }
+        let rcode = (flags & RCODE_MASK) as u8;
+        let response_code =
+            ResponseCode::try_from(rcode).map_err(|_| ParseError::InvalidFieldValue {
+                field: "dns_response_code",
+                value: rcode as usize,
+            })?;
+
+        let mut questions = Vec::new();
+        for _ in 0..question_count {
+            let offset = data.len() - parser.remaining().len();
+            let (name, len) = decode_name(data, offset)?;
+            parser.skip(len)?;
+            let record_type = parser.parse_u16()?;
+            let _class = parser.parse_u16()?;
+
+            let record_type =
+                RecordType::try_from(record_type).map_err(|_| ParseError::InvalidFieldValue {
+                    field: "dns_question_type",
+                    value: record_type as usize,
+                })?;
+            questions.push(DnsQuestion { name, record_type });
+        }
+
+        let mut sections = [Vec::new(), Vec::new(), Vec::new()];
+        for (section, count) in sections.iter_mut().zip(counts) {
+            for _ in 0..count {
+                if let Some(record) = decode_record(data, &mut parser)? {
+                    section.push(record);
+                }
+            }
+        }
+        let [answers, authorities, additionals] = sections;
+
+        Ok(Self {
+            id,
+            response: flags & FLAG_RESPONSE != 0,
+            authoritative: flags & FLAG_AUTHORITATIVE != 0,
+            truncated: flags & FLAG_TRUNCATED != 0,
+            recursion_desired: flags & FLAG_RECURSION_DESIRED != 0,
+            recursion_available: flags & FLAG_RECURSION_AVAILABLE != 0,
+            response_code,
+            questions,
+            answers,
+            authorities,
+            additionals,
+        })
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        DnsMessage, DnsQuestion, DnsRecord, RecordData, RecordType, ResponseCode, DNS_HEADER_SIZE,
+    };
+    use crate::protocols::{Packet, ParseError};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn marshall_and_unmarshall() {
+        let query = DnsMessage::query(
+            0x1234,
+            DnsQuestion::new("WWW.example.com.", RecordType::A),
+            true,
+        );
+        let mut message = DnsMessage::response_to(&query, ResponseCode::NoError);
+        message.authoritative = true;
+        message.answers = vec![
+            DnsRecord::new(
+                "www.example.com",
+                300,
+                RecordData::Cname("web.example.com".into()),
+            ),
+            DnsRecord::new(
+                "web.example.com",
+                300,
+                RecordData::A(Ipv4Addr::new(10, 0, 2, 80)),
+            ),
+        ];
+        message.authorities = vec![DnsRecord::new(
+            "example.com",
+            3600,
+            RecordData::Ns("ns1.example.com".into()),
+        )];
+        message.additionals = vec![DnsRecord::new(
+            "ns1.example.com",
+            3600,
+            RecordData::Aaaa(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x53)),
+        )];
+
+        let bytes = message.to_bytes();
+        assert_eq!(message.questions[0].name, "www.example.com");
+        assert_eq!(Ok(&message), DnsMessage::decode(&bytes).as_ref());
+
+        // every name after the question is a pointer or a label followed by one
+        let question_len = "www.example.com".len() + 2 + 4;
+        let records_len = (2 + 10 + 6) + (2 + 10 + 4) + (2 + 10 + 6) + (2 + 10 + 16);
+        assert_eq!(bytes.len(), DNS_HEADER_SIZE + question_len + records_len);
+    }
+
+    #[test]
+    fn pointer_loops() {
+        let query = DnsMessage::query(1, DnsQuestion::new("", RecordType::Ns), false);
+        let mut bytes = query.to_bytes().to_vec();
+        // the root name becomes a pointer to itself
+        bytes[DNS_HEADER_SIZE] = 0xC0;
+        bytes.insert(DNS_HEADER_SIZE + 1, DNS_HEADER_SIZE as u8);
+
+        assert_eq!(
+            DnsMessage::decode(&bytes),
+            Err(ParseError::InvalidFieldValue {
+                field: "dns_name_pointer",
+                value: DNS_HEADER_SIZE,
+            })
+        );
+    }
+}
@@ -6,11 +6,16 @@ use std::{
     num::ParseIntError,
 };
 
-use super::{ParseError, Parser};
+use super::{arp::ArpPacket, ipv4::Ipv4Packet, ipv6::Ipv6Packet, Packet, ParseError, Parser};
 
 pub const ETHERNET_CRC_SIZE: usize = 4;
 pub const ETHERNET_MAC_ADDR_SIZE: usize = 6;
 pub const ETHERNET_BROADCAST_MAC_ADDR: MacAddress = MacAddress::new([255; ETHERNET_MAC_ADDR_SIZE]);
+pub const ETHERNET_HEADER_SIZE: usize = 2 * ETHERNET_MAC_ADDR_SIZE + 2;
+
+const DESTIN_OFFSET: usize = 0;
+const SOURCE_OFFSET: usize = ETHERNET_MAC_ADDR_SIZE;
+const PROTOCOL_OFFSET: usize = 2 * ETHERNET_MAC_ADDR_SIZE;
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Copy)]
 pub struct MacAddress([u8; ETHERNET_MAC_ADDR_SIZE]);
@@ -53,6 +58,11 @@ impl MacAddress {
     pub fn as_bytes(&self) -> &[u8] {
         &self.0
     }
+
+    // the broadcast address included
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
 }
 
 impl Display for MacAddress {
@@ -80,53 +90,164 @@ impl Debug for EthernetFrame {
             .field("source", &format_args!("{}", self.source))
             .field("destin", &format_args!("{}", self.destin))
             .field("protocol", &self.protocol)
-            .field("data", &"...") // TODO: do something about this
+            .field(
+                "data",
+                &format_args!(
+                    "{}",
+                    super::dissector::dissect_payload(self.protocol, &self.data).summary()
+                ),
+            )
             .finish()
     }
 }
 
 impl EthernetFrame {
-    pub fn to_bytes(&self) -> Box<[u8]> {
-        let mut buffer: Vec<u8> = Vec::new();
-        super::write_bytes(&mut buffer, self.destin.as_bytes());
-        super::write_bytes(&mut buffer, self.source.as_bytes());
-        super::write_u16(&mut buffer, self.protocol as u16);
-        super::write_bytes(&mut buffer, self.data.as_ref());
-        super::write_bytes(&mut buffer, &[0; ETHERNET_CRC_SIZE]);
-        buffer.into()
-    }
-
     // I purposedfully ignored the first 8 bytes (the preamble). 7 of which are patterns in the
     // form 010101... to make the NIC aware that something is going to be sent over the wire
     // (and not some random noise) and another one  which signals the start of the transmission
     // (https://en.wikipedia.org/wiki/Ethernet_frame#Preamble_and_start_frame_delimiter).
     pub fn from_raw_bytes(data: &[u8]) -> Result<Self, ParseError> {
-        let mut parser = Parser::build(data);
+        EthernetFrameRef::new(data)?.to_frame()
+    }
+}
 
-        let destin = MacAddress::new(parser.parse_chunk()?);
-        let source = MacAddress::new(parser.parse_chunk()?);
+impl Packet for EthernetFrame {
+    type Payload = FramePayload;
 
-        let protocol = parser.parse_u16()?;
+    fn header_len(&self) -> usize {
+        ETHERNET_HEADER_SIZE
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        super::write_bytes(writer, self.destin.as_bytes());
+        super::write_bytes(writer, self.source.as_bytes());
+        super::write_u16(writer, self.protocol as u16);
+        super::write_bytes(writer, self.data.as_ref());
+        super::write_bytes(writer, &[0; ETHERNET_CRC_SIZE]);
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        Self::from_raw_bytes(data)
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        self.protocol.decode(&self.data)
+    }
+}
 
-        let mut data_and_crc = parser.collect();
-        if data_and_crc.len() < ETHERNET_CRC_SIZE {
+// A view over the bytes of a frame, it lets forwarding devices look at the headers without
+// copying anything.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct EthernetFrameRef<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> EthernetFrameRef<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, ParseError> {
+        if data.len() < ETHERNET_HEADER_SIZE + ETHERNET_CRC_SIZE {
             Err(ParseError::MissingBytes)
         } else {
-            // TODO: check the the CRC bytes are all set to 0
-            data_and_crc.resize(data_and_crc.len() - ETHERNET_CRC_SIZE, 0);
-            Ok(Self {
-                source,
-                destin,
-                protocol: protocol
-                    .try_into()
-                    .map_err(|_| ParseError::InvalidFieldValue {
-                        field: "ethernet_protocol",
-                        value: protocol as usize,
-                    })?,
-                data: data_and_crc.into(),
-            })
+            Ok(Self { data })
         }
     }
+
+    pub fn destin(&self) -> MacAddress {
+        MacAddress::new(self.data[DESTIN_OFFSET..SOURCE_OFFSET].try_into().unwrap())
+    }
+
+    pub fn source(&self) -> MacAddress {
+        MacAddress::new(
+            self.data[SOURCE_OFFSET..PROTOCOL_OFFSET]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    // the raw EtherType, which might not be one of the FrameProtocols we know about
+    pub fn ether_type(&self) -> u16 {
+        u16::from_be_bytes([self.data[PROTOCOL_OFFSET], self.data[PROTOCOL_OFFSET + 1]])
+    }
+
+    pub fn protocol(&self) -> Result<FrameProtocol, ParseError> {
+        let ether_type = self.ether_type();
+        ether_type
+            .try_into()
+            .map_err(|_| ParseError::InvalidFieldValue {
+                field: "ethernet_protocol",
+                value: ether_type as usize,
+            })
+    }
+
+    pub fn payload(&self) -> &'a [u8] {
+        // TODO: check the the CRC bytes are all set to 0
+        &self.data[ETHERNET_HEADER_SIZE..self.data.len() - ETHERNET_CRC_SIZE]
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn to_frame(self) -> Result<EthernetFrame, ParseError> {
+        Ok(EthernetFrame {
+            source: self.source(),
+            destin: self.destin(),
+            protocol: self.protocol()?,
+            data: self.payload().into(),
+        })
+    }
+}
+
+impl Debug for EthernetFrameRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EthernetFrameRef")
+            .field("source", &format_args!("{}", self.source()))
+            .field("destin", &format_args!("{}", self.destin()))
+            .field("ether_type", &format_args!("{:#06x}", self.ether_type()))
+            .field("payload_len", &self.payload().len())
+            .finish()
+    }
+}
+
+// Writes the headers of a frame straight into an existing buffer (which must already have the
+// size of the whole frame, see `EthernetFrameMut::frame_size`).
+pub struct EthernetFrameMut<'a> {
+    data: &'a mut [u8],
+}
+
+impl<'a> EthernetFrameMut<'a> {
+    pub const fn frame_size(payload_len: usize) -> usize {
+        ETHERNET_HEADER_SIZE + payload_len + ETHERNET_CRC_SIZE
+    }
+
+    pub fn new(data: &'a mut [u8]) -> Result<Self, ParseError> {
+        EthernetFrameRef::new(data)?;
+        Ok(Self { data })
+    }
+
+    pub fn set_destin(&mut self, destin: MacAddress) -> &mut Self {
+        self.data[DESTIN_OFFSET..SOURCE_OFFSET].copy_from_slice(destin.as_bytes());
+        self
+    }
+
+    pub fn set_source(&mut self, source: MacAddress) -> &mut Self {
+        self.data[SOURCE_OFFSET..PROTOCOL_OFFSET].copy_from_slice(source.as_bytes());
+        self
+    }
+
+    pub fn set_protocol(&mut self, protocol: FrameProtocol) -> &mut Self {
+        self.data[PROTOCOL_OFFSET..ETHERNET_HEADER_SIZE]
+            .copy_from_slice(&(protocol as u16).to_be_bytes());
+        self
+    }
+
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let end = self.data.len() - ETHERNET_CRC_SIZE;
+        &mut self.data[ETHERNET_HEADER_SIZE..end]
+    }
+
+    pub fn as_ref(&self) -> EthernetFrameRef<'_> {
+        EthernetFrameRef { data: self.data }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -134,6 +255,78 @@ impl EthernetFrame {
 pub enum FrameProtocol {
     Ipv4 = 0x0800,
     Apr = 0x0806,
+    Vlan = 0x8100,
+    Ipv6 = 0x86DD,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum FramePayload {
+    Ipv4(Ipv4Packet),
+    Arp(ArpPacket),
+    Vlan(VlanFrame),
+    Ipv6(Ipv6Packet),
+}
+
+impl FrameProtocol {
+    pub fn decode(self, data: &[u8]) -> Result<FramePayload, ParseError> {
+        Ok(match self {
+            Self::Ipv4 => FramePayload::Ipv4(Ipv4Packet::decode(data)?),
+            Self::Apr => FramePayload::Arp(ArpPacket::decode(data)?),
+            Self::Vlan => FramePayload::Vlan(VlanFrame::decode(data)?),
+            Self::Ipv6 => FramePayload::Ipv6(Ipv6Packet::decode(data)?),
+        })
+    }
+}
+
+pub const VLAN_TAG_SIZE: usize = 4;
+
+// What follows the 0x8100 EtherType of an 802.1Q tagged frame: the tag control information and
+// then the EtherType of the frame being carried.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct VlanFrame {
+    pub priority: u8,
+    pub drop_eligible: bool,
+    pub vlan_id: u16,
+    pub protocol: FrameProtocol,
+    pub data: Box<[u8]>,
+}
+
+impl Packet for VlanFrame {
+    type Payload = FramePayload;
+
+    fn header_len(&self) -> usize {
+        VLAN_TAG_SIZE
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        let tci = (self.priority as u16) << 13 | (self.drop_eligible as u16) << 12 | self.vlan_id;
+        super::write_u16(writer, tci);
+        super::write_u16(writer, self.protocol as u16);
+        super::write_bytes(writer, &self.data);
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        let mut parser = Parser::build(data);
+        let tci = parser.parse_u16()?;
+        let protocol = parser.parse_u16()?;
+
+        Ok(Self {
+            priority: (tci >> 13) as u8,
+            drop_eligible: tci & 0x1000 != 0,
+            vlan_id: tci & 0x0FFF,
+            protocol: protocol
+                .try_into()
+                .map_err(|_| ParseError::InvalidFieldValue {
+                    field: "ethernet_protocol",
+                    value: protocol as usize,
+                })?,
+            data: parser.remaining().into(),
+        })
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        self.protocol.decode(&self.data)
+    }
 }
 
 impl TryFrom<u16> for FrameProtocol {
@@ -142,6 +335,8 @@ impl TryFrom<u16> for FrameProtocol {
         Ok(match value {
             0x0800 => Self::Ipv4,
             0x0806 => Self::Apr,
+            0x8100 => Self::Vlan,
+            0x86DD => Self::Ipv6,
             _ => return Err(()),
         })
     }
@@ -165,8 +360,11 @@ impl From<EthernetFrame> for Box<[u8]> {
 mod test {
     use std::net::IpAddr;
 
-    use super::{EthernetFrame, FrameProtocol, MacAddress, ETHERNET_MAC_ADDR_SIZE};
-    use crate::protocols::{self, ParseError};
+    use super::{
+        EthernetFrame, EthernetFrameMut, EthernetFrameRef, FrameProtocol, MacAddress,
+        ETHERNET_MAC_ADDR_SIZE,
+    };
+    use crate::protocols::{self, Packet, ParseError};
 
     struct TestEntry {
         address: &'static [u8],
@@ -281,6 +479,50 @@ mod test {
         );
     }
 
+    #[test]
+    fn frame_ref() {
+        let original = EthernetFrame {
+            source: MacAddress::new([10; ETHERNET_MAC_ADDR_SIZE]),
+            destin: MacAddress::new([11; ETHERNET_MAC_ADDR_SIZE]),
+            protocol: FrameProtocol::Apr,
+            data: Box::new([1, 2, 3, 4]),
+        };
+
+        let bytes = original.to_bytes();
+        let frame = EthernetFrameRef::new(&bytes).unwrap();
+
+        assert_eq!(frame.source(), original.source);
+        assert_eq!(frame.destin(), original.destin);
+        assert_eq!(frame.ether_type(), FrameProtocol::Apr as u16);
+        assert_eq!(frame.payload(), original.data.as_ref());
+        assert_eq!(Ok(original), frame.to_frame());
+
+        assert_eq!(
+            Err(ParseError::MissingBytes),
+            EthernetFrameRef::new(&bytes[..bytes.len() - 5])
+        );
+    }
+
+    #[test]
+    fn frame_mut_writes_in_place() {
+        let mut buffer = [0; EthernetFrameMut::frame_size(3)];
+        let mut frame = EthernetFrameMut::new(&mut buffer).unwrap();
+        frame
+            .set_source(MacAddress::new([1; ETHERNET_MAC_ADDR_SIZE]))
+            .set_destin(MacAddress::new([2; ETHERNET_MAC_ADDR_SIZE]))
+            .set_protocol(FrameProtocol::Ipv4)
+            .payload_mut()
+            .copy_from_slice(&[7, 8, 9]);
+
+        let expected = EthernetFrame {
+            source: MacAddress::new([1; ETHERNET_MAC_ADDR_SIZE]),
+            destin: MacAddress::new([2; ETHERNET_MAC_ADDR_SIZE]),
+            protocol: FrameProtocol::Ipv4,
+            data: Box::new([7, 8, 9]),
+        };
+        assert_eq!(expected.to_bytes().as_ref(), &buffer);
+    }
+
     // TODO: finish later
     //#[test]
     //fn correct_parsing() {
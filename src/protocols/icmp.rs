@@ -0,0 +1,173 @@
+use super::{Packet, ParseError, Parser};
+use std::io::Write;
+
+pub const ICMP_HEADER_SIZE: usize = 8;
+// how much of the offending datagram goes into error messages (its header plus 8 bytes)
+pub const ICMP_ERROR_QUOTE_SIZE: usize = 28;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum IcmpType {
+    EchoReply = 0,
+    DestinationUnreachable = 3,
+    EchoRequest = 8,
+    TimeExceeded = 11,
+}
+
+impl TryFrom<u8> for IcmpType {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::EchoReply,
+            3 => Self::DestinationUnreachable,
+            8 => Self::EchoRequest,
+            11 => Self::TimeExceeded,
+            _ => return Err(()),
+        })
+    }
+}
+
+// codes for DestinationUnreachable
+pub const ICMP_NET_UNREACHABLE: u8 = 0;
+pub const ICMP_HOST_UNREACHABLE: u8 = 1;
+pub const ICMP_PROTOCOL_UNREACHABLE: u8 = 2;
+pub const ICMP_PORT_UNREACHABLE: u8 = 3;
+pub const ICMP_ADMIN_PROHIBITED: u8 = 13;
+// codes for TimeExceeded
+pub const ICMP_TTL_EXCEEDED: u8 = 0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IcmpPacket {
+    pub message_type: IcmpType,
+    pub code: u8,
+    // identifier and sequence number for echo messages, unused by the others
+    pub rest_of_header: u32,
+    pub data: Box<[u8]>,
+}
+
+impl IcmpPacket {
+    pub fn echo_request(identifier: u16, sequence: u16, data: Box<[u8]>) -> Self {
+        Self {
+            message_type: IcmpType::EchoRequest,
+            code: 0,
+            rest_of_header: (identifier as u32) << 16 | sequence as u32,
+            data,
+        }
+    }
+
+    pub fn echo_reply(&self) -> Self {
+        Self {
+            message_type: IcmpType::EchoReply,
+            ..self.clone()
+        }
+    }
+
+    // `datagram` is the ipv4 packet that caused the error
+    pub fn error(message_type: IcmpType, code: u8, datagram: &[u8]) -> Self {
+        Self {
+            message_type,
+            code,
+            rest_of_header: 0,
+            data: datagram[..datagram.len().min(ICMP_ERROR_QUOTE_SIZE)].into(),
+        }
+    }
+
+    pub fn identifier(&self) -> u16 {
+        (self.rest_of_header >> 16) as u16
+    }
+
+    pub fn sequence(&self) -> u16 {
+        self.rest_of_header as u16
+    }
+
+    fn encode(&self, checksum: u16) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(ICMP_HEADER_SIZE + self.data.len());
+        super::write_u8(&mut buffer, self.message_type as u8);
+        super::write_u8(&mut buffer, self.code);
+        super::write_u16(&mut buffer, checksum);
+        super::write_u32(&mut buffer, self.rest_of_header);
+        super::write_bytes(&mut buffer, &self.data);
+        buffer
+    }
+}
+
+impl Packet for IcmpPacket {
+    // for errors this is the beginning of the datagram that caused it
+    type Payload = Box<[u8]>;
+
+    fn header_len(&self) -> usize {
+        ICMP_HEADER_SIZE
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        let checksum = super::internet_checksum(&self.encode(0));
+        super::write_bytes(writer, &self.encode(checksum));
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        let mut parser = Parser::build(data);
+
+        let message_type = parser.parse_u8()?;
+        let code = parser.parse_u8()?;
+        let checksum = parser.parse_u16()?;
+        let rest_of_header = parser.parse_u32()?;
+
+        if super::internet_checksum(data) != 0 {
+            return Err(ParseError::InvalidFieldValue {
+                field: "icmp_checksum",
+                value: checksum as usize,
+            });
+        }
+
+        Ok(Self {
+            message_type: message_type
+                .try_into()
+                .map_err(|_| ParseError::InvalidFieldValue {
+                    field: "icmp_type",
+                    value: message_type as usize,
+                })?,
+            code,
+            rest_of_header,
+            data: parser.remaining().into(),
+        })
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        Ok(self.data.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IcmpPacket, IcmpType, ICMP_ERROR_QUOTE_SIZE};
+    use crate::protocols::{Packet, ParseError};
+
+    #[test]
+    fn echo() {
+        let request = IcmpPacket::echo_request(7, 42, Box::new([1, 2, 3]));
+        let decoded = IcmpPacket::decode(&request.to_bytes()).unwrap();
+
+        assert_eq!((decoded.identifier(), decoded.sequence()), (7, 42));
+        assert_eq!(decoded, request);
+
+        let reply = decoded.echo_reply();
+        assert_eq!(reply.message_type, IcmpType::EchoReply);
+        assert_eq!(reply.data, request.data);
+    }
+
+    #[test]
+    fn error_quotes_datagram() {
+        let error = IcmpPacket::error(IcmpType::TimeExceeded, 0, &[9; 100]);
+        assert_eq!(error.data.len(), ICMP_ERROR_QUOTE_SIZE);
+
+        let mut bytes = error.to_bytes().to_vec();
+        bytes[10] = 0;
+        assert!(matches!(
+            IcmpPacket::decode(&bytes),
+            Err(ParseError::InvalidFieldValue {
+                field: "icmp_checksum",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,522 @@
+use super::{
+    ethernet::MacAddress,
+    ipv6::{Ipv6Prefix, IPV6_HEADER_SIZE, IPV6_MIN_MTU},
+    Packet, ParseError, Parser,
+};
+use std::{io::Write, net::Ipv6Addr};
+
+pub const ICMPV6_HEADER_SIZE: usize = 4;
+// errors quote as much of the offending packet as fits in the minimum MTU
+pub const ICMPV6_ERROR_QUOTE_SIZE: usize = IPV6_MIN_MTU - IPV6_HEADER_SIZE - ICMPV6_HEADER_SIZE - 4;
+
+// codes for DestinationUnreachable
+pub const ICMPV6_NO_ROUTE: u8 = 0;
+pub const ICMPV6_ADMIN_PROHIBITED: u8 = 1;
+pub const ICMPV6_ADDRESS_UNREACHABLE: u8 = 3;
+pub const ICMPV6_PORT_UNREACHABLE: u8 = 4;
+// codes for TimeExceeded
+pub const ICMPV6_HOP_LIMIT_EXCEEDED: u8 = 0;
+
+// neighbor discovery options
+const OPTION_SOURCE_LINK_ADDRESS: u8 = 1;
+const OPTION_TARGET_LINK_ADDRESS: u8 = 2;
+const OPTION_PREFIX_INFORMATION: u8 = 3;
+const OPTION_MTU: u8 = 5;
+
+const FLAG_ON_LINK: u8 = 0x80;
+const FLAG_AUTONOMOUS: u8 = 0x40;
+const FLAG_MANAGED: u8 = 0x80;
+const FLAG_OTHER: u8 = 0x40;
+const FLAG_ROUTER: u32 = 0x8000_0000;
+const FLAG_SOLICITED: u32 = 0x4000_0000;
+const FLAG_OVERRIDE: u32 = 0x2000_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Icmpv6Type {
+    DestinationUnreachable = 1,
+    PacketTooBig = 2,
+    TimeExceeded = 3,
+    EchoRequest = 128,
+    EchoReply = 129,
+    RouterSolicitation = 133,
+    RouterAdvertisement = 134,
+    NeighborSolicitation = 135,
+    NeighborAdvertisement = 136,
+}
+
+impl TryFrom<u8> for Icmpv6Type {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::DestinationUnreachable,
+            2 => Self::PacketTooBig,
+            3 => Self::TimeExceeded,
+            128 => Self::EchoRequest,
+            129 => Self::EchoReply,
+            133 => Self::RouterSolicitation,
+            134 => Self::RouterAdvertisement,
+            135 => Self::NeighborSolicitation,
+            136 => Self::NeighborAdvertisement,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixInformation {
+    pub prefix: Ipv6Prefix,
+    pub on_link: bool,
+    // whether hosts may build their addresses in the prefix (SLAAC)
+    pub autonomous: bool,
+    // in seconds, u32::MAX being forever
+    pub valid_lifetime: u32,
+    pub preferred_lifetime: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouterAdvertisement {
+    pub hop_limit: u8,
+    // addresses come from DHCPv6 (M) or only other configuration (O)
+    pub managed: bool,
+    pub other: bool,
+    // in seconds, 0 when the router shouldn't be used as a default router
+    pub router_lifetime: u16,
+    // in milliseconds
+    pub reachable_time: u32,
+    pub retransmit_timer: u32,
+    pub source_mac: Option<MacAddress>,
+    pub mtu: Option<u32>,
+    pub prefixes: Vec<PrefixInformation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NeighborAdvertisement {
+    pub target: Ipv6Addr,
+    pub router: bool,
+    pub solicited: bool,
+    // whether the cached link address should be replaced
+    pub override_cache: bool,
+    pub target_mac: Option<MacAddress>,
+}
+
+// ICMPv6 (https://www.rfc-editor.org/rfc/rfc4443) with the neighbor discovery messages of
+// https://www.rfc-editor.org/rfc/rfc4861. Unknown options are skipped when decoding. The checksum
+// covers the IPv6 addresses so it is left to `Ipv6Packet`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Icmpv6Packet {
+    DestinationUnreachable {
+        code: u8,
+        data: Box<[u8]>,
+    },
+    PacketTooBig {
+        mtu: u32,
+        data: Box<[u8]>,
+    },
+    TimeExceeded {
+        code: u8,
+        data: Box<[u8]>,
+    },
+    EchoRequest {
+        identifier: u16,
+        sequence: u16,
+        data: Box<[u8]>,
+    },
+    EchoReply {
+        identifier: u16,
+        sequence: u16,
+        data: Box<[u8]>,
+    },
+    RouterSolicitation {
+        source_mac: Option<MacAddress>,
+    },
+    RouterAdvertisement(RouterAdvertisement),
+    NeighborSolicitation {
+        target: Ipv6Addr,
+        source_mac: Option<MacAddress>,
+    },
+    NeighborAdvertisement(NeighborAdvertisement),
+}
+
+impl Icmpv6Packet {
+    pub fn message_type(&self) -> Icmpv6Type {
+        match self {
+            Self::DestinationUnreachable { .. } => Icmpv6Type::DestinationUnreachable,
+            Self::PacketTooBig { .. } => Icmpv6Type::PacketTooBig,
+            Self::TimeExceeded { .. } => Icmpv6Type::TimeExceeded,
+            Self::EchoRequest { .. } => Icmpv6Type::EchoRequest,
+            Self::EchoReply { .. } => Icmpv6Type::EchoReply,
+            Self::RouterSolicitation { .. } => Icmpv6Type::RouterSolicitation,
+            Self::RouterAdvertisement(_) => Icmpv6Type::RouterAdvertisement,
+            Self::NeighborSolicitation { .. } => Icmpv6Type::NeighborSolicitation,
+            Self::NeighborAdvertisement(_) => Icmpv6Type::NeighborAdvertisement,
+        }
+    }
+
+    // `packet` is the IPv6 packet that caused the error
+    pub fn quote(packet: &[u8]) -> Box<[u8]> {
+        packet[..packet.len().min(ICMPV6_ERROR_QUOTE_SIZE)].into()
+    }
+
+    fn code(&self) -> u8 {
+        match self {
+            Self::DestinationUnreachable { code, .. } | Self::TimeExceeded { code, .. } => *code,
+            _ => 0,
+        }
+    }
+}
+
+fn write_link_address(writer: &mut impl Write, option: u8, mac: Option<MacAddress>) {
+    if let Some(mac) = mac {
+        super::write_u8(writer, option);
+        super::write_u8(writer, 1);
+        super::write_bytes(writer, mac.as_bytes());
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    source_mac: Option<MacAddress>,
+    target_mac: Option<MacAddress>,
+    mtu: Option<u32>,
+    prefixes: Vec<PrefixInformation>,
+}
+
+fn parse_options(mut parser: Parser) -> Result<Options, ParseError> {
+    let mut options = Options::default();
+    while !parser.remaining().is_empty() {
+        let option = parser.parse_u8()?;
+        let len = parser.parse_u8()? as usize * 8;
+        if len == 0 {
+            return Err(ParseError::InvalidFieldValue {
+                field: "ndp_option_length",
+                value: 0,
+            });
+        }
+        let value = parser
+            .remaining()
+            .get(..len - 2)
+            .ok_or(ParseError::MissingBytes)?;
+        parser.skip(len - 2)?;
+        let mut value_parser = Parser::build(value);
+
+        match option {
+            OPTION_SOURCE_LINK_ADDRESS | OPTION_TARGET_LINK_ADDRESS => {
+                let mac = MacAddress::new(value_parser.parse_chunk::<6>()?);
+                if option == OPTION_SOURCE_LINK_ADDRESS {
+                    options.source_mac = Some(mac);
+                } else {
+                    options.target_mac = Some(mac);
+                }
+            }
+            OPTION_PREFIX_INFORMATION => {
+                let prefix_len = value_parser.parse_u8()?;
+                let flags = value_parser.parse_u8()?;
+                let valid_lifetime = value_parser.parse_u32()?;
+                let preferred_lifetime = value_parser.parse_u32()?;
+                let _reserved = value_parser.parse_u32()?;
+                let prefix = Ipv6Addr::from(value_parser.parse_chunk::<16>()?);
+                if prefix_len > 128 {
+                    return Err(ParseError::InvalidFieldValue {
+                        field: "ndp_prefix_length",
+                        value: prefix_len as usize,
+                    });
+                }
+                options.prefixes.push(PrefixInformation {
+                    prefix: Ipv6Prefix::new(prefix, prefix_len),
+                    on_link: flags & FLAG_ON_LINK != 0,
+                    autonomous: flags & FLAG_AUTONOMOUS != 0,
+                    valid_lifetime,
+                    preferred_lifetime,
+                });
+            }
+            OPTION_MTU => {
+                let _reserved = value_parser.parse_u16()?;
+                options.mtu = Some(value_parser.parse_u32()?);
+            }
+            _ => {}
+        }
+    }
+    Ok(options)
+}
+
+impl Packet for Icmpv6Packet {
+    type Payload = ();
+
+    fn header_len(&self) -> usize {
+        ICMPV6_HEADER_SIZE
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        super::write_u8(writer, self.message_type() as u8);
+        super::write_u8(writer, self.code());
+        super::write_u16(writer, 0); // checksum
+
+        match self {
+            Self::DestinationUnreachable { data, .. } | Self::TimeExceeded { data, .. } => {
+                super::write_u32(writer, 0);
+                super::write_bytes(writer, data);
+            }
+            Self::PacketTooBig { mtu, data } => {
+                super::write_u32(writer, *mtu);
+                super::write_bytes(writer, data);
+            }
+            Self::EchoRequest {
+                identifier,
+                sequence,
+                data,
+            }
+            | Self::EchoReply {
+                identifier,
+                sequence,
+                data,
+            } => {
+                super::write_u16(writer, *identifier);
+                super::write_u16(writer, *sequence);
+                super::write_bytes(writer, data);
+            }
+            Self::RouterSolicitation { source_mac } => {
+                super::write_u32(writer, 0);
+                write_link_address(writer, OPTION_SOURCE_LINK_ADDRESS, *source_mac);
+            }
+            Self::RouterAdvertisement(advertisement) => {
+                let mut flags = 0;
+                if advertisement.managed {
+                    flags |= FLAG_MANAGED;
+                }
+                if advertisement.other {
+                    flags |= FLAG_OTHER;
+                }
+                super::write_u8(writer, advertisement.hop_limit);
+                super::write_u8(writer, flags);
+                super::write_u16(writer, advertisement.router_lifetime);
+                super::write_u32(writer, advertisement.reachable_time);
+                super::write_u32(writer, advertisement.retransmit_timer);
+                write_link_address(writer, OPTION_SOURCE_LINK_ADDRESS, advertisement.source_mac);
+                if let Some(mtu) = advertisement.mtu {
+                    super::write_u8(writer, OPTION_MTU);
+                    super::write_u8(writer, 1);
+                    super::write_u16(writer, 0);
+                    super::write_u32(writer, mtu);
+                }
+                for info in &advertisement.prefixes {
+                    let mut flags = 0;
+                    if info.on_link {
+                        flags |= FLAG_ON_LINK;
+                    }
+                    if info.autonomous {
+                        flags |= FLAG_AUTONOMOUS;
+                    }
+                    super::write_u8(writer, OPTION_PREFIX_INFORMATION);
+                    super::write_u8(writer, 4);
+                    super::write_u8(writer, info.prefix.prefix_len());
+                    super::write_u8(writer, flags);
+                    super::write_u32(writer, info.valid_lifetime);
+                    super::write_u32(writer, info.preferred_lifetime);
+                    super::write_u32(writer, 0);
+                    super::write_bytes(writer, &info.prefix.address().octets());
+                }
+            }
+            Self::NeighborSolicitation { target, source_mac } => {
+                super::write_u32(writer, 0);
+                super::write_bytes(writer, &target.octets());
+                write_link_address(writer, OPTION_SOURCE_LINK_ADDRESS, *source_mac);
+            }
+            Self::NeighborAdvertisement(advertisement) => {
+                let mut flags = 0;
+                for (set, flag) in [
+                    (advertisement.router, FLAG_ROUTER),
+                    (advertisement.solicited, FLAG_SOLICITED),
+                    (advertisement.override_cache, FLAG_OVERRIDE),
+                ] {
+                    if set {
+                        flags |= flag;
+                    }
+                }
+                super::write_u32(writer, flags);
+                super::write_bytes(writer, &advertisement.target.octets());
+                write_link_address(writer, OPTION_TARGET_LINK_ADDRESS, advertisement.target_mac);
+            }
+        }
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        let mut parser = Parser::build(data);
+
+        let message_type = parser.parse_u8()?;
+        let code = parser.parse_u8()?;
+        let _checksum = parser.parse_u16()?;
+
+        let message_type =
+            Icmpv6Type::try_from(message_type).map_err(|_| ParseError::InvalidFieldValue {
+                field: "icmpv6_type",
+                value: message_type as usize,
+            })?;
+
+        Ok(match message_type {
+            Icmpv6Type::DestinationUnreachable | Icmpv6Type::TimeExceeded => {
+                let _unused = parser.parse_u32()?;
+                let data = parser.remaining().into();
+                if message_type == Icmpv6Type::TimeExceeded {
+                    Self::TimeExceeded { code, data }
+                } else {
+                    Self::DestinationUnreachable { code, data }
+                }
+            }
+            Icmpv6Type::PacketTooBig => Self::PacketTooBig {
+                mtu: parser.parse_u32()?,
+                data: parser.remaining().into(),
+            },
+            Icmpv6Type::EchoRequest | Icmpv6Type::EchoReply => {
+                let identifier = parser.parse_u16()?;
+                let sequence = parser.parse_u16()?;
+                let data = parser.remaining().into();
+                if message_type == Icmpv6Type::EchoRequest {
+                    Self::EchoRequest {
+                        identifier,
+                        sequence,
+                        data,
+                    }
+                } else {
+                    Self::EchoReply {
+                        identifier,
+                        sequence,
+                        data,
+                    }
+                }
+            }
+            Icmpv6Type::RouterSolicitation => {
+                let _reserved = parser.parse_u32()?;
+                Self::RouterSolicitation {
+                    source_mac: parse_options(parser)?.source_mac,
+                }
+            }
+            Icmpv6Type::RouterAdvertisement => {
+                let hop_limit = parser.parse_u8()?;
+                let flags = parser.parse_u8()?;
+                let router_lifetime = parser.parse_u16()?;
+                let reachable_time = parser.parse_u32()?;
+                let retransmit_timer = parser.parse_u32()?;
+                let options = parse_options(parser)?;
+                Self::RouterAdvertisement(RouterAdvertisement {
+                    hop_limit,
+                    managed: flags & FLAG_MANAGED != 0,
+                    other: flags & FLAG_OTHER != 0,
+                    router_lifetime,
+                    reachable_time,
+                    retransmit_timer,
+                    source_mac: options.source_mac,
+                    mtu: options.mtu,
+                    prefixes: options.prefixes,
+                })
+            }
+            Icmpv6Type::NeighborSolicitation => {
+                let _reserved = parser.parse_u32()?;
+                let target = Ipv6Addr::from(parser.parse_chunk::<16>()?);
+                Self::NeighborSolicitation {
+                    target,
+                    source_mac: parse_options(parser)?.source_mac,
+                }
+            }
+            Icmpv6Type::NeighborAdvertisement => {
+                let flags = parser.parse_u32()?;
+                let target = Ipv6Addr::from(parser.parse_chunk::<16>()?);
+                Self::NeighborAdvertisement(NeighborAdvertisement {
+                    target,
+                    router: flags & FLAG_ROUTER != 0,
+                    solicited: flags & FLAG_SOLICITED != 0,
+                    override_cache: flags & FLAG_OVERRIDE != 0,
+                    target_mac: parse_options(parser)?.target_mac,
+                })
+            }
+        })
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Icmpv6Packet, NeighborAdvertisement, PrefixInformation, RouterAdvertisement};
+    use crate::protocols::{
+        ethernet::MacAddress,
+        ipv6::{Ipv6Packet, NextHeader},
+        Packet, ParseError,
+    };
+
+    #[test]
+    fn marshall_and_unmarshall() {
+        let mac = Some(MacAddress::new([0x11; 6]));
+        let messages = [
+            Icmpv6Packet::EchoRequest {
+                identifier: 7,
+                sequence: 42,
+                data: Box::new([1, 2, 3]),
+            },
+            Icmpv6Packet::TimeExceeded {
+                code: 0,
+                data: Box::new([6; 48]),
+            },
+            Icmpv6Packet::RouterSolicitation { source_mac: mac },
+            Icmpv6Packet::RouterAdvertisement(RouterAdvertisement {
+                hop_limit: 64,
+                managed: false,
+                other: true,
+                router_lifetime: 1800,
+                reachable_time: 0,
+                retransmit_timer: 0,
+                source_mac: mac,
+                mtu: Some(1500),
+                prefixes: vec![PrefixInformation {
+                    prefix: "2001:db8:1::/64".parse().unwrap(),
+                    on_link: true,
+                    autonomous: true,
+                    valid_lifetime: 86400,
+                    preferred_lifetime: 14400,
+                }],
+            }),
+            Icmpv6Packet::NeighborSolicitation {
+                target: "2001:db8:1::1".parse().unwrap(),
+                source_mac: None,
+            },
+            Icmpv6Packet::NeighborAdvertisement(NeighborAdvertisement {
+                target: "2001:db8:1::1".parse().unwrap(),
+                router: true,
+                solicited: true,
+                override_cache: false,
+                target_mac: mac,
+            }),
+        ];
+
+        for message in messages {
+            // through IPv6 so the checksum is filled in and checked
+            let packet = Ipv6Packet::new(
+                "fe80::1".parse().unwrap(),
+                "ff02::1".parse().unwrap(),
+                NextHeader::Icmpv6,
+                message.to_bytes(),
+            );
+            let packet = Ipv6Packet::decode(&packet.to_bytes()).unwrap();
+            assert_eq!(Ok(&message), Icmpv6Packet::decode(&packet.data).as_ref());
+        }
+    }
+
+    #[test]
+    fn zero_length_option() {
+        let message = Icmpv6Packet::RouterSolicitation {
+            source_mac: Some(MacAddress::new([0x11; 6])),
+        };
+        let mut bytes = message.to_bytes().to_vec();
+        bytes[9] = 0;
+
+        assert_eq!(
+            Icmpv6Packet::decode(&bytes),
+            Err(ParseError::InvalidFieldValue {
+                field: "ndp_option_length",
+                value: 0
+            })
+        );
+    }
+}
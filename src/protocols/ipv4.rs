@@ -0,0 +1,372 @@
+use super::{
+    icmp::IcmpPacket, ospf::OspfPacket, tcp::TcpSegment, udp::UdpDatagram, Packet, ParseError,
+    Parser,
+};
+use std::{
+    fmt::{Debug, Display},
+    io::Write,
+    net::Ipv4Addr,
+    str::FromStr,
+};
+
+pub const IPV4_MIN_HEADER_SIZE: usize = 20;
+pub const IPV4_DEFAULT_TTL: u8 = 64;
+
+const IPV4_VERSION: u8 = 4;
+const IPV4_DONT_FRAGMENT: u16 = 0x4000;
+
+// A network address with its prefix length (e.g. 10.0.1.0/24), the host bits are always zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Ipv4Prefix {
+    address: Ipv4Addr,
+    prefix_len: u8,
+}
+
+#[derive(Debug)]
+pub struct InvalidPrefix;
+
+impl Ipv4Prefix {
+    pub const DEFAULT: Self = Self {
+        address: Ipv4Addr::UNSPECIFIED,
+        prefix_len: 0,
+    };
+
+    pub fn new(address: Ipv4Addr, prefix_len: u8) -> Self {
+        assert!(prefix_len <= 32, "Invalid prefix length: {prefix_len}");
+        let mask = Self::mask_bits(prefix_len);
+        Self {
+            address: Ipv4Addr::from(u32::from(address) & mask),
+            prefix_len,
+        }
+    }
+
+    // only contiguous masks are accepted
+    pub fn from_mask(address: Ipv4Addr, mask: Ipv4Addr) -> Option<Self> {
+        let mask = u32::from(mask);
+        let prefix_len = mask.leading_ones();
+        (mask.count_ones() == prefix_len).then(|| Self::new(address, prefix_len as u8))
+    }
+
+    fn mask_bits(prefix_len: u8) -> u32 {
+        u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0)
+    }
+
+    pub fn address(&self) -> Ipv4Addr {
+        self.address
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    pub fn mask(&self) -> Ipv4Addr {
+        Ipv4Addr::from(Self::mask_bits(self.prefix_len))
+    }
+
+    pub fn contains(&self, address: Ipv4Addr) -> bool {
+        u32::from(address) & Self::mask_bits(self.prefix_len) == u32::from(self.address)
+    }
+
+    pub fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.address) | !Self::mask_bits(self.prefix_len))
+    }
+}
+
+impl Display for Ipv4Prefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+impl FromStr for Ipv4Prefix {
+    type Err = InvalidPrefix;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = value.split_once('/').ok_or(InvalidPrefix)?;
+        let address = address.parse().map_err(|_| InvalidPrefix)?;
+        match prefix_len.parse() {
+            Ok(prefix_len) if prefix_len <= 32 => Ok(Self::new(address, prefix_len)),
+            _ => Err(InvalidPrefix),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IpProtocol {
+    Icmp,
+    Tcp,
+    Udp,
+    Ospf,
+    // the ones without a decoder, e.g. GRE (47)
+    Other(u8),
+}
+
+impl From<u8> for IpProtocol {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Self::Icmp,
+            6 => Self::Tcp,
+            17 => Self::Udp,
+            89 => Self::Ospf,
+            value => Self::Other(value),
+        }
+    }
+}
+
+impl From<IpProtocol> for u8 {
+    fn from(protocol: IpProtocol) -> Self {
+        match protocol {
+            IpProtocol::Icmp => 1,
+            IpProtocol::Tcp => 6,
+            IpProtocol::Udp => 17,
+            IpProtocol::Ospf => 89,
+            IpProtocol::Other(value) => value,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Ipv4Payload {
+    Icmp(IcmpPacket),
+    Tcp(TcpSegment),
+    Udp(UdpDatagram),
+    Ospf(OspfPacket),
+}
+
+impl IpProtocol {
+    pub fn decode(self, data: &[u8]) -> Result<Ipv4Payload, ParseError> {
+        Ok(match self {
+            Self::Icmp => Ipv4Payload::Icmp(IcmpPacket::decode(data)?),
+            Self::Tcp => Ipv4Payload::Tcp(TcpSegment::decode(data)?),
+            Self::Udp => Ipv4Payload::Udp(UdpDatagram::decode(data)?),
+            Self::Ospf => Ipv4Payload::Ospf(OspfPacket::decode(data)?),
+            Self::Other(value) => {
+                return Err(ParseError::InvalidFieldValue {
+                    field: "ipv4_protocol",
+                    value: value as usize,
+                })
+            }
+        })
+    }
+}
+
+// Options are skipped when decoding and fragmentation is not supported, every packet is sent with
+// the Don't Fragment flag set.
+#[derive(PartialEq, Eq, Clone)]
+pub struct Ipv4Packet {
+    pub source: Ipv4Addr,
+    pub destin: Ipv4Addr,
+    pub protocol: IpProtocol,
+    pub ttl: u8,
+    pub tos: u8,
+    pub identification: u16,
+    pub data: Box<[u8]>,
+}
+
+impl Ipv4Packet {
+    pub fn new(source: Ipv4Addr, destin: Ipv4Addr, protocol: IpProtocol, data: Box<[u8]>) -> Self {
+        Self {
+            source,
+            destin,
+            protocol,
+            ttl: IPV4_DEFAULT_TTL,
+            tos: 0,
+            identification: 0,
+            data,
+        }
+    }
+
+    fn encode_header(&self, checksum: u16) -> [u8; IPV4_MIN_HEADER_SIZE] {
+        let mut header = Vec::with_capacity(IPV4_MIN_HEADER_SIZE);
+        let total_len = (IPV4_MIN_HEADER_SIZE + self.data.len()) as u16;
+
+        super::write_u8(
+            &mut header,
+            IPV4_VERSION << 4 | (IPV4_MIN_HEADER_SIZE / 4) as u8,
+        );
+        super::write_u8(&mut header, self.tos);
+        super::write_u16(&mut header, total_len);
+        super::write_u16(&mut header, self.identification);
+        super::write_u16(&mut header, IPV4_DONT_FRAGMENT);
+        super::write_u8(&mut header, self.ttl);
+        super::write_u8(&mut header, self.protocol.into());
+        super::write_u16(&mut header, checksum);
+        super::write_bytes(&mut header, &self.source.octets());
+        super::write_bytes(&mut header, &self.destin.octets());
+
+        header.try_into().unwrap()
+    }
+}
+
+impl Packet for Ipv4Packet {
+    type Payload = Ipv4Payload;
+
+    fn header_len(&self) -> usize {
+        IPV4_MIN_HEADER_SIZE
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        let checksum = super::internet_checksum(&self.encode_header(0));
+        super::write_bytes(writer, &self.encode_header(checksum));
+        super::write_bytes(writer, &self.data);
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        let mut parser = Parser::build(data);
+
+        let version_ihl = parser.parse_u8()?;
+        let version = version_ihl >> 4;
+        let header_len = (version_ihl & 0x0F) as usize * 4;
+
+        if version != IPV4_VERSION {
+            return Err(ParseError::InvalidFieldValue {
+                field: "ipv4_version",
+                value: version as usize,
+            });
+        }
+
+        if header_len < IPV4_MIN_HEADER_SIZE {
+            return Err(ParseError::InvalidFieldValue {
+                field: "ipv4_header_length",
+                value: header_len,
+            });
+        }
+
+        let tos = parser.parse_u8()?;
+        let total_len = parser.parse_u16()? as usize;
+        let identification = parser.parse_u16()?;
+        let _flags_fragment = parser.parse_u16()?;
+        let ttl = parser.parse_u8()?;
+        let protocol = parser.parse_u8()?;
+        let checksum = parser.parse_u16()?;
+        let source = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+        let destin = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+        parser.skip(header_len - IPV4_MIN_HEADER_SIZE)?;
+
+        if data.len() < total_len || total_len < header_len {
+            return Err(ParseError::MissingBytes);
+        }
+
+        if super::internet_checksum(&data[..header_len]) != 0 {
+            return Err(ParseError::InvalidFieldValue {
+                field: "ipv4_checksum",
+                value: checksum as usize,
+            });
+        }
+
+        Ok(Self {
+            source,
+            destin,
+            protocol: protocol.into(),
+            ttl,
+            tos,
+            identification,
+            // anything after total_len is padding (e.g. from a minimum sized ethernet frame)
+            data: data[header_len..total_len].into(),
+        })
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        self.protocol.decode(&self.data)
+    }
+}
+
+impl Debug for Ipv4Packet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ipv4Packet")
+            .field("source", &format_args!("{}", self.source))
+            .field("destin", &format_args!("{}", self.destin))
+            .field("protocol", &self.protocol)
+            .field("ttl", &self.ttl)
+            .field("data_len", &self.data.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IpProtocol, Ipv4Packet, Ipv4Payload, Ipv4Prefix, IPV4_MIN_HEADER_SIZE};
+    use crate::protocols::{Packet, ParseError};
+    use std::net::Ipv4Addr;
+
+    fn packet() -> Ipv4Packet {
+        Ipv4Packet::new(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(192, 168, 1, 254),
+            IpProtocol::Tcp,
+            Box::new([1, 2, 3, 4, 5]),
+        )
+    }
+
+    #[test]
+    fn prefixes() {
+        let prefix: Ipv4Prefix = "10.0.1.7/24".parse().unwrap();
+
+        assert_eq!(prefix.to_string(), "10.0.1.0/24");
+        assert_eq!(prefix.mask(), Ipv4Addr::new(255, 255, 255, 0));
+        assert_eq!(prefix.broadcast(), Ipv4Addr::new(10, 0, 1, 255));
+        assert!(prefix.contains(Ipv4Addr::new(10, 0, 1, 200)));
+        assert!(!prefix.contains(Ipv4Addr::new(10, 0, 2, 1)));
+        assert!(Ipv4Prefix::DEFAULT.contains(Ipv4Addr::new(8, 8, 8, 8)));
+
+        assert_eq!(
+            Ipv4Prefix::from_mask(Ipv4Addr::new(10, 0, 0, 0), Ipv4Addr::new(255, 0, 0, 0)),
+            Some("10.0.0.0/8".parse().unwrap())
+        );
+        assert_eq!(
+            Ipv4Prefix::from_mask(Ipv4Addr::new(10, 0, 0, 0), Ipv4Addr::new(255, 0, 255, 0)),
+            None
+        );
+        assert!("10.0.0.0/33".parse::<Ipv4Prefix>().is_err());
+    }
+
+    #[test]
+    fn marshall_and_unmarshall() {
+        let original = packet();
+        let bytes = original.to_bytes();
+
+        assert_eq!(bytes.len(), IPV4_MIN_HEADER_SIZE + 5);
+        assert_eq!(Ok(original), Ipv4Packet::decode(&bytes));
+    }
+
+    #[test]
+    fn ignores_padding() {
+        let original = packet();
+        let mut bytes = original.to_bytes().to_vec();
+        bytes.extend([0; 20]);
+
+        assert_eq!(Ok(original), Ipv4Packet::decode(&bytes));
+    }
+
+    #[test]
+    fn invalid_checksum() {
+        let mut bytes = packet().to_bytes().to_vec();
+        bytes[8] -= 1; // the ttl
+
+        assert!(matches!(
+            Ipv4Packet::decode(&bytes),
+            Err(ParseError::InvalidFieldValue {
+                field: "ipv4_checksum",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn malformed_payload() {
+        assert_eq!(Err(ParseError::MissingBytes), packet().decode_payload());
+    }
+
+    #[test]
+    fn unknown_protocol() {
+        let original = Ipv4Packet {
+            protocol: IpProtocol::Other(47),
+            ..packet()
+        };
+        let bytes = original.to_bytes();
+
+        assert_eq!(bytes[9], 47);
+        let decoded = Ipv4Packet::decode(&bytes).unwrap();
+        assert_eq!(decoded, original);
+        assert!(decoded.decode_payload().is_err());
+    }
+}
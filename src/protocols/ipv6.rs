@@ -0,0 +1,482 @@
+use super::{ethernet::MacAddress, Packet, ParseError, Parser};
+use std::{
+    fmt::{Debug, Display},
+    io::Write,
+    net::Ipv6Addr,
+    str::FromStr,
+};
+
+pub const IPV6_HEADER_SIZE: usize = 40;
+pub const IPV6_DEFAULT_HOP_LIMIT: u8 = 64;
+// every link has to carry packets at least that big
+pub const IPV6_MIN_MTU: usize = 1280;
+
+pub const ALL_NODES: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+pub const ALL_ROUTERS: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 2);
+
+const IPV6_VERSION: u8 = 6;
+const FRAGMENT_HEADER_SIZE: usize = 8;
+// where the checksum sits in an ICMPv6 message
+const ICMPV6_CHECKSUM_OFFSET: usize = 2;
+
+// A network address with its prefix length (e.g. 2001:db8::/64), the host bits are always zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Ipv6Prefix {
+    address: Ipv6Addr,
+    prefix_len: u8,
+}
+
+#[derive(Debug)]
+pub struct InvalidPrefix;
+
+impl Ipv6Prefix {
+    pub const DEFAULT: Self = Self {
+        address: Ipv6Addr::UNSPECIFIED,
+        prefix_len: 0,
+    };
+
+    pub fn new(address: Ipv6Addr, prefix_len: u8) -> Self {
+        assert!(prefix_len <= 128, "Invalid prefix length: {prefix_len}");
+        let mask = Self::mask_bits(prefix_len);
+        Self {
+            address: Ipv6Addr::from(u128::from(address) & mask),
+            prefix_len,
+        }
+    }
+
+    fn mask_bits(prefix_len: u8) -> u128 {
+        u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0)
+    }
+
+    pub fn address(&self) -> Ipv6Addr {
+        self.address
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    pub fn contains(&self, address: Ipv6Addr) -> bool {
+        u128::from(address) & Self::mask_bits(self.prefix_len) == u128::from(self.address)
+    }
+
+    // the address built from the MAC address with EUI-64, only for /64 prefixes
+    pub fn with_interface_id(&self, mac: MacAddress) -> Ipv6Addr {
+        assert_eq!(self.prefix_len, 64, "EUI-64 needs a /64 prefix");
+        let mut octets = self.address.octets();
+        octets[8..].copy_from_slice(&interface_id(mac));
+        Ipv6Addr::from(octets)
+    }
+}
+
+impl Display for Ipv6Prefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+impl FromStr for Ipv6Prefix {
+    type Err = InvalidPrefix;
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = value.split_once('/').ok_or(InvalidPrefix)?;
+        let address = address.parse().map_err(|_| InvalidPrefix)?;
+        match prefix_len.parse() {
+            Ok(prefix_len) if prefix_len <= 128 => Ok(Self::new(address, prefix_len)),
+            _ => Err(InvalidPrefix),
+        }
+    }
+}
+
+// The modified EUI-64 interface identifier (https://www.rfc-editor.org/rfc/rfc4291#appendix-A):
+// FF:FE goes in the middle of the MAC address and the universal/local bit is flipped.
+pub fn interface_id(mac: MacAddress) -> [u8; 8] {
+    let mac = mac.as_bytes();
+    [
+        mac[0] ^ 0x02,
+        mac[1],
+        mac[2],
+        0xFF,
+        0xFE,
+        mac[3],
+        mac[4],
+        mac[5],
+    ]
+}
+
+pub fn link_local_address(mac: MacAddress) -> Ipv6Addr {
+    Ipv6Prefix::new(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 64).with_interface_id(mac)
+}
+
+// the group of the nodes whose addresses end with the same 24 bits, where neighbor solicitations
+// are sent to instead of broadcasting them
+pub fn solicited_node_address(address: Ipv6Addr) -> Ipv6Addr {
+    let octets = address.octets();
+    Ipv6Addr::from([
+        0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0xff, octets[13], octets[14], octets[15],
+    ])
+}
+
+// https://www.rfc-editor.org/rfc/rfc2464#section-7
+pub fn multicast_mac_address(address: Ipv6Addr) -> MacAddress {
+    let octets = address.octets();
+    MacAddress::new([0x33, 0x33, octets[12], octets[13], octets[14], octets[15]])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum NextHeader {
+    HopByHop = 0,
+    Tcp = 6,
+    Udp = 17,
+    Routing = 43,
+    Fragment = 44,
+    Icmpv6 = 58,
+    // No Next Header, nothing follows
+    NoNext = 59,
+    DestinationOptions = 60,
+}
+
+impl TryFrom<u8> for NextHeader {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::HopByHop,
+            6 => Self::Tcp,
+            17 => Self::Udp,
+            43 => Self::Routing,
+            44 => Self::Fragment,
+            58 => Self::Icmpv6,
+            59 => Self::NoNext,
+            60 => Self::DestinationOptions,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl NextHeader {
+    pub fn is_extension(self) -> bool {
+        matches!(
+            self,
+            Self::HopByHop | Self::Routing | Self::Fragment | Self::DestinationOptions
+        )
+    }
+}
+
+// An extension header without its next header and length fields. The data of the fragment header
+// is always 6 bytes, the others are padded with zeros (Pad1 options) to a multiple of 8 bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionHeader {
+    pub header_type: NextHeader,
+    pub data: Box<[u8]>,
+}
+
+impl ExtensionHeader {
+    fn encoded_len(&self) -> usize {
+        match self.header_type {
+            NextHeader::Fragment => FRAGMENT_HEADER_SIZE,
+            _ => (2 + self.data.len()).next_multiple_of(8),
+        }
+    }
+}
+
+// https://www.rfc-editor.org/rfc/rfc8200. Extension headers are chained in the order they are
+// given and `protocol` is the header that comes after them. Fragments are never reassembled.
+#[derive(PartialEq, Eq, Clone)]
+pub struct Ipv6Packet {
+    pub source: Ipv6Addr,
+    pub destin: Ipv6Addr,
+    pub protocol: NextHeader,
+    pub hop_limit: u8,
+    pub traffic_class: u8,
+    pub flow_label: u32,
+    pub extension_headers: Vec<ExtensionHeader>,
+    pub data: Box<[u8]>,
+}
+
+// the sum over the pseudo header of https://www.rfc-editor.org/rfc/rfc8200#section-8.1
+fn pseudo_header_checksum(
+    source: Ipv6Addr,
+    destin: Ipv6Addr,
+    protocol: NextHeader,
+    data: &[u8],
+) -> u16 {
+    let mut buffer = Vec::with_capacity(IPV6_HEADER_SIZE + data.len());
+    super::write_bytes(&mut buffer, &source.octets());
+    super::write_bytes(&mut buffer, &destin.octets());
+    super::write_u32(&mut buffer, data.len() as u32);
+    super::write_u32(&mut buffer, protocol as u32);
+    super::write_bytes(&mut buffer, data);
+    super::internet_checksum(&buffer)
+}
+
+impl Ipv6Packet {
+    // The ICMPv6 checksum covers the addresses, so it is filled in here. Like over IPv4, UDP and TCP
+    // are sent without one.
+    pub fn new(source: Ipv6Addr, destin: Ipv6Addr, protocol: NextHeader, data: Box<[u8]>) -> Self {
+        let mut data = data;
+        if protocol == NextHeader::Icmpv6 && data.len() >= ICMPV6_CHECKSUM_OFFSET + 2 {
+            data[ICMPV6_CHECKSUM_OFFSET..ICMPV6_CHECKSUM_OFFSET + 2].fill(0);
+            let checksum = pseudo_header_checksum(source, destin, protocol, &data);
+            data[ICMPV6_CHECKSUM_OFFSET..ICMPV6_CHECKSUM_OFFSET + 2]
+                .copy_from_slice(&checksum.to_be_bytes());
+        }
+
+        Self {
+            source,
+            destin,
+            protocol,
+            hop_limit: IPV6_DEFAULT_HOP_LIMIT,
+            traffic_class: 0,
+            flow_label: 0,
+            extension_headers: Vec::new(),
+            data,
+        }
+    }
+}
+
+impl Packet for Ipv6Packet {
+    type Payload = Box<[u8]>;
+
+    fn header_len(&self) -> usize {
+        IPV6_HEADER_SIZE
+            + self
+                .extension_headers
+                .iter()
+                .map(ExtensionHeader::encoded_len)
+                .sum::<usize>()
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        let payload_len = self.header_len() - IPV6_HEADER_SIZE + self.data.len();
+        let first = self
+            .extension_headers
+            .first()
+            .map_or(self.protocol, |header| header.header_type);
+
+        super::write_u32(
+            writer,
+            (IPV6_VERSION as u32) << 28
+                | (self.traffic_class as u32) << 20
+                | self.flow_label & 0xFFFFF,
+        );
+        super::write_u16(writer, payload_len as u16);
+        super::write_u8(writer, first as u8);
+        super::write_u8(writer, self.hop_limit);
+        super::write_bytes(writer, &self.source.octets());
+        super::write_bytes(writer, &self.destin.octets());
+
+        for (index, header) in self.extension_headers.iter().enumerate() {
+            let next = self
+                .extension_headers
+                .get(index + 1)
+                .map_or(self.protocol, |next| next.header_type);
+            let len = header.encoded_len();
+
+            super::write_u8(writer, next as u8);
+            match header.header_type {
+                NextHeader::Fragment => super::write_u8(writer, 0), // reserved
+                _ => super::write_u8(writer, (len / 8 - 1) as u8),
+            }
+            super::write_bytes(writer, &header.data);
+            super::write_bytes(writer, &vec![0; len - 2 - header.data.len()]);
+        }
+        super::write_bytes(writer, &self.data);
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        let mut parser = Parser::build(data);
+
+        let first_word = parser.parse_u32()?;
+        let payload_len = parser.parse_u16()? as usize;
+        let mut next = parser.parse_u8()?;
+        let hop_limit = parser.parse_u8()?;
+        let source = Ipv6Addr::from(parser.parse_chunk::<16>()?);
+        let destin = Ipv6Addr::from(parser.parse_chunk::<16>()?);
+
+        let version = (first_word >> 28) as u8;
+        if version != IPV6_VERSION {
+            return Err(ParseError::InvalidFieldValue {
+                field: "ipv6_version",
+                value: version as usize,
+            });
+        }
+
+        // anything after the payload is padding (e.g. from a minimum sized ethernet frame)
+        let payload = parser
+            .remaining()
+            .get(..payload_len)
+            .ok_or(ParseError::MissingBytes)?;
+        let mut parser = Parser::build(payload);
+
+        let mut extension_headers = Vec::new();
+        let protocol = loop {
+            let header_type =
+                NextHeader::try_from(next).map_err(|_| ParseError::InvalidFieldValue {
+                    field: "ipv6_next_header",
+                    value: next as usize,
+                })?;
+            if !header_type.is_extension() {
+                break header_type;
+            }
+
+            next = parser.parse_u8()?;
+            let len = match header_type {
+                NextHeader::Fragment => {
+                    parser.parse_u8()?;
+                    FRAGMENT_HEADER_SIZE
+                }
+                _ => (parser.parse_u8()? as usize + 1) * 8,
+            };
+            let header_data = parser
+                .remaining()
+                .get(..len - 2)
+                .ok_or(ParseError::MissingBytes)?;
+            parser.skip(len - 2)?;
+
+            let data = match header_type {
+                NextHeader::Fragment => header_data.into(),
+                // trailing zeros are taken for padding and aren't kept
+                _ => {
+                    let end = header_data
+                        .iter()
+                        .rposition(|byte| *byte != 0)
+                        .map_or(0, |position| position + 1);
+                    header_data[..end].into()
+                }
+            };
+            extension_headers.push(ExtensionHeader { header_type, data });
+        };
+
+        let data: Box<[u8]> = parser.remaining().into();
+        if protocol == NextHeader::Icmpv6
+            && pseudo_header_checksum(source, destin, protocol, &data) != 0
+        {
+            return Err(ParseError::InvalidFieldValue {
+                field: "icmpv6_checksum",
+                value: data
+                    .get(ICMPV6_CHECKSUM_OFFSET..ICMPV6_CHECKSUM_OFFSET + 2)
+                    .map_or(0, |bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize),
+            });
+        }
+
+        Ok(Self {
+            source,
+            destin,
+            protocol,
+            hop_limit,
+            traffic_class: (first_word >> 20) as u8,
+            flow_label: first_word & 0xFFFFF,
+            extension_headers,
+            data,
+        })
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        Ok(self.data.clone())
+    }
+}
+
+impl Debug for Ipv6Packet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ipv6Packet")
+            .field("source", &format_args!("{}", self.source))
+            .field("destin", &format_args!("{}", self.destin))
+            .field("protocol", &self.protocol)
+            .field("hop_limit", &self.hop_limit)
+            .field("data_len", &self.data.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        link_local_address, multicast_mac_address, solicited_node_address, ExtensionHeader,
+        Ipv6Packet, Ipv6Prefix, NextHeader, IPV6_HEADER_SIZE,
+    };
+    use crate::protocols::{ethernet::MacAddress, Packet, ParseError};
+    use std::net::Ipv6Addr;
+
+    #[test]
+    fn addresses() {
+        let mac = MacAddress::new([0x00, 0x1b, 0x21, 0x3c, 0x4d, 0x5e]);
+        let link_local: Ipv6Addr = "fe80::21b:21ff:fe3c:4d5e".parse().unwrap();
+        assert_eq!(link_local_address(mac), link_local);
+
+        let prefix: Ipv6Prefix = "2001:db8:1:2::7/64".parse().unwrap();
+        assert_eq!(prefix.to_string(), "2001:db8:1:2::/64");
+        assert_eq!(
+            prefix.with_interface_id(mac),
+            "2001:db8:1:2:21b:21ff:fe3c:4d5e"
+                .parse::<Ipv6Addr>()
+                .unwrap()
+        );
+        assert!(prefix.contains(prefix.with_interface_id(mac)));
+        assert!(!prefix.contains(link_local));
+
+        let solicited = solicited_node_address(link_local);
+        assert_eq!(solicited, "ff02::1:ff3c:4d5e".parse::<Ipv6Addr>().unwrap());
+        assert_eq!(
+            multicast_mac_address(solicited),
+            MacAddress::new([0x33, 0x33, 0xff, 0x3c, 0x4d, 0x5e])
+        );
+    }
+
+    #[test]
+    fn extension_headers() {
+        let mut packet = Ipv6Packet::new(
+            "2001:db8::1".parse().unwrap(),
+            "2001:db8::2".parse().unwrap(),
+            NextHeader::Udp,
+            Box::new([1, 2, 3]),
+        );
+        packet.flow_label = 0x12345;
+        packet.extension_headers = vec![
+            ExtensionHeader {
+                header_type: NextHeader::HopByHop,
+                data: Box::new([5, 2, 0, 1]),
+            },
+            ExtensionHeader {
+                header_type: NextHeader::Fragment,
+                data: Box::new([0, 0, 0, 0, 0, 7]),
+            },
+            ExtensionHeader {
+                header_type: NextHeader::DestinationOptions,
+                data: Box::new([1; 10]),
+            },
+        ];
+
+        let bytes = packet.to_bytes();
+        assert_eq!(bytes.len(), IPV6_HEADER_SIZE + 8 + 8 + 16 + 3);
+        assert_eq!(bytes[6], NextHeader::HopByHop as u8);
+        assert_eq!(bytes[IPV6_HEADER_SIZE], NextHeader::Fragment as u8);
+        assert_eq!(Ok(&packet), Ipv6Packet::decode(&bytes).as_ref());
+
+        assert_eq!(
+            Ipv6Packet::decode(&bytes[..bytes.len() - 1]),
+            Err(ParseError::MissingBytes)
+        );
+    }
+
+    #[test]
+    fn icmpv6_checksum() {
+        let packet = Ipv6Packet::new(
+            "fe80::1".parse().unwrap(),
+            "fe80::2".parse().unwrap(),
+            NextHeader::Icmpv6,
+            Box::new([128, 0, 0, 0, 0, 1, 0, 1]),
+        );
+        let mut bytes = packet.to_bytes().to_vec();
+        assert_eq!(Ok(&packet), Ipv6Packet::decode(&bytes).as_ref());
+
+        // the destination is covered by the checksum
+        bytes[IPV6_HEADER_SIZE - 1] = 3;
+        assert!(matches!(
+            Ipv6Packet::decode(&bytes),
+            Err(ParseError::InvalidFieldValue {
+                field: "icmpv6_checksum",
+                ..
+            })
+        ));
+    }
+}
@@ -1,4 +1,17 @@
+pub mod arp;
+pub mod bgp;
+pub mod dhcp;
+pub mod dissector;
+pub mod dns;
 pub mod ethernet;
+pub mod icmp;
+pub mod icmpv6;
+pub mod ipv4;
+pub mod ipv6;
+pub mod ospf;
+pub mod rip;
+pub mod tcp;
+pub mod udp;
 use std::io::Write;
 
 #[derive(Debug, PartialEq, Eq)]
@@ -9,6 +22,57 @@ pub enum ParseError {
 
 type Result<T> = std::result::Result<T, ParseError>;
 
+// Every protocol encodes its header followed by the payload it carries, which is usually the
+// encoding of the protocol above it. Decoding goes the other way around: `decode` only parses this
+// layer and `decode_payload` dispatches to the decoder of the next one.
+pub trait Packet: Sized {
+    type Payload;
+
+    fn header_len(&self) -> usize;
+    fn encode_into(&self, writer: &mut impl Write);
+    fn decode(data: &[u8]) -> Result<Self>;
+    fn decode_payload(&self) -> Result<Self::Payload>;
+
+    fn to_bytes(&self) -> Box<[u8]> {
+        let mut buffer = Vec::new();
+        self.encode_into(&mut buffer);
+        buffer.into()
+    }
+}
+
+// https://www.rfc-editor.org/rfc/rfc1071
+pub fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = ones_complement_words(data).map(u32::from).sum();
+
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn ones_complement_words(data: &[u8]) -> impl Iterator<Item = u16> + '_ {
+    data.chunks(2).map(|chunk| match chunk {
+        [high, low] => u16::from_be_bytes([*high, *low]),
+        [high] => u16::from_be_bytes([*high, 0]),
+        _ => unreachable!(),
+    })
+}
+
+// Updates a checksum after `old` was replaced by `new` in the data it covers, without going over
+// the whole data again (https://www.rfc-editor.org/rfc/rfc1624).
+pub fn adjust_checksum(checksum: u16, old: &[u8], new: &[u8]) -> u16 {
+    let mut sum = !checksum as u32;
+    sum += ones_complement_words(old)
+        .map(|word| !word as u32)
+        .sum::<u32>();
+    sum += ones_complement_words(new).map(u32::from).sum::<u32>();
+
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
 struct Parser<'a> {
     iter: std::slice::Iter<'a, u8>,
 }
@@ -31,6 +95,10 @@ impl<'a> Parser<'a> {
         self.iter.next().copied()
     }
 
+    fn parse_u8(&mut self) -> Result<u8> {
+        self.next_u8().ok_or(ParseError::MissingBytes)
+    }
+
     fn parse_u16(&mut self) -> Result<u16> {
         let bytes = self.parse_chunk()?;
         Ok(u16::from_be_bytes(bytes))
@@ -49,6 +117,18 @@ impl<'a> Parser<'a> {
     fn collect(self) -> Vec<u8> {
         self.iter.clone().copied().collect()
     }
+
+    fn remaining(&self) -> &'a [u8] {
+        self.iter.as_slice()
+    }
+
+    fn skip(&mut self, count: usize) -> Result<()> {
+        if self.remaining().len() < count {
+            return Err(ParseError::MissingBytes);
+        }
+        self.iter = self.remaining()[count..].iter();
+        Ok(())
+    }
 }
 
 #[inline]
@@ -56,6 +136,11 @@ fn write_u32(writer: &mut impl Write, value: u32) {
     writer.write_all(&value.to_be_bytes()).unwrap()
 }
 
+#[inline]
+fn write_u8(writer: &mut impl Write, value: u8) {
+    writer.write_all(&[value]).unwrap()
+}
+
 #[inline]
 fn write_u16(writer: &mut impl Write, value: u16) {
     writer.write_all(&value.to_be_bytes()).unwrap()
@@ -76,4 +161,24 @@ mod test {
         assert_eq!(parser.next_u8(), Some(0));
         assert_eq!(&[1, 2], parser.collect().as_slice());
     }
+
+    #[test]
+    fn checksum() {
+        // example from https://en.wikipedia.org/wiki/Internet_checksum
+        let header = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xc0, 0xa8,
+            0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+        assert_eq!(internet_checksum(&header), 0xb861);
+    }
+
+    #[test]
+    fn adjusted_checksum() {
+        let mut data = [0x12, 0x34, 0xab, 0xcd, 0x00, 0x01, 0xff, 0xfe];
+        let checksum = internet_checksum(&data);
+        data[2..6].copy_from_slice(&[0x0a, 0x00, 0x00, 0x02]);
+
+        let adjusted = adjust_checksum(checksum, &[0xab, 0xcd, 0x00, 0x01], &data[2..6]);
+        assert_eq!(adjusted, internet_checksum(&data));
+    }
 }
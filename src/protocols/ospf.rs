@@ -0,0 +1,424 @@
+use super::{Packet, ParseError, Parser};
+use std::{io::Write, net::Ipv4Addr};
+
+pub const OSPF_ALL_ROUTERS: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 5);
+pub const OSPF_HEADER_SIZE: usize = 24;
+pub const OSPF_INITIAL_SEQUENCE: i32 = i32::MIN + 1;
+// seconds
+pub const OSPF_MAX_AGE: u16 = 3600;
+
+const OSPF_VERSION: u8 = 2;
+const LSA_HEADER_SIZE: usize = 20;
+const LSA_ROUTER_TYPE: u8 = 1;
+const ROUTER_LINK_SIZE: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OspfPacketType {
+    Hello = 1,
+    LinkStateUpdate = 4,
+    LinkStateAck = 5,
+}
+
+impl TryFrom<u8> for OspfPacketType {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::Hello,
+            4 => Self::LinkStateUpdate,
+            5 => Self::LinkStateAck,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RouterLinkType {
+    PointToPoint = 1,
+    Stub = 3,
+}
+
+impl TryFrom<u8> for RouterLinkType {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::PointToPoint,
+            3 => Self::Stub,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouterLink {
+    pub link_type: RouterLinkType,
+    // the neighbour's router id for point-to-point links, the network address for stub ones
+    pub link_id: Ipv4Addr,
+    // the interface address for point-to-point links, the network mask for stub ones
+    pub link_data: Ipv4Addr,
+    pub metric: u16,
+}
+
+// Only router-LSAs exist, so the link state id is always the advertising router.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LsaHeader {
+    pub age: u16,
+    pub advertising_router: Ipv4Addr,
+    pub sequence: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lsa {
+    pub header: LsaHeader,
+    pub links: Vec<RouterLink>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OspfHello {
+    pub network_mask: Ipv4Addr,
+    pub hello_interval: u16,
+    pub dead_interval: u32,
+    // the routers heard from on this interface
+    pub neighbors: Vec<Ipv4Addr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OspfBody {
+    Hello(OspfHello),
+    LinkStateUpdate(Vec<Lsa>),
+    LinkStateAck(Vec<LsaHeader>),
+}
+
+// A subset of OSPFv2 (https://www.rfc-editor.org/rfc/rfc2328): there is no database description
+// exchange, no designated routers and a single area. Authentication is not supported and the LSA
+// checksums are neither computed nor checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OspfPacket {
+    pub router_id: Ipv4Addr,
+    pub area_id: Ipv4Addr,
+    pub body: OspfBody,
+}
+
+impl OspfPacket {
+    pub fn packet_type(&self) -> OspfPacketType {
+        match self.body {
+            OspfBody::Hello(_) => OspfPacketType::Hello,
+            OspfBody::LinkStateUpdate(_) => OspfPacketType::LinkStateUpdate,
+            OspfBody::LinkStateAck(_) => OspfPacketType::LinkStateAck,
+        }
+    }
+
+    fn encode(&self, checksum: u16) -> Vec<u8> {
+        let mut body = Vec::new();
+        match &self.body {
+            OspfBody::Hello(hello) => {
+                super::write_bytes(&mut body, &hello.network_mask.octets());
+                super::write_u16(&mut body, hello.hello_interval);
+                super::write_u8(&mut body, 0); // options
+                super::write_u8(&mut body, 0); // priority, never a designated router
+                super::write_u32(&mut body, hello.dead_interval);
+                super::write_u32(&mut body, 0); // designated router
+                super::write_u32(&mut body, 0); // backup designated router
+                for neighbor in hello.neighbors.iter() {
+                    super::write_bytes(&mut body, &neighbor.octets());
+                }
+            }
+            OspfBody::LinkStateUpdate(lsas) => {
+                super::write_u32(&mut body, lsas.len() as u32);
+                for lsa in lsas {
+                    encode_lsa_header(
+                        &mut body,
+                        &lsa.header,
+                        LSA_HEADER_SIZE + 4 + lsa.links.len() * ROUTER_LINK_SIZE,
+                    );
+                    super::write_u16(&mut body, 0); // flags
+                    super::write_u16(&mut body, lsa.links.len() as u16);
+                    for link in lsa.links.iter() {
+                        super::write_bytes(&mut body, &link.link_id.octets());
+                        super::write_bytes(&mut body, &link.link_data.octets());
+                        super::write_u8(&mut body, link.link_type as u8);
+                        super::write_u8(&mut body, 0); // no TOS metrics
+                        super::write_u16(&mut body, link.metric);
+                    }
+                }
+            }
+            OspfBody::LinkStateAck(headers) => {
+                for header in headers {
+                    encode_lsa_header(&mut body, header, 0);
+                }
+            }
+        }
+
+        let mut buffer = Vec::with_capacity(OSPF_HEADER_SIZE + body.len());
+        super::write_u8(&mut buffer, OSPF_VERSION);
+        super::write_u8(&mut buffer, self.packet_type() as u8);
+        super::write_u16(&mut buffer, (OSPF_HEADER_SIZE + body.len()) as u16);
+        super::write_bytes(&mut buffer, &self.router_id.octets());
+        super::write_bytes(&mut buffer, &self.area_id.octets());
+        super::write_u16(&mut buffer, checksum);
+        super::write_u16(&mut buffer, 0); // no authentication
+        super::write_bytes(&mut buffer, &[0; 8]);
+        super::write_bytes(&mut buffer, &body);
+        buffer
+    }
+}
+
+fn encode_lsa_header(writer: &mut impl Write, header: &LsaHeader, length: usize) {
+    super::write_u16(writer, header.age);
+    super::write_u8(writer, 0); // options
+    super::write_u8(writer, LSA_ROUTER_TYPE);
+    super::write_bytes(writer, &header.advertising_router.octets()); // link state id
+    super::write_bytes(writer, &header.advertising_router.octets());
+    super::write_u32(writer, header.sequence as u32);
+    super::write_u16(writer, 0); // checksum
+    super::write_u16(writer, length as u16);
+}
+
+fn parse_lsa_header(parser: &mut Parser) -> Result<(LsaHeader, usize), ParseError> {
+    let age = parser.parse_u16()?;
+    let _options = parser.parse_u8()?;
+    let lsa_type = parser.parse_u8()?;
+    let _link_state_id = parser.parse_chunk::<4>()?;
+    let advertising_router = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+    let sequence = parser.parse_u32()? as i32;
+    let _checksum = parser.parse_u16()?;
+    let length = parser.parse_u16()? as usize;
+
+    if lsa_type != LSA_ROUTER_TYPE {
+        return Err(ParseError::InvalidFieldValue {
+            field: "lsa_type",
+            value: lsa_type as usize,
+        });
+    }
+
+    let header = LsaHeader {
+        age,
+        advertising_router,
+        sequence,
+    };
+    Ok((header, length))
+}
+
+fn parse_lsa(parser: &mut Parser) -> Result<Lsa, ParseError> {
+    let (header, length) = parse_lsa_header(parser)?;
+    let _flags = parser.parse_u16()?;
+    let link_nr = parser.parse_u16()? as usize;
+
+    if length != LSA_HEADER_SIZE + 4 + link_nr * ROUTER_LINK_SIZE {
+        return Err(ParseError::InvalidFieldValue {
+            field: "lsa_length",
+            value: length,
+        });
+    }
+
+    let links = (0..link_nr)
+        .map(|_| {
+            let link_id = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+            let link_data = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+            let link_type = parser.parse_u8()?;
+            let _tos_nr = parser.parse_u8()?;
+            let metric = parser.parse_u16()?;
+
+            Ok(RouterLink {
+                link_type: link_type
+                    .try_into()
+                    .map_err(|_| ParseError::InvalidFieldValue {
+                        field: "router_link_type",
+                        value: link_type as usize,
+                    })?,
+                link_id,
+                link_data,
+                metric,
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Lsa { header, links })
+}
+
+impl Packet for OspfPacket {
+    type Payload = ();
+
+    fn header_len(&self) -> usize {
+        OSPF_HEADER_SIZE
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        let checksum = super::internet_checksum(&self.encode(0));
+        super::write_bytes(writer, &self.encode(checksum));
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        let mut parser = Parser::build(data);
+
+        let version = parser.parse_u8()?;
+        let packet_type = parser.parse_u8()?;
+        let length = parser.parse_u16()? as usize;
+        let router_id = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+        let area_id = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+        let checksum = parser.parse_u16()?;
+        let _autype = parser.parse_u16()?;
+        let _authentication = parser.parse_chunk::<8>()?;
+
+        if version != OSPF_VERSION {
+            return Err(ParseError::InvalidFieldValue {
+                field: "ospf_version",
+                value: version as usize,
+            });
+        }
+
+        if length < OSPF_HEADER_SIZE || length > data.len() {
+            return Err(ParseError::InvalidFieldValue {
+                field: "ospf_length",
+                value: length,
+            });
+        }
+
+        if super::internet_checksum(&data[..length]) != 0 {
+            return Err(ParseError::InvalidFieldValue {
+                field: "ospf_checksum",
+                value: checksum as usize,
+            });
+        }
+
+        let packet_type: OspfPacketType =
+            packet_type
+                .try_into()
+                .map_err(|_| ParseError::InvalidFieldValue {
+                    field: "ospf_type",
+                    value: packet_type as usize,
+                })?;
+
+        let mut parser = Parser::build(&data[OSPF_HEADER_SIZE..length]);
+        let body = match packet_type {
+            OspfPacketType::Hello => {
+                let network_mask = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+                let hello_interval = parser.parse_u16()?;
+                let _options = parser.parse_u8()?;
+                let _priority = parser.parse_u8()?;
+                let dead_interval = parser.parse_u32()?;
+                parser.skip(8)?; // designated routers
+
+                if !parser.remaining().len().is_multiple_of(4) {
+                    return Err(ParseError::MissingBytes);
+                }
+
+                let mut neighbors = Vec::new();
+                while !parser.remaining().is_empty() {
+                    neighbors.push(Ipv4Addr::from(parser.parse_chunk::<4>()?));
+                }
+
+                OspfBody::Hello(OspfHello {
+                    network_mask,
+                    hello_interval,
+                    dead_interval,
+                    neighbors,
+                })
+            }
+            OspfPacketType::LinkStateUpdate => {
+                let lsa_nr = parser.parse_u32()?;
+                OspfBody::LinkStateUpdate(
+                    (0..lsa_nr)
+                        .map(|_| parse_lsa(&mut parser))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            OspfPacketType::LinkStateAck => {
+                let mut headers = Vec::new();
+                while !parser.remaining().is_empty() {
+                    headers.push(parse_lsa_header(&mut parser)?.0);
+                }
+                OspfBody::LinkStateAck(headers)
+            }
+        };
+
+        Ok(Self {
+            router_id,
+            area_id,
+            body,
+        })
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Lsa, LsaHeader, OspfBody, OspfHello, OspfPacket, RouterLink, RouterLinkType,
+        OSPF_HEADER_SIZE, OSPF_INITIAL_SEQUENCE,
+    };
+    use crate::protocols::{Packet, ParseError};
+    use std::net::Ipv4Addr;
+
+    fn packet(body: OspfBody) -> OspfPacket {
+        OspfPacket {
+            router_id: Ipv4Addr::new(1, 1, 1, 1),
+            area_id: Ipv4Addr::UNSPECIFIED,
+            body,
+        }
+    }
+
+    #[test]
+    fn marshall_and_unmarshall() {
+        let header = LsaHeader {
+            age: 1,
+            advertising_router: Ipv4Addr::new(1, 1, 1, 1),
+            sequence: OSPF_INITIAL_SEQUENCE,
+        };
+
+        let packets = [
+            packet(OspfBody::Hello(OspfHello {
+                network_mask: Ipv4Addr::new(255, 255, 255, 0),
+                hello_interval: 10,
+                dead_interval: 40,
+                neighbors: vec![Ipv4Addr::new(2, 2, 2, 2)],
+            })),
+            packet(OspfBody::LinkStateUpdate(vec![Lsa {
+                header,
+                links: vec![
+                    RouterLink {
+                        link_type: RouterLinkType::PointToPoint,
+                        link_id: Ipv4Addr::new(2, 2, 2, 2),
+                        link_data: Ipv4Addr::new(10, 0, 0, 1),
+                        metric: 1,
+                    },
+                    RouterLink {
+                        link_type: RouterLinkType::Stub,
+                        link_id: Ipv4Addr::new(10, 0, 0, 0),
+                        link_data: Ipv4Addr::new(255, 255, 255, 0),
+                        metric: 10,
+                    },
+                ],
+            }])),
+            packet(OspfBody::LinkStateAck(vec![header, header])),
+        ];
+
+        for packet in packets {
+            let bytes = packet.to_bytes();
+            assert_eq!(Ok(&packet), OspfPacket::decode(&bytes).as_ref());
+            assert_eq!(
+                Err(ParseError::MissingBytes),
+                OspfPacket::decode(&bytes[..OSPF_HEADER_SIZE - 1])
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_checksum() {
+        let mut bytes = packet(OspfBody::LinkStateAck(Vec::new()))
+            .to_bytes()
+            .to_vec();
+        bytes[4] ^= 1;
+        assert!(matches!(
+            OspfPacket::decode(&bytes),
+            Err(ParseError::InvalidFieldValue {
+                field: "ospf_checksum",
+                ..
+            })
+        ));
+    }
+}
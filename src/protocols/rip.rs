@@ -0,0 +1,165 @@
+use super::{ipv4::Ipv4Prefix, Packet, ParseError, Parser};
+use std::{io::Write, net::Ipv4Addr};
+
+pub const RIP_PORT: u16 = 520;
+pub const RIP_MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 9);
+pub const RIP_INFINITY: u32 = 16;
+pub const RIP_MAX_ENTRIES: usize = 25;
+
+const RIP_VERSION: u8 = 2;
+const RIP_HEADER_SIZE: usize = 4;
+const RIP_ENTRY_SIZE: usize = 20;
+const RIP_AFI_IPV4: u16 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RipCommand {
+    Request = 1,
+    Response = 2,
+}
+
+impl TryFrom<u8> for RipCommand {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Self::Request,
+            2 => Self::Response,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RipEntry {
+    pub prefix: Ipv4Prefix,
+    // 0.0.0.0 means "through whoever sent this"
+    pub next_hop: Ipv4Addr,
+    pub metric: u32,
+    pub route_tag: u16,
+}
+
+// RIP version 2 (https://www.rfc-editor.org/rfc/rfc2453), authentication entries are not
+// supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RipMessage {
+    pub command: RipCommand,
+    pub entries: Vec<RipEntry>,
+}
+
+impl Packet for RipMessage {
+    type Payload = ();
+
+    fn header_len(&self) -> usize {
+        RIP_HEADER_SIZE
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        super::write_u8(writer, self.command as u8);
+        super::write_u8(writer, RIP_VERSION);
+        super::write_u16(writer, 0);
+
+        for entry in self.entries.iter() {
+            super::write_u16(writer, RIP_AFI_IPV4);
+            super::write_u16(writer, entry.route_tag);
+            super::write_bytes(writer, &entry.prefix.address().octets());
+            super::write_bytes(writer, &entry.prefix.mask().octets());
+            super::write_bytes(writer, &entry.next_hop.octets());
+            super::write_u32(writer, entry.metric);
+        }
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        let mut parser = Parser::build(data);
+
+        let command = parser.parse_u8()?;
+        let version = parser.parse_u8()?;
+        let _zero = parser.parse_u16()?;
+
+        if version != RIP_VERSION {
+            return Err(ParseError::InvalidFieldValue {
+                field: "rip_version",
+                value: version as usize,
+            });
+        }
+
+        if !parser.remaining().len().is_multiple_of(RIP_ENTRY_SIZE) {
+            return Err(ParseError::MissingBytes);
+        }
+
+        let mut entries = Vec::new();
+        while !parser.remaining().is_empty() {
+            let afi = parser.parse_u16()?;
+            let route_tag = parser.parse_u16()?;
+            let address = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+            let mask = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+            let next_hop = Ipv4Addr::from(parser.parse_chunk::<4>()?);
+            let metric = parser.parse_u32()?;
+
+            if afi != RIP_AFI_IPV4 {
+                continue;
+            }
+
+            entries.push(RipEntry {
+                prefix: Ipv4Prefix::from_mask(address, mask).ok_or(
+                    ParseError::InvalidFieldValue {
+                        field: "rip_subnet_mask",
+                        value: u32::from(mask) as usize,
+                    },
+                )?,
+                next_hop,
+                metric,
+                route_tag,
+            });
+        }
+
+        Ok(Self {
+            command: command
+                .try_into()
+                .map_err(|_| ParseError::InvalidFieldValue {
+                    field: "rip_command",
+                    value: command as usize,
+                })?,
+            entries,
+        })
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RipCommand, RipEntry, RipMessage};
+    use crate::protocols::{Packet, ParseError};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn marshall_and_unmarshall() {
+        let message = RipMessage {
+            command: RipCommand::Response,
+            entries: vec![
+                RipEntry {
+                    prefix: "10.0.1.0/24".parse().unwrap(),
+                    next_hop: Ipv4Addr::UNSPECIFIED,
+                    metric: 1,
+                    route_tag: 0,
+                },
+                RipEntry {
+                    prefix: "192.168.0.0/16".parse().unwrap(),
+                    next_hop: Ipv4Addr::new(10, 0, 1, 1),
+                    metric: 16,
+                    route_tag: 7,
+                },
+            ],
+        };
+
+        let bytes = message.to_bytes();
+        assert_eq!(bytes.len(), 4 + 2 * 20);
+        assert_eq!(Ok(message), RipMessage::decode(&bytes));
+        assert_eq!(
+            Err(ParseError::MissingBytes),
+            RipMessage::decode(&bytes[..30])
+        );
+    }
+}
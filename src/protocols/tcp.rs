@@ -0,0 +1,148 @@
+use super::{Packet, ParseError, Parser};
+use std::{fmt::Display, io::Write};
+
+pub const TCP_MIN_HEADER_SIZE: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TcpFlags(pub u8);
+
+impl TcpFlags {
+    pub const FIN: Self = Self(0x01);
+    pub const SYN: Self = Self(0x02);
+    pub const RST: Self = Self(0x04);
+    pub const PSH: Self = Self(0x08);
+    pub const ACK: Self = Self(0x10);
+
+    const NAMES: [(Self, &'static str); 5] = [
+        (Self::FIN, "FIN"),
+        (Self::SYN, "SYN"),
+        (Self::RST, "RST"),
+        (Self::PSH, "PSH"),
+        (Self::ACK, "ACK"),
+    ];
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for TcpFlags {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl Display for TcpFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<_> = Self::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect();
+        write!(f, "[{}]", names.join(", "))
+    }
+}
+
+// Options are skipped when decoding and, like for udp, the checksum is neither computed nor
+// checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpSegment {
+    pub source_port: u16,
+    pub destin_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: TcpFlags,
+    pub window: u16,
+    pub data: Box<[u8]>,
+}
+
+impl Packet for TcpSegment {
+    type Payload = Box<[u8]>;
+
+    fn header_len(&self) -> usize {
+        TCP_MIN_HEADER_SIZE
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        super::write_u16(writer, self.source_port);
+        super::write_u16(writer, self.destin_port);
+        super::write_u32(writer, self.seq);
+        super::write_u32(writer, self.ack);
+        super::write_u8(writer, ((TCP_MIN_HEADER_SIZE / 4) as u8) << 4);
+        super::write_u8(writer, self.flags.0);
+        super::write_u16(writer, self.window);
+        super::write_u16(writer, 0); // checksum
+        super::write_u16(writer, 0); // urgent pointer
+        super::write_bytes(writer, &self.data);
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        let mut parser = Parser::build(data);
+
+        let source_port = parser.parse_u16()?;
+        let destin_port = parser.parse_u16()?;
+        let seq = parser.parse_u32()?;
+        let ack = parser.parse_u32()?;
+        let header_len = (parser.parse_u8()? >> 4) as usize * 4;
+        let flags = TcpFlags(parser.parse_u8()?);
+        let window = parser.parse_u16()?;
+        let _checksum = parser.parse_u16()?;
+        let _urgent = parser.parse_u16()?;
+
+        if header_len < TCP_MIN_HEADER_SIZE {
+            return Err(ParseError::InvalidFieldValue {
+                field: "tcp_data_offset",
+                value: header_len,
+            });
+        }
+        parser.skip(header_len - TCP_MIN_HEADER_SIZE)?;
+
+        Ok(Self {
+            source_port,
+            destin_port,
+            seq,
+            ack,
+            flags,
+            window,
+            data: parser.remaining().into(),
+        })
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        Ok(self.data.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TcpFlags, TcpSegment, TCP_MIN_HEADER_SIZE};
+    use crate::protocols::{Packet, ParseError};
+
+    #[test]
+    fn marshall_and_unmarshall() {
+        let segment = TcpSegment {
+            source_port: 179,
+            destin_port: 40000,
+            seq: 1,
+            ack: 1000,
+            flags: TcpFlags::SYN | TcpFlags::ACK,
+            window: 65535,
+            data: Box::new([]),
+        };
+        let bytes = segment.to_bytes();
+
+        assert_eq!(bytes.len(), TCP_MIN_HEADER_SIZE);
+        assert_eq!(Ok(segment), TcpSegment::decode(&bytes));
+        assert_eq!(
+            Err(ParseError::MissingBytes),
+            TcpSegment::decode(&bytes[..10])
+        );
+    }
+
+    #[test]
+    fn flags_format() {
+        assert_eq!((TcpFlags::SYN | TcpFlags::ACK).to_string(), "[SYN, ACK]");
+        assert_eq!(TcpFlags::default().to_string(), "[]");
+    }
+}
@@ -0,0 +1,124 @@
+use super::{Packet, ParseError, Parser};
+use std::io::Write;
+
+pub const UDP_HEADER_SIZE: usize = 8;
+
+// The checksum is optional over IPv4, datagrams are sent without one and the one received is not
+// checked.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UdpDatagram {
+    pub source_port: u16,
+    pub destin_port: u16,
+    pub data: Box<[u8]>,
+}
+
+impl Packet for UdpDatagram {
+    // whatever the application sent
+    type Payload = Box<[u8]>;
+
+    fn header_len(&self) -> usize {
+        UDP_HEADER_SIZE
+    }
+
+    fn encode_into(&self, writer: &mut impl Write) {
+        super::write_u16(writer, self.source_port);
+        super::write_u16(writer, self.destin_port);
+        super::write_u16(writer, (UDP_HEADER_SIZE + self.data.len()) as u16);
+        super::write_u16(writer, 0);
+        super::write_bytes(writer, &self.data);
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, ParseError> {
+        let mut parser = Parser::build(data);
+
+        let source_port = parser.parse_u16()?;
+        let destin_port = parser.parse_u16()?;
+        let length = parser.parse_u16()? as usize;
+        let _checksum = parser.parse_u16()?;
+
+        if length < UDP_HEADER_SIZE {
+            return Err(ParseError::InvalidFieldValue {
+                field: "udp_length",
+                value: length,
+            });
+        }
+
+        Ok(Self {
+            source_port,
+            destin_port,
+            data: data
+                .get(UDP_HEADER_SIZE..length)
+                .ok_or(ParseError::MissingBytes)?
+                .into(),
+        })
+    }
+
+    fn decode_payload(&self) -> Result<Self::Payload, ParseError> {
+        Ok(self.data.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UdpDatagram;
+    use crate::protocols::{
+        ethernet::{EthernetFrame, FramePayload, FrameProtocol, MacAddress},
+        ipv4::{IpProtocol, Ipv4Packet, Ipv4Payload},
+        Packet, ParseError,
+    };
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn missing_data() {
+        let datagram = UdpDatagram {
+            source_port: 1000,
+            destin_port: 53,
+            data: Box::new([1; 10]),
+        };
+        let bytes = datagram.to_bytes();
+
+        assert_eq!(Ok(datagram), UdpDatagram::decode(&bytes));
+        assert_eq!(
+            Err(ParseError::MissingBytes),
+            UdpDatagram::decode(&bytes[..bytes.len() - 1])
+        );
+    }
+
+    #[test]
+    fn layering() {
+        let datagram = UdpDatagram {
+            source_port: 4000,
+            destin_port: 7,
+            data: "Hello, world".as_bytes().into(),
+        };
+        let packet = Ipv4Packet::new(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            IpProtocol::Udp,
+            datagram.to_bytes(),
+        );
+        let frame = EthernetFrame {
+            source: MacAddress::new([1; 6]),
+            destin: MacAddress::new([2; 6]),
+            protocol: FrameProtocol::Ipv4,
+            data: packet.to_bytes(),
+        };
+
+        let bytes = frame.to_bytes();
+        let decoded = EthernetFrame::decode(&bytes).unwrap();
+
+        let FramePayload::Ipv4(decoded_packet) = decoded.decode_payload().unwrap() else {
+            panic!("Expected an ipv4 packet");
+        };
+        assert_eq!(packet, decoded_packet);
+
+        let Ipv4Payload::Udp(decoded_datagram) = decoded_packet.decode_payload().unwrap() else {
+            panic!("Expected an udp datagram");
+        };
+        assert_eq!(datagram, decoded_datagram);
+        assert_eq!(
+            decoded_datagram.decode_payload().unwrap().as_ref(),
+            "Hello, world".as_bytes()
+        );
+    }
+}
@@ -0,0 +1,1228 @@
+use super::{InterfaceConfig, Outgoing, Route, RouteSource, RoutingProtocol};
+use crate::{
+    protocols::{
+        bgp::{
+            BgpMessage, BgpNotification, BgpOpen, BgpUpdate, Origin, PathAttributes,
+            BGP_BAD_PEER_AS, BGP_FSM_ERROR, BGP_HOLD_TIMER_EXPIRED, BGP_MESSAGE_HEADER_ERROR,
+            BGP_OPEN_MESSAGE_ERROR, BGP_PORT, BGP_UPDATE_MESSAGE_ERROR,
+        },
+        ipv4::{IpProtocol, Ipv4Packet, Ipv4Prefix},
+        tcp::{TcpFlags, TcpSegment},
+        Packet,
+    },
+    transport::tcp::TcpConnection,
+};
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    net::{Ipv4Addr, SocketAddrV4},
+    time::{Duration, Instant},
+};
+
+const EPHEMERAL_PORTS: u16 = 49152;
+// keeps UPDATEs well under the maximum message size
+const MAX_PREFIXES_PER_UPDATE: usize = 500;
+
+// What the neighbour is to us, following https://doi.org/10.1109/90.974523 (Gao and Rexford)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relationship {
+    // pays us for transit
+    Customer,
+    // exchanges its customers' traffic with ours for free
+    Peer,
+    // we pay it for transit
+    Provider,
+}
+
+impl Relationship {
+    // the money making routes are preferred
+    pub fn local_pref(self) -> u32 {
+        match self {
+            Self::Customer => 200,
+            Self::Peer => 100,
+            Self::Provider => 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportPolicy {
+    // routes of customers (and our own) go to everyone, routes of peers and providers only go to
+    // customers, so nobody carries traffic it isn't paid for
+    GaoRexford,
+    // everything goes, which is how route leaks happen
+    All,
+}
+
+#[derive(Debug, Clone)]
+pub struct BgpNeighborConfig {
+    pub address: Ipv4Addr,
+    pub remote_asn: u16,
+    pub relationship: Relationship,
+    pub export: ExportPolicy,
+    // LOCAL_PREF given to the routes learned from the neighbour, defaults to the one of the
+    // relationship
+    pub local_pref: Option<u32>,
+    // MED sent along with the routes advertised to the neighbour
+    pub med: Option<u32>,
+    // routes to these prefixes, or to more specific ones, aren't accepted from the neighbour
+    pub deny: Vec<Ipv4Prefix>,
+}
+
+impl BgpNeighborConfig {
+    pub fn new(address: Ipv4Addr, remote_asn: u16, relationship: Relationship) -> Self {
+        Self {
+            address,
+            remote_asn,
+            relationship,
+            export: ExportPolicy::GaoRexford,
+            local_pref: None,
+            med: None,
+            deny: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BgpConfig {
+    pub asn: u16,
+    pub hold_time: Duration,
+    // how long to wait before connecting again after a session went down, way shorter than the
+    // 120 seconds of the RFC
+    pub connect_retry: Duration,
+    // prefixes originated by this AS
+    pub networks: Vec<Ipv4Prefix>,
+    pub neighbors: Vec<BgpNeighborConfig>,
+}
+
+impl BgpConfig {
+    pub fn new(asn: u16) -> Self {
+        Self {
+            asn,
+            hold_time: Duration::from_secs(90),
+            connect_retry: Duration::from_secs(5),
+            networks: Vec::new(),
+            neighbors: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    // waiting to connect again
+    Idle,
+    // waiting for the TCP connection to be established
+    Connect,
+    // waiting for the neighbour to connect
+    Active,
+    OpenSent,
+    OpenConfirm,
+    Established,
+}
+
+struct Session {
+    config: BgpNeighborConfig,
+    interface_id: u32,
+    local_address: Ipv4Addr,
+    up: bool,
+    state: SessionState,
+    connection: Option<TcpConnection>,
+    // received bytes that don't make a whole message yet
+    buffer: Vec<u8>,
+    peer_id: Ipv4Addr,
+    hold_time: Duration,
+    hold_expires: Option<Instant>,
+    next_keepalive: Option<Instant>,
+    retry_at: Option<Instant>,
+    adj_rib_in: BTreeMap<Ipv4Prefix, PathAttributes>,
+    adj_rib_out: BTreeMap<Ipv4Prefix, PathAttributes>,
+}
+
+impl Session {
+    // only one side connects, so both never race to open a connection
+    fn is_active(&self) -> bool {
+        self.local_address < self.config.address
+    }
+
+    fn send(&mut self, message: &BgpMessage, now: Instant) {
+        if let Some(connection) = self.connection.as_mut() {
+            connection.send(&message.to_bytes());
+        }
+        // any message will do for the neighbour's hold timer
+        if matches!(
+            self.state,
+            SessionState::OpenConfirm | SessionState::Established
+        ) && !self.hold_time.is_zero()
+        {
+            self.next_keepalive = Some(now + self.hold_time / 3);
+        }
+    }
+}
+
+struct BestPath {
+    attributes: PathAttributes,
+    // None for the networks we originate
+    session: Option<usize>,
+}
+
+fn notification(code: u8, subcode: u8) -> BgpNotification {
+    BgpNotification {
+        code,
+        subcode,
+        data: Box::new([]),
+    }
+}
+
+// A BGP-4 speaker (see protocols::bgp) talking to its neighbours over a minimal TCP. Only eBGP
+// between directly connected routers is supported, which is all that is needed to play with the
+// policies between ASes.
+pub struct Bgp {
+    config: BgpConfig,
+    router_id: Ipv4Addr,
+    sessions: Vec<Session>,
+    loc_rib: BTreeMap<Ipv4Prefix, BestPath>,
+    next_port: u16,
+    next_iss: u32,
+    routes: Vec<Route>,
+}
+
+impl Bgp {
+    pub fn new(config: BgpConfig) -> Self {
+        Self {
+            config,
+            router_id: Ipv4Addr::UNSPECIFIED,
+            sessions: Vec::new(),
+            loc_rib: BTreeMap::new(),
+            next_port: 0,
+            next_iss: 0,
+            routes: Vec::new(),
+        }
+    }
+
+    pub fn asn(&self) -> u16 {
+        self.config.asn
+    }
+
+    pub fn session_state(&self, neighbor: Ipv4Addr) -> Option<SessionState> {
+        self.sessions
+            .iter()
+            .find(|session| session.config.address == neighbor)
+            .map(|session| session.state)
+    }
+
+    // the path picked to reach `prefix`, the AS_PATH is empty for our own networks
+    pub fn best_path(&self, prefix: Ipv4Prefix) -> Option<&PathAttributes> {
+        self.loc_rib.get(&prefix).map(|best| &best.attributes)
+    }
+
+    fn connect(&mut self, index: usize) {
+        let port = EPHEMERAL_PORTS + self.next_port % (u16::MAX - EPHEMERAL_PORTS);
+        let iss = self.next_iss;
+        self.next_port = self.next_port.wrapping_add(1);
+        self.next_iss = self.next_iss.wrapping_add(0x0100_0000);
+
+        let session = &mut self.sessions[index];
+        session.connection = Some(TcpConnection::connect(
+            SocketAddrV4::new(session.local_address, port),
+            SocketAddrV4::new(session.config.address, BGP_PORT),
+            iss,
+        ));
+        session.state = SessionState::Connect;
+        session.retry_at = None;
+    }
+
+    fn session_down(
+        &mut self,
+        index: usize,
+        notification: Option<BgpNotification>,
+        now: Instant,
+        out: &mut Vec<Outgoing>,
+    ) {
+        let asn = self.config.asn;
+        let session = &mut self.sessions[index];
+
+        if let Some(notification) = notification {
+            log::info!(
+                "BGP AS{asn}: sending notification {}/{} to {}",
+                notification.code,
+                notification.subcode,
+                session.config.address
+            );
+            session.send(&BgpMessage::Notification(notification), now);
+            Self::flush(session, now, out);
+        }
+        if let Some(connection) = session.connection.as_mut() {
+            connection.abort();
+            Self::flush(session, now, out);
+        }
+
+        if session.state == SessionState::Established {
+            log::info!(
+                "BGP AS{asn}: session with {} (AS{}) is down",
+                session.config.address,
+                session.config.remote_asn
+            );
+        }
+
+        session.connection = None;
+        session.buffer.clear();
+        session.hold_expires = None;
+        session.next_keepalive = None;
+        session.adj_rib_in.clear();
+        session.adj_rib_out.clear();
+        if session.is_active() {
+            session.state = SessionState::Idle;
+            session.retry_at = Some(now + self.config.connect_retry);
+        } else {
+            session.state = SessionState::Active;
+        }
+    }
+
+    // hands the segment to the session's connection and handles whatever BGP messages came in
+    fn receive(
+        &mut self,
+        index: usize,
+        segment: &TcpSegment,
+        now: Instant,
+        out: &mut Vec<Outgoing>,
+    ) {
+        let session = &mut self.sessions[index];
+        let Some(connection) = session.connection.as_mut() else {
+            return;
+        };
+        connection.handle_segment(segment, now);
+        let received = connection.read();
+
+        if connection.is_established() && session.state == SessionState::Connect {
+            let open = BgpMessage::Open(BgpOpen {
+                my_as: self.config.asn,
+                hold_time: self.config.hold_time.as_secs() as u16,
+                bgp_identifier: self.router_id,
+            });
+            session.state = SessionState::OpenSent;
+            session.hold_expires = Some(now + self.config.hold_time);
+            session.send(&open, now);
+        }
+        session.buffer.extend(received);
+
+        while let Some(len) = BgpMessage::message_len(&self.sessions[index].buffer) {
+            let bytes: Vec<_> = self.sessions[index].buffer.drain(..len).collect();
+            let result = match BgpMessage::decode(&bytes) {
+                Ok(BgpMessage::Notification(notification)) => {
+                    log::info!(
+                        "BGP AS{}: notification {}/{} from {}",
+                        self.config.asn,
+                        notification.code,
+                        notification.subcode,
+                        self.sessions[index].config.address
+                    );
+                    Err(None)
+                }
+                Ok(message) => self.handle_message(index, message, now).map_err(Some),
+                Err(err) => {
+                    log::warn!(
+                        "BGP AS{}: invalid message from {}: {err:?}",
+                        self.config.asn,
+                        self.sessions[index].config.address
+                    );
+                    Err(Some(notification(BGP_MESSAGE_HEADER_ERROR, 0)))
+                }
+            };
+
+            if let Err(notification) = result {
+                self.session_down(index, notification, now, out);
+                return;
+            }
+        }
+    }
+
+    fn handle_message(
+        &mut self,
+        index: usize,
+        message: BgpMessage,
+        now: Instant,
+    ) -> Result<(), BgpNotification> {
+        let asn = self.config.asn;
+        let session = &mut self.sessions[index];
+        if let Some(hold_expires) = session.hold_expires.as_mut() {
+            *hold_expires = now + session.hold_time;
+        }
+
+        match (session.state, message) {
+            (SessionState::OpenSent, BgpMessage::Open(open)) => {
+                if open.my_as != session.config.remote_asn {
+                    log::warn!(
+                        "BGP AS{asn}: {} is AS{} instead of AS{}",
+                        session.config.address,
+                        open.my_as,
+                        session.config.remote_asn
+                    );
+                    return Err(notification(BGP_OPEN_MESSAGE_ERROR, BGP_BAD_PEER_AS));
+                }
+
+                session.peer_id = open.bgp_identifier;
+                session.hold_time = self
+                    .config
+                    .hold_time
+                    .min(Duration::from_secs(open.hold_time as u64));
+                session.hold_expires =
+                    (!session.hold_time.is_zero()).then(|| now + session.hold_time);
+                session.state = SessionState::OpenConfirm;
+                session.send(&BgpMessage::Keepalive, now);
+            }
+            (SessionState::OpenConfirm, BgpMessage::Keepalive) => {
+                log::info!(
+                    "BGP AS{asn}: session with {} (AS{}) established",
+                    session.config.address,
+                    session.config.remote_asn
+                );
+                session.state = SessionState::Established;
+            }
+            (SessionState::Established, BgpMessage::Keepalive) => {}
+            (SessionState::Established, BgpMessage::Update(update)) => {
+                self.import(index, update)?;
+            }
+            (state, message) => {
+                log::warn!(
+                    "BGP AS{asn}: unexpected {:?} from {} in state {state:?}",
+                    message.message_type(),
+                    session.config.address
+                );
+                return Err(notification(BGP_FSM_ERROR, 0));
+            }
+        }
+        Ok(())
+    }
+
+    fn import(&mut self, index: usize, update: BgpUpdate) -> Result<(), BgpNotification> {
+        let asn = self.config.asn;
+        let session = &mut self.sessions[index];
+
+        for prefix in update.withdrawn.iter() {
+            session.adj_rib_in.remove(prefix);
+        }
+
+        let Some(mut attributes) = update.attributes else {
+            return match update.nlri.is_empty() {
+                true => Ok(()),
+                // missing well-known attribute
+                false => Err(notification(BGP_UPDATE_MESSAGE_ERROR, 3)),
+            };
+        };
+
+        // LOCAL_PREF is never taken from an external neighbour
+        attributes.local_pref = Some(
+            session
+                .config
+                .local_pref
+                .unwrap_or(session.config.relationship.local_pref()),
+        );
+        let looped = attributes.as_path.contains(&asn);
+
+        for prefix in update.nlri {
+            let denied = session.config.deny.iter().any(|deny| {
+                deny.prefix_len() <= prefix.prefix_len() && deny.contains(prefix.address())
+            });
+
+            // a new path replaces the old one, even when it isn't accepted
+            session.adj_rib_in.remove(&prefix);
+            if looped {
+                log::debug!(
+                    "BGP AS{asn}: path {:?} to {prefix} from {} has a loop",
+                    attributes.as_path,
+                    session.config.address
+                );
+            } else if !denied {
+                session.adj_rib_in.insert(prefix, attributes.clone());
+            }
+        }
+        Ok(())
+    }
+
+    // whether `a` is better than `b`, following the decision process of the RFC
+    fn is_better(&self, a: &BestPath, b: &BestPath) -> bool {
+        let (a_session, b_session) = match (a.session, b.session) {
+            (None, _) => return true,
+            (_, None) => return false,
+            (Some(a), Some(b)) => (&self.sessions[a], &self.sessions[b]),
+        };
+
+        let key = |path: &BestPath| {
+            (
+                Reverse(path.attributes.local_pref),
+                path.attributes.as_path.len(),
+                path.attributes.origin,
+            )
+        };
+        if key(a) != key(b) {
+            return key(a) < key(b);
+        }
+
+        // MEDs are only comparable between routes from the same AS
+        let (a_med, b_med) = (a.attributes.med, b.attributes.med);
+        if a.attributes.as_path.first() == b.attributes.as_path.first() && a_med != b_med {
+            return a_med.unwrap_or(0) < b_med.unwrap_or(0);
+        }
+
+        (a_session.peer_id, a_session.config.address)
+            < (b_session.peer_id, b_session.config.address)
+    }
+
+    fn decide(&mut self) {
+        let mut loc_rib = BTreeMap::new();
+        for prefix in self.config.networks.iter() {
+            let attributes = PathAttributes {
+                origin: Origin::Igp,
+                as_path: Vec::new(),
+                next_hop: self.router_id,
+                med: None,
+                local_pref: None,
+            };
+            loc_rib.insert(
+                *prefix,
+                BestPath {
+                    attributes,
+                    session: None,
+                },
+            );
+        }
+
+        for (index, session) in self.sessions.iter().enumerate() {
+            if session.state != SessionState::Established {
+                continue;
+            }
+            for (prefix, attributes) in session.adj_rib_in.iter() {
+                let candidate = BestPath {
+                    attributes: attributes.clone(),
+                    session: Some(index),
+                };
+                match loc_rib.get(prefix) {
+                    Some(best) if !self.is_better(&candidate, best) => {}
+                    _ => {
+                        loc_rib.insert(*prefix, candidate);
+                    }
+                }
+            }
+        }
+
+        self.routes = loc_rib
+            .iter()
+            .filter_map(|(prefix, best)| {
+                let session = &self.sessions[best.session?];
+                Some(Route {
+                    prefix: *prefix,
+                    interface_id: session.interface_id,
+                    next_hop: Some(best.attributes.next_hop),
+                    metric: best.attributes.as_path.len() as u32,
+                    source: RouteSource::Bgp,
+                })
+            })
+            .collect();
+        self.loc_rib = loc_rib;
+    }
+
+    fn exports(&self, best: &BestPath, to: usize) -> bool {
+        let session = &self.sessions[to];
+        if best.session == Some(to) {
+            return false;
+        }
+
+        match session.config.export {
+            ExportPolicy::All => true,
+            ExportPolicy::GaoRexford => match best.session {
+                None => true,
+                Some(from) => {
+                    self.sessions[from].config.relationship == Relationship::Customer
+                        || session.config.relationship == Relationship::Customer
+                }
+            },
+        }
+    }
+
+    // sends the changes between what each neighbour was told and what it should know now
+    fn advertise(&mut self, now: Instant) {
+        for index in 0..self.sessions.len() {
+            if self.sessions[index].state != SessionState::Established {
+                continue;
+            }
+
+            let session = &self.sessions[index];
+            let exported: BTreeMap<_, _> = self
+                .loc_rib
+                .iter()
+                .filter(|(_, best)| self.exports(best, index))
+                .map(|(prefix, best)| {
+                    let mut as_path = vec![self.config.asn];
+                    as_path.extend(best.attributes.as_path.iter());
+                    let attributes = PathAttributes {
+                        origin: best.attributes.origin,
+                        as_path,
+                        next_hop: session.local_address,
+                        med: session.config.med,
+                        local_pref: None,
+                    };
+                    (*prefix, attributes)
+                })
+                .collect();
+
+            let withdrawn: Vec<_> = session
+                .adj_rib_out
+                .keys()
+                .filter(|prefix| !exported.contains_key(prefix))
+                .copied()
+                .collect();
+
+            let mut announced: Vec<(&PathAttributes, Vec<Ipv4Prefix>)> = Vec::new();
+            for (prefix, attributes) in exported.iter() {
+                if session.adj_rib_out.get(prefix) == Some(attributes) {
+                    continue;
+                }
+                match announced.iter_mut().find(|(other, _)| *other == attributes) {
+                    Some((_, prefixes)) => prefixes.push(*prefix),
+                    None => announced.push((attributes, vec![*prefix])),
+                }
+            }
+
+            let mut updates = Vec::new();
+            for chunk in withdrawn.chunks(MAX_PREFIXES_PER_UPDATE) {
+                updates.push(BgpUpdate {
+                    withdrawn: chunk.to_vec(),
+                    attributes: None,
+                    nlri: Vec::new(),
+                });
+            }
+            for (attributes, prefixes) in announced {
+                for chunk in prefixes.chunks(MAX_PREFIXES_PER_UPDATE) {
+                    updates.push(BgpUpdate {
+                        withdrawn: Vec::new(),
+                        attributes: Some(attributes.clone()),
+                        nlri: chunk.to_vec(),
+                    });
+                }
+            }
+
+            let session = &mut self.sessions[index];
+            for update in updates {
+                session.send(&BgpMessage::Update(update), now);
+            }
+            session.adj_rib_out = exported;
+        }
+    }
+
+    fn flush(session: &mut Session, now: Instant, out: &mut Vec<Outgoing>) {
+        let Some(connection) = session.connection.as_mut() else {
+            return;
+        };
+
+        for segment in connection.poll(now) {
+            let mut packet = Ipv4Packet::new(
+                session.local_address,
+                session.config.address,
+                IpProtocol::Tcp,
+                segment.to_bytes(),
+            );
+            // neighbours are directly connected
+            packet.ttl = 1;
+            out.push(Outgoing::OnInterface(session.interface_id, packet));
+        }
+    }
+
+    // brings everything up to date after something happened
+    fn update(&mut self, now: Instant, out: &mut Vec<Outgoing>) {
+        for index in 0..self.sessions.len() {
+            let closed = self.sessions[index]
+                .connection
+                .as_ref()
+                .is_some_and(|connection| connection.is_closed() || connection.peer_closed());
+            if closed {
+                self.session_down(index, None, now, out);
+            }
+        }
+
+        self.decide();
+        self.advertise(now);
+        for session in self.sessions.iter_mut() {
+            Self::flush(session, now, out);
+        }
+    }
+
+    fn reset(session: &Session, segment: &TcpSegment, out: &mut Vec<Outgoing>) {
+        let reset = TcpSegment {
+            source_port: segment.destin_port,
+            destin_port: segment.source_port,
+            seq: segment.ack,
+            ack: 0,
+            flags: TcpFlags::RST,
+            window: 0,
+            data: Box::new([]),
+        };
+        let mut packet = Ipv4Packet::new(
+            session.local_address,
+            session.config.address,
+            IpProtocol::Tcp,
+            reset.to_bytes(),
+        );
+        packet.ttl = 1;
+        out.push(Outgoing::OnInterface(session.interface_id, packet));
+    }
+}
+
+impl RoutingProtocol for Bgp {
+    fn source(&self) -> RouteSource {
+        RouteSource::Bgp
+    }
+
+    fn start(&mut self, interfaces: &[InterfaceConfig], now: Instant, out: &mut Vec<Outgoing>) {
+        self.router_id = interfaces
+            .iter()
+            .map(|interface| interface.address)
+            .max()
+            .unwrap_or(Ipv4Addr::UNSPECIFIED);
+        self.next_iss = u32::from(self.router_id);
+
+        for neighbor in self.config.neighbors.clone() {
+            let interface = interfaces
+                .iter()
+                .find(|interface| interface.prefix.contains(neighbor.address));
+
+            let Some(interface) = interface else {
+                log::warn!(
+                    "BGP AS{}: neighbor {} isn't directly connected, ignoring it",
+                    self.config.asn,
+                    neighbor.address
+                );
+                continue;
+            };
+            if neighbor.remote_asn == self.config.asn {
+                log::warn!(
+                    "BGP AS{}: iBGP isn't supported, ignoring neighbor {}",
+                    self.config.asn,
+                    neighbor.address
+                );
+                continue;
+            }
+
+            self.sessions.push(Session {
+                config: neighbor,
+                interface_id: interface.interface_id,
+                local_address: interface.address,
+                up: true,
+                state: SessionState::Active,
+                connection: None,
+                buffer: Vec::new(),
+                peer_id: Ipv4Addr::UNSPECIFIED,
+                hold_time: self.config.hold_time,
+                hold_expires: None,
+                next_keepalive: None,
+                retry_at: None,
+                adj_rib_in: BTreeMap::new(),
+                adj_rib_out: BTreeMap::new(),
+            });
+        }
+
+        for index in 0..self.sessions.len() {
+            if self.sessions[index].is_active() {
+                self.connect(index);
+            }
+        }
+        self.update(now, out);
+    }
+
+    fn handle_packet(
+        &mut self,
+        _interface_id: u32,
+        packet: &Ipv4Packet,
+        now: Instant,
+        out: &mut Vec<Outgoing>,
+    ) -> bool {
+        if packet.protocol != IpProtocol::Tcp {
+            return false;
+        }
+
+        let Some(index) = self.sessions.iter().position(|session| {
+            session.config.address == packet.source && session.local_address == packet.destin
+        }) else {
+            return false;
+        };
+
+        let segment = match TcpSegment::decode(&packet.data) {
+            Ok(segment) if segment.source_port == BGP_PORT || segment.destin_port == BGP_PORT => {
+                segment
+            }
+            _ => return false,
+        };
+
+        let session = &self.sessions[index];
+        if !session.up {
+            return true;
+        }
+
+        let known = session.connection.as_ref().is_some_and(|connection| {
+            connection.local().port() == segment.destin_port
+                && connection.remote().port() == segment.source_port
+        });
+        let syn = segment.flags.contains(TcpFlags::SYN) && !segment.flags.contains(TcpFlags::ACK);
+
+        if known {
+            self.receive(index, &segment, now, out);
+        } else if syn && segment.destin_port == BGP_PORT && !session.is_active() {
+            if session.connection.is_some() {
+                log::info!(
+                    "BGP AS{}: {} connected again",
+                    self.config.asn,
+                    session.config.address
+                );
+                self.session_down(index, None, now, out);
+            }
+
+            let session = &mut self.sessions[index];
+            session.connection = TcpConnection::accept(
+                SocketAddrV4::new(packet.destin, segment.destin_port),
+                SocketAddrV4::new(packet.source, segment.source_port),
+                &segment,
+                self.next_iss,
+            );
+            session.state = SessionState::Connect;
+            self.next_iss = self.next_iss.wrapping_add(0x0100_0000);
+        } else if !segment.flags.contains(TcpFlags::RST) {
+            // a connection we forgot about, most likely because we restarted
+            Self::reset(session, &segment, out);
+        }
+
+        self.update(now, out);
+        true
+    }
+
+    fn link_state_changed(
+        &mut self,
+        interface_id: u32,
+        up: bool,
+        now: Instant,
+        out: &mut Vec<Outgoing>,
+    ) {
+        for index in 0..self.sessions.len() {
+            let session = &mut self.sessions[index];
+            if session.interface_id != interface_id || session.up == up {
+                continue;
+            }
+            session.up = up;
+
+            if up {
+                if session.is_active() {
+                    self.connect(index);
+                }
+            } else {
+                // nothing can be sent through the interface anymore
+                self.session_down(index, None, now, &mut Vec::new());
+                self.sessions[index].retry_at = None;
+            }
+        }
+        self.update(now, out);
+    }
+
+    fn poll(&mut self, now: Instant, out: &mut Vec<Outgoing>) {
+        for index in 0..self.sessions.len() {
+            let session = &mut self.sessions[index];
+
+            if session.up
+                && session.state == SessionState::Idle
+                && session.retry_at.is_some_and(|retry| retry <= now)
+            {
+                self.connect(index);
+                continue;
+            }
+
+            if session.hold_expires.is_some_and(|hold| hold <= now) {
+                log::info!(
+                    "BGP AS{}: hold timer of {} expired",
+                    self.config.asn,
+                    session.config.address
+                );
+                let notification = notification(BGP_HOLD_TIMER_EXPIRED, 0);
+                self.session_down(index, Some(notification), now, out);
+                continue;
+            }
+
+            if session
+                .next_keepalive
+                .is_some_and(|keepalive| keepalive <= now)
+            {
+                session.send(&BgpMessage::Keepalive, now);
+            }
+        }
+        self.update(now, out);
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.sessions
+            .iter()
+            .flat_map(|session| {
+                let retry = session.retry_at.filter(|_| session.up);
+                let connection = session
+                    .connection
+                    .as_ref()
+                    .and_then(|connection| connection.next_deadline());
+                [
+                    retry,
+                    session.hold_expires,
+                    session.next_keepalive,
+                    connection,
+                ]
+            })
+            .flatten()
+            .min()
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        self.routes.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Bgp, BgpConfig, BgpNeighborConfig, ExportPolicy, Relationship, SessionState};
+    use crate::{
+        protocols::ipv4::Ipv4Prefix,
+        routing::{InterfaceConfig, Outgoing, RoutingProtocol},
+    };
+    use std::{
+        net::Ipv4Addr,
+        time::{Duration, Instant},
+    };
+
+    // a speaker and one of its interfaces
+    type End = (usize, u32);
+
+    fn stub(r: usize) -> Ipv4Prefix {
+        Ipv4Prefix::new(Ipv4Addr::new(192, 168, r as u8, 0), 24)
+    }
+
+    // Speaker r is AS 65000 + r and originates 192.168.r.0/24. A link (a, b, relationship) is
+    // the network 10.0.k.0/24 between a and b, where b is the `relationship` of a.
+    struct Network {
+        speakers: Vec<Bgp>,
+        configs: Vec<BgpConfig>,
+        links: Vec<(End, End, bool)>,
+        interfaces: Vec<Vec<InterfaceConfig>>,
+        now: Instant,
+    }
+
+    impl Network {
+        fn new(
+            size: usize,
+            links: &[(usize, usize, Relationship)],
+            configure: impl FnOnce(&mut [BgpConfig]),
+        ) -> Self {
+            let mut interfaces: Vec<_> = (0..size)
+                .map(|r| {
+                    vec![InterfaceConfig::new(
+                        0,
+                        Ipv4Addr::new(192, 168, r as u8, 1),
+                        24,
+                    )]
+                })
+                .collect();
+            let mut configs: Vec<_> = (0..size)
+                .map(|r| {
+                    let mut config = BgpConfig::new(65000 + r as u16);
+                    config.networks.push(stub(r));
+                    config
+                })
+                .collect();
+
+            let links = links
+                .iter()
+                .enumerate()
+                .map(|(k, &(a, b, relationship))| {
+                    let reverse = match relationship {
+                        Relationship::Customer => Relationship::Provider,
+                        Relationship::Peer => Relationship::Peer,
+                        Relationship::Provider => Relationship::Customer,
+                    };
+                    let address = |host| Ipv4Addr::new(10, 0, k as u8, host);
+
+                    for (r, other, host, relationship) in
+                        [(a, b, 1, relationship), (b, a, 2, reverse)]
+                    {
+                        configs[r].neighbors.push(BgpNeighborConfig::new(
+                            address(3 - host),
+                            65000 + other as u16,
+                            relationship,
+                        ));
+                    }
+
+                    let ends = [(a, 1), (b, 2)].map(|(r, host)| {
+                        let interface_id = interfaces[r].len() as u32;
+                        interfaces[r].push(InterfaceConfig::new(interface_id, address(host), 24));
+                        (r, interface_id)
+                    });
+                    (ends[0], ends[1], true)
+                })
+                .collect();
+            configure(&mut configs);
+
+            let mut network = Self {
+                speakers: Vec::new(),
+                configs,
+                links,
+                interfaces,
+                now: Instant::now(),
+            };
+            // nothing is delivered before everyone is listening
+            let mut outs = Vec::new();
+            for r in 0..size {
+                let mut speaker = Bgp::new(network.configs[r].clone());
+                let mut out = Vec::new();
+                speaker.start(&network.interfaces[r], network.now, &mut out);
+                network.speakers.push(speaker);
+                outs.push(out);
+            }
+            for (r, out) in outs.into_iter().enumerate() {
+                network.deliver(r, out);
+            }
+            network
+        }
+
+        fn start(&mut self, speaker: usize) {
+            self.speakers[speaker] = Bgp::new(self.configs[speaker].clone());
+            let mut out = Vec::new();
+            self.speakers[speaker].start(&self.interfaces[speaker], self.now, &mut out);
+            self.deliver(speaker, out);
+        }
+
+        fn deliver(&mut self, from: usize, out: Vec<Outgoing>) {
+            let mut queue: Vec<_> = out.into_iter().map(|outgoing| (from, outgoing)).collect();
+
+            while let Some((from, outgoing)) = queue.pop() {
+                let Outgoing::OnInterface(interface_id, packet) = outgoing else {
+                    panic!("BGP only talks to its neighbours");
+                };
+
+                let destin = self.links.iter().find_map(|&(a, b, up)| match up {
+                    true if a == (from, interface_id) => Some(b),
+                    true if b == (from, interface_id) => Some(a),
+                    _ => None,
+                });
+                let Some((to, to_interface)) = destin else {
+                    continue;
+                };
+
+                let mut out = Vec::new();
+                assert!(self.speakers[to].handle_packet(to_interface, &packet, self.now, &mut out));
+                queue.extend(out.into_iter().map(|outgoing| (to, outgoing)));
+            }
+        }
+
+        fn advance(&mut self, duration: Duration) {
+            let end = self.now + duration;
+            loop {
+                let next = self
+                    .speakers
+                    .iter()
+                    .filter_map(|speaker| speaker.next_deadline())
+                    .min()
+                    .filter(|next| *next <= end);
+
+                let Some(next) = next else { break };
+                self.now = self.now.max(next);
+
+                for r in 0..self.speakers.len() {
+                    let mut out = Vec::new();
+                    self.speakers[r].poll(self.now, &mut out);
+                    self.deliver(r, out);
+                }
+            }
+            self.now = end;
+        }
+
+        // `notify` is whether the speakers see the link going down, otherwise only their hold
+        // timers tell
+        fn set_link(&mut self, link: usize, up: bool, notify: bool) {
+            self.links[link].2 = up;
+            if !notify {
+                return;
+            }
+
+            let (a, b, _) = self.links[link];
+            for (speaker, interface_id) in [a, b] {
+                let mut out = Vec::new();
+                self.speakers[speaker].link_state_changed(interface_id, up, self.now, &mut out);
+                self.deliver(speaker, out);
+            }
+        }
+
+        // the speakers on the path picked by `speaker` to the stub network of `to`
+        fn path(&self, speaker: usize, to: usize) -> Option<Vec<usize>> {
+            self.speakers[speaker].best_path(stub(to)).map(|best| {
+                best.as_path
+                    .iter()
+                    .map(|asn| (asn - 65000) as usize)
+                    .collect()
+            })
+        }
+    }
+
+    #[test]
+    fn converges_valley_free() {
+        // 0 is the provider of 1 and 2, which peer with each other, and 3 is a customer of 1
+        let mut network = Network::new(
+            4,
+            &[
+                (0, 1, Relationship::Customer),
+                (0, 2, Relationship::Customer),
+                (1, 2, Relationship::Peer),
+                (1, 3, Relationship::Customer),
+            ],
+            |_| {},
+        );
+        network.advance(Duration::from_secs(5));
+
+        assert_eq!(network.path(0, 0), Some(vec![]));
+        assert_eq!(network.path(0, 3), Some(vec![1, 3]));
+        assert_eq!(network.path(3, 2), Some(vec![1, 2]));
+        assert_eq!(network.path(3, 0), Some(vec![1, 0]));
+        // the peer is cheaper than the provider
+        assert_eq!(network.path(2, 3), Some(vec![1, 3]));
+        // 1 doesn't carry traffic between its provider and its peer
+        assert_eq!(network.path(2, 0), Some(vec![0]));
+        assert_eq!(network.path(0, 2), Some(vec![2]));
+
+        let route = network.speakers[3]
+            .routes()
+            .into_iter()
+            .find(|route| route.prefix == stub(2))
+            .unwrap();
+        assert_eq!(route.next_hop, Some(Ipv4Addr::new(10, 0, 3, 1)));
+
+        network.set_link(3, false, true);
+        network.advance(Duration::from_secs(1));
+        assert_eq!(network.path(0, 3), None);
+        assert_eq!(network.path(3, 2), None);
+
+        network.set_link(3, true, true);
+        network.advance(Duration::from_secs(5));
+        assert_eq!(network.path(0, 3), Some(vec![1, 3]));
+    }
+
+    #[test]
+    fn local_pref_and_med() {
+        // like above without 3 and with two links between 0 and 1
+        let links = [
+            (0, 1, Relationship::Customer),
+            (0, 1, Relationship::Customer),
+            (0, 2, Relationship::Customer),
+            (1, 2, Relationship::Peer),
+        ];
+
+        let mut network = Network::new(3, &links, |configs| {
+            configs[1].neighbors[0].med = Some(20);
+            configs[1].neighbors[1].med = Some(10);
+        });
+        network.advance(Duration::from_secs(5));
+        assert_eq!(network.path(1, 2), Some(vec![2]));
+
+        let route = network.speakers[0]
+            .routes()
+            .into_iter()
+            .find(|route| route.prefix == stub(1))
+            .unwrap();
+        assert_eq!(route.next_hop, Some(Ipv4Addr::new(10, 0, 1, 2)));
+
+        // the provider beats the peer, even with a longer path
+        let mut network = Network::new(3, &links, |configs| {
+            configs[1].neighbors[0].local_pref = Some(300);
+        });
+        network.advance(Duration::from_secs(5));
+        assert_eq!(network.path(1, 2), Some(vec![0, 2]));
+    }
+
+    #[test]
+    fn route_leak() {
+        // 0 and 1 peer with each other and 2 is a customer of both
+        let links = [
+            (0, 1, Relationship::Peer),
+            (0, 2, Relationship::Customer),
+            (1, 2, Relationship::Customer),
+        ];
+
+        let mut network = Network::new(3, &links, |_| {});
+        network.advance(Duration::from_secs(5));
+        assert_eq!(network.path(0, 1), Some(vec![1]));
+        assert_eq!(network.path(1, 0), Some(vec![0]));
+
+        // 2 tells 0 about everything it knows, which 0 likes better than what its peer says as
+        // it comes from a customer: the traffic between 0 and 1 now goes through 2
+        let mut network = Network::new(3, &links, |configs| {
+            configs[2].neighbors[0].export = ExportPolicy::All;
+        });
+        network.advance(Duration::from_secs(5));
+        assert_eq!(network.path(0, 1), Some(vec![2, 1]));
+        assert_eq!(network.path(1, 0), Some(vec![0]));
+    }
+
+    #[test]
+    fn as_path_loops_are_rejected() {
+        let links = [
+            (0, 1, Relationship::Peer),
+            (1, 2, Relationship::Peer),
+            (2, 0, Relationship::Peer),
+        ];
+        let mut network = Network::new(3, &links, |configs| {
+            for neighbor in configs
+                .iter_mut()
+                .flat_map(|config| config.neighbors.iter_mut())
+            {
+                neighbor.export = ExportPolicy::All;
+            }
+        });
+        network.advance(Duration::from_secs(5));
+
+        // the routes to its own network came back to each speaker and were dropped
+        for (r, speaker) in network.speakers.iter().enumerate() {
+            for session in speaker.sessions.iter() {
+                assert_eq!(session.state, SessionState::Established);
+                assert!(!session.adj_rib_in.contains_key(&stub(r)));
+                assert_eq!(session.adj_rib_in.len(), 2);
+            }
+        }
+
+        network.set_link(2, false, true);
+        network.advance(Duration::from_secs(1));
+        assert_eq!(network.path(0, 2), Some(vec![1, 2]));
+        assert_eq!(network.path(2, 0), Some(vec![1, 0]));
+    }
+
+    #[test]
+    fn dead_and_restarted_neighbors() {
+        let mut network = Network::new(2, &[(0, 1, Relationship::Peer)], |_| {});
+        network.advance(Duration::from_secs(5));
+        let neighbor = Ipv4Addr::new(10, 0, 0, 2);
+        assert_eq!(
+            network.speakers[0].session_state(neighbor),
+            Some(SessionState::Established)
+        );
+
+        network.set_link(0, false, false);
+        network.advance(Duration::from_secs(80));
+        assert_eq!(network.path(0, 1), Some(vec![1]));
+
+        // the hold time is 90 seconds
+        network.advance(Duration::from_secs(15));
+        assert_eq!(network.path(0, 1), None);
+
+        network.set_link(0, true, false);
+        network.advance(Duration::from_secs(70));
+        assert_eq!(network.path(0, 1), Some(vec![1]));
+
+        // 0 only finds out when its next keepalive is answered with a reset
+        network.start(1);
+        network.advance(Duration::from_secs(1));
+        assert_eq!(network.path(0, 1), Some(vec![1]));
+        network.advance(Duration::from_secs(40));
+        assert_eq!(network.path(1, 0), Some(vec![0]));
+        assert_eq!(
+            network.speakers[0].session_state(neighbor),
+            Some(SessionState::Established)
+        );
+    }
+}
@@ -0,0 +1,217 @@
+pub mod bgp;
+pub mod ospf;
+pub mod rip;
+
+use crate::protocols::ipv4::{Ipv4Packet, Ipv4Prefix};
+use std::{fmt::Display, net::Ipv4Addr, time::Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteSource {
+    Connected,
+    Static,
+    Bgp,
+    Ospf,
+    Rip,
+}
+
+impl RouteSource {
+    // the lower the more trusted, used when several sources know a route to the same prefix
+    pub fn admin_distance(self) -> u32 {
+        match self {
+            Self::Connected => 0,
+            Self::Static => 1,
+            // external BGP
+            Self::Bgp => 20,
+            Self::Ospf => 110,
+            Self::Rip => 120,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub prefix: Ipv4Prefix,
+    pub interface_id: u32,
+    // None for directly connected networks
+    pub next_hop: Option<Ipv4Addr>,
+    pub metric: u32,
+    pub source: RouteSource,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RoutingTable {
+    routes: Vec<Route>,
+}
+
+impl RoutingTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, route: Route) {
+        self.routes
+            .retain(|other| other.prefix != route.prefix || other.source != route.source);
+        self.routes.push(route);
+    }
+
+    pub fn remove(&mut self, prefix: Ipv4Prefix, source: RouteSource) {
+        self.routes
+            .retain(|route| route.prefix != prefix || route.source != source);
+    }
+
+    // Replaces every route learned from `source`, returns whether anything changed.
+    pub fn replace_source(&mut self, source: RouteSource, routes: Vec<Route>) -> bool {
+        let mut old: Vec<_> = self
+            .routes
+            .iter()
+            .filter(|route| route.source == source)
+            .cloned()
+            .collect();
+        let mut new = routes;
+        old.sort_by_key(|route| route.prefix);
+        new.sort_by_key(|route| route.prefix);
+
+        if old == new {
+            return false;
+        }
+
+        self.routes.retain(|route| route.source != source);
+        self.routes.extend(new);
+        true
+    }
+
+    // longest prefix match, ties are broken by the administrative distance and then the metric
+    pub fn lookup(&self, destin: Ipv4Addr) -> Option<&Route> {
+        self.routes
+            .iter()
+            .filter(|route| route.prefix.contains(destin))
+            .min_by_key(|route| {
+                (
+                    u8::MAX - route.prefix.prefix_len(),
+                    route.source.admin_distance(),
+                    route.metric,
+                )
+            })
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+}
+
+impl Display for RoutingTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut routes: Vec<_> = self.routes.iter().collect();
+        routes.sort_by_key(|route| (route.prefix, route.source.admin_distance()));
+
+        for route in routes {
+            let via = match route.next_hop {
+                Some(next_hop) => format!("via {next_hop}"),
+                None => "directly connected".to_string(),
+            };
+            writeln!(
+                f,
+                "{:<18} {via:<22} interface {:<3} metric {:<4} {:?}",
+                route.prefix.to_string(),
+                route.interface_id,
+                route.metric,
+                route.source
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterfaceConfig {
+    pub interface_id: u32,
+    pub address: Ipv4Addr,
+    pub prefix: Ipv4Prefix,
+}
+
+impl InterfaceConfig {
+    pub fn new(interface_id: u32, address: Ipv4Addr, prefix_len: u8) -> Self {
+        Self {
+            interface_id,
+            address,
+            prefix: Ipv4Prefix::new(address, prefix_len),
+        }
+    }
+}
+
+pub enum Outgoing {
+    // link local traffic, like multicasting to the neighbours on an interface
+    OnInterface(u32, Ipv4Packet),
+    // forwarded through the routing table like any other packet
+    Routed(Ipv4Packet),
+}
+
+// A routing protocol running inside a router. The router hands it the packets addressed to itself
+// and the changes of its links, sends whatever the protocol asks it to and installs the routes it
+// computes. Time is always given by the router so protocols can be driven by hand in tests.
+pub trait RoutingProtocol: Send {
+    fn source(&self) -> RouteSource;
+    fn start(&mut self, interfaces: &[InterfaceConfig], now: Instant, out: &mut Vec<Outgoing>);
+    // returns whether the packet belonged to the protocol
+    fn handle_packet(
+        &mut self,
+        interface_id: u32,
+        packet: &Ipv4Packet,
+        now: Instant,
+        out: &mut Vec<Outgoing>,
+    ) -> bool;
+    fn link_state_changed(
+        &mut self,
+        interface_id: u32,
+        up: bool,
+        now: Instant,
+        out: &mut Vec<Outgoing>,
+    );
+    fn poll(&mut self, now: Instant, out: &mut Vec<Outgoing>);
+    fn next_deadline(&self) -> Option<Instant>;
+    fn routes(&self) -> Vec<Route>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Route, RouteSource, RoutingTable};
+    use std::net::Ipv4Addr;
+
+    fn route(prefix: &str, interface_id: u32, metric: u32, source: RouteSource) -> Route {
+        Route {
+            prefix: prefix.parse().unwrap(),
+            interface_id,
+            next_hop: None,
+            metric,
+            source,
+        }
+    }
+
+    #[test]
+    fn longest_prefix_match() {
+        let mut table = RoutingTable::new();
+        table.add(route("0.0.0.0/0", 0, 1, RouteSource::Static));
+        table.add(route("10.0.0.0/8", 1, 5, RouteSource::Rip));
+        table.add(route("10.1.0.0/16", 2, 5, RouteSource::Rip));
+        table.add(route("10.1.0.0/16", 3, 1, RouteSource::Connected));
+
+        let lookup = |address: [u8; 4]| table.lookup(Ipv4Addr::from(address)).unwrap().interface_id;
+        assert_eq!(lookup([8, 8, 8, 8]), 0);
+        assert_eq!(lookup([10, 2, 0, 1]), 1);
+        assert_eq!(lookup([10, 1, 0, 1]), 3);
+    }
+
+    #[test]
+    fn replace_source() {
+        let mut table = RoutingTable::new();
+        table.add(route("10.0.0.0/24", 0, 1, RouteSource::Connected));
+
+        let rip = vec![route("10.0.1.0/24", 1, 2, RouteSource::Rip)];
+        assert!(table.replace_source(RouteSource::Rip, rip.clone()));
+        assert!(!table.replace_source(RouteSource::Rip, rip));
+        assert_eq!(table.routes().len(), 2);
+
+        assert!(table.replace_source(RouteSource::Rip, Vec::new()));
+        assert_eq!(table.routes().len(), 1);
+    }
+}
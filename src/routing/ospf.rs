@@ -0,0 +1,1037 @@
+use super::{InterfaceConfig, Outgoing, Route, RouteSource, RoutingProtocol};
+use crate::protocols::{
+    ipv4::{IpProtocol, Ipv4Packet, Ipv4Prefix},
+    ospf::{
+        Lsa, LsaHeader, OspfBody, OspfHello, OspfPacket, RouterLink, RouterLinkType,
+        OSPF_ALL_ROUTERS, OSPF_INITIAL_SEQUENCE,
+    },
+    Packet,
+};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap},
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone)]
+pub struct OspfConfig {
+    pub hello_interval: Duration,
+    // neighbours not heard from for this long are considered gone
+    pub dead_interval: Duration,
+    // how often unacknowledged LSAs are sent again
+    pub retransmit_interval: Duration,
+    // own LSAs are originated again this often so they never age out elsewhere
+    pub refresh_interval: Duration,
+    pub max_age: Duration,
+    // changes to the database are batched for this long before running SPF
+    pub spf_delay: Duration,
+    pub default_cost: u16,
+}
+
+impl Default for OspfConfig {
+    fn default() -> Self {
+        Self {
+            hello_interval: Duration::from_secs(10),
+            dead_interval: Duration::from_secs(40),
+            retransmit_interval: Duration::from_secs(5),
+            refresh_interval: Duration::from_secs(1800),
+            max_age: Duration::from_secs(3600),
+            spf_delay: Duration::from_secs(1),
+            default_cost: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NeighborState {
+    // heard from, but it didn't hear from us yet
+    Init,
+    // both sides hear each other, without database description packets this is also the point
+    // where the databases get synchronized
+    Full,
+}
+
+struct Neighbor {
+    address: Ipv4Addr,
+    state: NeighborState,
+    dead_at: Instant,
+    // LSAs flooded to the neighbour it didn't acknowledge yet, by advertising router
+    retransmit: BTreeMap<Ipv4Addr, i32>,
+}
+
+struct OspfInterface {
+    config: InterfaceConfig,
+    cost: u16,
+    up: bool,
+    neighbors: BTreeMap<Ipv4Addr, Neighbor>,
+    next_hello: Option<Instant>,
+}
+
+struct LsdbEntry {
+    lsa: Lsa,
+    installed_at: Instant,
+}
+
+impl LsdbEntry {
+    fn age(&self, now: Instant) -> Duration {
+        Duration::from_secs(self.lsa.header.age as u64) + now.duration_since(self.installed_at)
+    }
+
+    // the LSA as it should be sent right now
+    fn aged(&self, now: Instant) -> Lsa {
+        let mut lsa = self.lsa.clone();
+        lsa.header.age = self.age(now).as_secs().min(u16::MAX as u64) as u16;
+        lsa
+    }
+}
+
+// A simplified OSPFv2 (see protocols::ospf) with a single area where every router only originates
+// a router-LSA. Routes are computed with Dijkstra over the links both ends agree on.
+pub struct Ospf {
+    config: OspfConfig,
+    costs: HashMap<u32, u16>,
+    router_id: Ipv4Addr,
+    interfaces: Vec<OspfInterface>,
+    lsdb: BTreeMap<Ipv4Addr, LsdbEntry>,
+    sequence: i32,
+    next_refresh: Option<Instant>,
+    next_retransmit: Option<Instant>,
+    spf_at: Option<Instant>,
+    routes: Vec<Route>,
+}
+
+impl Ospf {
+    pub fn new(config: OspfConfig) -> Self {
+        Self {
+            config,
+            costs: HashMap::new(),
+            router_id: Ipv4Addr::UNSPECIFIED,
+            interfaces: Vec::new(),
+            lsdb: BTreeMap::new(),
+            sequence: OSPF_INITIAL_SEQUENCE - 1,
+            next_refresh: None,
+            next_retransmit: None,
+            spf_at: None,
+            routes: Vec::new(),
+        }
+    }
+
+    // Must be called before the router starts.
+    pub fn set_cost(&mut self, interface_id: u32, cost: u16) {
+        self.costs.insert(interface_id, cost);
+    }
+
+    pub fn router_id(&self) -> Ipv4Addr {
+        self.router_id
+    }
+
+    pub fn sequence_of(&self, router_id: Ipv4Addr) -> Option<i32> {
+        self.lsdb
+            .get(&router_id)
+            .map(|entry| entry.lsa.header.sequence)
+    }
+
+    fn interface_mut(&mut self, interface_id: u32) -> Option<&mut OspfInterface> {
+        self.interfaces
+            .iter_mut()
+            .find(|interface| interface.config.interface_id == interface_id)
+    }
+
+    fn message(&self, source: Ipv4Addr, destin: Ipv4Addr, body: OspfBody) -> Ipv4Packet {
+        let packet = OspfPacket {
+            router_id: self.router_id,
+            area_id: Ipv4Addr::UNSPECIFIED,
+            body,
+        };
+        let mut packet = Ipv4Packet::new(source, destin, IpProtocol::Ospf, packet.to_bytes());
+        packet.ttl = 1;
+        packet
+    }
+
+    fn send_hello(&mut self, index: usize, now: Instant, out: &mut Vec<Outgoing>) {
+        let interface = &self.interfaces[index];
+        let hello = OspfHello {
+            network_mask: interface.config.prefix.mask(),
+            hello_interval: self.config.hello_interval.as_secs() as u16,
+            dead_interval: self.config.dead_interval.as_secs() as u32,
+            neighbors: interface.neighbors.keys().copied().collect(),
+        };
+        out.push(Outgoing::OnInterface(
+            interface.config.interface_id,
+            self.message(
+                interface.config.address,
+                OSPF_ALL_ROUTERS,
+                OspfBody::Hello(hello),
+            ),
+        ));
+        self.interfaces[index].next_hello = Some(now + self.config.hello_interval);
+    }
+
+    fn schedule_spf(&mut self, now: Instant) {
+        self.spf_at.get_or_insert(now + self.config.spf_delay);
+    }
+
+    fn originate(&mut self, now: Instant, out: &mut Vec<Outgoing>) {
+        let mut links = Vec::new();
+        for interface in self.interfaces.iter().filter(|interface| interface.up) {
+            links.extend(
+                interface
+                    .neighbors
+                    .iter()
+                    .filter(|(_, neighbor)| neighbor.state == NeighborState::Full)
+                    .map(|(router_id, _)| RouterLink {
+                        link_type: RouterLinkType::PointToPoint,
+                        link_id: *router_id,
+                        link_data: interface.config.address,
+                        metric: interface.cost,
+                    }),
+            );
+            links.push(RouterLink {
+                link_type: RouterLinkType::Stub,
+                link_id: interface.config.prefix.address(),
+                link_data: interface.config.prefix.mask(),
+                metric: interface.cost,
+            });
+        }
+
+        self.sequence += 1;
+        let lsa = Lsa {
+            header: LsaHeader {
+                age: 0,
+                advertising_router: self.router_id,
+                sequence: self.sequence,
+            },
+            links,
+        };
+
+        log::debug!(
+            "OSPF {}: originating LSA {:#010x}",
+            self.router_id,
+            self.sequence
+        );
+        self.lsdb.insert(
+            self.router_id,
+            LsdbEntry {
+                lsa: lsa.clone(),
+                installed_at: now,
+            },
+        );
+        self.flood(lsa, None, now, out);
+        self.next_refresh = Some(now + self.config.refresh_interval);
+        self.schedule_spf(now);
+    }
+
+    // Sends the LSA to every adjacent router but the one it came from.
+    fn flood(&mut self, lsa: Lsa, except: Option<Ipv4Addr>, now: Instant, out: &mut Vec<Outgoing>) {
+        let header = lsa.header;
+        for interface in self.interfaces.iter_mut().filter(|interface| interface.up) {
+            let mut recipients = interface
+                .neighbors
+                .iter_mut()
+                .filter(|(router_id, neighbor)| {
+                    neighbor.state == NeighborState::Full && Some(**router_id) != except
+                })
+                .peekable();
+
+            if recipients.peek().is_none() {
+                continue;
+            }
+            recipients.for_each(|(_, neighbor)| {
+                neighbor
+                    .retransmit
+                    .insert(header.advertising_router, header.sequence);
+            });
+
+            let packet = OspfPacket {
+                router_id: self.router_id,
+                area_id: Ipv4Addr::UNSPECIFIED,
+                body: OspfBody::LinkStateUpdate(vec![lsa.clone()]),
+            };
+            let mut packet = Ipv4Packet::new(
+                interface.config.address,
+                OSPF_ALL_ROUTERS,
+                IpProtocol::Ospf,
+                packet.to_bytes(),
+            );
+            packet.ttl = 1;
+            out.push(Outgoing::OnInterface(interface.config.interface_id, packet));
+            self.next_retransmit
+                .get_or_insert(now + self.config.retransmit_interval);
+        }
+    }
+
+    fn handle_hello(
+        &mut self,
+        index: usize,
+        router_id: Ipv4Addr,
+        source: Ipv4Addr,
+        hello: OspfHello,
+        now: Instant,
+        out: &mut Vec<Outgoing>,
+    ) {
+        let interface = &mut self.interfaces[index];
+        if hello.network_mask != interface.config.prefix.mask()
+            || hello.hello_interval as u64 != self.config.hello_interval.as_secs()
+            || hello.dead_interval as u64 != self.config.dead_interval.as_secs()
+        {
+            log::warn!(
+                "OSPF {}: hello from {router_id} doesn't match the configuration of interface {}",
+                self.router_id,
+                interface.config.interface_id
+            );
+            return;
+        }
+
+        // answering right away lets the neighbour see itself without waiting a whole interval
+        let mut answer = !interface.neighbors.contains_key(&router_id);
+        let neighbor = interface.neighbors.entry(router_id).or_insert(Neighbor {
+            address: source,
+            state: NeighborState::Init,
+            dead_at: now,
+            retransmit: BTreeMap::new(),
+        });
+        neighbor.address = source;
+        neighbor.dead_at = now + self.config.dead_interval;
+
+        let two_way = hello.neighbors.contains(&self.router_id);
+        match (neighbor.state, two_way) {
+            (NeighborState::Init, true) => {
+                neighbor.state = NeighborState::Full;
+                log::info!(
+                    "OSPF {}: adjacency with {router_id} on interface {} is up",
+                    self.router_id,
+                    interface.config.interface_id
+                );
+
+                // the whole database is sent instead of being described and requested
+                let lsas: Vec<_> = self.lsdb.values().map(|entry| entry.aged(now)).collect();
+                neighbor.retransmit.extend(
+                    lsas.iter()
+                        .map(|lsa| (lsa.header.advertising_router, lsa.header.sequence)),
+                );
+                let address = interface.config.address;
+                out.push(Outgoing::OnInterface(
+                    interface.config.interface_id,
+                    self.message(address, source, OspfBody::LinkStateUpdate(lsas)),
+                ));
+                self.next_retransmit
+                    .get_or_insert(now + self.config.retransmit_interval);
+                self.originate(now, out);
+            }
+            (NeighborState::Full, false) => {
+                // it restarted and forgot about us
+                neighbor.state = NeighborState::Init;
+                neighbor.retransmit.clear();
+                log::info!(
+                    "OSPF {}: adjacency with {router_id} on interface {} is down",
+                    self.router_id,
+                    interface.config.interface_id
+                );
+                answer = true;
+                self.originate(now, out);
+            }
+            _ => {}
+        }
+
+        if answer {
+            self.send_hello(index, now, out);
+        }
+    }
+
+    fn handle_lsa(
+        &mut self,
+        neighbor_id: Ipv4Addr,
+        reply_to: (u32, Ipv4Addr, Ipv4Addr),
+        lsa: Lsa,
+        now: Instant,
+        out: &mut Vec<Outgoing>,
+    ) {
+        let header = lsa.header;
+        let max_age = header.age as u64 >= self.config.max_age.as_secs();
+        let existing = self
+            .lsdb
+            .get(&header.advertising_router)
+            .map(|entry| entry.lsa.header.sequence);
+
+        if header.advertising_router == self.router_id {
+            // a copy from before we restarted, take over its sequence number
+            if header.sequence > self.sequence {
+                self.sequence = header.sequence;
+                self.originate(now, out);
+            }
+            return;
+        }
+
+        match existing {
+            Some(sequence) if sequence > header.sequence => {
+                // the neighbour is behind, help it catch up
+                let (interface_id, source, destin) = reply_to;
+                let ours = self.lsdb[&header.advertising_router].aged(now);
+                out.push(Outgoing::OnInterface(
+                    interface_id,
+                    self.message(source, destin, OspfBody::LinkStateUpdate(vec![ours])),
+                ));
+            }
+            Some(sequence) if sequence == header.sequence && !max_age => {
+                // it already has it, as good as an acknowledgment
+                if let Some(neighbor) = self
+                    .interface_mut(reply_to.0)
+                    .and_then(|interface| interface.neighbors.get_mut(&neighbor_id))
+                {
+                    neighbor.retransmit.remove(&header.advertising_router);
+                }
+            }
+            _ if max_age => {
+                if self.lsdb.remove(&header.advertising_router).is_some() {
+                    self.flood(lsa, Some(neighbor_id), now, out);
+                    self.schedule_spf(now);
+                }
+            }
+            _ => {
+                log::debug!(
+                    "OSPF {}: installing LSA {:#010x} from {}",
+                    self.router_id,
+                    header.sequence,
+                    header.advertising_router
+                );
+                self.lsdb.insert(
+                    header.advertising_router,
+                    LsdbEntry {
+                        lsa: lsa.clone(),
+                        installed_at: now,
+                    },
+                );
+                self.flood(lsa, Some(neighbor_id), now, out);
+                self.schedule_spf(now);
+            }
+        }
+    }
+
+    fn retransmit(&mut self, now: Instant, out: &mut Vec<Outgoing>) {
+        let mut pending = false;
+        let mut updates = Vec::new();
+
+        for interface in self.interfaces.iter_mut().filter(|interface| interface.up) {
+            for neighbor in interface.neighbors.values_mut() {
+                // forget about whatever was replaced in the meantime
+                neighbor.retransmit.retain(|router_id, sequence| {
+                    self.lsdb
+                        .get(router_id)
+                        .is_some_and(|entry| entry.lsa.header.sequence == *sequence)
+                });
+
+                if neighbor.retransmit.is_empty() {
+                    continue;
+                }
+                pending = true;
+
+                let lsas = neighbor
+                    .retransmit
+                    .keys()
+                    .map(|router_id| self.lsdb[router_id].aged(now))
+                    .collect();
+                updates.push((
+                    interface.config.interface_id,
+                    interface.config.address,
+                    neighbor.address,
+                    lsas,
+                ));
+            }
+        }
+
+        for (interface_id, source, destin, lsas) in updates {
+            out.push(Outgoing::OnInterface(
+                interface_id,
+                self.message(source, destin, OspfBody::LinkStateUpdate(lsas)),
+            ));
+        }
+
+        self.next_retransmit = pending.then(|| now + self.config.retransmit_interval);
+    }
+
+    fn run_spf(&mut self) {
+        let root = self.router_id;
+        // cost and first hop (interface and neighbour address) of every reachable router
+        let mut reached: BTreeMap<Ipv4Addr, (u32, Option<(u32, Ipv4Addr)>)> = BTreeMap::new();
+        let mut queue = BinaryHeap::from([Reverse((0, root))]);
+        reached.insert(root, (0, None));
+
+        let mut done = Vec::new();
+        while let Some(Reverse((cost, router_id))) = queue.pop() {
+            if done.contains(&router_id) {
+                continue;
+            }
+            done.push(router_id);
+
+            let Some(entry) = self.lsdb.get(&router_id) else {
+                continue;
+            };
+            let first_hop = reached[&router_id].1;
+
+            for link in entry.lsa.links.iter() {
+                if link.link_type != RouterLinkType::PointToPoint {
+                    continue;
+                }
+
+                // links only count if both ends advertise them
+                let neighbor = link.link_id;
+                let two_way = self.lsdb.get(&neighbor).is_some_and(|entry| {
+                    entry.lsa.links.iter().any(|back| {
+                        back.link_type == RouterLinkType::PointToPoint && back.link_id == router_id
+                    })
+                });
+                if !two_way {
+                    continue;
+                }
+
+                let hop = match first_hop {
+                    Some(hop) => hop,
+                    None => {
+                        let Some(hop) = self.first_hop_to(neighbor, link.link_data) else {
+                            continue;
+                        };
+                        hop
+                    }
+                };
+
+                let cost = cost + link.metric as u32;
+                if reached
+                    .get(&neighbor)
+                    .is_none_or(|(known, _)| cost < *known)
+                {
+                    reached.insert(neighbor, (cost, Some(hop)));
+                    queue.push(Reverse((cost, neighbor)));
+                }
+            }
+        }
+
+        let connected: Vec<_> = self
+            .interfaces
+            .iter()
+            .filter(|interface| interface.up)
+            .map(|interface| interface.config.prefix)
+            .collect();
+
+        let mut routes: BTreeMap<Ipv4Prefix, Route> = BTreeMap::new();
+        for (router_id, (cost, hop)) in reached.iter() {
+            let Some((interface_id, next_hop)) = hop else {
+                continue;
+            };
+
+            let stubs = self.lsdb[router_id]
+                .lsa
+                .links
+                .iter()
+                .filter(|link| link.link_type == RouterLinkType::Stub);
+
+            for link in stubs {
+                let Some(prefix) = Ipv4Prefix::from_mask(link.link_id, link.link_data) else {
+                    continue;
+                };
+                if connected.contains(&prefix) {
+                    continue;
+                }
+
+                let metric = cost + link.metric as u32;
+                if routes
+                    .get(&prefix)
+                    .is_none_or(|route| metric < route.metric)
+                {
+                    routes.insert(
+                        prefix,
+                        Route {
+                            prefix,
+                            interface_id: *interface_id,
+                            next_hop: Some(*next_hop),
+                            metric,
+                            source: RouteSource::Ospf,
+                        },
+                    );
+                }
+            }
+        }
+
+        log::info!(
+            "OSPF {}: SPF found {} routers and {} routes",
+            self.router_id,
+            reached.len(),
+            routes.len()
+        );
+        self.routes = routes.into_values().collect();
+    }
+
+    // the interface (and address) through which we are adjacent to `neighbor`, preferring the one
+    // the link in our LSA was advertised for
+    fn first_hop_to(&self, neighbor: Ipv4Addr, local_address: Ipv4Addr) -> Option<(u32, Ipv4Addr)> {
+        self.interfaces
+            .iter()
+            .filter(|interface| interface.up)
+            .filter_map(|interface| {
+                let adjacent = interface.neighbors.get(&neighbor)?;
+                (adjacent.state == NeighborState::Full).then_some((
+                    interface.config.address != local_address,
+                    interface.config.interface_id,
+                    adjacent.address,
+                ))
+            })
+            .min()
+            .map(|(_, interface_id, address)| (interface_id, address))
+    }
+}
+
+impl RoutingProtocol for Ospf {
+    fn source(&self) -> RouteSource {
+        RouteSource::Ospf
+    }
+
+    fn start(&mut self, interfaces: &[InterfaceConfig], now: Instant, out: &mut Vec<Outgoing>) {
+        self.router_id = interfaces
+            .iter()
+            .map(|interface| interface.address)
+            .max()
+            .unwrap_or(Ipv4Addr::UNSPECIFIED);
+
+        for interface in interfaces {
+            self.interfaces.push(OspfInterface {
+                config: *interface,
+                cost: self
+                    .costs
+                    .get(&interface.interface_id)
+                    .copied()
+                    .unwrap_or(self.config.default_cost),
+                up: true,
+                neighbors: BTreeMap::new(),
+                next_hello: None,
+            });
+        }
+
+        for index in 0..self.interfaces.len() {
+            self.send_hello(index, now, out);
+        }
+        self.originate(now, out);
+    }
+
+    fn handle_packet(
+        &mut self,
+        interface_id: u32,
+        packet: &Ipv4Packet,
+        now: Instant,
+        out: &mut Vec<Outgoing>,
+    ) -> bool {
+        if packet.protocol != IpProtocol::Ospf {
+            return false;
+        }
+
+        let message = match OspfPacket::decode(&packet.data) {
+            Ok(message) => message,
+            Err(err) => {
+                log::warn!(
+                    "OSPF {}: invalid packet from {}: {err:?}",
+                    self.router_id,
+                    packet.source
+                );
+                return true;
+            }
+        };
+
+        let Some(index) = self
+            .interfaces
+            .iter()
+            .position(|interface| interface.config.interface_id == interface_id && interface.up)
+        else {
+            return true;
+        };
+
+        let interface = &self.interfaces[index];
+        if message.router_id == self.router_id
+            || !message.area_id.is_unspecified()
+            || !interface.config.prefix.contains(packet.source)
+        {
+            return true;
+        }
+
+        let address = interface.config.address;
+        let known = interface.neighbors.contains_key(&message.router_id);
+
+        match message.body {
+            OspfBody::Hello(hello) => {
+                self.handle_hello(index, message.router_id, packet.source, hello, now, out)
+            }
+            OspfBody::LinkStateUpdate(lsas) if known => {
+                let acks = lsas.iter().map(|lsa| lsa.header).collect();
+                for lsa in lsas {
+                    self.handle_lsa(
+                        message.router_id,
+                        (interface_id, address, packet.source),
+                        lsa,
+                        now,
+                        out,
+                    );
+                }
+                out.push(Outgoing::OnInterface(
+                    interface_id,
+                    self.message(address, packet.source, OspfBody::LinkStateAck(acks)),
+                ));
+            }
+            OspfBody::LinkStateAck(headers) if known => {
+                let neighbor = self.interfaces[index]
+                    .neighbors
+                    .get_mut(&message.router_id)
+                    .unwrap();
+                for header in headers {
+                    if neighbor.retransmit.get(&header.advertising_router) == Some(&header.sequence)
+                    {
+                        neighbor.retransmit.remove(&header.advertising_router);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    fn link_state_changed(
+        &mut self,
+        interface_id: u32,
+        up: bool,
+        now: Instant,
+        out: &mut Vec<Outgoing>,
+    ) {
+        let Some(index) = self
+            .interfaces
+            .iter()
+            .position(|interface| interface.config.interface_id == interface_id)
+        else {
+            return;
+        };
+
+        let interface = &mut self.interfaces[index];
+        if interface.up == up {
+            return;
+        }
+        interface.up = up;
+
+        if up {
+            log::info!("OSPF {}: interface {interface_id} is up", self.router_id);
+            self.send_hello(index, now, out);
+        } else {
+            log::info!("OSPF {}: interface {interface_id} is down", self.router_id);
+            interface.neighbors.clear();
+            interface.next_hello = None;
+        }
+        self.originate(now, out);
+    }
+
+    fn poll(&mut self, now: Instant, out: &mut Vec<Outgoing>) {
+        for index in 0..self.interfaces.len() {
+            let interface = &self.interfaces[index];
+            if interface.up && interface.next_hello.is_some_and(|hello| hello <= now) {
+                self.send_hello(index, now, out);
+            }
+        }
+
+        let mut adjacency_lost = false;
+        for interface in self.interfaces.iter_mut() {
+            interface.neighbors.retain(|router_id, neighbor| {
+                let dead = neighbor.dead_at <= now;
+                if dead {
+                    log::info!(
+                        "OSPF {}: neighbor {router_id} on interface {} is dead",
+                        self.router_id,
+                        interface.config.interface_id
+                    );
+                    adjacency_lost |= neighbor.state == NeighborState::Full;
+                }
+                !dead
+            });
+        }
+
+        let router_id = self.router_id;
+        let max_age = self.config.max_age;
+        let before = self.lsdb.len();
+        self.lsdb.retain(|advertising_router, entry| {
+            *advertising_router == router_id || entry.age(now) < max_age
+        });
+        if self.lsdb.len() != before {
+            self.schedule_spf(now);
+        }
+
+        if adjacency_lost || self.next_refresh.is_some_and(|refresh| refresh <= now) {
+            self.originate(now, out);
+        }
+
+        if self
+            .next_retransmit
+            .is_some_and(|retransmit| retransmit <= now)
+        {
+            self.retransmit(now, out);
+        }
+
+        if self.spf_at.is_some_and(|spf| spf <= now) {
+            self.spf_at = None;
+            self.run_spf();
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        let hellos = self
+            .interfaces
+            .iter()
+            .filter(|interface| interface.up)
+            .filter_map(|interface| interface.next_hello);
+        let dead = self
+            .interfaces
+            .iter()
+            .flat_map(|interface| interface.neighbors.values())
+            .map(|neighbor| neighbor.dead_at);
+        let expiries = self
+            .lsdb
+            .iter()
+            .filter(|(router_id, _)| **router_id != self.router_id)
+            .map(|(_, entry)| {
+                entry.installed_at
+                    + self
+                        .config
+                        .max_age
+                        .saturating_sub(Duration::from_secs(entry.lsa.header.age as u64))
+            });
+
+        hellos
+            .chain(dead)
+            .chain(expiries)
+            .chain(self.next_refresh)
+            .chain(self.next_retransmit)
+            .chain(self.spf_at)
+            .min()
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        self.routes.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Ospf, OspfConfig};
+    use crate::{
+        protocols::ipv4::Ipv4Prefix,
+        routing::{InterfaceConfig, Outgoing, RoutingProtocol},
+    };
+    use std::{
+        net::Ipv4Addr,
+        time::{Duration, Instant},
+    };
+
+    // a router and one of its interfaces
+    type End = (usize, u32);
+
+    // Routers connected by point-to-point links, link k being the network 10.0.k.0/24. Every
+    // router also has a stub network 192.168.r.0/24 on interface 0.
+    struct Network {
+        routers: Vec<Ospf>,
+        // both ends as (router, interface) and whether it delivers anything
+        links: Vec<(End, End, bool)>,
+        interfaces: Vec<Vec<InterfaceConfig>>,
+        now: Instant,
+    }
+
+    impl Network {
+        fn new(size: usize, links: &[(usize, usize)]) -> Self {
+            let mut interfaces: Vec<_> = (0..size)
+                .map(|r| {
+                    vec![InterfaceConfig::new(
+                        0,
+                        Ipv4Addr::new(192, 168, r as u8, 1),
+                        24,
+                    )]
+                })
+                .collect();
+
+            let links = links
+                .iter()
+                .enumerate()
+                .map(|(k, &(a, b))| {
+                    let ends = [(a, 1), (b, 2)].map(|(r, host)| {
+                        let interface_id = interfaces[r].len() as u32;
+                        interfaces[r].push(InterfaceConfig::new(
+                            interface_id,
+                            Ipv4Addr::new(10, 0, k as u8, host),
+                            24,
+                        ));
+                        (r, interface_id)
+                    });
+                    (ends[0], ends[1], true)
+                })
+                .collect();
+
+            let mut network = Self {
+                routers: Vec::new(),
+                links,
+                interfaces,
+                now: Instant::now(),
+            };
+            for r in 0..size {
+                network.routers.push(Ospf::new(OspfConfig::default()));
+                network.start(r);
+            }
+            network
+        }
+
+        fn start(&mut self, router: usize) {
+            self.routers[router] = Ospf::new(OspfConfig::default());
+            let mut out = Vec::new();
+            self.routers[router].start(&self.interfaces[router], self.now, &mut out);
+            self.deliver(router, out);
+        }
+
+        fn deliver(&mut self, from: usize, out: Vec<Outgoing>) {
+            let mut queue: Vec<_> = out.into_iter().map(|outgoing| (from, outgoing)).collect();
+
+            while let Some((from, outgoing)) = queue.pop() {
+                let Outgoing::OnInterface(interface_id, packet) = outgoing else {
+                    panic!("OSPF only talks to its neighbours");
+                };
+
+                let destin = self.links.iter().find_map(|&(a, b, up)| match up {
+                    true if a == (from, interface_id) => Some(b),
+                    true if b == (from, interface_id) => Some(a),
+                    _ => None,
+                });
+
+                // routers that weren't started yet
+                let Some((to, to_interface)) = destin.filter(|(to, _)| *to < self.routers.len())
+                else {
+                    continue;
+                };
+
+                let mut out = Vec::new();
+                assert!(self.routers[to].handle_packet(to_interface, &packet, self.now, &mut out));
+                queue.extend(out.into_iter().map(|outgoing| (to, outgoing)));
+            }
+        }
+
+        fn advance(&mut self, duration: Duration) {
+            let end = self.now + duration;
+            loop {
+                let next = self
+                    .routers
+                    .iter()
+                    .filter_map(|router| router.next_deadline())
+                    .min()
+                    .filter(|next| *next <= end);
+
+                let Some(next) = next else { break };
+                self.now = self.now.max(next);
+
+                for r in 0..self.routers.len() {
+                    let mut out = Vec::new();
+                    self.routers[r].poll(self.now, &mut out);
+                    self.deliver(r, out);
+                }
+            }
+            self.now = end;
+        }
+
+        // `notify` is whether the routers see the link going down, otherwise they only notice
+        // once their neighbour is declared dead
+        fn set_link(&mut self, link: usize, up: bool, notify: bool) {
+            self.links[link].2 = up;
+            if !notify {
+                return;
+            }
+
+            let (a, b, _) = self.links[link];
+            for (router, interface_id) in [a, b] {
+                let mut out = Vec::new();
+                self.routers[router].link_state_changed(interface_id, up, self.now, &mut out);
+                self.deliver(router, out);
+            }
+        }
+
+        // metric and next hop router from `router` to the stub network of `stub`
+        fn route(&self, router: usize, stub: u8) -> Option<(u32, Ipv4Addr)> {
+            let prefix = Ipv4Prefix::new(Ipv4Addr::new(192, 168, stub, 0), 24);
+            self.routers[router]
+                .routes()
+                .into_iter()
+                .find(|route| route.prefix == prefix)
+                .map(|route| (route.metric, route.next_hop.unwrap()))
+        }
+    }
+
+    #[test]
+    fn converges_and_reroutes() {
+        // a ring: 0 - 1 - 2 - 3 - 0
+        let mut network = Network::new(4, &[(0, 1), (1, 2), (2, 3), (3, 0)]);
+        network.advance(Duration::from_secs(5));
+
+        // from 0 to the stub of 2 there are two paths of the same cost
+        assert_eq!(network.route(0, 1), Some((2, Ipv4Addr::new(10, 0, 0, 2))));
+        assert_eq!(network.route(0, 3), Some((2, Ipv4Addr::new(10, 0, 3, 1))));
+        assert_eq!(network.route(0, 2).unwrap().0, 3);
+        // transit networks are routed as well
+        assert_eq!(network.routers[0].routes().len(), 3 + 2);
+
+        network.set_link(0, false, true);
+        network.advance(Duration::from_secs(2));
+        assert_eq!(network.route(0, 1), Some((4, Ipv4Addr::new(10, 0, 3, 1))));
+        assert_eq!(network.route(1, 0), Some((4, Ipv4Addr::new(10, 0, 1, 2))));
+
+        network.set_link(0, true, true);
+        network.advance(Duration::from_secs(5));
+        assert_eq!(network.route(0, 1), Some((2, Ipv4Addr::new(10, 0, 0, 2))));
+    }
+
+    #[test]
+    fn dead_neighbors() {
+        let mut network = Network::new(3, &[(0, 1), (1, 2)]);
+        network.advance(Duration::from_secs(5));
+        assert_eq!(network.route(0, 2).unwrap().0, 3);
+
+        network.set_link(1, false, false);
+        network.advance(Duration::from_secs(30));
+        assert!(network.route(0, 2).is_some());
+
+        network.advance(Duration::from_secs(20));
+        assert_eq!(network.route(0, 2), None);
+    }
+
+    #[test]
+    fn restarted_router_takes_over_its_sequence_number() {
+        let mut network = Network::new(2, &[(0, 1)]);
+        // a few flaps leave router 1 further ahead than where it restarts from
+        for _ in 0..3 {
+            network.set_link(0, false, true);
+            network.set_link(0, true, true);
+            network.advance(Duration::from_secs(5));
+        }
+        let router_id = network.routers[1].router_id();
+        let before = network.routers[0].sequence_of(router_id).unwrap();
+
+        network.start(1);
+        network.advance(Duration::from_secs(5));
+
+        let after = network.routers[0].sequence_of(router_id).unwrap();
+        assert!(after > before, "{after:#x} <= {before:#x}");
+        assert_eq!(network.routers[1].sequence_of(router_id), Some(after));
+        assert_eq!(network.route(0, 1).unwrap().0, 2);
+    }
+
+    #[test]
+    fn lsas_age_out() {
+        let mut network = Network::new(2, &[(0, 1)]);
+        network.advance(Duration::from_secs(5));
+        let router_id = network.routers[1].router_id();
+
+        // router 1 vanishes without its neighbour noticing, its LSA stays around until it is too
+        // old, it would have been refreshed long before that
+        network.routers.truncate(1);
+        network.advance(Duration::from_secs(3000));
+        assert!(network.routers[0].sequence_of(router_id).is_some());
+
+        network.advance(Duration::from_secs(700));
+        assert_eq!(network.routers[0].sequence_of(router_id), None);
+    }
+}
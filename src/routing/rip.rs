@@ -0,0 +1,671 @@
+use super::{InterfaceConfig, Outgoing, Route, RouteSource, RoutingProtocol};
+use crate::protocols::{
+    ipv4::{IpProtocol, Ipv4Packet, Ipv4Prefix},
+    rip::{
+        RipCommand, RipEntry, RipMessage, RIP_INFINITY, RIP_MAX_ENTRIES, RIP_MULTICAST_ADDR,
+        RIP_PORT,
+    },
+    udp::UdpDatagram,
+    Packet,
+};
+use std::{
+    collections::BTreeMap,
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitHorizon {
+    // advertise every route on every interface, which lets count-to-infinity happen
+    Disabled,
+    // don't advertise routes on the interface they were learned from
+    Simple,
+    // advertise them back as unreachable
+    PoisonedReverse,
+}
+
+#[derive(Debug, Clone)]
+pub struct RipConfig {
+    pub update_interval: Duration,
+    // how long a route lives without being refreshed
+    pub timeout: Duration,
+    // how long an unreachable route is still advertised before being deleted
+    pub garbage_collection: Duration,
+    // changes are batched for this long before a triggered update is sent
+    pub triggered_update_delay: Duration,
+    pub split_horizon: SplitHorizon,
+}
+
+impl Default for RipConfig {
+    // the timers from the RFC, which make convergence quite slow to watch
+    fn default() -> Self {
+        Self {
+            update_interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(180),
+            garbage_collection: Duration::from_secs(120),
+            triggered_update_delay: Duration::from_secs(1),
+            split_horizon: SplitHorizon::PoisonedReverse,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RipRoute {
+    interface_id: u32,
+    // None for the networks the router is directly connected to
+    next_hop: Option<Ipv4Addr>,
+    metric: u32,
+    timeout_at: Option<Instant>,
+    garbage_at: Option<Instant>,
+    changed: bool,
+}
+
+struct RipInterface {
+    config: InterfaceConfig,
+    up: bool,
+}
+
+// RIP version 2 (https://www.rfc-editor.org/rfc/rfc2453), every link has a cost of 1.
+pub struct Rip {
+    config: RipConfig,
+    router_id: Ipv4Addr,
+    interfaces: Vec<RipInterface>,
+    routes: BTreeMap<Ipv4Prefix, RipRoute>,
+    next_update: Option<Instant>,
+    triggered_update_at: Option<Instant>,
+}
+
+impl Rip {
+    pub fn new(config: RipConfig) -> Self {
+        Self {
+            config,
+            router_id: Ipv4Addr::UNSPECIFIED,
+            interfaces: Vec::new(),
+            routes: BTreeMap::new(),
+            next_update: None,
+            triggered_update_at: None,
+        }
+    }
+
+    pub fn metric(&self, prefix: Ipv4Prefix) -> Option<u32> {
+        self.routes.get(&prefix).map(|route| route.metric)
+    }
+
+    fn interface(&self, interface_id: u32) -> Option<&RipInterface> {
+        self.interfaces
+            .iter()
+            .find(|interface| interface.config.interface_id == interface_id)
+    }
+
+    fn add_connected(&mut self, interface: InterfaceConfig) {
+        self.routes.insert(
+            interface.prefix,
+            RipRoute {
+                interface_id: interface.interface_id,
+                next_hop: None,
+                metric: 1,
+                timeout_at: None,
+                garbage_at: None,
+                changed: true,
+            },
+        );
+    }
+
+    fn schedule_triggered_update(&mut self, now: Instant) {
+        self.triggered_update_at
+            .get_or_insert(now + self.config.triggered_update_delay);
+    }
+
+    fn invalidate(&mut self, prefix: Ipv4Prefix, now: Instant) {
+        let route = self.routes.get_mut(&prefix).unwrap();
+        route.metric = RIP_INFINITY;
+        route.timeout_at = None;
+        route.garbage_at = Some(now + self.config.garbage_collection);
+        route.changed = true;
+
+        log::info!("RIP {}: {prefix} is now unreachable", self.router_id);
+        self.schedule_triggered_update(now);
+    }
+
+    fn handle_response(
+        &mut self,
+        interface_id: u32,
+        from: Ipv4Addr,
+        entries: &[RipEntry],
+        now: Instant,
+    ) {
+        for entry in entries {
+            if entry.metric == 0 || entry.metric > RIP_INFINITY {
+                continue;
+            }
+
+            let metric = (entry.metric + 1).min(RIP_INFINITY);
+            let next_hop = match entry.next_hop {
+                next_hop if next_hop.is_unspecified() => from,
+                next_hop => next_hop,
+            };
+
+            let Some(route) = self.routes.get_mut(&entry.prefix) else {
+                if metric < RIP_INFINITY {
+                    log::info!(
+                        "RIP {}: learned {} via {next_hop} with metric {metric}",
+                        self.router_id,
+                        entry.prefix
+                    );
+                    self.routes.insert(
+                        entry.prefix,
+                        RipRoute {
+                            interface_id,
+                            next_hop: Some(next_hop),
+                            metric,
+                            timeout_at: Some(now + self.config.timeout),
+                            garbage_at: None,
+                            changed: true,
+                        },
+                    );
+                    self.schedule_triggered_update(now);
+                }
+                continue;
+            };
+
+            if route.next_hop.is_none() && route.metric < RIP_INFINITY {
+                continue;
+            }
+
+            let same_router = route.next_hop == Some(next_hop);
+            if same_router && metric < RIP_INFINITY {
+                route.timeout_at = Some(now + self.config.timeout);
+                route.garbage_at = None;
+            }
+
+            if (same_router && metric != route.metric) || metric < route.metric {
+                if metric >= RIP_INFINITY {
+                    self.invalidate(entry.prefix, now);
+                    continue;
+                }
+
+                log::info!(
+                    "RIP {}: {} is now via {next_hop} with metric {metric}",
+                    self.router_id,
+                    entry.prefix
+                );
+                route.interface_id = interface_id;
+                route.next_hop = Some(next_hop);
+                route.metric = metric;
+                route.timeout_at = Some(now + self.config.timeout);
+                route.garbage_at = None;
+                route.changed = true;
+                self.schedule_triggered_update(now);
+            }
+        }
+    }
+
+    fn update_entries(&self, interface_id: u32, only_changed: bool) -> Vec<RipEntry> {
+        self.routes
+            .iter()
+            .filter(|(_, route)| !only_changed || route.changed)
+            .filter_map(|(prefix, route)| {
+                let learned_here = route.interface_id == interface_id;
+                let metric = match self.config.split_horizon {
+                    SplitHorizon::Simple if learned_here => return None,
+                    SplitHorizon::PoisonedReverse if learned_here => RIP_INFINITY,
+                    _ => route.metric,
+                };
+
+                Some(RipEntry {
+                    prefix: *prefix,
+                    next_hop: Ipv4Addr::UNSPECIFIED,
+                    metric,
+                    route_tag: 0,
+                })
+            })
+            .collect()
+    }
+
+    fn send_update(&self, only_changed: bool, out: &mut Vec<Outgoing>) {
+        for interface in self.interfaces.iter().filter(|interface| interface.up) {
+            let entries = self.update_entries(interface.config.interface_id, only_changed);
+            for chunk in entries.chunks(RIP_MAX_ENTRIES) {
+                out.push(Outgoing::OnInterface(
+                    interface.config.interface_id,
+                    Self::message(
+                        interface.config.address,
+                        RIP_MULTICAST_ADDR,
+                        RipCommand::Response,
+                        chunk.to_vec(),
+                    ),
+                ));
+            }
+        }
+    }
+
+    fn send_request(&self, interface: &InterfaceConfig, out: &mut Vec<Outgoing>) {
+        // a single entry with metric 16 and no address asks for the whole table
+        let entry = RipEntry {
+            prefix: Ipv4Prefix::DEFAULT,
+            next_hop: Ipv4Addr::UNSPECIFIED,
+            metric: RIP_INFINITY,
+            route_tag: 0,
+        };
+        out.push(Outgoing::OnInterface(
+            interface.interface_id,
+            Self::message(
+                interface.address,
+                RIP_MULTICAST_ADDR,
+                RipCommand::Request,
+                vec![entry],
+            ),
+        ));
+    }
+
+    fn message(
+        source: Ipv4Addr,
+        destin: Ipv4Addr,
+        command: RipCommand,
+        entries: Vec<RipEntry>,
+    ) -> Ipv4Packet {
+        let datagram = UdpDatagram {
+            source_port: RIP_PORT,
+            destin_port: RIP_PORT,
+            data: RipMessage { command, entries }.to_bytes(),
+        };
+        let mut packet = Ipv4Packet::new(source, destin, IpProtocol::Udp, datagram.to_bytes());
+        packet.ttl = 1;
+        packet
+    }
+}
+
+impl RoutingProtocol for Rip {
+    fn source(&self) -> RouteSource {
+        RouteSource::Rip
+    }
+
+    fn start(&mut self, interfaces: &[InterfaceConfig], now: Instant, out: &mut Vec<Outgoing>) {
+        self.router_id = interfaces
+            .iter()
+            .map(|interface| interface.address)
+            .max()
+            .unwrap_or(Ipv4Addr::UNSPECIFIED);
+
+        for interface in interfaces {
+            self.interfaces.push(RipInterface {
+                config: *interface,
+                up: true,
+            });
+            self.add_connected(*interface);
+            self.send_request(interface, out);
+        }
+
+        self.next_update = Some(now);
+    }
+
+    fn handle_packet(
+        &mut self,
+        interface_id: u32,
+        packet: &Ipv4Packet,
+        now: Instant,
+        out: &mut Vec<Outgoing>,
+    ) -> bool {
+        if packet.protocol != IpProtocol::Udp {
+            return false;
+        }
+
+        let Ok(datagram) = UdpDatagram::decode(&packet.data) else {
+            return false;
+        };
+
+        if datagram.destin_port != RIP_PORT {
+            return false;
+        }
+
+        let Some(interface) = self
+            .interface(interface_id)
+            .map(|interface| interface.config)
+        else {
+            return true;
+        };
+
+        // only neighbours on the same network are listened to
+        if packet.source == interface.address || !interface.prefix.contains(packet.source) {
+            return true;
+        }
+
+        let message = match RipMessage::decode(&datagram.data) {
+            Ok(message) => message,
+            Err(err) => {
+                log::warn!(
+                    "RIP {}: invalid message from {}: {err:?}",
+                    self.router_id,
+                    packet.source
+                );
+                return true;
+            }
+        };
+
+        match message.command {
+            RipCommand::Request => {
+                let entries = self.update_entries(interface_id, false);
+                for chunk in entries.chunks(RIP_MAX_ENTRIES) {
+                    out.push(Outgoing::OnInterface(
+                        interface_id,
+                        Self::message(
+                            interface.address,
+                            packet.source,
+                            RipCommand::Response,
+                            chunk.to_vec(),
+                        ),
+                    ));
+                }
+            }
+            // responses must come from the RIP port of a neighbour
+            RipCommand::Response if datagram.source_port == RIP_PORT => {
+                self.handle_response(interface_id, packet.source, &message.entries, now)
+            }
+            RipCommand::Response => {}
+        }
+
+        true
+    }
+
+    fn link_state_changed(
+        &mut self,
+        interface_id: u32,
+        up: bool,
+        now: Instant,
+        out: &mut Vec<Outgoing>,
+    ) {
+        let Some(interface) = self
+            .interfaces
+            .iter_mut()
+            .find(|interface| interface.config.interface_id == interface_id)
+        else {
+            return;
+        };
+
+        if interface.up == up {
+            return;
+        }
+        interface.up = up;
+        let config = interface.config;
+
+        if up {
+            log::info!("RIP {}: interface {interface_id} is up", self.router_id);
+            self.add_connected(config);
+            self.send_request(&config, out);
+            self.schedule_triggered_update(now);
+            return;
+        }
+
+        log::info!("RIP {}: interface {interface_id} is down", self.router_id);
+        let lost: Vec<_> = self
+            .routes
+            .iter()
+            .filter(|(_, route)| route.interface_id == interface_id && route.metric < RIP_INFINITY)
+            .map(|(prefix, _)| *prefix)
+            .collect();
+
+        for prefix in lost {
+            self.invalidate(prefix, now);
+        }
+    }
+
+    fn poll(&mut self, now: Instant, out: &mut Vec<Outgoing>) {
+        let expired: Vec<_> = self
+            .routes
+            .iter()
+            .filter(|(_, route)| route.timeout_at.is_some_and(|timeout| timeout <= now))
+            .map(|(prefix, _)| *prefix)
+            .collect();
+
+        for prefix in expired {
+            self.invalidate(prefix, now);
+        }
+
+        self.routes.retain(|prefix, route| {
+            let collect = route.garbage_at.is_some_and(|garbage| garbage <= now);
+            if collect {
+                log::debug!("RIP {}: deleted {prefix}", self.router_id);
+            }
+            !collect
+        });
+
+        if self.next_update.is_some_and(|update| update <= now) {
+            self.send_update(false, out);
+            self.next_update = Some(now + self.config.update_interval);
+        } else if self.triggered_update_at.is_some_and(|update| update <= now) {
+            self.send_update(true, out);
+        } else {
+            return;
+        }
+
+        self.triggered_update_at = None;
+        self.routes
+            .values_mut()
+            .for_each(|route| route.changed = false);
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.routes
+            .values()
+            .flat_map(|route| [route.timeout_at, route.garbage_at])
+            .chain([self.next_update, self.triggered_update_at])
+            .flatten()
+            .min()
+    }
+
+    fn routes(&self) -> Vec<Route> {
+        self.routes
+            .iter()
+            .filter(|(_, route)| route.metric < RIP_INFINITY)
+            .filter_map(|(prefix, route)| {
+                Some(Route {
+                    prefix: *prefix,
+                    interface_id: route.interface_id,
+                    next_hop: Some(route.next_hop?),
+                    metric: route.metric,
+                    source: RouteSource::Rip,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Rip, RipConfig, SplitHorizon};
+    use crate::{
+        protocols::{ipv4::Ipv4Prefix, rip::RIP_INFINITY},
+        routing::{InterfaceConfig, Outgoing, RoutingProtocol},
+    };
+    use std::{
+        net::Ipv4Addr,
+        time::{Duration, Instant},
+    };
+
+    // A chain of routers where router i and i + 1 share the network 10.0.i.0/24, router i being
+    // .1 on its right interface (1) and router i + 1 being .2 on its left one (0). Every router
+    // also has a stub network 192.168.i.0/24 on interface 2.
+    struct Chain {
+        routers: Vec<Rip>,
+        links_up: Vec<bool>,
+        now: Instant,
+    }
+
+    impl Chain {
+        fn new(size: usize, split_horizon: SplitHorizon) -> Self {
+            let now = Instant::now();
+            let config = RipConfig {
+                split_horizon,
+                ..RipConfig::default()
+            };
+
+            let mut chain = Self {
+                routers: Vec::new(),
+                links_up: vec![true; size - 1],
+                now,
+            };
+
+            let mut started = Vec::new();
+            for i in 0..size {
+                let mut interfaces = vec![InterfaceConfig::new(
+                    2,
+                    Ipv4Addr::new(192, 168, i as u8, 1),
+                    24,
+                )];
+                if i > 0 {
+                    interfaces.push(InterfaceConfig::new(
+                        0,
+                        Ipv4Addr::new(10, 0, i as u8 - 1, 2),
+                        24,
+                    ));
+                }
+                if i + 1 < size {
+                    interfaces.push(InterfaceConfig::new(
+                        1,
+                        Ipv4Addr::new(10, 0, i as u8, 1),
+                        24,
+                    ));
+                }
+
+                let mut rip = Rip::new(config.clone());
+                let mut out = Vec::new();
+                rip.start(&interfaces, now, &mut out);
+                chain.routers.push(rip);
+                started.push(out);
+            }
+
+            for (i, out) in started.into_iter().enumerate() {
+                chain.deliver(i, out);
+            }
+            chain
+        }
+
+        fn deliver(&mut self, from: usize, out: Vec<Outgoing>) {
+            let mut queue: Vec<_> = out.into_iter().map(|outgoing| (from, outgoing)).collect();
+
+            while let Some((from, outgoing)) = queue.pop() {
+                let Outgoing::OnInterface(interface_id, packet) = outgoing else {
+                    panic!("RIP only talks to its neighbours");
+                };
+
+                let (to, to_interface) = match interface_id {
+                    0 if self.links_up[from - 1] => (from - 1, 1),
+                    1 if self.links_up[from] => (from + 1, 0),
+                    _ => continue,
+                };
+
+                let mut out = Vec::new();
+                assert!(self.routers[to].handle_packet(to_interface, &packet, self.now, &mut out));
+                queue.extend(out.into_iter().map(|outgoing| (to, outgoing)));
+            }
+        }
+
+        fn advance(&mut self, duration: Duration) {
+            let end = self.now + duration;
+            loop {
+                let next = self
+                    .routers
+                    .iter()
+                    .filter_map(|router| router.next_deadline())
+                    .min()
+                    .filter(|next| *next <= end);
+
+                let Some(next) = next else { break };
+                self.now = self.now.max(next);
+
+                for i in 0..self.routers.len() {
+                    let mut out = Vec::new();
+                    self.routers[i].poll(self.now, &mut out);
+                    self.deliver(i, out);
+                }
+            }
+            self.now = end;
+        }
+
+        fn set_link(&mut self, link: usize, up: bool) {
+            self.links_up[link] = up;
+            for (router, interface_id) in [(link, 1), (link + 1, 0)] {
+                let mut out = Vec::new();
+                self.routers[router].link_state_changed(interface_id, up, self.now, &mut out);
+                self.deliver(router, out);
+            }
+        }
+
+        fn metric(&self, router: usize, stub: u8) -> Option<u32> {
+            self.routers[router].metric(Ipv4Prefix::new(Ipv4Addr::new(192, 168, stub, 0), 24))
+        }
+    }
+
+    #[test]
+    fn converges() {
+        let mut chain = Chain::new(4, SplitHorizon::PoisonedReverse);
+        chain.advance(Duration::from_secs(5));
+
+        for router in 0..4 {
+            for stub in 0..4 {
+                let hops = (router as i32 - stub as i32).unsigned_abs();
+                assert_eq!(chain.metric(router, stub), Some(hops + 1));
+            }
+        }
+
+        let routes = chain.routers[0].routes();
+        let route = routes
+            .iter()
+            .find(|route| route.prefix.contains(Ipv4Addr::new(192, 168, 3, 1)))
+            .unwrap();
+        assert_eq!(route.next_hop, Some(Ipv4Addr::new(10, 0, 0, 2)));
+        assert_eq!(route.interface_id, 1);
+    }
+
+    #[test]
+    fn routes_time_out() {
+        let mut chain = Chain::new(2, SplitHorizon::PoisonedReverse);
+        chain.advance(Duration::from_secs(5));
+        assert_eq!(chain.metric(0, 1), Some(2));
+
+        // the link silently stops delivering, without either router noticing it went down
+        chain.links_up[0] = false;
+        chain.advance(Duration::from_secs(170));
+        assert_eq!(chain.metric(0, 1), Some(2));
+
+        chain.advance(Duration::from_secs(10));
+        assert_eq!(chain.metric(0, 1), Some(RIP_INFINITY));
+        assert!(chain.routers[0].routes().is_empty());
+
+        chain.advance(Duration::from_secs(120));
+        assert_eq!(chain.metric(0, 1), None);
+    }
+
+    #[test]
+    fn count_to_infinity_without_split_horizon() {
+        let mut chain = Chain::new(3, SplitHorizon::Disabled);
+        chain.advance(Duration::from_millis(29_500));
+        assert_eq!(chain.metric(0, 2), Some(3));
+
+        // router 1 loses its route to router 2's stub and, before its triggered update goes out,
+        // hears router 0's periodic update advertising the route it learned from router 1
+        chain.set_link(1, false);
+        chain.advance(Duration::from_secs(2));
+        let metric = chain.metric(0, 2).unwrap();
+        assert!(metric > 3, "metric {metric}");
+
+        chain.advance(Duration::from_secs(600));
+        assert_eq!(chain.metric(0, 2), None);
+        assert_eq!(chain.metric(1, 2), None);
+    }
+
+    #[test]
+    fn poisoned_reverse_prevents_count_to_infinity() {
+        let mut chain = Chain::new(3, SplitHorizon::PoisonedReverse);
+        chain.advance(Duration::from_millis(29_500));
+
+        chain.set_link(1, false);
+        chain.advance(Duration::from_secs(2));
+        assert_eq!(chain.metric(0, 2), Some(RIP_INFINITY));
+        assert_eq!(chain.metric(1, 2), Some(RIP_INFINITY));
+
+        chain.set_link(1, true);
+        chain.advance(Duration::from_secs(5));
+        assert_eq!(chain.metric(0, 2), Some(3));
+    }
+}
@@ -1,15 +1,105 @@
 use crate::{
-    devices::{Device, Module},
-    links::{self, Link, LinkEnd},
+    devices::{Device, DeviceStats, Gate, Module, ModuleHandle, SharedTable},
+    links::{
+        self,
+        wireless::{Position, StationStats, WirelessConfig, WirelessMedium},
+        Link, LinkEnd, LinkHandle, LinkStats,
+    },
     protocols::ethernet::MacAddress,
+    topology::{ExportOptions, Topology, TopologyDevice, TopologyLink},
+    trace::Tracer,
+};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
-use std::{collections::HashMap, thread};
 
 pub struct Simulator {
     devices: HashMap<MacAddress, Box<dyn Device + Send>>,
-    links: Vec<(InterfaceSpec, InterfaceSpec)>,
+    links: Vec<SimLink>,
+    wireless: Vec<(WirelessMedium, Vec<WirelessStation>)>,
+    events: Vec<(Duration, SimEvent)>,
+    tracer: Option<Tracer>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkId(usize);
+
+impl LinkId {
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimEvent {
+    LinkDown(LinkId),
+    LinkUp(LinkId),
+}
+
+struct SimLink {
+    source: InterfaceSpec,
+    destin: InterfaceSpec,
+    // taken once the link gets attached to the devices
+    ends: Option<(LinkEnd, LinkEnd)>,
+    handle: LinkHandle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MediumId(usize);
+
+// Returned by Simulator::run, it allows changing the topology while the simulation runs.
+pub struct SimulatorHandle {
+    devices: HashMap<MacAddress, RunningDevice>,
+    // removed links leave a hole so that every LinkId stays valid
+    links: Vec<Option<SimLink>>,
+    // and the interfaces of their stations
+    wireless: Vec<(WirelessMedium, Vec<InterfaceSpec>)>,
+    threads: Vec<JoinHandle<()>>,
+    // dropping it cancels the scheduled events not applied yet
+    stop_events: mpsc::Sender<()>,
+    tracer: Option<Tracer>,
+    gate: Arc<Gate>,
+}
+
+struct RunningDevice {
+    module: ModuleHandle,
+    thread: JoinHandle<()>,
+    // as it was when it started, for diagrams
+    kind: &'static str,
+    addresses: Vec<String>,
+    tables: Vec<(&'static str, SharedTable)>,
+}
+
+struct WirelessStation {
+    interface: InterfaceSpec,
+    position: Position,
+    range: f64,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkReport {
+    pub link: LinkId,
+    pub source: InterfaceSpec,
+    pub destin: InterfaceSpec,
+    pub stats: LinkStats,
+}
+
+// The counters of every device, link and wireless station, sorted by address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NetworkStats {
+    pub devices: Vec<(MacAddress, DeviceStats)>,
+    pub links: Vec<LinkReport>,
+    pub stations: Vec<(MacAddress, StationStats)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct InterfaceSpec {
     mac_address: MacAddress,
     interface_id: u32,
@@ -22,6 +112,22 @@ impl InterfaceSpec {
             interface_id,
         }
     }
+
+    fn pair(&self) -> (MacAddress, u32) {
+        (self.mac_address, self.interface_id)
+    }
+}
+
+impl SimLink {
+    fn topology(&self, id: usize) -> TopologyLink {
+        TopologyLink {
+            id,
+            source: self.source.pair(),
+            destin: self.destin.pair(),
+            up: self.handle.is_up(),
+            stats: self.handle.stats(),
+        }
+    }
 }
 
 impl Simulator {
@@ -29,9 +135,21 @@ impl Simulator {
         Self {
             devices: HashMap::new(),
             links: Vec::new(),
+            wireless: Vec::new(),
+            events: Vec::new(),
+            tracer: None,
         }
     }
 
+    // Traces every frame going through the devices, including the ones added while it runs.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
     pub fn add_device<T>(&mut self, device: T)
     where
         T: Device + 'static,
@@ -44,14 +162,100 @@ impl Simulator {
     }
 
     // TODO: add more things
-    pub fn add_link(&mut self, source: InterfaceSpec, destin: InterfaceSpec) {
-        self.links.push((source, destin))
+    pub fn add_link(&mut self, source: InterfaceSpec, destin: InterfaceSpec) -> LinkId {
+        let (end_1, end_2) = links::create_link();
+        self.links.push(SimLink {
+            source,
+            destin,
+            handle: end_1.handle(),
+            ends: Some((end_1, end_2)),
+        });
+        LinkId(self.links.len() - 1)
+    }
+
+    // The handle can be moved anywhere (e.g. into a device) to bring the link down or up while
+    // the simulation is running.
+    pub fn link_handle(&self, link: LinkId) -> LinkHandle {
+        self.links
+            .get(link.0)
+            .expect("Failed to find link")
+            .handle
+            .clone()
+    }
+
+    // `at` is relative to the moment the simulation starts running
+    pub fn schedule(&mut self, at: Duration, event: SimEvent) {
+        self.events.push((at, event))
+    }
+
+    pub fn add_wireless_medium(&mut self, config: WirelessConfig) -> MediumId {
+        self.wireless
+            .push((WirelessMedium::new(config), Vec::new()));
+        MediumId(self.wireless.len() - 1)
+    }
+
+    pub fn add_wireless_station(
+        &mut self,
+        medium: MediumId,
+        interface: InterfaceSpec,
+        position: Position,
+        range: f64,
+    ) {
+        let (_, stations) = self
+            .wireless
+            .get_mut(medium.0)
+            .expect("Failed to find wireless medium");
+
+        stations.push(WirelessStation {
+            interface,
+            position,
+            range,
+        })
+    }
+
+    // the topology as it will start, see SimulatorHandle::topology() for the utilization
+    pub fn topology(&self) -> Topology {
+        let devices = self
+            .devices
+            .iter()
+            .map(|(mac, device)| TopologyDevice {
+                mac: *mac,
+                kind: device.kind(),
+                addresses: device.addresses(),
+            })
+            .collect();
+        let links = self
+            .links
+            .iter()
+            .enumerate()
+            .map(|(idx, link)| link.topology(idx))
+            .collect();
+        let media = self
+            .wireless
+            .iter()
+            .map(|(_, stations)| {
+                stations
+                    .iter()
+                    .map(|station| station.interface.pair())
+                    .collect()
+            })
+            .collect();
+        Topology::sorted(devices, links, media)
+    }
+
+    pub fn export_dot(&self, options: &ExportOptions) -> String {
+        self.topology().to_dot(options)
+    }
+
+    pub fn export_mermaid(&self, options: &ExportOptions) -> String {
+        self.topology().to_mermaid(options)
     }
 
     // TODO: an Result<...>
     fn create_network(&mut self) {
-        for (src, dst) in self.links.iter() {
-            let (end_1, end_2) = links::create_link();
+        for link in self.links.iter_mut() {
+            let (src, dst) = (&link.source, &link.destin);
+            let (end_1, end_2) = link.ends.take().expect("Link already attached");
             let device_1 = self
                 .devices
                 .get_mut(&src.mac_address)
@@ -66,19 +270,408 @@ impl Simulator {
 
             device_2.get_module().attach_link(dst.interface_id, end_2);
         }
+
+        for (medium, stations) in self.wireless.iter() {
+            for station in stations {
+                let spec = &station.interface;
+                let link_end =
+                    medium.add_station(spec.mac_address, station.position, station.range);
+                self.devices
+                    .get_mut(&spec.mac_address)
+                    .expect("Failed to find wireless device")
+                    .get_module()
+                    .attach_link(spec.interface_id, link_end);
+            }
+        }
     }
 
-    pub fn run(mut self) {
+    pub fn run(mut self) -> SimulatorHandle {
         self.create_network();
 
-        let mut handlers = Vec::new();
+        let start = Instant::now();
+        let mut threads = Vec::new();
+
+        let mut events = std::mem::take(&mut self.events);
+        events.sort_by_key(|(at, _)| *at);
+        let events: Vec<_> = events
+            .into_iter()
+            .map(|(at, event)| {
+                let handle = match event {
+                    SimEvent::LinkDown(link) | SimEvent::LinkUp(link) => self.link_handle(link),
+                };
+                (at, event, handle)
+            })
+            .collect();
 
-        for mut device in self.devices.into_values() {
-            handlers.push(thread::spawn(move || device.run()));
+        let wireless = self
+            .wireless
+            .into_iter()
+            .map(|(medium, stations)| {
+                let running = medium.clone();
+                threads.push(thread::spawn(move || running.run()));
+                let interfaces = stations.iter().map(|station| station.interface).collect();
+                (medium, interfaces)
+            })
+            .collect();
+
+        let (stop_events, stopped) = mpsc::channel();
+        threads.push(thread::spawn(move || {
+            for (at, event, handle) in events {
+                let left = (start + at).saturating_duration_since(Instant::now());
+                if stopped.recv_timeout(left) != Err(RecvTimeoutError::Timeout) {
+                    return;
+                }
+                log::info!("Applying event {event:?}");
+                handle.set_up(matches!(event, SimEvent::LinkUp(_)));
+            }
+        }));
+
+        let mut handle = SimulatorHandle {
+            devices: HashMap::new(),
+            links: self.links.into_iter().map(Some).collect(),
+            wireless,
+            threads,
+            stop_events,
+            tracer: self.tracer,
+            gate: Arc::new(Gate::default()),
+        };
+
+        for device in self.devices.into_values() {
+            handle.spawn_device(device);
+        }
+
+        handle
+    }
+}
+
+impl SimulatorHandle {
+    fn spawn_device(&mut self, mut device: Box<dyn Device + Send>) {
+        let mac = device.get_mac_address();
+        let module = device.get_module().handle();
+        if let Some(tracer) = &self.tracer {
+            module.set_tracer(tracer.clone(), mac);
         }
+        module.set_gate(Arc::clone(&self.gate));
+        let (kind, addresses, tables) = (device.kind(), device.addresses(), device.tables());
+        let thread = thread::spawn(move || device.run());
+        self.devices.insert(
+            mac,
+            RunningDevice {
+                module,
+                thread,
+                kind,
+                addresses,
+                tables,
+            },
+        );
+    }
 
-        for handler in handlers {
-            handler.join().unwrap();
+    fn module(&self, mac_address: MacAddress) -> &ModuleHandle {
+        &self
+            .devices
+            .get(&mac_address)
+            .unwrap_or_else(|| panic!("Failed to find device '{mac_address}'"))
+            .module
+    }
+
+    pub fn add_device<T>(&mut self, device: T)
+    where
+        T: Device + 'static,
+    {
+        let mac = device.get_mac_address();
+        assert!(
+            !self.devices.contains_key(&mac),
+            "Mac address '{mac}' already assigned!"
+        );
+        self.spawn_device(Box::new(device));
+    }
+
+    // Unplugs every link of the device and waits for it to return from run()
+    pub fn remove_device(&mut self, mac_address: MacAddress) {
+        let attached: Vec<_> = self
+            .links
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, link)| {
+                let link = link.as_ref()?;
+                (link.source.mac_address == mac_address || link.destin.mac_address == mac_address)
+                    .then_some(LinkId(idx))
+            })
+            .collect();
+
+        for link in attached {
+            self.remove_link(link);
+        }
+
+        let device = self
+            .devices
+            .remove(&mac_address)
+            .unwrap_or_else(|| panic!("Failed to find device '{mac_address}'"));
+        // its stations stay in the media, silent for good once their ends are dropped
+        for (_, interfaces) in &mut self.wireless {
+            for interface in interfaces.iter() {
+                if interface.mac_address == mac_address {
+                    drop(device.module.detach_link(interface.interface_id));
+                }
+            }
+            interfaces.retain(|interface| interface.mac_address != mac_address);
+        }
+        device.module.shutdown();
+        device.thread.join().unwrap();
+    }
+
+    pub fn add_link(&mut self, source: InterfaceSpec, destin: InterfaceSpec) -> LinkId {
+        let (end_1, end_2) = links::create_link();
+        let handle = end_1.handle();
+
+        self.module(source.mac_address)
+            .attach_link(source.interface_id, end_1);
+        self.module(destin.mac_address)
+            .attach_link(destin.interface_id, end_2);
+
+        self.links.push(Some(SimLink {
+            source,
+            destin,
+            ends: None,
+            handle,
+        }));
+        LinkId(self.links.len() - 1)
+    }
+
+    pub fn remove_link(&mut self, link: LinkId) {
+        let SimLink { source, destin, .. } = self
+            .links
+            .get_mut(link.0)
+            .and_then(Option::take)
+            .expect("Failed to find link");
+
+        // dropping the ends detaches them from the link
+        drop(
+            self.module(source.mac_address)
+                .detach_link(source.interface_id),
+        );
+        drop(
+            self.module(destin.mac_address)
+                .detach_link(destin.interface_id),
+        );
+    }
+
+    pub fn link_handle(&self, link: LinkId) -> LinkHandle {
+        self.links
+            .get(link.0)
+            .and_then(Option::as_ref)
+            .expect("Failed to find link")
+            .handle
+            .clone()
+    }
+
+    // The devices stop handling events until resumed, timers included. Scheduled link events
+    // still happen on time.
+    pub fn pause(&self) {
+        self.gate.pause();
+    }
+
+    pub fn resume(&self) {
+        self.gate.resume();
+    }
+
+    // while paused, lets one device handle one event
+    pub fn step(&self) {
+        self.gate.step();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.gate.is_paused()
+    }
+
+    // the tables the device keeps by name, as they are now
+    pub fn tables(&self, mac_address: MacAddress) -> Vec<(&'static str, String)> {
+        let device = self
+            .devices
+            .get(&mac_address)
+            .unwrap_or_else(|| panic!("Failed to find device '{mac_address}'"));
+        device
+            .tables
+            .iter()
+            .map(|(name, table)| (*name, table.lock().unwrap().to_string()))
+            .collect()
+    }
+
+    // the links are coloured by what they carried so far
+    pub fn topology(&self) -> Topology {
+        let devices = self
+            .devices
+            .iter()
+            .map(|(mac, device)| TopologyDevice {
+                mac: *mac,
+                kind: device.kind,
+                addresses: device.addresses.clone(),
+            })
+            .collect();
+        let links = self
+            .links
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, link)| Some(link.as_ref()?.topology(idx)))
+            .collect();
+        let media = self
+            .wireless
+            .iter()
+            .map(|(_, interfaces)| interfaces.iter().map(InterfaceSpec::pair).collect())
+            .collect();
+        Topology::sorted(devices, links, media)
+    }
+
+    pub fn export_dot(&self, options: &ExportOptions) -> String {
+        self.topology().to_dot(options)
+    }
+
+    pub fn export_mermaid(&self, options: &ExportOptions) -> String {
+        self.topology().to_mermaid(options)
+    }
+
+    // can be taken while the simulation runs
+    pub fn stats(&self) -> NetworkStats {
+        let devices = self
+            .devices
+            .iter()
+            .map(|(mac, device)| (*mac, device.module.stats()))
+            .collect();
+        self.network_stats(devices)
+    }
+
+    fn network_stats(&self, mut devices: Vec<(MacAddress, DeviceStats)>) -> NetworkStats {
+        devices.sort_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+
+        let links = self
+            .links
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, link)| {
+                let link = link.as_ref()?;
+                Some(LinkReport {
+                    link: LinkId(idx),
+                    source: link.source,
+                    destin: link.destin,
+                    stats: link.handle.stats(),
+                })
+            })
+            .collect();
+
+        let mut stations: Vec<_> = self
+            .wireless
+            .iter()
+            .flat_map(|(medium, interfaces)| {
+                interfaces.iter().filter_map(|interface| {
+                    let mac = interface.mac_address;
+                    Some((mac, medium.station_stats(mac)?))
+                })
+            })
+            .collect();
+        stations.sort_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+
+        NetworkStats {
+            devices,
+            links,
+            stations,
+        }
+    }
+
+    // the final counters of the devices, once their threads are done
+    fn join_devices(&mut self) -> Vec<(MacAddress, DeviceStats)> {
+        std::mem::take(&mut self.devices)
+            .into_iter()
+            .map(|(mac, device)| {
+                device.thread.join().unwrap();
+                (mac, device.module.stats())
+            })
+            .collect()
+    }
+
+    // Stops every device, returning what went through the network.
+    pub fn shutdown(mut self) -> NetworkStats {
+        for device in self.devices.values() {
+            device.module.shutdown();
+        }
+        self.wait()
+    }
+
+    // blocks until every device returns
+    pub fn wait(mut self) -> NetworkStats {
+        let devices = self.join_devices();
+        let stats = self.network_stats(devices);
+        if let Some(tracer) = &self.tracer {
+            tracer.flush();
+        }
+
+        // the media and the scheduled events have nothing left to do without the devices
+        for (medium, _) in &self.wireless {
+            medium.stop();
+        }
+        drop(self.stop_events);
+        for thread in self.threads {
+            thread.join().unwrap();
+        }
+        stats
+    }
+}
+
+impl Display for NetworkStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (mac, device) in &self.devices {
+            writeln!(
+                f,
+                "device {mac}: {} events queued, {} at most",
+                device.queued, device.max_queued
+            )?;
+            for (interface_id, interface) in device.interfaces.iter().enumerate() {
+                write!(
+                    f,
+                    "  interface {interface_id}: rx {} frames {} bytes, tx {} frames {} bytes",
+                    interface.rx_frames,
+                    interface.rx_bytes,
+                    interface.tx_frames,
+                    interface.tx_bytes
+                )?;
+                for (reason, count) in &interface.drops {
+                    write!(f, ", {count} dropped ({reason:?})")?;
+                }
+                if interface.parse_errors > 0 {
+                    write!(f, ", {} parse errors", interface.parse_errors)?;
+                }
+                writeln!(f)?;
+            }
+            for (counter, count) in &device.counters {
+                writeln!(f, "  {counter}: {count}")?;
+            }
+        }
+        for link in &self.links {
+            writeln!(
+                f,
+                "link {} ({}/{} - {}/{}): {} frames, {} lost, {} queued, {} at most",
+                link.link.index(),
+                link.source.mac_address,
+                link.source.interface_id,
+                link.destin.mac_address,
+                link.destin.interface_id,
+                link.stats.frames,
+                link.stats.lost,
+                link.stats.queued,
+                link.stats.max_queued
+            )?;
+        }
+        for (mac, station) in &self.stations {
+            writeln!(
+                f,
+                "station {mac}: {} sent, {} received, {} retries, {} dropped, {} collisions, {} queued at most",
+                station.sent,
+                station.received,
+                station.retries,
+                station.dropped,
+                station.collisions,
+                station.max_queued
+            )?;
         }
+        Ok(())
     }
 }
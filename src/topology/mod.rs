@@ -0,0 +1,298 @@
+use crate::{links::LinkStats, protocols::ethernet::MacAddress};
+use std::fmt::Write as _;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopologyDevice {
+    pub mac: MacAddress,
+    // e.g. "router", see Device::kind()
+    pub kind: &'static str,
+    pub addresses: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TopologyLink {
+    // the index of its LinkId
+    pub id: usize,
+    pub source: (MacAddress, u32),
+    pub destin: (MacAddress, u32),
+    pub up: bool,
+    pub stats: LinkStats,
+}
+
+// The devices and how they're connected, sorted by address (and links by id).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Topology {
+    pub devices: Vec<TopologyDevice>,
+    pub links: Vec<TopologyLink>,
+    // the stations of each wireless medium
+    pub media: Vec<Vec<(MacAddress, u32)>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExportOptions {
+    // colours the links by the frames they carried compared to the busiest one
+    pub utilization: bool,
+}
+
+// from green to red as links get busier
+fn utilization_colour(share: f64) -> &'static str {
+    if share < 1.0 / 3.0 {
+        "#2ca02c"
+    } else if share < 2.0 / 3.0 {
+        "#ff7f0e"
+    } else {
+        "#d62728"
+    }
+}
+
+fn node_id(mac: MacAddress) -> String {
+    let hex: Vec<_> = mac
+        .as_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("d{}", hex.join(""))
+}
+
+impl Topology {
+    pub fn sorted(
+        mut devices: Vec<TopologyDevice>,
+        links: Vec<TopologyLink>,
+        media: Vec<Vec<(MacAddress, u32)>>,
+    ) -> Self {
+        devices.sort_by(|a, b| a.mac.as_bytes().cmp(b.mac.as_bytes()));
+        Self {
+            devices,
+            links,
+            media,
+        }
+    }
+
+    // the share of the busiest link's frames each link carried
+    fn utilization(&self) -> Vec<f64> {
+        let busiest = self
+            .links
+            .iter()
+            .map(|link| link.stats.frames)
+            .max()
+            .unwrap_or_default();
+        self.links
+            .iter()
+            .map(|link| match busiest {
+                0 => 0.0,
+                busiest => link.stats.frames as f64 / busiest as f64,
+            })
+            .collect()
+    }
+
+    fn label_lines(device: &TopologyDevice) -> Vec<String> {
+        let mut lines = vec![device.kind.to_string(), device.mac.to_string()];
+        lines.extend(device.addresses.iter().cloned());
+        lines
+    }
+
+    // Graphviz, e.g. `dot -Tsvg`. Links are labelled with their interfaces at each end, links
+    // that are down are dashed.
+    pub fn to_dot(&self, options: &ExportOptions) -> String {
+        let mut dot = String::from("graph topology {\n    node [shape=box];\n");
+        for device in &self.devices {
+            let label = Self::label_lines(device).join("\\n");
+            writeln!(dot, "    {} [label=\"{label}\"];", node_id(device.mac)).unwrap();
+        }
+
+        for (link, share) in self.links.iter().zip(self.utilization()) {
+            let label = match options.utilization {
+                true => format!("link {}: {} frames", link.id, link.stats.frames),
+                false => format!("link {}", link.id),
+            };
+            let mut attributes = vec![
+                format!("label=\"{label}\""),
+                format!("taillabel=\"{}\"", link.source.1),
+                format!("headlabel=\"{}\"", link.destin.1),
+            ];
+            if !link.up {
+                attributes.push("style=dashed".into());
+            }
+            if options.utilization {
+                attributes.push(format!("color=\"{}\"", utilization_colour(share)));
+                attributes.push(format!("penwidth={:.1}", 1.0 + 3.0 * share));
+            }
+            writeln!(
+                dot,
+                "    {} -- {} [{}];",
+                node_id(link.source.0),
+                node_id(link.destin.0),
+                attributes.join(", ")
+            )
+            .unwrap();
+        }
+
+        for (idx, stations) in self.media.iter().enumerate() {
+            writeln!(
+                dot,
+                "    medium{idx} [shape=ellipse, label=\"wireless {idx}\"];"
+            )
+            .unwrap();
+            for (mac, interface_id) in stations {
+                writeln!(
+                    dot,
+                    "    {} -- medium{idx} [taillabel=\"{interface_id}\", style=dotted];",
+                    node_id(*mac)
+                )
+                .unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    // Mermaid, for markdown documentation. Same as the DOT one, down links are dotted.
+    pub fn to_mermaid(&self, options: &ExportOptions) -> String {
+        let mut mermaid = String::from("graph LR\n");
+        for device in &self.devices {
+            let label = Self::label_lines(device).join("<br/>");
+            writeln!(mermaid, "    {}[\"{label}\"]", node_id(device.mac)).unwrap();
+        }
+
+        // mermaid styles the links by the order they were declared in
+        let mut styles = Vec::new();
+        for (idx, (link, share)) in self.links.iter().zip(self.utilization()).enumerate() {
+            let mut label = format!("{} - {}", link.source.1, link.destin.1);
+            if options.utilization {
+                write!(label, ": {} frames", link.stats.frames).unwrap();
+                styles.push(format!(
+                    "    linkStyle {idx} stroke:{},stroke-width:{:.0}px",
+                    utilization_colour(share),
+                    1.0 + 3.0 * share
+                ));
+            }
+            let arrow = if link.up { "---" } else { "-.-" };
+            writeln!(
+                mermaid,
+                "    {} {arrow}|\"{label}\"| {}",
+                node_id(link.source.0),
+                node_id(link.destin.0)
+            )
+            .unwrap();
+        }
+
+        for (idx, stations) in self.media.iter().enumerate() {
+            writeln!(mermaid, "    medium{idx}((\"wireless {idx}\"))").unwrap();
+            for (mac, interface_id) in stations {
+                writeln!(
+                    mermaid,
+                    "    {} -.-|\"{interface_id}\"| medium{idx}",
+                    node_id(*mac)
+                )
+                .unwrap();
+            }
+        }
+        for style in styles {
+            writeln!(mermaid, "{style}").unwrap();
+        }
+        mermaid
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ExportOptions, Topology, TopologyLink};
+    use crate::{
+        devices::{
+            host::{AddressConfig, Host},
+            router::Router,
+            switch::Layer2Switch,
+        },
+        links::LinkStats,
+        protocols::ethernet::MacAddress,
+        simulator::{InterfaceSpec, Simulator},
+    };
+    use std::net::Ipv4Addr;
+
+    // host -- switch -- router
+    fn simulator() -> Simulator {
+        let mut sim = Simulator::new();
+        sim.add_device(Host::new(
+            MacAddress::new([1; 6]),
+            AddressConfig::Static {
+                address: Ipv4Addr::new(10, 0, 0, 2),
+                prefix_len: 24,
+                gateway: None,
+            },
+        ));
+        sim.add_device(Layer2Switch::new(MacAddress::new([2; 6]), 2));
+        let mut router = Router::new(MacAddress::new([3; 6]), 2);
+        router.set_interface_address(0, Ipv4Addr::new(10, 0, 0, 1), 24);
+        sim.add_device(router);
+        sim.add_link(
+            InterfaceSpec::new(MacAddress::new([1; 6]), 0),
+            InterfaceSpec::new(MacAddress::new([2; 6]), 0),
+        );
+        sim.add_link(
+            InterfaceSpec::new(MacAddress::new([2; 6]), 1),
+            InterfaceSpec::new(MacAddress::new([3; 6]), 0),
+        );
+        sim
+    }
+
+    #[test]
+    fn dot_export() {
+        let dot = simulator().export_dot(&ExportOptions::default());
+        let lines: Vec<_> = dot.lines().collect();
+
+        assert_eq!(lines[0], "graph topology {");
+        assert_eq!(
+            lines[2],
+            "    d010101010101 [label=\"host\\n01:01:01:01:01:01\\n10.0.0.2/24\"];"
+        );
+        assert_eq!(
+            lines[3],
+            "    d020202020202 [label=\"switch\\n02:02:02:02:02:02\"];"
+        );
+        assert_eq!(
+            lines[4],
+            "    d030303030303 [label=\"router\\n03:03:03:03:03:03\\n0: 10.0.0.1/24\"];"
+        );
+        assert_eq!(
+            lines[6],
+            "    d020202020202 -- d030303030303 [label=\"link 1\", taillabel=\"1\", headlabel=\"0\"];"
+        );
+        assert_eq!(lines.last(), Some(&"}"));
+    }
+
+    #[test]
+    fn mermaid_export() {
+        let mermaid = simulator().export_mermaid(&ExportOptions::default());
+        assert!(mermaid.starts_with("graph LR\n"));
+        assert!(mermaid.contains("    d020202020202[\"switch<br/>02:02:02:02:02:02\"]\n"));
+        assert!(mermaid.contains("    d010101010101 ---|\"0 - 0\"| d020202020202\n"));
+    }
+
+    #[test]
+    fn colours_by_utilization() {
+        let link = |id, frames, up| TopologyLink {
+            id,
+            source: (MacAddress::new([1; 6]), id as u32),
+            destin: (MacAddress::new([2; 6]), id as u32),
+            up,
+            stats: LinkStats {
+                frames,
+                ..Default::default()
+            },
+        };
+        let topology = Topology {
+            links: vec![link(0, 100, true), link(1, 10, false)],
+            ..Default::default()
+        };
+        let options = ExportOptions { utilization: true };
+
+        let dot = topology.to_dot(&options);
+        assert!(dot.contains("[label=\"link 0: 100 frames\", taillabel=\"0\", headlabel=\"0\", color=\"#d62728\", penwidth=4.0];"));
+        assert!(dot.contains("style=dashed, color=\"#2ca02c\", penwidth=1.3];"));
+
+        let mermaid = topology.to_mermaid(&options);
+        assert!(mermaid.contains("-.-|\"1 - 1: 10 frames\"|"));
+        assert!(mermaid.contains("    linkStyle 0 stroke:#d62728,stroke-width:4px\n"));
+        assert!(mermaid.contains("    linkStyle 1 stroke:#2ca02c,stroke-width:1px\n"));
+    }
+}
@@ -0,0 +1,671 @@
+use crate::{links::LinkData, protocols::ethernet::MacAddress};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::{self, Display, Write as _},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+// how many transmissions are remembered to find where the frames received came from, their
+// buffers are kept until they're forgotten
+const SENT_FRAMES: usize = 1 << 12;
+// how many events are kept for recent()
+const RECENT_EVENTS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEvent {
+    Receive,
+    Transmit,
+    Drop,
+    // the decisions of forwarding devices, the transmissions follow
+    Forward,
+    Flood,
+}
+
+impl TraceEvent {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Receive => "rx",
+            Self::Transmit => "tx",
+            Self::Drop => "drop",
+            Self::Forward => "forward",
+            Self::Flood => "flood",
+        }
+    }
+}
+
+// A device a frame (or a copy of it) went through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HopId(usize);
+
+// Drops and decisions of the devices about the frame they're handling.
+pub struct TraceRecord<'a> {
+    pub device: MacAddress,
+    pub interface: Option<u32>,
+    pub event: TraceEvent,
+    // None if the device isn't handling a frame
+    pub frame: Option<u64>,
+    pub length: Option<usize>,
+    pub reason: Option<&'a str>,
+    // the hop of the frame at the device, if paths are collected
+    pub hop: Option<HopId>,
+}
+
+// Where a frame went from where it was injected, with one branch for every copy of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathHop {
+    pub device: MacAddress,
+    // None where the frame was injected
+    pub ingress: Option<u32>,
+    // since the tracer was created
+    pub time: Duration,
+    // where copies of it left and when
+    pub egress: Vec<(u32, Duration)>,
+    // what the device decided about it, e.g. "flood (unknown unicast)"
+    pub decision: Option<String>,
+    pub dropped: Option<String>,
+    pub next: Vec<PathHop>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FramePath {
+    pub frame: u64,
+    pub root: PathHop,
+}
+
+struct Hop {
+    frame: u64,
+    parent: Option<HopId>,
+    device: MacAddress,
+    ingress: Option<u32>,
+    time: Duration,
+    egress: Vec<(u32, Duration)>,
+    decision: Option<String>,
+    dropped: Option<String>,
+}
+
+impl Hop {
+    fn new(
+        frame: u64,
+        parent: Option<HopId>,
+        device: MacAddress,
+        ingress: Option<u32>,
+        time: Duration,
+    ) -> Self {
+        Self {
+            frame,
+            parent,
+            device,
+            ingress,
+            time,
+            egress: Vec::new(),
+            decision: None,
+            dropped: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Paths {
+    hops: Vec<Hop>,
+}
+
+impl Paths {
+    fn add(&mut self, hop: Hop) -> HopId {
+        self.hops.push(hop);
+        HopId(self.hops.len() - 1)
+    }
+
+    fn tree(&self, hop: HopId, children: &HashMap<HopId, Vec<HopId>>) -> PathHop {
+        let data = &self.hops[hop.0];
+        PathHop {
+            device: data.device,
+            ingress: data.ingress,
+            time: data.time,
+            egress: data.egress.clone(),
+            decision: data.decision.clone(),
+            dropped: data.dropped.clone(),
+            next: children
+                .get(&hop)
+                .into_iter()
+                .flatten()
+                .map(|child| self.tree(*child, children))
+                .collect(),
+        }
+    }
+}
+
+// The links hand the buffer of a frame as it is to the other end (and every copy of a flooded
+// frame shares one), so while a frame is on its way its buffer tells which frame it is.
+fn buffer_key(data: &LinkData) -> usize {
+    Arc::as_ptr(data) as *const u8 as usize
+}
+
+struct Sent {
+    frame: u64,
+    hop: Option<HopId>,
+    // keeps the address of the buffer from being reused while it's remembered
+    buffer: Weak<[u8]>,
+}
+
+#[derive(Default)]
+struct State {
+    // the last id handed out
+    frames: u64,
+    // the frames on their way, by buffer
+    sent: HashMap<usize, Sent>,
+    sent_order: VecDeque<usize>,
+    // the frames waiting to be handled by the devices
+    received: HashMap<(MacAddress, usize), (u64, Option<HopId>)>,
+    // only if they're collected
+    paths: Option<Paths>,
+}
+
+impl State {
+    // for frames coming out of nowhere, i.e. sent by a device that wasn't forwarding anything
+    // or received from outside of the traced devices
+    fn inject(
+        &mut self,
+        device: MacAddress,
+        ingress: Option<u32>,
+        time: Duration,
+    ) -> (u64, Option<HopId>) {
+        self.frames += 1;
+        let frame = self.frames;
+        let hop = self
+            .paths
+            .as_mut()
+            .map(|paths| paths.add(Hop::new(frame, None, device, ingress, time)));
+        (frame, hop)
+    }
+
+    fn receive(
+        &mut self,
+        device: MacAddress,
+        interface: u32,
+        data: &LinkData,
+        time: Duration,
+    ) -> (u64, Option<HopId>) {
+        let received = match self.sent.get(&buffer_key(data)) {
+            Some(sent) => {
+                let hop = match (&mut self.paths, sent.hop) {
+                    (Some(paths), Some(parent)) => Some(paths.add(Hop::new(
+                        sent.frame,
+                        Some(parent),
+                        device,
+                        Some(interface),
+                        time,
+                    ))),
+                    _ => None,
+                };
+                (sent.frame, hop)
+            }
+            None => self.inject(device, Some(interface), time),
+        };
+        self.received.insert((device, buffer_key(data)), received);
+        received
+    }
+
+    fn transmit(
+        &mut self,
+        device: MacAddress,
+        interface: u32,
+        data: &LinkData,
+        forwarded: Option<(u64, Option<HopId>)>,
+        time: Duration,
+    ) -> (u64, Option<HopId>) {
+        let (frame, hop) = forwarded.unwrap_or_else(|| self.inject(device, None, time));
+        if let (Some(paths), Some(hop)) = (&mut self.paths, hop) {
+            paths.hops[hop.0].egress.push((interface, time));
+        }
+
+        let key = buffer_key(data);
+        let sent = Sent {
+            frame,
+            hop,
+            buffer: Arc::downgrade(data),
+        };
+        if self.sent.insert(key, sent).is_none() {
+            self.sent_order.push_back(key);
+        }
+        if self.sent.len() > SENT_FRAMES {
+            // the buffers nothing holds anymore were received (or lost) already
+            let sent = &mut self.sent;
+            sent.retain(|_, sent| sent.buffer.strong_count() > 0);
+            self.sent_order.retain(|key| sent.contains_key(key));
+            while self.sent.len() > SENT_FRAMES / 2 {
+                let oldest = self.sent_order.pop_front().unwrap();
+                self.sent.remove(&oldest);
+            }
+        }
+        (frame, hop)
+    }
+}
+
+// Writes what happens to frames as JSON lines, e.g.
+//   {"time":0.000153,"device":"11:11:11:11:11:11","interface":0,"event":"rx","frame":3,
+//    "length":60,"reason":null}
+// Times are in seconds since the tracer was created. Frames get an id where they're injected,
+// which they keep while they're forwarded. With collect_paths() it also keeps the path of every
+// traced frame, see paths().
+#[derive(Clone)]
+pub struct Tracer {
+    start: Instant,
+    output: Arc<Mutex<Box<dyn Write + Send>>>,
+    state: Arc<Mutex<State>>,
+    recent: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl Tracer {
+    pub fn new(output: impl Write + Send + 'static) -> Self {
+        Self {
+            start: Instant::now(),
+            output: Arc::new(Mutex::new(Box::new(output))),
+            state: Arc::new(Mutex::new(State::default())),
+            recent: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    // every hop of every frame is kept until the tracer is dropped
+    pub fn collect_paths(self) -> Self {
+        self.state.lock().unwrap().paths = Some(Paths::default());
+        self
+    }
+
+    // a frame arriving at a device, before it's queued
+    pub fn receive(&self, device: MacAddress, interface: u32, data: &LinkData) {
+        let time = self.start.elapsed();
+        let (frame, hop) = self
+            .state
+            .lock()
+            .unwrap()
+            .receive(device, interface, data, time);
+        self.write(
+            time,
+            &TraceRecord {
+                device,
+                interface: Some(interface),
+                event: TraceEvent::Receive,
+                frame: Some(frame),
+                length: Some(data.len()),
+                reason: None,
+                hop,
+            },
+        );
+    }
+
+    // Called once the device takes the frame it received off its queue, returns its id and hop.
+    pub fn handling(&self, device: MacAddress, data: &LinkData) -> Option<(u64, Option<HopId>)> {
+        let mut state = self.state.lock().unwrap();
+        state.received.remove(&(device, buffer_key(data)))
+    }
+
+    // `forwarded` is the frame (and hop) the device is forwarding, if any. Returns the ones sent.
+    pub fn transmit(
+        &self,
+        device: MacAddress,
+        interface: u32,
+        data: &LinkData,
+        forwarded: Option<(u64, Option<HopId>)>,
+    ) -> (u64, Option<HopId>) {
+        let time = self.start.elapsed();
+        let (frame, hop) = self
+            .state
+            .lock()
+            .unwrap()
+            .transmit(device, interface, data, forwarded, time);
+        self.write(
+            time,
+            &TraceRecord {
+                device,
+                interface: Some(interface),
+                event: TraceEvent::Transmit,
+                frame: Some(frame),
+                length: Some(data.len()),
+                reason: None,
+                hop,
+            },
+        );
+        (frame, hop)
+    }
+
+    pub fn record(&self, record: TraceRecord) {
+        let time = self.start.elapsed();
+        if let (Some(paths), Some(hop)) = (&mut self.state.lock().unwrap().paths, record.hop) {
+            let reason = record.reason.unwrap_or_default();
+            let hop = &mut paths.hops[hop.0];
+            match record.event {
+                TraceEvent::Drop => hop.dropped = Some(reason.into()),
+                event => hop.decision = Some(format!("{} ({reason})", event.name())),
+            }
+        }
+        self.write(time, &record);
+    }
+
+    fn write(&self, time: Duration, record: &TraceRecord) {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "null".into());
+        let line = format!(
+            "{{\"time\":{:.6},\"device\":\"{}\",\"interface\":{},\"event\":\"{}\",\"frame\":{},\"length\":{},\"reason\":{}}}\n",
+            time.as_secs_f64(),
+            record.device,
+            optional(record.interface.map(|interface| interface.to_string())),
+            record.event.name(),
+            optional(record.frame.map(|frame| frame.to_string())),
+            optional(record.length.map(|length| length.to_string())),
+            optional(record.reason.map(|reason| format!("\"{}\"", escape(reason)))),
+        );
+        // a trace that can't be written shouldn't stop the simulation
+        let _ = self.output.lock().unwrap().write_all(line.as_bytes());
+
+        let mut summary = format!(
+            "{:.6} {} {:<7} {:>2}",
+            time.as_secs_f64(),
+            record.device,
+            record.event.name(),
+            record
+                .interface
+                .map_or("-".to_string(), |interface| interface.to_string()),
+        );
+        if let Some(frame) = record.frame {
+            write!(summary, " frame {frame}").unwrap();
+        }
+        if let Some(length) = record.length {
+            write!(summary, " {length} bytes").unwrap();
+        }
+        if let Some(reason) = record.reason {
+            write!(summary, " {reason}").unwrap();
+        }
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == RECENT_EVENTS {
+            recent.pop_front();
+        }
+        recent.push_back(summary);
+    }
+
+    // the last events as one line each, oldest first
+    pub fn recent(&self) -> Vec<String> {
+        self.recent.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn path(&self, frame: u64) -> Option<FramePath> {
+        self.paths().into_iter().find(|path| path.frame == frame)
+    }
+
+    // every frame traced so far in the order they were injected, if paths are collected
+    pub fn paths(&self) -> Vec<FramePath> {
+        let state = self.state.lock().unwrap();
+        let Some(paths) = &state.paths else {
+            return Vec::new();
+        };
+        let mut children: HashMap<HopId, Vec<HopId>> = HashMap::new();
+        for (idx, hop) in paths.hops.iter().enumerate() {
+            if let Some(parent) = hop.parent {
+                children.entry(parent).or_default().push(HopId(idx));
+            }
+        }
+        paths
+            .hops
+            .iter()
+            .enumerate()
+            .filter(|(_, hop)| hop.parent.is_none())
+            .map(|(idx, hop)| FramePath {
+                frame: hop.frame,
+                root: paths.tree(HopId(idx), &children),
+            })
+            .collect()
+    }
+
+    pub fn flush(&self) {
+        let _ = self.output.lock().unwrap().flush();
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+impl Display for PathHop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.6} {}", self.time.as_secs_f64(), self.device)?;
+        if let Some(ingress) = self.ingress {
+            write!(f, " in {ingress}")?;
+        }
+        if !self.egress.is_empty() {
+            let egress: Vec<_> = self.egress.iter().map(|(id, _)| id.to_string()).collect();
+            write!(f, " out {}", egress.join(","))?;
+        }
+        if let Some(decision) = &self.decision {
+            write!(f, ", {decision}")?;
+        }
+        if let Some(dropped) = &self.dropped {
+            write!(f, ", dropped ({dropped})")?;
+        }
+        Ok(())
+    }
+}
+
+// As a tree, the copies of flooded frames branch out:
+//   frame 1
+//   0.000012 22:22:22:22:22:22 out 0
+//   └─ 0.000020 11:11:11:11:11:11 in 0 out 1,2, flood (broadcast)
+//      ├─ 0.000031 33:33:33:33:33:33 in 0
+//      └─ 0.000035 44:44:44:44:44:44 in 0
+impl Display for FramePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn branches(f: &mut fmt::Formatter<'_>, hop: &PathHop, indent: &str) -> fmt::Result {
+            for (idx, next) in hop.next.iter().enumerate() {
+                let last = idx + 1 == hop.next.len();
+                writeln!(f, "{indent}{}{next}", if last { "└─ " } else { "├─ " })?;
+                let indent = format!("{indent}{}", if last { "   " } else { "│  " });
+                branches(f, next, &indent)?;
+            }
+            Ok(())
+        }
+
+        writeln!(f, "frame {}", self.frame)?;
+        writeln!(f, "{}", self.root)?;
+        branches(f, &self.root, "")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PathHop, Tracer};
+    use crate::{
+        devices::{switch::Layer2Switch, Device, ProgrammableDevice},
+        links,
+        protocols::{
+            ethernet::{self, EthernetFrame, FrameProtocol, MacAddress},
+            Packet,
+        },
+        simulator::{InterfaceSpec, Simulator},
+    };
+    use std::{
+        io::{self, Write},
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // the fields of the JSON lines, without the times
+    fn events(output: &Output) -> Vec<String> {
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        output
+            .lines()
+            .map(|line| line.split_once(",\"device\"").unwrap().1.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn traces_a_switch() {
+        let switch_mac = MacAddress::new([1; 6]);
+        let mut switch = Layer2Switch::new(switch_mac, 3);
+        let output = Output::default();
+        let tracer = Tracer::new(output.clone());
+        switch
+            .get_module()
+            .handle()
+            .set_tracer(tracer.clone(), switch_mac);
+
+        let peers: Vec<_> = (0..2)
+            .map(|interface_id| {
+                let (end, peer) = links::create_link();
+                peer.attach_receiver(|_| {}).unwrap();
+                switch.get_module().attach_link(interface_id, end);
+                peer
+            })
+            .collect();
+        let handle = switch.get_module().handle();
+
+        let frame = |source: u8, destin: u8| EthernetFrame {
+            source: MacAddress::new([source; 6]),
+            destin: MacAddress::new([destin; 6]),
+            protocol: FrameProtocol::Ipv4,
+            data: Box::new([0; 46]),
+        };
+        // Flooded, then forwarded to where 2 was learned, then dropped for going back there, then
+        // the first one again, now that 4 was learned. Each gets an id as the peers aren't traced.
+        let frames = [frame(2, 4), frame(4, 2), frame(2, 2), frame(2, 4)];
+        peers[0].send(frames[0].to_bytes()).unwrap();
+        peers[1].send(frames[1].to_bytes()).unwrap();
+        peers[0].send(frames[2].to_bytes()).unwrap();
+        peers[0].send(frames[3].to_bytes()).unwrap();
+        // handled once everything was received, to keep the order of the events
+        handle.shutdown();
+        switch.run();
+
+        let length = frames[0].to_bytes().len().to_string();
+        let event = |interface: &str, event: &str, frame: u64, length: &str, reason: &str| {
+            format!(
+                ":\"{switch_mac}\",\"interface\":{interface},\"event\":\"{event}\",\"frame\":{frame},\"length\":{length},\"reason\":{reason}}}"
+            )
+        };
+        assert_eq!(
+            events(&output),
+            [
+                event("0", "rx", 1, &length, "null"),
+                event("1", "rx", 2, &length, "null"),
+                event("0", "rx", 3, &length, "null"),
+                event("0", "rx", 4, &length, "null"),
+                event("null", "flood", 1, "null", "\"unknown unicast\""),
+                event("1", "tx", 1, &length, "null"),
+                event("0", "forward", 2, "null", "\"learned\""),
+                event("0", "tx", 2, &length, "null"),
+                event("0", "drop", 3, "null", "\"SameInterface\""),
+                // the same as the first one, but another frame
+                event("1", "forward", 4, "null", "\"learned\""),
+                event("1", "tx", 4, &length, "null"),
+            ]
+        );
+        // they weren't asked for
+        assert!(tracer.paths().is_empty());
+    }
+
+    // the devices and interfaces of a path, without the times
+    fn hops(hop: &PathHop) -> Vec<(u8, Option<u32>, Vec<u32>)> {
+        let egress = hop.egress.iter().map(|(id, _)| *id).collect();
+        let mut path = vec![(hop.device.as_bytes()[0], hop.ingress, egress)];
+        path.extend(hop.next.iter().flat_map(hops));
+        path
+    }
+
+    #[test]
+    fn follows_flooded_copies() {
+        // a -- switch 1 -- switch 2 -- b
+        //                           \-- c
+        let mac = |byte| MacAddress::new([byte; 6]);
+        let tracer = Tracer::new(io::sink()).collect_paths();
+        let mut sim = Simulator::new();
+        sim.set_tracer(tracer.clone());
+
+        let frame = EthernetFrame {
+            source: mac(0xa),
+            destin: ethernet::ETHERNET_BROADCAST_MAC_ADDR,
+            protocol: FrameProtocol::Ipv4,
+            data: Box::new([0; 46]),
+        };
+        sim.add_device(ProgrammableDevice::new(mac(0xa), 1, move |_, module| {
+            module
+                .get_interface(0)
+                .unwrap()
+                .send(frame.to_bytes())
+                .unwrap();
+            while module.wait_for_msg().is_some() {}
+        }));
+        for byte in [0xb, 0xc] {
+            sim.add_device(ProgrammableDevice::new(mac(byte), 1, |_, module| {
+                while module.wait_for_msg().is_some() {}
+            }));
+        }
+        sim.add_device(Layer2Switch::new(mac(1), 2));
+        sim.add_device(Layer2Switch::new(mac(2), 3));
+        for (source, destin) in [
+            ((0xa, 0), (1, 0)),
+            ((1, 1), (2, 0)),
+            ((2, 1), (0xb, 0)),
+            ((2, 2), (0xc, 0)),
+        ] {
+            sim.add_link(
+                InterfaceSpec::new(mac(source.0), source.1),
+                InterfaceSpec::new(mac(destin.0), destin.1),
+            );
+        }
+        let network = sim.run();
+        thread::sleep(Duration::from_millis(100));
+        network.shutdown();
+
+        let paths = tracer.paths();
+        assert_eq!(paths.len(), 1);
+        let path = &paths[0];
+        assert_eq!(
+            hops(&path.root),
+            [
+                (0xa, None, vec![0]),
+                (1, Some(0), vec![1]),
+                (2, Some(0), vec![1, 2]),
+                (0xb, Some(0), vec![]),
+                (0xc, Some(0), vec![]),
+            ]
+        );
+        assert_eq!(
+            path.root.next[0].decision.as_deref(),
+            Some("flood (broadcast)")
+        );
+        assert_eq!(tracer.path(path.frame), Some(path.clone()));
+
+        let tree: Vec<_> = path.to_string().lines().map(str::to_string).collect();
+        assert_eq!(tree[0], "frame 1");
+        assert!(tree[3].starts_with("   └─ ") && tree[3].contains("out 1,2, flood"));
+        assert!(tree[4].starts_with("      ├─ ") && tree[5].starts_with("      └─ "));
+        assert!(tree[5].ends_with("0C:0C:0C:0C:0C:0C in 0"), "{}", tree[5]);
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+// sequence number and send timestamp at the start of every generated datagram
+pub const PROBE_HEADER_SIZE: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    // constant bit rate, rates are in bits per second of UDP payload
+    Cbr {
+        rate: u64,
+        packet_size: usize,
+    },
+    // exponentially distributed gaps averaging the rate
+    Poisson {
+        rate: u64,
+        packet_size: usize,
+    },
+    // Sends at a constant rate during the on periods and nothing during the off ones, both Pareto
+    // distributed with the given means. The shape has to be above 1, the closer to it the heavier
+    // the tail.
+    OnOff {
+        rate: u64,
+        packet_size: usize,
+        mean_on: Duration,
+        mean_off: Duration,
+        shape: f64,
+    },
+}
+
+impl Pattern {
+    pub fn packet_size(&self) -> usize {
+        let (Self::Cbr { packet_size, .. }
+        | Self::Poisson { packet_size, .. }
+        | Self::OnOff { packet_size, .. }) = self;
+        (*packet_size).max(PROBE_HEADER_SIZE)
+    }
+
+    fn rate(&self) -> u64 {
+        let (Self::Cbr { rate, .. } | Self::Poisson { rate, .. } | Self::OnOff { rate, .. }) = self;
+        *rate
+    }
+}
+
+// When the packets of a pattern are sent, reproducible for a given seed.
+pub struct Generator {
+    pattern: Pattern,
+    rng: u64,
+    // what is left of the current on period
+    on_left: Duration,
+}
+
+impl Generator {
+    pub fn new(pattern: Pattern, seed: u64) -> Self {
+        assert!(pattern.rate() > 0, "traffic needs a rate");
+        let mut generator = Self {
+            pattern,
+            // spread the bits of small seeds, xorshift starts slowly from them
+            rng: seed.wrapping_mul(0x9E37_79B9_7F4A_7C15).max(1),
+            on_left: Duration::ZERO,
+        };
+        if let Pattern::OnOff { mean_on, shape, .. } = generator.pattern {
+            generator.on_left = generator.pareto(mean_on, shape);
+        }
+        generator
+    }
+
+    pub fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+
+    // xorshift64, uniform in (0, 1]
+    fn uniform(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        ((self.rng >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    fn pareto(&mut self, mean: Duration, shape: f64) -> Duration {
+        let scale = mean.as_secs_f64() * (shape - 1.0) / shape;
+        Duration::from_secs_f64(scale / self.uniform().powf(1.0 / shape))
+    }
+
+    // at the rate of the pattern
+    fn packet_interval(&self) -> Duration {
+        let bits = self.pattern.packet_size() as f64 * 8.0;
+        Duration::from_secs_f64(bits / self.pattern.rate() as f64)
+    }
+
+    // the time between the last packet and the next one
+    pub fn next_gap(&mut self) -> Duration {
+        let interval = self.packet_interval();
+        match self.pattern {
+            Pattern::Cbr { .. } => interval,
+            Pattern::Poisson { .. } => interval.mul_f64(-self.uniform().ln()),
+            Pattern::OnOff {
+                mean_on,
+                mean_off,
+                shape,
+                ..
+            } => {
+                if interval <= self.on_left {
+                    self.on_left -= interval;
+                    return interval;
+                }
+                // the next packet starts the next on period
+                let gap = self.on_left + self.pareto(mean_off, shape);
+                self.on_left = self.pareto(mean_on, shape);
+                gap
+            }
+        }
+    }
+}
+
+// Generated traffic carries its send time, which has to mean the same to every device: they all
+// run in this process and count from the same instant.
+fn epoch() -> Instant {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    *EPOCH.get_or_init(Instant::now)
+}
+
+// fills the packet with its header, followed by zeros
+pub fn encode_probe(sequence: u64, sent_at: Instant, packet_size: usize) -> Box<[u8]> {
+    let mut data = vec![0; packet_size.max(PROBE_HEADER_SIZE)];
+    let timestamp = sent_at.saturating_duration_since(epoch()).as_nanos() as u64;
+    data[..8].copy_from_slice(&sequence.to_be_bytes());
+    data[8..16].copy_from_slice(&timestamp.to_be_bytes());
+    data.into()
+}
+
+// the sequence number and send time of a generated packet
+pub fn decode_probe(data: &[u8]) -> Option<(u64, Instant)> {
+    let sequence = u64::from_be_bytes(data.get(..8)?.try_into().unwrap());
+    let timestamp = u64::from_be_bytes(data.get(8..16)?.try_into().unwrap());
+    Some((sequence, epoch() + Duration::from_nanos(timestamp)))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceStats {
+    pub packets: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SinkStats {
+    pub packets: u64,
+    pub bytes: u64,
+    // the packets that were skipped in the sequence, they may still arrive out of order
+    pub lost: u64,
+    pub first_at: Option<Instant>,
+    pub last_at: Option<Instant>,
+    pub min_latency: Option<Duration>,
+    pub max_latency: Option<Duration>,
+    latency_sum: Duration,
+    // interarrival jitter (https://www.rfc-editor.org/rfc/rfc3550#appendix-A.8), in seconds
+    jitter: f64,
+    last_transit: Option<Duration>,
+    next_sequence: u64,
+}
+
+impl SinkStats {
+    // records data that isn't generated traffic, like a bulk transfer
+    pub fn record_bytes(&mut self, len: usize, now: Instant) {
+        self.bytes += len as u64;
+        self.first_at.get_or_insert(now);
+        self.last_at = Some(now);
+    }
+
+    // records a generated packet, returns false if it isn't one
+    pub fn record(&mut self, data: &[u8], now: Instant) -> bool {
+        let Some((sequence, sent_at)) = decode_probe(data) else {
+            return false;
+        };
+        self.packets += 1;
+        self.record_bytes(data.len(), now);
+
+        if sequence >= self.next_sequence {
+            self.lost += sequence - self.next_sequence;
+            self.next_sequence = sequence + 1;
+        } else {
+            // a late one that was counted as lost
+            self.lost = self.lost.saturating_sub(1);
+        }
+
+        let transit = now.saturating_duration_since(sent_at);
+        self.latency_sum += transit;
+        self.min_latency = Some(self.min_latency.map_or(transit, |min| min.min(transit)));
+        self.max_latency = Some(self.max_latency.map_or(transit, |max| max.max(transit)));
+        if let Some(last) = self.last_transit {
+            let difference = transit.abs_diff(last).as_secs_f64();
+            self.jitter += (difference - self.jitter) / 16.0;
+        }
+        self.last_transit = Some(transit);
+        true
+    }
+
+    pub fn mean_latency(&self) -> Option<Duration> {
+        (self.packets > 0).then(|| self.latency_sum / self.packets as u32)
+    }
+
+    pub fn jitter(&self) -> Duration {
+        Duration::from_secs_f64(self.jitter)
+    }
+
+    // in bits per second, between the first and the last arrival
+    pub fn throughput(&self) -> f64 {
+        match (self.first_at, self.last_at) {
+            (Some(first), Some(last)) if last > first => {
+                self.bytes as f64 * 8.0 / (last - first).as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{encode_probe, epoch, Generator, Pattern, SinkStats};
+    use std::time::Duration;
+
+    // the rate that comes out of a few thousand packets
+    fn measured_rate(pattern: Pattern) -> f64 {
+        let packet_size = pattern.packet_size();
+        let mut generator = Generator::new(pattern, 42);
+        let elapsed: Duration = (0..5000).map(|_| generator.next_gap()).sum();
+        5000.0 * packet_size as f64 * 8.0 / elapsed.as_secs_f64()
+    }
+
+    #[test]
+    fn generated_rates() {
+        let cbr = Pattern::Cbr {
+            rate: 1_000_000,
+            packet_size: 1000,
+        };
+        let mut generator = Generator::new(cbr.clone(), 1);
+        assert_eq!(generator.next_gap(), Duration::from_millis(8));
+        assert_eq!(measured_rate(cbr), 1_000_000.0);
+
+        let poisson = Pattern::Poisson {
+            rate: 1_000_000,
+            packet_size: 1000,
+        };
+        let rate = measured_rate(poisson);
+        assert!((950_000.0..1_050_000.0).contains(&rate), "{rate}");
+
+        // on half of the time, on average
+        let on_off = Pattern::OnOff {
+            rate: 1_000_000,
+            packet_size: 1000,
+            mean_on: Duration::from_millis(100),
+            mean_off: Duration::from_millis(100),
+            shape: 2.5,
+        };
+        let rate = measured_rate(on_off);
+        assert!((350_000.0..650_000.0).contains(&rate), "{rate}");
+    }
+
+    #[test]
+    fn sink_statistics() {
+        let mut stats = SinkStats::default();
+        let start = epoch();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        // 1 ms of latency, then 3 ms, and the third packet is missing
+        assert!(stats.record(&encode_probe(0, at(0), 100), at(1)));
+        assert!(stats.record(&encode_probe(1, at(10), 100), at(13)));
+        assert!(stats.record(&encode_probe(3, at(30), 100), at(31)));
+        assert!(!stats.record(&[1, 2, 3], at(40)));
+
+        assert_eq!(stats.packets, 3);
+        assert_eq!(stats.bytes, 300);
+        assert_eq!(stats.lost, 1);
+        assert_eq!(stats.min_latency, Some(Duration::from_millis(1)));
+        assert_eq!(stats.max_latency, Some(Duration::from_millis(3)));
+        assert_eq!(stats.mean_latency(), Some(Duration::from_nanos(1_666_666)));
+        let jitter = stats.jitter().as_secs_f64();
+        assert!((jitter - 0.000_242).abs() < 0.000_001, "{jitter}");
+        assert_eq!(stats.throughput(), 300.0 * 8.0 / 0.030);
+
+        // it was late after all
+        stats.record(&encode_probe(2, at(20), 100), at(35));
+        assert_eq!(stats.lost, 0);
+    }
+}
@@ -0,0 +1,591 @@
+use crate::protocols::tcp::{TcpFlags, TcpSegment};
+use std::{
+    collections::VecDeque,
+    net::SocketAddrV4,
+    time::{Duration, Instant},
+};
+
+pub const TCP_MSS: usize = 1460;
+
+const TCP_WINDOW: u16 = u16::MAX;
+const INITIAL_RTO: Duration = Duration::from_secs(1);
+const MAX_RTO: Duration = Duration::from_secs(60);
+// how long TIME-WAIT lasts, way shorter than the 2 * MSL of the RFC
+const TIME_WAIT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpState {
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+    Closed,
+}
+
+// `a < b` in sequence number space
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+// One end of a TCP connection (https://www.rfc-editor.org/rfc/rfc9293) that doesn't do any IO:
+// segments are handed to it and the ones it wants to send are taken out with `poll`. It is kept
+// simple: retransmissions are go-back-N with an exponential backoff, out of order segments are
+// dropped, there are no options and no congestion control.
+pub struct TcpConnection {
+    local: SocketAddrV4,
+    remote: SocketAddrV4,
+    state: TcpState,
+    iss: u32,
+    snd_una: u32,
+    snd_nxt: u32,
+    // the highest snd_nxt ever was, retransmissions move snd_nxt back
+    snd_max: u32,
+    snd_wnd: u32,
+    rcv_nxt: u32,
+    // everything written that wasn't acknowledged yet, starting at snd_una
+    send_buffer: VecDeque<u8>,
+    received: VecDeque<u8>,
+    fin_queued: bool,
+    fin_acked: bool,
+    ack_needed: bool,
+    rto: Duration,
+    retransmit_at: Option<Instant>,
+    time_wait_until: Option<Instant>,
+    // segments sent again after a timeout
+    retransmissions: u64,
+    outgoing: Vec<TcpSegment>,
+}
+
+impl TcpConnection {
+    fn new(local: SocketAddrV4, remote: SocketAddrV4, state: TcpState, iss: u32) -> Self {
+        Self {
+            local,
+            remote,
+            state,
+            iss,
+            snd_una: iss,
+            snd_nxt: iss,
+            snd_max: iss,
+            snd_wnd: TCP_WINDOW as u32,
+            rcv_nxt: 0,
+            send_buffer: VecDeque::new(),
+            received: VecDeque::new(),
+            fin_queued: false,
+            fin_acked: false,
+            ack_needed: false,
+            rto: INITIAL_RTO,
+            retransmit_at: None,
+            time_wait_until: None,
+            retransmissions: 0,
+            outgoing: Vec::new(),
+        }
+    }
+
+    // active open, `iss` is the initial sequence number
+    pub fn connect(local: SocketAddrV4, remote: SocketAddrV4, iss: u32) -> Self {
+        Self::new(local, remote, TcpState::SynSent, iss)
+    }
+
+    // passive open, returns None if `syn` doesn't open a connection
+    pub fn accept(
+        local: SocketAddrV4,
+        remote: SocketAddrV4,
+        syn: &TcpSegment,
+        iss: u32,
+    ) -> Option<Self> {
+        if !syn.flags.contains(TcpFlags::SYN) || syn.flags.contains(TcpFlags::ACK) {
+            return None;
+        }
+
+        let mut connection = Self::new(local, remote, TcpState::SynReceived, iss);
+        connection.rcv_nxt = syn.seq.wrapping_add(1);
+        connection.snd_wnd = syn.window as u32;
+        Some(connection)
+    }
+
+    pub fn local(&self) -> SocketAddrV4 {
+        self.local
+    }
+
+    pub fn remote(&self) -> SocketAddrV4 {
+        self.remote
+    }
+
+    pub fn state(&self) -> TcpState {
+        self.state
+    }
+
+    pub fn is_established(&self) -> bool {
+        self.state == TcpState::Established
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state == TcpState::Closed
+    }
+
+    // bytes written but not acknowledged yet
+    pub fn unacked(&self) -> usize {
+        self.send_buffer.len()
+    }
+
+    // sent but not acknowledged yet
+    pub fn in_flight(&self) -> usize {
+        self.snd_max.wrapping_sub(self.snd_una) as usize
+    }
+
+    // what the peer is willing to receive, there is no congestion window
+    pub fn send_window(&self) -> usize {
+        self.snd_wnd as usize
+    }
+
+    pub fn retransmissions(&self) -> u64 {
+        self.retransmissions
+    }
+
+    pub fn send(&mut self, data: &[u8]) {
+        if matches!(
+            self.state,
+            TcpState::SynSent | TcpState::SynReceived | TcpState::Established | TcpState::CloseWait
+        ) && !self.fin_queued
+        {
+            self.send_buffer.extend(data);
+        }
+    }
+
+    pub fn read(&mut self) -> Vec<u8> {
+        self.received.drain(..).collect()
+    }
+
+    // whether the peer won't send anything else
+    pub fn peer_closed(&self) -> bool {
+        matches!(
+            self.state,
+            TcpState::CloseWait
+                | TcpState::Closing
+                | TcpState::LastAck
+                | TcpState::TimeWait
+                | TcpState::Closed
+        )
+    }
+
+    // the FIN goes after whatever is still buffered
+    pub fn close(&mut self) {
+        self.fin_queued = true;
+    }
+
+    pub fn abort(&mut self) {
+        if self.state != TcpState::Closed {
+            let segment = self.segment(self.snd_nxt, TcpFlags::RST, Box::new([]));
+            self.outgoing.push(segment);
+            self.state = TcpState::Closed;
+        }
+    }
+
+    // a segment to send, counted when it carries what was already sent
+    fn outgoing_segment(&mut self, seq: u32, flags: TcpFlags, data: Box<[u8]>) -> TcpSegment {
+        if seq_lt(seq, self.snd_max) {
+            self.retransmissions += 1;
+        }
+        self.segment(seq, flags, data)
+    }
+
+    fn segment(&self, seq: u32, flags: TcpFlags, data: Box<[u8]>) -> TcpSegment {
+        TcpSegment {
+            source_port: self.local.port(),
+            destin_port: self.remote.port(),
+            seq,
+            ack: if flags.contains(TcpFlags::ACK) {
+                self.rcv_nxt
+            } else {
+                0
+            },
+            flags,
+            window: TCP_WINDOW,
+            data,
+        }
+    }
+
+    fn syn_acked(&self) -> bool {
+        self.snd_una != self.iss
+    }
+
+    fn fin_seq(&self) -> u32 {
+        // the SYN takes the first sequence number
+        self.iss
+            .wrapping_add(1)
+            .wrapping_add(self.acked_data_len())
+            .wrapping_add(self.send_buffer.len() as u32)
+    }
+
+    fn acked_data_len(&self) -> u32 {
+        self.snd_una
+            .wrapping_sub(self.iss)
+            .wrapping_sub(self.syn_acked() as u32)
+            .wrapping_sub(self.fin_acked as u32)
+    }
+
+    // whether the FIN is in flight (or acknowledged)
+    fn fin_sent(&self) -> bool {
+        self.fin_acked || (self.fin_queued && seq_lt(self.fin_seq(), self.snd_nxt))
+    }
+
+    pub fn handle_segment(&mut self, segment: &TcpSegment, now: Instant) {
+        if self.state == TcpState::Closed {
+            return;
+        }
+
+        if segment.flags.contains(TcpFlags::RST) {
+            log::debug!("TCP {} -> {}: connection reset", self.local, self.remote);
+            self.state = TcpState::Closed;
+            self.retransmit_at = None;
+            return;
+        }
+
+        if self.state == TcpState::SynSent {
+            let syn_ack = TcpFlags::SYN | TcpFlags::ACK;
+            if segment.flags.contains(syn_ack) && segment.ack == self.iss.wrapping_add(1) {
+                self.rcv_nxt = segment.seq.wrapping_add(1);
+                self.state = TcpState::Established;
+                self.ack_needed = true;
+                self.handle_ack(segment, now);
+            }
+            return;
+        }
+
+        // the peer didn't get our ACK to its SYN
+        if segment.flags.contains(TcpFlags::SYN) {
+            self.ack_needed = true;
+            return;
+        }
+
+        if !segment.flags.contains(TcpFlags::ACK) {
+            return;
+        }
+
+        if self.state == TcpState::SynReceived {
+            if segment.ack != self.iss.wrapping_add(1) {
+                return;
+            }
+            self.state = TcpState::Established;
+        }
+
+        self.handle_ack(segment, now);
+        self.handle_data(segment, now);
+    }
+
+    fn handle_ack(&mut self, segment: &TcpSegment, now: Instant) {
+        self.snd_wnd = segment.window as u32;
+        if !seq_lt(self.snd_una, segment.ack) || seq_lt(self.snd_max, segment.ack) {
+            return;
+        }
+
+        let mut acked = segment.ack.wrapping_sub(self.snd_una) as usize;
+        if !self.syn_acked() {
+            acked -= 1;
+        }
+        let fin_acked = self.fin_queued
+            && seq_lt(self.fin_seq(), self.snd_max)
+            && segment.ack == self.fin_seq().wrapping_add(1);
+        let data_acked = acked.min(self.send_buffer.len());
+        self.send_buffer.drain(..data_acked);
+        self.snd_una = segment.ack;
+        self.fin_acked |= fin_acked;
+        if seq_lt(self.snd_nxt, self.snd_una) {
+            self.snd_nxt = self.snd_una;
+        }
+
+        self.rto = INITIAL_RTO;
+        self.retransmit_at = (self.snd_una != self.snd_max).then(|| now + self.rto);
+
+        if fin_acked {
+            self.state = match self.state {
+                TcpState::FinWait1 => TcpState::FinWait2,
+                TcpState::Closing => self.enter_time_wait(now),
+                TcpState::LastAck => TcpState::Closed,
+                state => state,
+            };
+        }
+    }
+
+    fn handle_data(&mut self, segment: &TcpSegment, now: Instant) {
+        let carries_data = !segment.data.is_empty() || segment.flags.contains(TcpFlags::FIN);
+        if !carries_data {
+            return;
+        }
+        self.ack_needed = true;
+
+        // out of order segments are dropped, the duplicate ACK tells the peer where we are
+        if segment.seq != self.rcv_nxt {
+            return;
+        }
+
+        if matches!(
+            self.state,
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+        ) {
+            self.received.extend(segment.data.iter());
+        }
+        self.rcv_nxt = self.rcv_nxt.wrapping_add(segment.data.len() as u32);
+
+        if segment.flags.contains(TcpFlags::FIN) {
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+            self.state = match self.state {
+                TcpState::Established => TcpState::CloseWait,
+                TcpState::FinWait1 => TcpState::Closing,
+                TcpState::FinWait2 => self.enter_time_wait(now),
+                state => state,
+            };
+        }
+    }
+
+    fn enter_time_wait(&mut self, now: Instant) -> TcpState {
+        self.time_wait_until = Some(now + TIME_WAIT);
+        self.retransmit_at = None;
+        TcpState::TimeWait
+    }
+
+    // Returns the segments to send.
+    pub fn poll(&mut self, now: Instant) -> Vec<TcpSegment> {
+        if self.time_wait_until.is_some_and(|until| until <= now) {
+            self.time_wait_until = None;
+            self.state = TcpState::Closed;
+        }
+
+        if self.retransmit_at.is_some_and(|at| at <= now) {
+            log::debug!(
+                "TCP {} -> {}: retransmission timeout ({:?})",
+                self.local,
+                self.remote,
+                self.rto
+            );
+            self.snd_nxt = self.snd_una;
+            self.rto = (self.rto * 2).min(MAX_RTO);
+            self.retransmit_at = None;
+        }
+
+        if !self.is_closed() {
+            self.transmit(now);
+        }
+        std::mem::take(&mut self.outgoing)
+    }
+
+    fn transmit(&mut self, now: Instant) {
+        let start = self.snd_nxt;
+
+        match self.state {
+            TcpState::SynSent if self.snd_nxt == self.iss => {
+                let syn = self.outgoing_segment(self.iss, TcpFlags::SYN, Box::new([]));
+                self.outgoing.push(syn);
+                self.snd_nxt = self.iss.wrapping_add(1);
+            }
+            TcpState::SynReceived if self.snd_nxt == self.iss => {
+                let syn_ack =
+                    self.outgoing_segment(self.iss, TcpFlags::SYN | TcpFlags::ACK, Box::new([]));
+                self.outgoing.push(syn_ack);
+                self.snd_nxt = self.iss.wrapping_add(1);
+                self.ack_needed = false;
+            }
+            TcpState::Established
+            | TcpState::CloseWait
+            | TcpState::FinWait1
+            | TcpState::Closing
+            | TcpState::LastAck => self.transmit_data(),
+            _ => {}
+        }
+
+        if self.ack_needed && self.state != TcpState::SynSent {
+            let ack = self.segment(self.snd_nxt, TcpFlags::ACK, Box::new([]));
+            self.outgoing.push(ack);
+            self.ack_needed = false;
+        }
+
+        if seq_lt(self.snd_max, self.snd_nxt) {
+            self.snd_max = self.snd_nxt;
+        }
+        if self.snd_nxt != start && self.retransmit_at.is_none() {
+            self.retransmit_at = Some(now + self.rto);
+        }
+    }
+
+    fn transmit_data(&mut self) {
+        while !self.fin_sent() {
+            let in_flight = self.snd_nxt.wrapping_sub(self.snd_una) as usize;
+            let offset = in_flight - !self.syn_acked() as usize;
+            let window = (self.snd_wnd as usize).saturating_sub(in_flight);
+            let len = (self.send_buffer.len() - offset).min(TCP_MSS).min(window);
+            let send_fin = self.fin_queued
+                && !self.fin_acked
+                && self.snd_nxt.wrapping_add(len as u32) == self.fin_seq();
+
+            if len == 0 && !send_fin {
+                return;
+            }
+
+            let data: Box<[u8]> = self
+                .send_buffer
+                .range(offset..offset + len)
+                .copied()
+                .collect();
+            let mut flags = TcpFlags::ACK;
+            if len > 0 {
+                flags = flags | TcpFlags::PSH;
+            }
+            if send_fin {
+                flags = flags | TcpFlags::FIN;
+                self.state = match self.state {
+                    TcpState::Established => TcpState::FinWait1,
+                    TcpState::CloseWait => TcpState::LastAck,
+                    state => state,
+                };
+            }
+
+            let segment = self.outgoing_segment(self.snd_nxt, flags, data);
+            self.outgoing.push(segment);
+            self.ack_needed = false;
+            self.snd_nxt = self
+                .snd_nxt
+                .wrapping_add(len as u32)
+                .wrapping_add(send_fin as u32);
+        }
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        [self.retransmit_at, self.time_wait_until]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TcpConnection, TcpState, TCP_MSS};
+    use crate::protocols::tcp::TcpSegment;
+    use std::{
+        net::{Ipv4Addr, SocketAddrV4},
+        time::{Duration, Instant},
+    };
+
+    const CLIENT: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 40000);
+    const SERVER: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 179);
+
+    // Moves segments between both ends, dropping the ones `drop` picks, until nothing is left
+    // to send. Time advances to the next deadline whenever the network goes quiet.
+    fn exchange(
+        client: &mut TcpConnection,
+        server: &mut Option<TcpConnection>,
+        now: &mut Instant,
+        mut drop: impl FnMut(usize) -> bool,
+    ) {
+        let mut sent = 0;
+        for _ in 0..10_000 {
+            let mut quiet = true;
+
+            for segment in client.poll(*now) {
+                sent += 1;
+                quiet = false;
+                if drop(sent) {
+                    continue;
+                }
+                match server {
+                    Some(server) => server.handle_segment(&segment, *now),
+                    None => {
+                        *server = TcpConnection::accept(SERVER, CLIENT, &segment, u32::MAX - 10)
+                    }
+                }
+            }
+
+            if let Some(server) = server {
+                for segment in server.poll(*now) {
+                    sent += 1;
+                    quiet = false;
+                    if !drop(sent) {
+                        client.handle_segment(&segment, *now);
+                    }
+                }
+            }
+
+            if quiet {
+                let deadline = [
+                    client.next_deadline(),
+                    server.as_ref().and_then(|s| s.next_deadline()),
+                ]
+                .into_iter()
+                .flatten()
+                .min();
+                match deadline {
+                    Some(deadline) => *now = deadline,
+                    None => return,
+                }
+            }
+        }
+        panic!("the connection never settled");
+    }
+
+    #[test]
+    fn transfer_over_lossy_network() {
+        let mut now = Instant::now();
+        let mut client = TcpConnection::connect(CLIENT, SERVER, 1000);
+        let mut server = None;
+
+        let data: Vec<u8> = (0..50 * TCP_MSS).map(|i| i as u8).collect();
+        client.send(&data);
+        client.close();
+
+        // every 7th segment (including SYNs, ACKs and FINs) is lost
+        exchange(&mut client, &mut server, &mut now, |n| n % 7 == 0);
+
+        let server = server.as_mut().unwrap();
+        assert_eq!(server.read(), data);
+        assert!(server.peer_closed());
+        assert!(client.retransmissions() > 0);
+
+        server.send(b"bye");
+        server.close();
+        let mut server = Some(std::mem::replace(
+            server,
+            TcpConnection::connect(SERVER, CLIENT, 0),
+        ));
+        exchange(&mut client, &mut server, &mut now, |n| n % 5 == 0);
+
+        assert_eq!(client.read(), b"bye");
+        assert!(client.is_closed());
+        assert!(server.unwrap().is_closed());
+    }
+
+    #[test]
+    fn reset() {
+        let mut now = Instant::now();
+        let mut client = TcpConnection::connect(CLIENT, SERVER, 0);
+        let mut server = None;
+        exchange(&mut client, &mut server, &mut now, |_| false);
+        assert!(client.is_established());
+
+        let mut server = server.unwrap();
+        server.abort();
+        for segment in server.poll(now) {
+            client.handle_segment(&segment, now);
+        }
+        assert_eq!(client.state(), TcpState::Closed);
+        assert!(client.poll(now + Duration::from_secs(10)).is_empty());
+    }
+
+    #[test]
+    fn rejects_non_syn() {
+        let segment = TcpSegment {
+            source_port: CLIENT.port(),
+            destin_port: SERVER.port(),
+            seq: 0,
+            ack: 0,
+            flags: Default::default(),
+            window: 0,
+            data: Box::new([]),
+        };
+        assert!(TcpConnection::accept(SERVER, CLIENT, &segment, 0).is_none());
+    }
+}
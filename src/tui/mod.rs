@@ -0,0 +1,345 @@
+use crate::{
+    simulator::{NetworkStats, Simulator, SimulatorHandle},
+    topology::{Topology, TopologyLink},
+    trace::Tracer,
+};
+use ratatui::{
+    backend::Backend,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Sparkline},
+    Frame, Terminal,
+};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    io,
+    time::{Duration, Instant},
+};
+
+// how often the network is sampled, the sparklines get a bar for each sample
+const TICK: Duration = Duration::from_millis(250);
+// the samples kept for each link
+const HISTORY: usize = 120;
+// the rows of each link: its description and its sparkline
+const LINK_ROWS: u16 = 3;
+
+const HELP: &str = "space pause/resume  s step  ↑↓ device  PgUp/PgDn packets  q quit";
+
+// Runs the simulation showing it live in the terminal until `q` is pressed, then shuts it down.
+// The packet list comes from the tracer of the simulation, one is set if it has none.
+pub fn run(mut sim: Simulator) -> io::Result<NetworkStats> {
+    let tracer = match sim.tracer() {
+        Some(tracer) => tracer.clone(),
+        None => {
+            let tracer = Tracer::new(io::sink());
+            sim.set_tracer(tracer.clone());
+            tracer
+        }
+    };
+
+    let mut app = App::new(sim.run(), tracer);
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+
+    let stats = app.network.shutdown();
+    result.map(|()| stats)
+}
+
+struct App {
+    network: SimulatorHandle,
+    tracer: Tracer,
+    started: Instant,
+    topology: Topology,
+    // the frames each link carried during each tick, by link id
+    throughput: BTreeMap<usize, VecDeque<u64>>,
+    last_frames: HashMap<usize, u64>,
+    // the device whose tables are shown
+    selected: usize,
+    // how far the packet list is scrolled back from the newest packets
+    scroll: usize,
+}
+
+impl App {
+    fn new(network: SimulatorHandle, tracer: Tracer) -> Self {
+        Self {
+            network,
+            tracer,
+            started: Instant::now(),
+            topology: Topology::default(),
+            throughput: BTreeMap::new(),
+            last_frames: HashMap::new(),
+            selected: 0,
+            scroll: 0,
+        }
+    }
+
+    fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
+        let mut next_sample = Instant::now();
+        loop {
+            if Instant::now() >= next_sample {
+                self.sample();
+                next_sample += TICK;
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if event::poll(next_sample.saturating_duration_since(Instant::now()))? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press && !self.handle_key(key.code) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    // returns false once the user is done
+    fn handle_key(&mut self, key: KeyCode) -> bool {
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char(' ') if self.network.is_paused() => self.network.resume(),
+            KeyCode::Char(' ') => self.network.pause(),
+            KeyCode::Char('s') if self.network.is_paused() => self.network.step(),
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                let last = self.topology.devices.len().saturating_sub(1);
+                self.selected = (self.selected + 1).min(last);
+            }
+            KeyCode::PageUp => self.scroll += 10,
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::End => self.scroll = 0,
+            _ => {}
+        }
+        true
+    }
+
+    fn sample(&mut self) {
+        self.topology = self.network.topology();
+        for link in &self.topology.links {
+            let last = self.last_frames.insert(link.id, link.stats.frames);
+            let history = self.throughput.entry(link.id).or_default();
+            if history.len() == HISTORY {
+                history.pop_front();
+            }
+            history.push_back(link.stats.frames - last.unwrap_or_default());
+        }
+        // removed links stop being shown
+        let links: Vec<_> = self.topology.links.iter().map(|link| link.id).collect();
+        self.throughput.retain(|id, _| links.contains(id));
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [status, body] =
+            Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)])
+                .areas(body);
+        let [devices, links] =
+            Layout::vertical([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(left);
+        let [tables, packets] =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(right);
+
+        let state = match self.network.is_paused() {
+            true => "paused",
+            false => "running",
+        };
+        let status_line = format!(
+            " {state} {:.1}s | {HELP}",
+            self.started.elapsed().as_secs_f64()
+        );
+        frame.render_widget(
+            Paragraph::new(status_line).style(Style::new().add_modifier(Modifier::REVERSED)),
+            status,
+        );
+
+        self.draw_devices(frame, devices);
+        self.draw_links(frame, links);
+        self.draw_tables(frame, tables);
+        self.draw_packets(frame, packets);
+    }
+
+    fn draw_devices(&self, frame: &mut Frame, area: Rect) {
+        let items: Vec<_> = self
+            .topology
+            .devices
+            .iter()
+            .map(|device| {
+                let mut line = format!("{:<11} {}", device.kind, device.mac);
+                if !device.addresses.is_empty() {
+                    line.push_str(&format!(" {}", device.addresses.join(", ")));
+                }
+                ListItem::new(line)
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::new().borders(Borders::ALL).title("Devices"))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(list, area, &mut state);
+    }
+
+    fn link_line(link: &TopologyLink, last: u64) -> String {
+        format!(
+            "link {} {}/{} -- {}/{} {} {last} frames",
+            link.id,
+            link.source.0,
+            link.source.1,
+            link.destin.0,
+            link.destin.1,
+            if link.up { "up" } else { "down" }
+        )
+    }
+
+    fn draw_links(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::new()
+            .borders(Borders::ALL)
+            .title(format!("Links (frames per {}ms)", TICK.as_millis()));
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+
+        let shown = (inner.height / LINK_ROWS) as usize;
+        let rows = Layout::vertical(vec![Constraint::Length(LINK_ROWS); shown]).split(inner);
+        for (link, row) in self.topology.links.iter().zip(rows.iter()) {
+            let history = &self.throughput[&link.id];
+            let [line, sparkline] =
+                Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(*row);
+            let last = history.back().copied().unwrap_or_default();
+            frame.render_widget(Paragraph::new(Self::link_line(link, last)), line);
+
+            // the newest samples, as many as fit
+            let skip = history.len().saturating_sub(sparkline.width as usize);
+            let color = if link.up {
+                Color::Green
+            } else {
+                Color::DarkGray
+            };
+            frame.render_widget(
+                Sparkline::default()
+                    .data(history.iter().skip(skip))
+                    .style(Style::new().fg(color)),
+                sparkline,
+            );
+        }
+    }
+
+    fn draw_tables(&self, frame: &mut Frame, area: Rect) {
+        let (title, text) = match self.topology.devices.get(self.selected) {
+            Some(device) => {
+                let tables = self.network.tables(device.mac);
+                let text = match tables.is_empty() {
+                    true => "no tables".to_string(),
+                    false => tables
+                        .iter()
+                        .map(|(name, table)| format!("{name}\n{table}"))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                };
+                (format!("Tables of {}", device.mac), text)
+            }
+            None => ("Tables".to_string(), String::new()),
+        };
+        frame.render_widget(
+            Paragraph::new(text).block(Block::new().borders(Borders::ALL).title(title)),
+            area,
+        );
+    }
+
+    fn draw_packets(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::new().borders(Borders::ALL).title("Packets");
+        let height = block.inner(area).height as usize;
+
+        let recent = self.tracer.recent();
+        let end = recent.len().saturating_sub(self.scroll);
+        let start = end.saturating_sub(height);
+        let items: Vec<_> = recent[start..end]
+            .iter()
+            .map(|line| ListItem::new(line.as_str()))
+            .collect();
+        frame.render_widget(List::new(items).block(block), area);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::App;
+    use crate::{
+        devices::{switch::Layer2Switch, ProgrammableDevice},
+        protocols::{
+            ethernet::{self, EthernetFrame, FrameProtocol, MacAddress},
+            Packet,
+        },
+        simulator::{InterfaceSpec, Simulator},
+        trace::Tracer,
+    };
+    use ratatui::{backend::TestBackend, crossterm::event::KeyCode, Terminal};
+    use std::{io, thread, time::Duration};
+
+    fn rows(terminal: &Terminal<TestBackend>) -> Vec<String> {
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn shows_the_network() {
+        // the switch learns where a is from its broadcast
+        let mac = |byte| MacAddress::new([byte; 6]);
+        let mut sim = Simulator::new();
+        let tracer = Tracer::new(io::sink());
+        sim.set_tracer(tracer.clone());
+        sim.add_device(Layer2Switch::new(mac(1), 2));
+        let frame = EthernetFrame {
+            source: mac(0xa),
+            destin: ethernet::ETHERNET_BROADCAST_MAC_ADDR,
+            protocol: FrameProtocol::Ipv4,
+            data: Box::new([0; 46]),
+        };
+        sim.add_device(ProgrammableDevice::new(mac(0xa), 1, move |_, module| {
+            module
+                .get_interface(0)
+                .unwrap()
+                .send(frame.to_bytes())
+                .unwrap();
+            while module.wait_for_msg().is_some() {}
+        }));
+        sim.add_device(ProgrammableDevice::new(mac(0xb), 1, |_, module| {
+            while module.wait_for_msg().is_some() {}
+        }));
+        sim.add_link(
+            InterfaceSpec::new(mac(0xa), 0),
+            InterfaceSpec::new(mac(1), 0),
+        );
+        sim.add_link(
+            InterfaceSpec::new(mac(0xb), 0),
+            InterfaceSpec::new(mac(1), 1),
+        );
+
+        let mut app = App::new(sim.run(), tracer);
+        thread::sleep(Duration::from_millis(100));
+        app.sample();
+        let mut terminal = Terminal::new(TestBackend::new(160, 30)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let screen = rows(&terminal);
+        let shown = |text: &str| screen.iter().any(|row| row.contains(text));
+
+        assert!(screen[0].contains("running"));
+        assert!(shown("switch      01:01:01:01:01:01"));
+        assert!(shown(
+            "link 0 0A:0A:0A:0A:0A:0A/0 -- 01:01:01:01:01:01/0 up 1 frames"
+        ));
+        assert!(shown("learning table"));
+        assert!(shown("0A:0A:0A:0A:0A:0A  interface 0"));
+        assert!(shown("01:01:01:01:01:01 flood"));
+
+        assert!(app.handle_key(KeyCode::Char(' ')));
+        assert!(app.network.is_paused());
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        assert!(rows(&terminal)[0].contains("paused"));
+        assert!(!app.handle_key(KeyCode::Char('q')));
+        app.network.shutdown();
+    }
+}